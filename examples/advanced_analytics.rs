@@ -71,10 +71,10 @@ impl AnalyticsClient {
                 salesAnalytics {
                     topCustomers {
                         customer {
-                            c_custkey
-                            c_name
-                            c_mktsegment
-                            c_acctbal
+                            cCustkey
+                            cName
+                            cMktsegment
+                            cAcctbal
                         }
                         totalSpent
                         orderCount
@@ -100,7 +100,7 @@ impl AnalyticsClient {
         let mut segments = std::collections::HashMap::new();
         
         for customer in analytics["topCustomers"].as_array().unwrap_or(&vec![]) {
-            let segment = customer["customer"]["c_mktsegment"].as_str().unwrap_or("Unknown");
+            let segment = customer["customer"]["cMktsegment"].as_str().unwrap_or("Unknown");
             let spent = customer["totalSpent"].as_f64().unwrap_or(0.0);
             let count = customer["orderCount"].as_i64().unwrap_or(0);
             
@@ -242,9 +242,9 @@ impl AnalyticsClient {
                     avgOrderValue
                     topCustomers {
                         customer {
-                            c_name
-                            c_mktsegment
-                            c_acctbal
+                            cName
+                            cMktsegment
+                            cAcctbal
                         }
                         totalSpent
                         orderCount
@@ -289,8 +289,8 @@ impl AnalyticsClient {
         println!("\n👥 TOP CUSTOMERS ANALYSIS");
         let top_customers = analytics["topCustomers"].as_array().unwrap_or(&vec![]);
         for (i, customer) in top_customers.iter().take(5).enumerate() {
-            let name = customer["customer"]["c_name"].as_str().unwrap_or("Unknown");
-            let segment = customer["customer"]["c_mktsegment"].as_str().unwrap_or("Unknown");
+            let name = customer["customer"]["cName"].as_str().unwrap_or("Unknown");
+            let segment = customer["customer"]["cMktsegment"].as_str().unwrap_or("Unknown");
             let spent = customer["totalSpent"].as_f64().unwrap_or(0.0);
             let orders = customer["orderCount"].as_i64().unwrap_or(0);
             
@@ -331,7 +331,7 @@ impl AnalyticsClient {
         // Find best performing segment
         let mut segment_performance = std::collections::HashMap::new();
         for customer in top_customers {
-            let segment = customer["customer"]["c_mktsegment"].as_str().unwrap_or("Unknown");
+            let segment = customer["customer"]["cMktsegment"].as_str().unwrap_or("Unknown");
             let spent = customer["totalSpent"].as_f64().unwrap_or(0.0);
             let entry = segment_performance.entry(segment).or_insert(0.0);
             *entry += spent;
@@ -91,11 +91,11 @@ impl TPCHClient {
         let query = r#"
             query {
                 customers(limit: 5) {
-                    c_custkey
-                    c_name
-                    c_address
-                    c_acctbal
-                    c_mktsegment
+                    cCustkey
+                    cName
+                    cAddress
+                    cAcctbal
+                    cMktsegment
                 }
             }
         "#;
@@ -118,11 +118,11 @@ impl TPCHClient {
         let query = r#"
             query {
                 orders(limit: 3) {
-                    o_orderkey
-                    o_custkey
-                    o_orderstatus
-                    o_totalprice
-                    o_orderdate
+                    oOrderkey
+                    oCustkey
+                    oOrderstatus
+                    oTotalprice
+                    oOrderdate
                 }
             }
         "#;
@@ -178,8 +178,8 @@ impl TPCHClient {
                     avgOrderValue
                     topCustomers {
                         customer {
-                            c_name
-                            c_mktsegment
+                            cName
+                            cMktsegment
                         }
                         totalSpent
                         orderCount
@@ -209,8 +209,8 @@ impl TPCHClient {
         for customer in analytics["topCustomers"].as_array().unwrap_or(&vec![]) {
             let cust = &customer["customer"];
             println!("  {} ({}): ${:.2}", 
-                cust["c_name"], 
-                cust["c_mktsegment"], 
+                cust["cName"], 
+                cust["cMktsegment"], 
                 customer["totalSpent"].as_f64().unwrap_or(0.0));
         }
         
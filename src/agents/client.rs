@@ -1,10 +1,21 @@
 //! Agent client for Ollama integration
 
 use crate::agents::types::{OllamaOptions, OllamaRequest, OllamaResponse};
+use crate::metrics::metrics;
 use crate::models::data::Customer;
 use async_graphql::Error;
 use reqwest::Client;
-use tracing::error;
+use std::time::Instant;
+use tracing::{error, info};
+
+/// Default upper bound on the size of one prompt, in bytes
+pub const DEFAULT_MAX_PROMPT_CHARS: usize = 8000;
+
+/// Smallest prompt size limit, leaving room for the instructions around the data
+pub const MIN_PROMPT_CHARS: usize = 512;
+
+/// Rounds of combining chunk summaries before the rest is truncated
+const MAX_REDUCE_ROUNDS: usize = 3;
 
 /// Agent client for interacting with Ollama
 #[derive(Debug, Clone)]
@@ -13,6 +24,7 @@ pub struct AgentClient {
     ollama_url: String,
     model: String,
     options: OllamaOptions,
+    max_prompt_chars: usize,
 }
 
 impl AgentClient {
@@ -22,34 +34,139 @@ impl AgentClient {
             ollama_url,
             model,
             options: OllamaOptions::default(),
+            max_prompt_chars: DEFAULT_MAX_PROMPT_CHARS,
         }
     }
 
-    /// Translate natural language to SQL
-    pub async fn translate_to_sql(&self, input: &str) -> Result<String, Error> {
-        let prompt = format!(
+    /// Limit the size of each prompt; limits below `MIN_PROMPT_CHARS` are raised to it
+    pub fn with_max_prompt_chars(mut self, max_prompt_chars: usize) -> Self {
+        self.max_prompt_chars = max_prompt_chars.max(MIN_PROMPT_CHARS);
+        self
+    }
+
+    /// Model name sent with every request
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Prompt asking the model to translate natural language to SQL
+    pub fn translation_prompt(&self, input: &str) -> String {
+        format!(
             "Translate this natural language query to SQL for TPCH database: '{}'. 
             Available tables: customer, orders, lineitem, part, supplier, nation, region, partsupp.
             Return only the SQL query, no explanations.",
             input
+        )
+    }
+
+    /// Translate natural language to SQL
+    pub async fn translate_to_sql(&self, input: &str) -> Result<String, Error> {
+        let prompt = self.translation_prompt(input);
+        self.call_ollama(&prompt).await.map(|sql| clean_sql(&sql))
+    }
+
+    /// Ask the model to fix SQL that failed against the actual schema
+    pub async fn correct_sql(
+        &self,
+        input: &str,
+        sql: &str,
+        error: &str,
+        schema: &str,
+    ) -> Result<String, Error> {
+        let prompt = format!(
+            "The SQL query generated for '{}' failed with error: {}
+            Failed query: {}
+            The actual database schema is:
+            {}
+            Return only the corrected SQL query, no explanations.",
+            input, error, sql, schema
         );
 
-        self.call_ollama(&prompt).await
+        self.call_ollama(&prompt).await.map(|sql| clean_sql(&sql))
     }
 
-    /// Generate insights from data
+    /// Generate insights from data. Records that do not fit in one prompt are
+    /// split into chunks, each chunk is summarized and the insights are generated
+    /// from the chunk summaries, so no prompt exceeds the prompt size limit.
     pub async fn generate_insights(&self, customers: Vec<Customer>) -> Result<String, Error> {
         if customers.is_empty() {
             return Ok("No data available for analysis.".to_string());
         }
 
-        let summary = self.summarize_customers(&customers);
-        let prompt = format!(
-            "Analyze this customer data and provide business insights: {}",
-            summary
+        let header = format!(
+            "Analyze this customer data and provide business insights: {}\n",
+            self.summarize_customers(&customers)
+        );
+        let header = truncate(&header, self.max_prompt_chars / 2).to_string();
+        let budget = self.max_prompt_chars - header.len();
+        let records: Vec<String> = customers.iter().map(describe_customer).collect();
+        let chunks = pack(&records, budget);
+        if chunks.len() == 1 {
+            return self.call_ollama(&format!("{}{}", header, chunks[0])).await;
+        }
+
+        info!(
+            "Generating insights over {} records in {} chunks",
+            customers.len(),
+            chunks.len()
         );
+        let mut summaries = Vec::with_capacity(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            let prompt = format!(
+                "Summarize the notable patterns in part {} of {} of this customer data:\n{}",
+                i + 1,
+                chunks.len(),
+                chunk
+            );
+            summaries.push(
+                self.call_ollama(truncate(&prompt, self.max_prompt_chars))
+                    .await?,
+            );
+        }
+
+        let header = format!("{}Summaries of its parts:\n", header);
+        let budget = self.max_prompt_chars.saturating_sub(header.len());
+        for _ in 0..MAX_REDUCE_ROUNDS {
+            let packs = pack(&summaries, budget);
+            if packs.len() == 1 {
+                break;
+            }
+            summaries = Vec::with_capacity(packs.len());
+            for pack in packs {
+                let prompt = format!(
+                    "Combine these summaries of customer data into one summary:\n{}",
+                    pack
+                );
+                summaries.push(
+                    self.call_ollama(truncate(&prompt, self.max_prompt_chars))
+                        .await?,
+                );
+            }
+        }
+
+        let prompt = format!("{}{}", header, summaries.join("\n"));
+        self.call_ollama(truncate(&prompt, self.max_prompt_chars))
+            .await
+    }
 
-        self.call_ollama(&prompt).await
+    /// Generate insights from the result of a question, given as one line per row
+    pub async fn generate_result_insights(
+        &self,
+        question: &str,
+        rows: &[String],
+    ) -> Result<String, Error> {
+        if rows.is_empty() {
+            return Ok("No data available for analysis.".to_string());
+        }
+
+        let header = format!(
+            "The question '{}' returned these rows. Provide business insights:\n",
+            question
+        );
+        let header = truncate(&header, self.max_prompt_chars / 2).to_string();
+        // Rows past the first prompt are left out rather than summarized
+        let rows = pack(rows, self.max_prompt_chars - header.len());
+        self.call_ollama(&format!("{}{}", header, rows[0])).await
     }
 
     /// Test connection to Ollama
@@ -70,6 +187,7 @@ impl AgentClient {
             options: Some(self.options.clone()),
         };
 
+        let started = Instant::now();
         let response = self
             .client
             .post(&format!("{}/api/generate", self.ollama_url))
@@ -92,6 +210,9 @@ impl AgentClient {
             error!("Failed to parse Ollama response: {}", e);
             Error::new(format!("Failed to parse response: {}", e))
         })?;
+        metrics()
+            .llm_latency
+            .observe(started.elapsed().as_secs_f64());
 
         Ok(ollama_response.response)
     }
@@ -110,7 +231,7 @@ impl AgentClient {
             customers
                 .iter()
                 .fold(std::collections::HashMap::new(), |mut acc, c| {
-                    *acc.entry(c.c_mktsegment.clone()).or_insert(0) += 1;
+                    *acc.entry(c.c_mktsegment.to_string()).or_insert(0) += 1;
                     acc
                 });
 
@@ -120,3 +241,53 @@ impl AgentClient {
         )
     }
 }
+
+/// One line describing a customer for the insight prompts
+fn describe_customer(customer: &Customer) -> String {
+    format!(
+        "customer {}: segment {}, nation {}, balance {:.2}",
+        customer.c_custkey, customer.c_mktsegment, customer.c_nationkey, customer.c_acctbal
+    )
+}
+
+/// Join lines into chunks of at most `budget` bytes, truncating longer lines
+fn pack(lines: &[String], budget: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for line in lines {
+        let line = truncate(line, budget);
+        if !chunk.is_empty() && chunk.len() + 1 + line.len() > budget {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        if !chunk.is_empty() {
+            chunk.push('\n');
+        }
+        chunk.push_str(line);
+    }
+    chunks.push(chunk);
+    chunks
+}
+
+/// Longest prefix of at most `max` bytes ending on a character boundary
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Strip markdown code fences and trailing semicolons from model output
+fn clean_sql(response: &str) -> String {
+    response
+        .trim()
+        .trim_start_matches("```sql")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim()
+        .trim_end_matches(';')
+        .to_string()
+}
@@ -0,0 +1,86 @@
+//! Agent health tracking
+//!
+//! Each agent has a circuit breaker, opened after consecutive failures, and a window
+//! of recent latencies. The orchestrator routes requests away from agents whose
+//! breaker is open or whose p95 latency is above the policy threshold.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// When an agent is taken out of rotation
+#[derive(Debug, Clone)]
+pub struct HealthPolicy {
+    /// Consecutive failures that open the circuit breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker keeps the agent out of rotation
+    pub open_duration: Duration,
+    /// Agents whose recent p95 latency exceeds this are skipped
+    pub max_p95_latency: Option<Duration>,
+    /// Latency samples kept per agent
+    pub window: usize,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 3,
+            open_duration: Duration::from_secs(30),
+            max_p95_latency: None,
+            window: 20,
+        }
+    }
+}
+
+/// Recent outcomes of calls to one agent
+#[derive(Debug, Default)]
+pub struct AgentHealth {
+    latencies: VecDeque<Duration>,
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl AgentHealth {
+    pub fn record(&mut self, latency: Duration, success: bool, policy: &HealthPolicy) {
+        if policy.window > 0 {
+            if self.latencies.len() >= policy.window {
+                self.latencies.pop_front();
+            }
+            self.latencies.push_back(latency);
+        }
+
+        if success {
+            self.consecutive_failures = 0;
+            self.open_until = None;
+        } else {
+            self.consecutive_failures += 1;
+            if self.consecutive_failures >= policy.failure_threshold {
+                self.open_until = Some(Instant::now() + policy.open_duration);
+            }
+        }
+    }
+
+    /// Whether the circuit breaker is open; once the open period ends the agent
+    /// gets another try and a single failure opens it again
+    pub fn is_open(&self) -> bool {
+        self.open_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// 95th percentile of the recent latencies, `None` without samples
+    pub fn p95_latency(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = self.latencies.iter().copied().collect();
+        sorted.sort();
+        let rank = (sorted.len() * 95).div_ceil(100);
+        Some(sorted[rank.saturating_sub(1)])
+    }
+
+    pub fn is_healthy(&self, policy: &HealthPolicy) -> bool {
+        let too_slow = match (policy.max_p95_latency, self.p95_latency()) {
+            (Some(max), Some(p95)) => p95 > max,
+            _ => false,
+        };
+        !self.is_open() && !too_slow
+    }
+}
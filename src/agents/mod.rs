@@ -1,6 +1,8 @@
 pub mod client;
 pub mod config;
+pub mod health;
 pub mod orchestrator;
+pub mod pipeline;
 pub mod types;
 
 pub use client::AgentClient;
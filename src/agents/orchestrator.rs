@@ -1,37 +1,60 @@
 //! Agent orchestrator for managing multiple AI agents
 
 use crate::agents::client::AgentClient;
+use crate::agents::health::{AgentHealth, HealthPolicy};
+use crate::agents::pipeline::{PipelineResult, SAMPLE_ROWS, prompt_hash, sample_rows};
 use crate::agents::types::{AgentConfig, AgentStatus};
-use crate::models::data::Customer;
+use crate::audit::{AuditLog, NlqAuditRecord};
+use crate::datafusion::context::{DataFusionContext, is_schema_error};
 use async_graphql::Error;
+use datafusion::arrow::record_batch::RecordBatch;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tracing::{error, info, warn};
 
 /// Agent orchestrator for managing multiple AI agents
-#[derive(Debug)]
 pub struct AgentOrchestrator {
     clients: HashMap<String, Arc<AgentClient>>,
     default_agent: String,
-    agent_stats: HashMap<String, u64>,
+    agent_stats: Mutex<HashMap<String, u64>>,
+    df_ctx: Option<Arc<DataFusionContext>>,
+    max_correction_attempts: usize,
+    audit_log: Arc<AuditLog>,
+    health: Mutex<HashMap<String, AgentHealth>>,
+    health_policy: HealthPolicy,
+    priority: Vec<String>,
+}
+
+/// Outcome of a natural language query
+pub struct NlqResult {
+    pub sql: String,
+    pub batches: Vec<RecordBatch>,
+    pub audit: NlqAuditRecord,
+    pub report: PipelineResult,
 }
 
 impl AgentOrchestrator {
     pub fn new() -> Self {
-        let mut clients = HashMap::new();
-
         // Initialize default agent
-        let default_client = Arc::new(AgentClient::new(
-            "http://localhost:11434".to_string(),
-            "llama2".to_string(),
-        ));
-        clients.insert("default".to_string(), default_client);
+        let default_client =
+            AgentClient::new("http://localhost:11434".to_string(), "llama2".to_string());
+
+        Self::empty().with_agent("default".to_string(), default_client)
+    }
 
+    /// Orchestrator without any agent clients, used when AI features are disabled
+    pub fn empty() -> Self {
         Self {
-            clients,
+            clients: HashMap::new(),
             default_agent: "default".to_string(),
-            agent_stats: HashMap::new(),
+            agent_stats: Mutex::new(HashMap::new()),
+            df_ctx: None,
+            max_correction_attempts: 2,
+            audit_log: Arc::new(AuditLog::default()),
+            health: Mutex::new(HashMap::new()),
+            health_policy: HealthPolicy::default(),
+            priority: Vec::new(),
         }
     }
 
@@ -40,62 +63,301 @@ impl AgentOrchestrator {
         self
     }
 
+    /// Attach the DataFusion context used to execute generated SQL
+    pub fn with_context(mut self, df_ctx: Arc<DataFusionContext>) -> Self {
+        self.df_ctx = Some(df_ctx);
+        self
+    }
+
+    /// Set how many times invalid SQL is sent back to the model for correction
+    pub fn with_max_correction_attempts(mut self, attempts: usize) -> Self {
+        self.max_correction_attempts = attempts;
+        self
+    }
+
+    /// Share an audit log, e.g. with the HTTP layer
+    pub fn with_audit_log(mut self, audit_log: Arc<AuditLog>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Set when agents are taken out of rotation
+    pub fn with_health_policy(mut self, policy: HealthPolicy) -> Self {
+        self.health_policy = policy;
+        self
+    }
+
+    /// Agents preferred, in order, for requests without an explicit agent type
+    pub fn with_priority(mut self, priority: Vec<String>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Answer a question end to end: translate it to SQL, execute it and generate
+    /// insights from a sample of the result. Failing insights only add a warning.
     pub async fn process_query(
-        &mut self,
+        &self,
         input: &str,
         agent_type: Option<String>,
-    ) -> Result<(Vec<Customer>, String), Error> {
-        let agent_name = agent_type.unwrap_or_else(|| self.default_agent.clone());
+    ) -> Result<PipelineResult, Error> {
+        let started = Instant::now();
+        let mut report = self
+            .execute_natural_language_for(None, input, agent_type)
+            .await?
+            .report;
+        let client = self.select_client(Some(report.agent.clone()))?;
+        let rows: Vec<String> = report.sample.iter().map(|row| row.to_string()).collect();
 
-        // Update stats
-        *self.agent_stats.entry(agent_name.clone()).or_insert(0) += 1;
+        let insights_started = Instant::now();
+        let insights = client.generate_result_insights(input, &rows).await;
+        self.record_agent_result(&report.agent, insights_started.elapsed(), insights.is_ok());
+        match insights {
+            Ok(insights) => report.insights = Some(insights),
+            Err(e) => report
+                .warnings
+                .push(format!("Insights unavailable: {}", e.message)),
+        }
+        report.durations.insights_ms = insights_started.elapsed().as_millis() as u64;
+        report.durations.total_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    /// Agent for a request without an explicit agent type: the first healthy agent
+    /// in priority order, else the healthy agent with the lowest p95 latency, else
+    /// the default agent, the first agent in priority order or the first by name.
+    /// `None` when no agent is configured.
+    pub fn select_agent(&self) -> Option<String> {
+        let health = self.health.lock().unwrap();
+        let healthy = |name: &String| {
+            health
+                .get(name)
+                .is_none_or(|agent| agent.is_healthy(&self.health_policy))
+        };
+
+        let by_priority = self
+            .priority
+            .iter()
+            .filter(|name| self.clients.contains_key(*name))
+            .find(|name| healthy(name));
+        if let Some(name) = by_priority {
+            info!("Selected agent '{}' by priority", name);
+            return Some(name.clone());
+        }
 
-        let client = self
+        // Agents without samples count as fastest; ties go to the default agent
+        let by_latency = self
             .clients
-            .get(&agent_name)
-            .ok_or_else(|| Error::new(format!("Agent '{}' not found", agent_name)))?;
+            .keys()
+            .filter(|name| healthy(name))
+            .min_by_key(|name| {
+                let p95 = health.get(*name).and_then(AgentHealth::p95_latency);
+                (
+                    p95.unwrap_or_default(),
+                    **name != self.default_agent,
+                    (*name).clone(),
+                )
+            });
+        match by_latency {
+            Some(name) => {
+                info!("Selected agent '{}' by latency", name);
+                Some(name.clone())
+            }
+            None => {
+                let fallback = self.fallback_agent()?;
+                warn!("No healthy agent, falling back to '{}'", fallback);
+                Some(fallback)
+            }
+        }
+    }
 
-        self.attempt_process_query(client, input).await
+    fn fallback_agent(&self) -> Option<String> {
+        if self.clients.contains_key(&self.default_agent) {
+            return Some(self.default_agent.clone());
+        }
+        self.priority
+            .iter()
+            .find(|name| self.clients.contains_key(*name))
+            .or_else(|| self.clients.keys().min())
+            .cloned()
+    }
+
+    /// Record the outcome of a call to an agent, updating its health. Also used to
+    /// inject failures and latencies in tests.
+    pub fn record_agent_result(&self, agent: &str, latency: Duration, success: bool) {
+        let mut health = self.health.lock().unwrap();
+        let entry = health.entry(agent.to_string()).or_default();
+        let was_open = entry.is_open();
+        entry.record(latency, success, &self.health_policy);
+        if entry.is_open() && !was_open {
+            warn!("Circuit breaker opened for agent '{}'", agent);
+        }
     }
 
-    async fn attempt_process_query(
+    /// Translate natural language to SQL and execute it, feeding schema errors
+    /// back to the model until the SQL runs or the correction budget is spent
+    pub async fn execute_natural_language(
         &self,
-        client: &Arc<AgentClient>,
         input: &str,
-    ) -> Result<(Vec<Customer>, String), Error> {
-        // Step 1: Translate natural language to SQL
-        let sql = client.translate_to_sql(input).await?;
-        info!("Generated SQL: {}", sql);
-
-        // Step 2: Execute SQL (in production, this would use DataFusion)
-        // For now, return mock data
-        let records = vec![
-            Customer {
-                c_custkey: 1,
-                c_name: "Customer#000000001".to_string(),
-                c_address: "Sample Address 1".to_string(),
-                c_nationkey: 1,
-                c_phone: "25-989-741-2988".to_string(),
-                c_acctbal: 100.0,
-                c_mktsegment: "BUILDING".to_string(),
-                c_comment: "Sample customer 1".to_string(),
-            },
-            Customer {
-                c_custkey: 2,
-                c_name: "Customer#000000002".to_string(),
-                c_address: "Sample Address 2".to_string(),
-                c_nationkey: 2,
-                c_phone: "23-768-687-3665".to_string(),
-                c_acctbal: 200.0,
-                c_mktsegment: "AUTOMOBILE".to_string(),
-                c_comment: "Sample customer 2".to_string(),
-            },
-        ];
-
-        // Step 3: Generate insights from the data
-        let insights = client.generate_insights(records.clone()).await?;
-
-        Ok((records, insights))
+        agent_type: Option<String>,
+    ) -> Result<(String, Vec<RecordBatch>), Error> {
+        let result = self
+            .execute_natural_language_for(None, input, agent_type)
+            .await?;
+        Ok((result.sql, result.batches))
+    }
+
+    /// Same as `execute_natural_language`, recording an audit entry for `user`
+    /// whether or not the generated SQL ran
+    pub async fn execute_natural_language_for(
+        &self,
+        user: Option<&str>,
+        input: &str,
+        agent_type: Option<String>,
+    ) -> Result<NlqResult, Error> {
+        let agent = agent_type
+            .or_else(|| self.select_agent())
+            .ok_or_else(|| Error::new("No agent is configured"))?;
+        let started = Instant::now();
+        let mut report = PipelineResult {
+            question: input.to_string(),
+            agent: agent.clone(),
+            agent_type: agent.clone(),
+            ..PipelineResult::default()
+        };
+
+        let result = self.run_natural_language(input, agent, &mut report).await;
+        if let Ok(batches) = &result {
+            let row_count = batches.iter().map(|batch| batch.num_rows()).sum();
+            report.validation.valid = true;
+            report.validation.error = None;
+            report.execution.row_count = Some(row_count);
+            if row_count == 0 {
+                report
+                    .warnings
+                    .push("The query returned no rows".to_string());
+            }
+            match sample_rows(batches, SAMPLE_ROWS) {
+                Ok(sample) => report.sample = sample,
+                Err(e) => report
+                    .warnings
+                    .push(format!("Failed to sample the result: {}", e)),
+            }
+        }
+        report.durations.total_ms = started.elapsed().as_millis() as u64;
+        let error = result.as_ref().err().map(|e| e.message.clone());
+        let audit = NlqAuditRecord::from_report(user, &report, error);
+        self.audit_log.record(audit.clone());
+
+        let batches = result?;
+        Ok(NlqResult {
+            sql: report.sql.clone().unwrap_or_default(),
+            batches,
+            audit,
+            report,
+        })
+    }
+
+    async fn run_natural_language(
+        &self,
+        input: &str,
+        agent: String,
+        report: &mut PipelineResult,
+    ) -> Result<Vec<RecordBatch>, Error> {
+        let df_ctx = self
+            .df_ctx
+            .as_ref()
+            .ok_or_else(|| Error::new("No DataFusion context attached to the orchestrator"))?;
+        let client = self.select_client(Some(agent.clone()))?;
+        report.model = client.model().to_string();
+        report.prompt_hash = Some(prompt_hash(&client.translation_prompt(input)));
+
+        let started = Instant::now();
+        let translated = client.translate_to_sql(input).await;
+        self.record_agent_result(&agent, started.elapsed(), translated.is_ok());
+        report.durations.llm_ms += started.elapsed().as_millis() as u64;
+        let mut sql = translated?;
+        loop {
+            info!("Generated SQL: {}", sql);
+            // Comments could carry text past checks on the statement, so the
+            // cleaned statement is what gets audited and executed
+            let normalized = df_ctx.normalize_sql(&sql);
+            if let Ok(normalized) = &normalized {
+                sql = normalized.clone();
+            }
+            report.sql = Some(sql.clone());
+            report.execution.tables = df_ctx.referenced_tables(&sql).unwrap_or_default();
+
+            let started = Instant::now();
+            let executed = match normalized {
+                Ok(_) => {
+                    let (executed, entry) = df_ctx.execute_query_logged(&sql).await;
+                    report.execution.lineage = entry.lineage;
+                    executed
+                }
+                Err(e) => Err(e),
+            };
+            report.durations.execution_ms += started.elapsed().as_millis() as u64;
+            if let Err(e) = &executed {
+                report.validation.error = Some(e.to_string());
+            }
+            match executed {
+                Ok(batches) => return Ok(batches),
+                Err(e)
+                    if is_schema_error(&e)
+                        && report.validation.corrections < self.max_correction_attempts =>
+                {
+                    report.validation.corrections += 1;
+                    report.warnings.push(format!(
+                        "Generated SQL failed and was corrected by the model: {}",
+                        e
+                    ));
+                    warn!(
+                        "Generated SQL failed ({}), requesting correction {}/{}",
+                        e, report.validation.corrections, self.max_correction_attempts
+                    );
+                    let schema = df_ctx
+                        .schema_summary()
+                        .await
+                        .map_err(|e| Error::new(format!("Failed to describe schema: {}", e)))?;
+
+                    let started = Instant::now();
+                    let corrected = client
+                        .correct_sql(input, &sql, &e.to_string(), &schema)
+                        .await;
+                    self.record_agent_result(&agent, started.elapsed(), corrected.is_ok());
+                    report.durations.llm_ms += started.elapsed().as_millis() as u64;
+                    sql = corrected?;
+                }
+                Err(e) => {
+                    return Err(Error::new(format!(
+                        "Generated SQL failed after {} correction attempt(s): {}",
+                        report.validation.corrections, e
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Audit trail of natural language queries
+    pub fn audit_log(&self) -> &Arc<AuditLog> {
+        &self.audit_log
+    }
+
+    fn select_client(&self, agent_type: Option<String>) -> Result<Arc<AgentClient>, Error> {
+        let agent_name = agent_type.unwrap_or_else(|| self.default_agent.clone());
+
+        // Update stats
+        *self
+            .agent_stats
+            .lock()
+            .unwrap()
+            .entry(agent_name.clone())
+            .or_insert(0) += 1;
+
+        self.clients
+            .get(&agent_name)
+            .cloned()
+            .ok_or_else(|| Error::new(format!("Agent '{}' not found", agent_name)))
     }
 
     pub async fn get_available_agents(&self) -> Vec<String> {
@@ -103,7 +365,13 @@ impl AgentOrchestrator {
     }
 
     pub async fn get_agent_status(&self, agent_type: &str) -> Option<AgentStatus> {
-        let requests = self.agent_stats.get(agent_type).copied().unwrap_or(0);
+        let requests = self
+            .agent_stats
+            .lock()
+            .unwrap()
+            .get(agent_type)
+            .copied()
+            .unwrap_or(0);
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or(Duration::from_secs(0))
@@ -0,0 +1,123 @@
+//! Report of a natural language query pipeline
+//!
+//! A `PipelineResult` records every stage of answering a question: the agent
+//! and prompt used, the SQL generated and whether it validated, what executing
+//! it returned and how long each stage took. It is the one shape shared by the
+//! orchestrator, the `naturalLanguageQuery` extension and the audit log; its JSON
+//! is part of the API, so fields are only ever added.
+
+use crate::models::data::ColumnLineage;
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::json::ArrayWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Rows of the result kept in the report
+pub const SAMPLE_ROWS: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineResult {
+    pub question: String,
+    /// Agent that translated the question
+    pub agent: String,
+    /// Same as `agent`, named like the `agentType` argument choosing it. Set
+    /// whether the agent was requested or picked by health and priority.
+    #[serde(default)]
+    pub agent_type: String,
+    pub model: String,
+    /// SHA-256 of the translation prompt, identifying the prompt without its text
+    pub prompt_hash: Option<String>,
+    /// Last SQL generated, `None` when translation failed
+    pub sql: Option<String>,
+    pub validation: ValidationOutcome,
+    /// Flattened, like the durations, so the earlier `tables` and `rowCount`
+    /// keys of the extension stay where they were
+    #[serde(flatten)]
+    pub execution: ExecutionStats,
+    /// First rows of the result as JSON objects
+    pub sample: Vec<serde_json::Value>,
+    /// Insights generated from the sample, `None` when none were requested
+    pub insights: Option<String>,
+    #[serde(flatten)]
+    pub durations: StageDurations,
+    /// Things that went wrong without failing the pipeline
+    pub warnings: Vec<String>,
+}
+
+/// Whether the generated SQL ran against the actual schema
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationOutcome {
+    pub valid: bool,
+    /// Times the SQL was sent back to the model after a schema error
+    pub corrections: usize,
+    /// Last error of the SQL, `None` once it ran
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionStats {
+    /// Tables referenced by the generated SQL
+    pub tables: Vec<String>,
+    /// Rows returned, `None` when the SQL did not run
+    pub row_count: Option<usize>,
+    /// Source columns of each output column, empty when the SQL did not run
+    #[serde(default)]
+    pub lineage: Vec<ColumnLineage>,
+}
+
+/// Time spent per stage in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageDurations {
+    /// Waiting on the model for the SQL, including corrections
+    pub llm_ms: u64,
+    pub execution_ms: u64,
+    pub insights_ms: u64,
+    pub total_ms: u64,
+}
+
+impl PipelineResult {
+    /// Report fit for a caller with `role`. The sample bypasses the row limits and
+    /// quotas of the caller's role and the prompt hash identifies prompts across
+    /// users, so both are only kept for admins.
+    pub fn for_role(&self, role: Option<&str>) -> Self {
+        let mut report = self.clone();
+        if role != Some("admin") {
+            report.prompt_hash = None;
+            report.sample = Vec::new();
+        }
+        report
+    }
+}
+
+/// Hex SHA-256 of a prompt
+pub fn prompt_hash(prompt: &str) -> String {
+    hex::encode(Sha256::digest(prompt.as_bytes()))
+}
+
+/// First `limit` rows of a result as JSON objects
+pub fn sample_rows(
+    batches: &[RecordBatch],
+    limit: usize,
+) -> Result<Vec<serde_json::Value>, ArrowError> {
+    let mut writer = ArrayWriter::new(Vec::new());
+    let mut remaining = limit;
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        let rows = batch.slice(0, batch.num_rows().min(remaining));
+        remaining -= rows.num_rows();
+        writer.write(&rows)?;
+    }
+    writer.finish()?;
+    let json = writer.into_inner();
+    if json.is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(&json).map_err(|e| ArrowError::JsonError(e.to_string()))
+}
@@ -0,0 +1,175 @@
+//! Audit trail for AI-generated SQL
+//!
+//! Every natural language query is recorded, whether or not its SQL ran, both in
+//! the `audit` tracing target and in a bounded in-memory log. SQL sent as is
+//! through `executeSql` and queries killed by admins are only recorded in the
+//! tracing target.
+
+use crate::agents::pipeline::PipelineResult;
+use crate::datafusion::query_log::QueryLogEntry;
+use crate::models::data::ColumnLineage;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::info;
+
+/// One natural language query and what it ran
+#[derive(Debug, Clone, Serialize)]
+pub struct NlqAuditRecord {
+    /// `sub` claim of the caller, `None` for anonymous requests
+    pub user: Option<String>,
+    pub question: String,
+    /// Last SQL generated, `None` when translation failed
+    pub sql: Option<String>,
+    pub agent: String,
+    pub model: String,
+    /// SHA-256 of the translation prompt
+    pub prompt_hash: Option<String>,
+    /// Tables referenced by the generated SQL
+    pub tables: Vec<String>,
+    /// Source columns of each output column of the SQL that ran
+    pub lineage: Vec<ColumnLineage>,
+    pub row_count: Option<usize>,
+    /// Time spent waiting on the model, including corrections
+    pub llm_ms: u64,
+    /// Time spent executing generated SQL
+    pub execution_ms: u64,
+    pub corrections: usize,
+    pub success: bool,
+    pub error: Option<String>,
+    pub warnings: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl NlqAuditRecord {
+    /// Record of a pipeline run by `user`, failed with `error` unless `None`
+    pub fn from_report(user: Option<&str>, report: &PipelineResult, error: Option<String>) -> Self {
+        Self {
+            user: user.map(str::to_string),
+            question: report.question.clone(),
+            sql: report.sql.clone(),
+            agent: report.agent.clone(),
+            model: report.model.clone(),
+            prompt_hash: report.prompt_hash.clone(),
+            tables: report.execution.tables.clone(),
+            lineage: report.execution.lineage.clone(),
+            row_count: report.execution.row_count,
+            llm_ms: report.durations.llm_ms,
+            execution_ms: report.durations.execution_ms,
+            corrections: report.validation.corrections,
+            success: error.is_none(),
+            error,
+            warnings: report.warnings.clone(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// One SQL statement a client ran as is
+#[derive(Debug, Clone, Serialize)]
+pub struct SqlAuditRecord {
+    /// `sub` claim of the caller, `None` for anonymous requests
+    pub user: Option<String>,
+    /// Id of the statement in the query log
+    pub query_id: String,
+    pub sql: String,
+    /// Source columns of each output column
+    pub lineage: Vec<ColumnLineage>,
+    pub row_count: Option<usize>,
+    pub execution_ms: u64,
+    pub success: bool,
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl SqlAuditRecord {
+    /// Record of a statement run by `user`, failed with `error` unless `None`
+    pub fn from_entry(user: Option<&str>, entry: &QueryLogEntry, error: Option<String>) -> Self {
+        Self {
+            user: user.map(str::to_string),
+            query_id: entry.id.clone(),
+            sql: entry.sql.clone(),
+            lineage: entry.lineage.clone(),
+            row_count: entry.success.then_some(entry.rows),
+            execution_ms: entry.duration.as_millis() as u64,
+            success: error.is_none(),
+            error,
+            timestamp: entry.timestamp,
+        }
+    }
+}
+
+/// A running query an admin killed through `killQuery`
+#[derive(Debug, Clone, Serialize)]
+pub struct KillAuditRecord {
+    /// `sub` claim of the admin
+    pub admin: String,
+    pub query_id: String,
+    pub sql: String,
+    /// How long the query had been running
+    pub elapsed_ms: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Bounded log of audit records, oldest dropped first
+#[derive(Debug)]
+pub struct AuditLog {
+    records: Mutex<VecDeque<NlqAuditRecord>>,
+    capacity: usize,
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn record(&self, record: NlqAuditRecord) {
+        info!(
+            target: "audit",
+            "{}",
+            serde_json::to_string(&record).unwrap_or_default()
+        );
+
+        if self.capacity == 0 {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Record a statement run through `executeSql`, in the tracing target only
+    pub fn record_sql(&self, record: &SqlAuditRecord) {
+        info!(
+            target: "audit",
+            "{}",
+            serde_json::to_string(record).unwrap_or_default()
+        );
+    }
+
+    /// Record a query killed by an admin, in the tracing target only
+    pub fn record_kill(&self, record: &KillAuditRecord) {
+        info!(
+            target: "audit",
+            "{}",
+            serde_json::to_string(record).unwrap_or_default()
+        );
+    }
+
+    /// Recorded entries, oldest first
+    pub fn records(&self) -> Vec<NlqAuditRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
@@ -5,7 +5,9 @@
 
 use actix_web::Error;
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use async_graphql::{Context, ErrorExtensions, Guard};
 use futures_util::future::{LocalBoxFuture, Ready, ready};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 
 /// Authentication middleware
 #[derive(Debug, Clone)]
@@ -84,3 +86,75 @@ impl Claims {
         }
     }
 }
+
+/// Caller identified by the verified client certificate of its TLS connection,
+/// kept in the connection data
+#[derive(Debug, Clone)]
+pub struct ClientIdentity(pub Claims);
+
+/// Verifies JWT bearer tokens and issues tokens signed with the same secret
+#[derive(Clone)]
+pub struct AuthGuard {
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    validation: Validation,
+}
+
+impl AuthGuard {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            validation: Validation::default(),
+        }
+    }
+
+    /// Tolerate clock skew between the token issuer and this server when
+    /// checking `exp` and `nbf`
+    pub fn with_leeway(mut self, leeway_secs: u64) -> Self {
+        self.validation.leeway = leeway_secs;
+        self
+    }
+
+    /// Decode and validate a token, returning its claims
+    pub fn verify_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+        decode::<Claims>(token, &self.decoding_key, &self.validation).map(|data| data.claims)
+    }
+
+    pub fn issue_token(&self, claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+        encode(&Header::default(), claims, &self.encoding_key)
+    }
+}
+
+/// Token from an `Authorization: Bearer <token>` header value
+pub fn bearer_token(header: &str) -> Option<&str> {
+    header
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Field guard requiring the caller's claims to carry a role; `admin` passes every guard
+pub struct RoleGuard {
+    role: &'static str,
+}
+
+impl RoleGuard {
+    pub fn new(role: &'static str) -> Self {
+        Self { role }
+    }
+}
+
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        match ctx.data_opt::<Claims>() {
+            Some(claims) if claims.role == self.role || claims.role == "admin" => Ok(()),
+            Some(_) => Err(
+                async_graphql::Error::new(format!("{} role required", self.role))
+                    .extend_with(|_, e| e.set("code", "FORBIDDEN")),
+            ),
+            None => Err(async_graphql::Error::new("Authentication required")
+                .extend_with(|_, e| e.set("code", "UNAUTHENTICATED"))),
+        }
+    }
+}
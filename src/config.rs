@@ -2,7 +2,17 @@
 //!
 //! Simplified configuration management for the GraphQL DataFusion server.
 
+use crate::datafusion::context::TableDef;
+use crate::datafusion::revisions::DEFAULT_KEPT_REVISIONS;
+use crate::graphql::allow_list::AllowListRules;
+use crate::graphql::naming::NamingPolicy;
+use crate::metrics::{self, DEFAULT_LATENCY_BUCKETS, DEFAULT_LLM_LATENCY_BUCKETS};
+use crate::models::dictionary::DataDictionary;
+use crate::notifier::WebhookEndpoint;
+use crate::quota::{RoleQuota, default_role_quotas};
+use actix_web::http::header::{HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
 
 /// Configuration for the GraphQL DataFusion server
@@ -11,43 +21,380 @@ pub struct Config {
     /// HTTP server port
     pub http_port: u16,
 
+    /// Port for GraphQL over WebSocket; equal to `http_port` serves both on one port
+    pub ws_port: u16,
+
     /// Data file path (CSV or Parquet)
     pub data_path: String,
 
+    /// Serve generated sample data instead of the files under `data_path`
+    pub demo_mode: bool,
+
     /// Table name for DataFusion
     pub table_name: String,
 
-    /// Ollama API URL
+    /// Tables registered at startup from parquet or CSV files; empty registers the
+    /// TPCH tables from `{data_path}/{table}.parquet`
+    pub tables: Vec<TableDef>,
+
+    /// Ollama API URL; empty disables the AI features
     pub ollama_url: String,
 
     /// Ollama model name
     pub ollama_model: String,
 
+    /// Largest prompt sent to Ollama in bytes; insights over more data than fits
+    /// are generated from summaries of chunks of it
+    pub ollama_max_prompt_chars: usize,
+
+    /// Further agents, as agent name to Ollama model served at `ollama_url`, next
+    /// to the `default` agent running `ollama_model`
+    pub agents: BTreeMap<String, String>,
+
+    /// Agents preferred, in order, for requests not naming one; without it the
+    /// healthy agent with the lowest recent latency is used
+    pub agent_priority: Vec<String>,
+
+    /// Consecutive failures that take an agent out of rotation
+    pub agent_failure_threshold: u32,
+
+    /// Seconds a failing agent stays out of rotation
+    pub agent_open_secs: u64,
+
+    /// Agents whose recent p95 latency is above this many milliseconds are
+    /// skipped; 0 disables the check
+    pub agent_max_p95_ms: u64,
+
+    /// Enable the AI features (natural language queries, insights, agent status)
+    pub enable_ai: bool,
+
+    /// Start in read-only mode, refusing mutations and exports until an admin
+    /// turns it off with `setReadOnly`
+    pub read_only: bool,
+
+    /// Whether read-only mode also refuses queries calling the AI models
+    pub read_only_blocks_ai: bool,
+
+    /// Spelling of the TPCH type fields: `raw` column names, `camel` case, or
+    /// `both`, with the raw names as deprecated aliases
+    pub field_naming: NamingPolicy,
+
     /// Enable metrics collection
     pub enable_metrics: bool,
 
+    /// Prepended to the name of every metric, e.g. `acme_`
+    pub metric_prefix: String,
+
+    /// Upper bounds in seconds of the `query_duration_seconds` buckets
+    pub query_latency_buckets: Vec<f64>,
+
+    /// Upper bounds in seconds of the `llm_request_duration_seconds` buckets
+    pub llm_latency_buckets: Vec<f64>,
+
+    /// Upper bounds in seconds of the `http_request_duration_seconds` buckets
+    pub http_latency_buckets: Vec<f64>,
+
+    /// Attach the request id to latency observations as exemplars, served to
+    /// scrapers accepting OpenMetrics
+    pub metric_exemplars: bool,
+
     /// Log level
     pub log_level: String,
 
     /// Maximum query timeout in seconds
     pub query_timeout: u64,
 
+    /// Seconds a whole GraphQL request may execute, including LLM calls and
+    /// building the response; 0 disables the deadline
+    pub request_timeout: u64,
+
     /// Enable query caching
     pub enable_caching: bool,
+
+    /// Times invalid generated SQL is sent back to the model for correction
+    pub nlq_max_correction_attempts: usize,
+
+    /// Maximum nesting depth of a GraphQL document
+    pub max_query_depth: usize,
+
+    /// Maximum complexity score of a GraphQL document
+    pub max_query_complexity: usize,
+
+    /// Times a GraphQL document may request one field with the same arguments,
+    /// e.g. under different aliases; 0 disables the limit
+    pub max_field_repeats: usize,
+
+    /// Maximum aliases in a GraphQL document; 0 disables the limit
+    pub max_query_aliases: usize,
+
+    /// Maximum list fields in a GraphQL document; 0 disables the limit
+    pub max_list_fields: usize,
+
+    /// Most distinct values of the pivot column a pivot may turn into columns
+    pub max_pivot_columns: usize,
+
+    /// Interactive GraphQL requests executing at the same time
+    pub max_concurrent_queries: usize,
+
+    /// Heavy GraphQL requests executing at the same time
+    pub max_concurrent_heavy_queries: usize,
+
+    /// Top-level GraphQL fields scheduled in the heavy pool
+    pub heavy_query_fields: Vec<String>,
+
+    /// GraphQL requests allowed to wait for an execution slot
+    pub query_queue_capacity: usize,
+
+    /// Longest time in milliseconds a request waits for an execution slot
+    pub query_queue_max_wait_ms: u64,
+
+    /// GraphQL requests one authenticated user may have in flight at the same
+    /// time; 0 for no limit
+    pub max_concurrent_queries_per_user: usize,
+
+    /// Longest time in milliseconds a GraphQL query waits for an identical query
+    /// already executing instead of running itself; 0 disables coalescing
+    pub singleflight_max_wait_ms: u64,
+
+    /// Seconds between sweeps removing idle state such as expired entries of
+    /// in-memory registries; 0 disables the sweeps
+    pub reaper_interval_secs: u64,
+
+    /// Retries for queries failing with transient object store errors
+    pub query_retry_attempts: u32,
+
+    /// Backoff in milliseconds before the first query retry
+    pub query_retry_backoff_ms: u64,
+
+    /// Times a whole GraphQL query failing transiently, e.g. during a data
+    /// reload, is executed again, at most 3; 0 disables these retries. I/O
+    /// errors are only retried here when `query_retry_attempts` is 0.
+    pub graphql_retry_attempts: u32,
+
+    /// Backoff in milliseconds before the first GraphQL query retry
+    pub graphql_retry_backoff_ms: u64,
+
+    /// Extra headers added to every response
+    pub custom_headers: BTreeMap<String, String>,
+
+    /// Keep the nation and region tables in memory
+    pub cache_dimension_tables: bool,
+
+    /// Readiness also checks that the source files of every table are reachable
+    pub check_data_source: bool,
+
+    /// Views registered at startup, as view name to `SELECT` statement
+    pub views: BTreeMap<String, String>,
+
+    /// Columns uniquely ordering the rows of a table, by table name. Paged
+    /// queries are ordered by them after any requested sort; they replace the
+    /// primary key of the TPCH tables.
+    pub default_sort: BTreeMap<String, Vec<String>>,
+
+    /// Secret for verifying JWT bearer tokens; empty disables authentication
+    pub jwt_secret: String,
+
+    /// Clock skew in seconds tolerated when checking token expiry
+    pub jwt_leeway_secs: u64,
+
+    /// PEM certificate chain served over TLS; empty serves plain HTTP
+    pub tls_cert_path: String,
+
+    /// PEM private key of the TLS certificate
+    pub tls_key_path: String,
+
+    /// PEM CA certificates client certificates must be signed by; set, every
+    /// connection must present a valid client certificate (mTLS)
+    pub tls_client_ca_path: String,
+
+    /// Role of each client certificate common name; a verified certificate whose
+    /// CN is not listed identifies the caller with `client_cert_default_role`
+    pub client_cert_roles: BTreeMap<String, String>,
+
+    /// Role of verified client certificates missing from `client_cert_roles`
+    pub client_cert_default_role: String,
+
+    /// Row limits per query and daily export quotas by JWT role
+    pub role_quotas: BTreeMap<String, RoleQuota>,
+
+    /// File keeping daily export counters across restarts; empty keeps them in the
+    /// state directory, or in memory without one
+    pub quota_state_path: String,
+
+    /// Directory of the server's state files; exported and imported as one archive
+    /// with the `export-state` and `import-state` subcommands. Empty disables it.
+    pub state_dir: String,
+
+    /// GraphQL operations and top-level fields each role may run; roles that are
+    /// not listed are unrestricted
+    pub operation_allow_list: AllowListRules,
+
+    /// JSON file with the operation allow-list, re-read when it changes; takes
+    /// precedence over `operation_allow_list`. Empty disables the file.
+    pub operation_allow_list_path: String,
+
+    /// YAML data dictionary with descriptions, tags and units of table columns.
+    /// Empty disables it.
+    pub data_dictionary_path: String,
+
+    /// Webhooks notified of events such as failed table reloads
+    pub webhooks: Vec<WebhookEndpoint>,
+
+    /// Retries of a failed webhook delivery before it is dead-lettered
+    pub webhook_retry_attempts: u32,
+
+    /// Backoff before the first webhook retry in milliseconds, doubled per retry
+    pub webhook_retry_backoff_ms: u64,
+
+    /// JSON lines file receiving undeliverable webhook events; empty only logs them
+    pub webhook_dead_letter_path: String,
+
+    /// Responses slower than this many milliseconds get `X-Over-Budget: true`; 0 disables it
+    pub response_time_budget_ms: u64,
+
+    /// Largest serialized GraphQL response in bytes; longer lists are cut short and
+    /// the response is marked truncated. 0 disables the limit.
+    pub max_response_bytes: usize,
+
+    /// Refuse GraphQL POST bodies that are neither `application/json` nor
+    /// `application/graphql` with a 415; off parses them as JSON
+    pub enforce_content_type: bool,
+
+    /// Largest JSON a query result is converted into by `executeSql`, the columnar
+    /// resolvers and `jsonExtract`, in bytes; larger results fail with
+    /// `RESULT_TOO_LARGE`. CSV exports stream and are exempt. 0 disables the limit.
+    pub max_result_json_bytes: usize,
+
+    /// Rows each query of `compareQueries` may return, since both results are
+    /// compared in memory; larger results fail with `RESULT_TOO_LARGE`. 0
+    /// disables the limit.
+    pub max_compared_rows: usize,
+
+    /// Largest CSV or JSON payload `uploadTable` accepts, in bytes
+    pub max_upload_bytes: usize,
+
+    /// Seconds an uploaded table stays registered
+    pub upload_ttl_secs: u64,
+
+    /// Uploaded tables registered at a time at most; 0 for no limit
+    pub max_uploaded_tables: usize,
+
+    /// Payload bytes of all uploaded tables registered at a time at most; 0 for
+    /// no limit
+    pub max_uploaded_bytes: usize,
+
+    /// Seconds the analysis filters of a caller stay active after their last use
+    pub analysis_filter_ttl_secs: u64,
+
+    /// Memory a single query may use, in megabytes; 0 leaves queries unbounded
+    pub query_memory_limit_mb: u64,
+
+    /// Queries running longer than this many milliseconds are logged as slow
+    pub slow_query_threshold_ms: u64,
+
+    /// Number of slowest queries kept for the `slowQueries` resolver
+    pub slow_query_log_size: usize,
+
+    /// Revisions of the tables kept after reloads for `executeSql(asOfRevision)`;
+    /// 0 keeps only the current one
+    pub kept_table_revisions: usize,
+
+    /// Recent GraphQL operations kept per user for `myQueryHistory`; 0 disables
+    /// the history
+    pub query_history_size: usize,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             http_port: 8080,
+            ws_port: 8081,
             data_path: "/opt/data/tpch".to_string(),
+            demo_mode: false,
             table_name: "customer".to_string(),
+            tables: Vec::new(),
             ollama_url: "http://localhost:11434".to_string(),
             ollama_model: "llama2".to_string(),
+            ollama_max_prompt_chars: 8000,
+            agents: BTreeMap::new(),
+            agent_priority: Vec::new(),
+            agent_failure_threshold: 3,
+            agent_open_secs: 30,
+            agent_max_p95_ms: 0,
+            enable_ai: true,
+            read_only: false,
+            read_only_blocks_ai: true,
+            field_naming: NamingPolicy::default(),
             enable_metrics: true,
+            metric_prefix: String::new(),
+            query_latency_buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            llm_latency_buckets: DEFAULT_LLM_LATENCY_BUCKETS.to_vec(),
+            http_latency_buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            metric_exemplars: false,
             log_level: "info".to_string(),
             query_timeout: 30,
+            request_timeout: 60,
             enable_caching: true,
+            nlq_max_correction_attempts: 2,
+            max_query_depth: 16,
+            max_query_complexity: 5000,
+            max_field_repeats: 5,
+            max_query_aliases: 30,
+            max_list_fields: 50,
+            max_pivot_columns: 100,
+            max_concurrent_queries: 8,
+            max_concurrent_heavy_queries: 2,
+            heavy_query_fields: vec![
+                "salesAnalytics".to_string(),
+                "naturalLanguageQuery".to_string(),
+                "insights".to_string(),
+            ],
+            query_queue_capacity: 64,
+            query_queue_max_wait_ms: 5000,
+            max_concurrent_queries_per_user: 5,
+            singleflight_max_wait_ms: 30000,
+            reaper_interval_secs: 60,
+            query_retry_attempts: 3,
+            query_retry_backoff_ms: 100,
+            graphql_retry_attempts: 0,
+            graphql_retry_backoff_ms: 200,
+            custom_headers: BTreeMap::new(),
+            cache_dimension_tables: true,
+            check_data_source: false,
+            views: BTreeMap::new(),
+            default_sort: BTreeMap::new(),
+            jwt_secret: String::new(),
+            jwt_leeway_secs: 60,
+            tls_cert_path: String::new(),
+            tls_key_path: String::new(),
+            tls_client_ca_path: String::new(),
+            client_cert_roles: BTreeMap::new(),
+            client_cert_default_role: "viewer".to_string(),
+            role_quotas: default_role_quotas(),
+            quota_state_path: String::new(),
+            operation_allow_list: AllowListRules::default(),
+            operation_allow_list_path: String::new(),
+            state_dir: String::new(),
+            data_dictionary_path: String::new(),
+            webhooks: Vec::new(),
+            webhook_retry_attempts: 3,
+            webhook_retry_backoff_ms: 500,
+            webhook_dead_letter_path: String::new(),
+            response_time_budget_ms: 0,
+            max_response_bytes: 64 * 1024 * 1024,
+            enforce_content_type: true,
+            max_result_json_bytes: 64 * 1024 * 1024,
+            max_compared_rows: 1_000_000,
+            max_upload_bytes: 10 * 1024 * 1024,
+            upload_ttl_secs: 3600,
+            max_uploaded_tables: 100,
+            max_uploaded_bytes: 200 * 1024 * 1024,
+            analysis_filter_ttl_secs: 3600,
+            query_memory_limit_mb: 0,
+            slow_query_threshold_ms: 1000,
+            slow_query_log_size: 20,
+            kept_table_revisions: DEFAULT_KEPT_REVISIONS,
+            query_history_size: 50,
         }
     }
 }
@@ -63,10 +410,22 @@ impl Config {
             }
         }
 
+        if let Ok(port) = env::var("WS_PORT") {
+            if let Ok(port_num) = port.parse() {
+                config.ws_port = port_num;
+            }
+        }
+
         if let Ok(path) = env::var("DATA_PATH") {
             config.data_path = path;
         }
 
+        if let Ok(demo_mode) = env::var("DEMO_MODE") {
+            if let Ok(demo_mode_flag) = demo_mode.parse() {
+                config.demo_mode = demo_mode_flag;
+            }
+        }
+
         if let Ok(table) = env::var("TABLE_NAME") {
             config.table_name = table;
         }
@@ -79,6 +438,96 @@ impl Config {
             config.ollama_model = model;
         }
 
+        if let Ok(max_chars) = env::var("OLLAMA_MAX_PROMPT_CHARS") {
+            if let Ok(max_chars_num) = max_chars.parse() {
+                config.ollama_max_prompt_chars = max_chars_num;
+            }
+        }
+
+        // JSON object of agent name to Ollama model
+        if let Ok(agents) = env::var("AGENTS") {
+            if let Ok(agents_map) = serde_json::from_str(&agents) {
+                config.agents = agents_map;
+            }
+        }
+
+        if let Ok(priority) = env::var("AGENT_PRIORITY") {
+            config.agent_priority = priority
+                .split(',')
+                .map(|agent| agent.trim().to_string())
+                .filter(|agent| !agent.is_empty())
+                .collect();
+        }
+
+        if let Ok(threshold) = env::var("AGENT_FAILURE_THRESHOLD") {
+            if let Ok(threshold_num) = threshold.parse() {
+                config.agent_failure_threshold = threshold_num;
+            }
+        }
+
+        if let Ok(secs) = env::var("AGENT_OPEN_SECS") {
+            if let Ok(secs_num) = secs.parse() {
+                config.agent_open_secs = secs_num;
+            }
+        }
+
+        if let Ok(latency) = env::var("AGENT_MAX_P95_MS") {
+            if let Ok(latency_num) = latency.parse() {
+                config.agent_max_p95_ms = latency_num;
+            }
+        }
+
+        if let Ok(enable) = env::var("ENABLE_AI") {
+            if let Ok(enable_flag) = enable.parse() {
+                config.enable_ai = enable_flag;
+            }
+        }
+
+        if let Ok(prefix) = env::var("METRIC_PREFIX") {
+            config.metric_prefix = prefix;
+        }
+
+        // Comma separated bounds; a bound that is not a number fails validation
+        let buckets = |value: String| -> Vec<f64> {
+            value
+                .split(',')
+                .map(|bound| bound.trim().parse().unwrap_or(f64::NAN))
+                .collect()
+        };
+        if let Ok(bounds) = env::var("QUERY_LATENCY_BUCKETS") {
+            config.query_latency_buckets = buckets(bounds);
+        }
+        if let Ok(bounds) = env::var("LLM_LATENCY_BUCKETS") {
+            config.llm_latency_buckets = buckets(bounds);
+        }
+        if let Ok(bounds) = env::var("HTTP_LATENCY_BUCKETS") {
+            config.http_latency_buckets = buckets(bounds);
+        }
+
+        if let Ok(exemplars) = env::var("METRIC_EXEMPLARS") {
+            if let Ok(exemplars_flag) = exemplars.parse() {
+                config.metric_exemplars = exemplars_flag;
+            }
+        }
+
+        if let Ok(read_only) = env::var("READ_ONLY") {
+            if let Ok(read_only_flag) = read_only.parse() {
+                config.read_only = read_only_flag;
+            }
+        }
+
+        if let Ok(blocks_ai) = env::var("READ_ONLY_BLOCKS_AI") {
+            if let Ok(blocks_ai_flag) = blocks_ai.parse() {
+                config.read_only_blocks_ai = blocks_ai_flag;
+            }
+        }
+
+        if let Ok(naming) = env::var("FIELD_NAMING") {
+            if let Ok(naming_policy) = naming.parse() {
+                config.field_naming = naming_policy;
+            }
+        }
+
         if let Ok(level) = env::var("LOG_LEVEL") {
             config.log_level = level;
         }
@@ -89,15 +538,359 @@ impl Config {
             }
         }
 
+        if let Ok(timeout) = env::var("REQUEST_TIMEOUT") {
+            if let Ok(timeout_num) = timeout.parse() {
+                config.request_timeout = timeout_num;
+            }
+        }
+
+        if let Ok(attempts) = env::var("NLQ_MAX_CORRECTION_ATTEMPTS") {
+            if let Ok(attempts_num) = attempts.parse() {
+                config.nlq_max_correction_attempts = attempts_num;
+            }
+        }
+
+        if let Ok(depth) = env::var("MAX_QUERY_DEPTH") {
+            if let Ok(depth_num) = depth.parse() {
+                config.max_query_depth = depth_num;
+            }
+        }
+
+        if let Ok(complexity) = env::var("MAX_QUERY_COMPLEXITY") {
+            if let Ok(complexity_num) = complexity.parse() {
+                config.max_query_complexity = complexity_num;
+            }
+        }
+
+        if let Ok(repeats) = env::var("MAX_FIELD_REPEATS") {
+            if let Ok(repeats_num) = repeats.parse() {
+                config.max_field_repeats = repeats_num;
+            }
+        }
+
+        if let Ok(aliases) = env::var("MAX_QUERY_ALIASES") {
+            if let Ok(aliases_num) = aliases.parse() {
+                config.max_query_aliases = aliases_num;
+            }
+        }
+
+        if let Ok(list_fields) = env::var("MAX_LIST_FIELDS") {
+            if let Ok(list_fields_num) = list_fields.parse() {
+                config.max_list_fields = list_fields_num;
+            }
+        }
+
+        if let Ok(columns) = env::var("MAX_PIVOT_COLUMNS") {
+            if let Ok(columns_num) = columns.parse() {
+                config.max_pivot_columns = columns_num;
+            }
+        }
+
+        if let Ok(max) = env::var("MAX_CONCURRENT_QUERIES") {
+            if let Ok(max_num) = max.parse() {
+                config.max_concurrent_queries = max_num;
+            }
+        }
+
+        if let Ok(max) = env::var("MAX_CONCURRENT_HEAVY_QUERIES") {
+            if let Ok(max_num) = max.parse() {
+                config.max_concurrent_heavy_queries = max_num;
+            }
+        }
+
+        if let Ok(fields) = env::var("HEAVY_QUERY_FIELDS") {
+            config.heavy_query_fields = fields
+                .split(',')
+                .map(|field| field.trim().to_string())
+                .filter(|field| !field.is_empty())
+                .collect();
+        }
+
+        if let Ok(capacity) = env::var("QUERY_QUEUE_CAPACITY") {
+            if let Ok(capacity_num) = capacity.parse() {
+                config.query_queue_capacity = capacity_num;
+            }
+        }
+
+        if let Ok(wait) = env::var("QUERY_QUEUE_MAX_WAIT_MS") {
+            if let Ok(wait_num) = wait.parse() {
+                config.query_queue_max_wait_ms = wait_num;
+            }
+        }
+
+        if let Ok(max) = env::var("MAX_CONCURRENT_QUERIES_PER_USER") {
+            if let Ok(max_num) = max.parse() {
+                config.max_concurrent_queries_per_user = max_num;
+            }
+        }
+
+        if let Ok(interval) = env::var("REAPER_INTERVAL_SECS") {
+            if let Ok(interval_num) = interval.parse() {
+                config.reaper_interval_secs = interval_num;
+            }
+        }
+
+        if let Ok(wait) = env::var("SINGLEFLIGHT_MAX_WAIT_MS") {
+            if let Ok(wait_num) = wait.parse() {
+                config.singleflight_max_wait_ms = wait_num;
+            }
+        }
+
+        if let Ok(attempts) = env::var("QUERY_RETRY_ATTEMPTS") {
+            if let Ok(attempts_num) = attempts.parse() {
+                config.query_retry_attempts = attempts_num;
+            }
+        }
+
+        if let Ok(backoff) = env::var("QUERY_RETRY_BACKOFF_MS") {
+            if let Ok(backoff_num) = backoff.parse() {
+                config.query_retry_backoff_ms = backoff_num;
+            }
+        }
+
+        if let Ok(attempts) = env::var("GRAPHQL_RETRY_ATTEMPTS") {
+            if let Ok(attempts_num) = attempts.parse() {
+                config.graphql_retry_attempts = attempts_num;
+            }
+        }
+
+        if let Ok(backoff) = env::var("GRAPHQL_RETRY_BACKOFF_MS") {
+            if let Ok(backoff_num) = backoff.parse() {
+                config.graphql_retry_backoff_ms = backoff_num;
+            }
+        }
+
+        if let Ok(cache) = env::var("CACHE_DIMENSION_TABLES") {
+            if let Ok(cache_flag) = cache.parse() {
+                config.cache_dimension_tables = cache_flag;
+            }
+        }
+
+        if let Ok(check) = env::var("CHECK_DATA_SOURCE") {
+            if let Ok(check_flag) = check.parse() {
+                config.check_data_source = check_flag;
+            }
+        }
+
+        // JSON array of `{ "name", "path", "format" }` objects
+        if let Ok(tables) = env::var("TABLES") {
+            if let Ok(table_defs) = serde_json::from_str(&tables) {
+                config.tables = table_defs;
+            }
+        }
+
+        // JSON object of view name to SELECT statement
+        if let Ok(views) = env::var("VIEWS") {
+            if let Ok(views_map) = serde_json::from_str(&views) {
+                config.views = views_map;
+            }
+        }
+
+        // JSON object of table name to its sort columns
+        if let Ok(sort) = env::var("DEFAULT_SORT") {
+            if let Ok(sort_map) = serde_json::from_str(&sort) {
+                config.default_sort = sort_map;
+            }
+        }
+
+        if let Ok(secret) = env::var("JWT_SECRET") {
+            config.jwt_secret = secret;
+        }
+
+        if let Ok(leeway) = env::var("JWT_LEEWAY_SECS") {
+            if let Ok(leeway_num) = leeway.parse() {
+                config.jwt_leeway_secs = leeway_num;
+            }
+        }
+
+        if let Ok(path) = env::var("TLS_CERT_PATH") {
+            config.tls_cert_path = path;
+        }
+
+        if let Ok(path) = env::var("TLS_KEY_PATH") {
+            config.tls_key_path = path;
+        }
+
+        if let Ok(path) = env::var("TLS_CLIENT_CA_PATH") {
+            config.tls_client_ca_path = path;
+        }
+
+        if let Ok(roles) = env::var("CLIENT_CERT_ROLES") {
+            if let Ok(roles_map) = serde_json::from_str(&roles) {
+                config.client_cert_roles = roles_map;
+            }
+        }
+
+        if let Ok(role) = env::var("CLIENT_CERT_DEFAULT_ROLE") {
+            config.client_cert_default_role = role;
+        }
+
+        if let Ok(threshold) = env::var("SLOW_QUERY_THRESHOLD_MS") {
+            if let Ok(threshold_num) = threshold.parse() {
+                config.slow_query_threshold_ms = threshold_num;
+            }
+        }
+
+        if let Ok(size) = env::var("SLOW_QUERY_LOG_SIZE") {
+            if let Ok(size_num) = size.parse() {
+                config.slow_query_log_size = size_num;
+            }
+        }
+
+        if let Ok(kept) = env::var("KEPT_TABLE_REVISIONS") {
+            if let Ok(kept_num) = kept.parse() {
+                config.kept_table_revisions = kept_num;
+            }
+        }
+
+        if let Ok(size) = env::var("QUERY_HISTORY_SIZE") {
+            if let Ok(size_num) = size.parse() {
+                config.query_history_size = size_num;
+            }
+        }
+
+        if let Ok(budget) = env::var("RESPONSE_TIME_BUDGET_MS") {
+            if let Ok(budget_num) = budget.parse() {
+                config.response_time_budget_ms = budget_num;
+            }
+        }
+
+        if let Ok(max_bytes) = env::var("MAX_RESPONSE_BYTES") {
+            if let Ok(max_bytes_num) = max_bytes.parse() {
+                config.max_response_bytes = max_bytes_num;
+            }
+        }
+
+        if let Ok(enforce) = env::var("ENFORCE_CONTENT_TYPE") {
+            if let Ok(enforce_flag) = enforce.parse() {
+                config.enforce_content_type = enforce_flag;
+            }
+        }
+
+        if let Ok(max_bytes) = env::var("MAX_RESULT_JSON_BYTES") {
+            if let Ok(max_bytes_num) = max_bytes.parse() {
+                config.max_result_json_bytes = max_bytes_num;
+            }
+        }
+
+        if let Ok(max_rows) = env::var("MAX_COMPARED_ROWS") {
+            if let Ok(max_rows_num) = max_rows.parse() {
+                config.max_compared_rows = max_rows_num;
+            }
+        }
+
+        if let Ok(max_bytes) = env::var("MAX_UPLOAD_BYTES") {
+            if let Ok(max_bytes_num) = max_bytes.parse() {
+                config.max_upload_bytes = max_bytes_num;
+            }
+        }
+
+        if let Ok(ttl) = env::var("UPLOAD_TTL_SECS") {
+            if let Ok(ttl_num) = ttl.parse() {
+                config.upload_ttl_secs = ttl_num;
+            }
+        }
+
+        if let Ok(max_tables) = env::var("MAX_UPLOADED_TABLES") {
+            if let Ok(max_tables_num) = max_tables.parse() {
+                config.max_uploaded_tables = max_tables_num;
+            }
+        }
+
+        if let Ok(max_bytes) = env::var("MAX_UPLOADED_BYTES") {
+            if let Ok(max_bytes_num) = max_bytes.parse() {
+                config.max_uploaded_bytes = max_bytes_num;
+            }
+        }
+
+        if let Ok(ttl) = env::var("ANALYSIS_FILTER_TTL_SECS") {
+            if let Ok(ttl_num) = ttl.parse() {
+                config.analysis_filter_ttl_secs = ttl_num;
+            }
+        }
+
+        if let Ok(limit) = env::var("QUERY_MEMORY_LIMIT_MB") {
+            if let Ok(limit_num) = limit.parse() {
+                config.query_memory_limit_mb = limit_num;
+            }
+        }
+
+        if let Ok(quotas) = env::var("ROLE_QUOTAS") {
+            if let Ok(quotas_map) = serde_json::from_str(&quotas) {
+                config.role_quotas = quotas_map;
+            }
+        }
+
+        if let Ok(path) = env::var("QUOTA_STATE_PATH") {
+            config.quota_state_path = path;
+        }
+
+        if let Ok(allow_list) = env::var("OPERATION_ALLOW_LIST") {
+            if let Ok(rules) = serde_json::from_str(&allow_list) {
+                config.operation_allow_list = rules;
+            }
+        }
+
+        if let Ok(path) = env::var("OPERATION_ALLOW_LIST_PATH") {
+            config.operation_allow_list_path = path;
+        }
+
+        if let Ok(dir) = env::var("STATE_DIR") {
+            config.state_dir = dir;
+        }
+
+        if let Ok(path) = env::var("DATA_DICTIONARY_PATH") {
+            config.data_dictionary_path = path;
+        }
+
+        // JSON array of endpoints, e.g. [{"url": "...", "secret": "...", "events": ["reload_failed"]}]
+        if let Ok(webhooks) = env::var("WEBHOOKS") {
+            if let Ok(endpoints) = serde_json::from_str(&webhooks) {
+                config.webhooks = endpoints;
+            }
+        }
+
+        if let Ok(attempts) = env::var("WEBHOOK_RETRY_ATTEMPTS") {
+            if let Ok(attempts_num) = attempts.parse() {
+                config.webhook_retry_attempts = attempts_num;
+            }
+        }
+
+        if let Ok(backoff) = env::var("WEBHOOK_RETRY_BACKOFF_MS") {
+            if let Ok(backoff_num) = backoff.parse() {
+                config.webhook_retry_backoff_ms = backoff_num;
+            }
+        }
+
+        if let Ok(path) = env::var("WEBHOOK_DEAD_LETTER_PATH") {
+            config.webhook_dead_letter_path = path;
+        }
+
+        // JSON object of header name to value, e.g. {"X-Frame-Options": "DENY"}
+        if let Ok(headers) = env::var("CUSTOM_HEADERS") {
+            if let Ok(headers_map) = serde_json::from_str(&headers) {
+                config.custom_headers = headers_map;
+            }
+        }
+
         config
     }
 
+    /// Whether AI features are enabled and an Ollama URL is configured
+    pub fn ai_enabled(&self) -> bool {
+        self.enable_ai && !self.ollama_url.is_empty()
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> Result<(), String> {
         if self.http_port == 0 || self.http_port > 65535 {
             return Err("Invalid HTTP port number".to_string());
         }
 
+        if self.ws_port == 0 {
+            return Err("Invalid WebSocket port number".to_string());
+        }
+
         if self.data_path.is_empty() {
             return Err("Data path cannot be empty".to_string());
         }
@@ -106,18 +899,83 @@ impl Config {
             return Err("Table name cannot be empty".to_string());
         }
 
-        if self.ollama_url.is_empty() {
-            return Err("Ollama URL cannot be empty".to_string());
+        if self.ai_enabled() && self.ollama_model.is_empty() {
+            return Err("Ollama model cannot be empty".to_string());
         }
 
-        if self.ollama_model.is_empty() {
-            return Err("Ollama model cannot be empty".to_string());
+        if let Some(name) = self
+            .agents
+            .iter()
+            .find(|(name, model)| name.is_empty() || model.is_empty())
+            .map(|(name, _)| name)
+        {
+            return Err(format!("Agent '{}' needs a name and a model", name));
+        }
+
+        if let Some(name) = self
+            .agent_priority
+            .iter()
+            .find(|name| **name != "default" && !self.agents.contains_key(*name))
+        {
+            return Err(format!("Prioritized agent '{}' is not configured", name));
+        }
+
+        if self.agent_failure_threshold == 0 {
+            return Err("Agent failure threshold must be greater than 0".to_string());
         }
 
         if self.query_timeout == 0 {
             return Err("Query timeout must be greater than 0".to_string());
         }
 
+        if self.max_query_depth == 0 || self.max_query_complexity == 0 {
+            return Err("Query depth and complexity limits must be greater than 0".to_string());
+        }
+
+        if self.max_pivot_columns == 0 {
+            return Err("Maximum pivot columns must be greater than 0".to_string());
+        }
+
+        if self.max_concurrent_queries == 0 || self.max_concurrent_heavy_queries == 0 {
+            return Err("Maximum concurrent queries must be greater than 0".to_string());
+        }
+
+        if !self.data_dictionary_path.is_empty() {
+            DataDictionary::load(&self.data_dictionary_path)?;
+        }
+
+        metrics::validate_prefix(&self.metric_prefix)?;
+        metrics::validate_buckets("Query latency buckets", &self.query_latency_buckets)?;
+        metrics::validate_buckets("LLM latency buckets", &self.llm_latency_buckets)?;
+        metrics::validate_buckets("HTTP latency buckets", &self.http_latency_buckets)?;
+
+        for webhook in &self.webhooks {
+            if url::Url::parse(&webhook.url).is_err() {
+                return Err(format!("Invalid webhook URL: {}", webhook.url));
+            }
+        }
+
+        for (name, value) in &self.custom_headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(format!("Invalid custom header name: {}", name));
+            }
+            if HeaderValue::from_str(value).is_err() {
+                return Err(format!("Invalid value for custom header {}", name));
+            }
+        }
+
+        if self.tls_cert_path.is_empty() != self.tls_key_path.is_empty() {
+            return Err("TLS needs both a certificate and a private key".to_string());
+        }
+
+        if !self.tls_client_ca_path.is_empty() && self.tls_cert_path.is_empty() {
+            return Err("Client certificate verification needs TLS to be enabled".to_string());
+        }
+
+        if !self.tls_cert_path.is_empty() && !cfg!(feature = "tls") {
+            return Err("TLS needs a build with the tls feature".to_string());
+        }
+
         Ok(())
     }
 }
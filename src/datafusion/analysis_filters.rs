@@ -0,0 +1,198 @@
+//! Session-scoped analysis filters
+//!
+//! For what-if analysis a caller can exclude rows from the analytics and typed
+//! resolvers without touching the data, e.g. recompute everything as if one
+//! market segment did not exist. The filters are checked against the registered
+//! tables when they are set and kept per caller until cleared or idle for their
+//! TTL. A query run with filters gets a filter over every scan of a table holding
+//! a filtered column, so the predicates are ANDed in wherever the table is read,
+//! subqueries and joins included.
+
+use crate::datafusion::context::DataFusionContext;
+use crate::models::data::{Filter, FilterOperator};
+use crate::reaper::{Expirable, TtlMap};
+use datafusion::arrow::array::StringArray;
+use datafusion::arrow::compute::{CastOptions, cast_with_options};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::Column;
+use datafusion::common::tree_node::Transformed;
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::{Expr, LogicalPlan, LogicalPlanBuilder, cast, lit};
+use std::time::{Duration, Instant};
+
+/// Filters of each caller, by session
+pub struct AnalysisFilters {
+    filters: TtlMap<Vec<Filter>>,
+}
+
+impl AnalysisFilters {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            filters: TtlMap::new("analysis_filters", ttl),
+        }
+    }
+
+    /// Replace the filters of a session; no filters clear them
+    pub fn set(&self, session: &str, filters: Vec<Filter>) {
+        if filters.is_empty() {
+            self.filters.remove(session);
+        } else {
+            self.filters.insert(session, filters);
+        }
+    }
+
+    /// Active filters of a session, empty when none are set or they expired
+    pub fn get(&self, session: &str) -> Vec<Filter> {
+        self.filters.get(session).unwrap_or_default()
+    }
+}
+
+impl Expirable for AnalysisFilters {
+    fn registry_name(&self) -> &str {
+        self.filters.registry_name()
+    }
+
+    fn reap_expired(&self, now: Instant) -> Vec<String> {
+        self.filters.reap_expired(now)
+    }
+
+    fn live_entries(&self) -> usize {
+        self.filters.live_entries()
+    }
+}
+
+/// Check that every filter names a column of a registered table, with an
+/// operator and a value that fit the column's type
+pub async fn validate_filters(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+) -> Result<(), DataFusionError> {
+    let mut columns = Vec::new();
+    for table in df_ctx.get_table_names() {
+        columns.extend(table_columns(df_ctx, &table).await?);
+    }
+    check_filters(&columns, filters)
+}
+
+/// `validate_filters` for filters on the columns of one table
+pub async fn validate_table_filters(
+    df_ctx: &DataFusionContext,
+    table: &str,
+    filters: &[Filter],
+) -> Result<(), DataFusionError> {
+    let columns = table_columns(df_ctx, table).await?;
+    check_filters(&columns, filters)
+}
+
+async fn table_columns(
+    df_ctx: &DataFusionContext,
+    table: &str,
+) -> Result<Vec<(String, DataType)>, DataFusionError> {
+    let schema = df_ctx.table_schema(table).await?;
+    Ok(schema
+        .fields()
+        .iter()
+        .map(|field| (field.name().clone(), field.data_type().clone()))
+        .collect())
+}
+
+fn check_filters(
+    columns: &[(String, DataType)],
+    filters: &[Filter],
+) -> Result<(), DataFusionError> {
+    for filter in filters {
+        let (_, data_type) = columns
+            .iter()
+            .find(|(name, _)| *name == filter.column)
+            .ok_or_else(|| {
+                DataFusionError::Plan(format!("Unknown filter column {}", filter.column))
+            })?;
+        if !filter.operator.supports(data_type) {
+            return Err(DataFusionError::Plan(format!(
+                "Operator {} cannot filter column {} of type {}",
+                filter.operator, filter.column, data_type
+            )));
+        }
+        if matches!(
+            filter.operator,
+            FilterOperator::Like | FilterOperator::ILike
+        ) {
+            continue;
+        }
+        // Values must convert, or every query of the caller would fail
+        let values = StringArray::from(filter_values(filter));
+        let options = CastOptions {
+            safe: false,
+            ..CastOptions::default()
+        };
+        cast_with_options(&values, data_type, &options).map_err(|e| {
+            DataFusionError::Plan(format!(
+                "Invalid value {} for column {}: {}",
+                filter.value, filter.column, e
+            ))
+        })?;
+    }
+    Ok(())
+}
+
+/// Values of a filter; `IN` takes a comma separated list
+fn filter_values(filter: &Filter) -> Vec<&str> {
+    match filter.operator {
+        FilterOperator::In => filter.value.split(',').map(str::trim).collect(),
+        _ => vec![filter.value.as_str()],
+    }
+}
+
+/// Predicate of a filter on a column of the given type
+fn predicate(column: Expr, data_type: &DataType, filter: &Filter) -> Expr {
+    let value = || cast(lit(filter.value.clone()), data_type.clone());
+    match filter.operator {
+        FilterOperator::Eq => column.eq(value()),
+        FilterOperator::Ne => column.not_eq(value()),
+        FilterOperator::Gt => column.gt(value()),
+        FilterOperator::Gte => column.gt_eq(value()),
+        FilterOperator::Lt => column.lt(value()),
+        FilterOperator::Lte => column.lt_eq(value()),
+        FilterOperator::Like => column.like(lit(filter.value.clone())),
+        FilterOperator::ILike => column.ilike(lit(filter.value.clone())),
+        FilterOperator::In => {
+            let values = filter_values(filter)
+                .into_iter()
+                .map(|value| cast(lit(value), data_type.clone()))
+                .collect();
+            column.in_list(values, false)
+        }
+    }
+}
+
+/// A plan reading only the rows passing the filters: each scan of a table with
+/// a filtered column is wrapped in a filter ANDing their predicates
+pub fn apply_filters(
+    plan: LogicalPlan,
+    filters: &[Filter],
+) -> Result<LogicalPlan, DataFusionError> {
+    if filters.is_empty() {
+        return Ok(plan);
+    }
+    plan.transform_up_with_subqueries(|plan| {
+        let LogicalPlan::TableScan(scan) = &plan else {
+            return Ok(Transformed::no(plan));
+        };
+        let schema = scan.source.schema();
+        let predicates: Vec<Expr> = filters
+            .iter()
+            .filter_map(|filter| {
+                let field = schema.field_with_name(&filter.column).ok()?;
+                let column =
+                    Expr::Column(Column::new(Some(scan.table_name.clone()), &filter.column));
+                Some(predicate(column, field.data_type(), filter))
+            })
+            .collect();
+        let Some(predicate) = predicates.into_iter().reduce(Expr::and) else {
+            return Ok(Transformed::no(plan));
+        };
+        let filtered = LogicalPlanBuilder::from(plan).filter(predicate)?.build()?;
+        Ok(Transformed::yes(filtered))
+    })
+    .map(|transformed| transformed.data)
+}
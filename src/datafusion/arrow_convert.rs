@@ -0,0 +1,61 @@
+//! Arrow values as API values
+//!
+//! The same TPCH column comes as `Date32` from one writer, `Date64` or a timestamp
+//! from another. `date_value` reads any of them as an ISO-8601 date, so resolvers
+//! need not care which one the data has.
+
+use chrono::NaiveDate;
+use datafusion::arrow::array::timezone::Tz;
+use datafusion::arrow::array::{Array, AsArray};
+use datafusion::arrow::datatypes::{
+    ArrowTimestampType, DataType, Date32Type, Date64Type, TimeUnit, TimestampMicrosecondType,
+    TimestampMillisecondType, TimestampNanosecondType, TimestampSecondType,
+};
+use datafusion::arrow::error::ArrowError;
+
+/// Date of a row of a `Date32`, `Date64` or timestamp column as `YYYY-MM-DD`,
+/// `None` when the value is null. Timestamps lose their time of day, taken in
+/// their timezone when they have one.
+pub fn date_value(array: &dyn Array, row: usize) -> Result<Option<String>, ArrowError> {
+    if array.is_null(row) {
+        return Ok(None);
+    }
+    let date = match array.data_type() {
+        DataType::Date32 => array.as_primitive::<Date32Type>().value_as_date(row),
+        DataType::Date64 => array.as_primitive::<Date64Type>().value_as_date(row),
+        DataType::Timestamp(unit, tz) => {
+            let tz = tz.as_deref();
+            match unit {
+                TimeUnit::Second => timestamp_date::<TimestampSecondType>(array, row, tz)?,
+                TimeUnit::Millisecond => {
+                    timestamp_date::<TimestampMillisecondType>(array, row, tz)?
+                }
+                TimeUnit::Microsecond => {
+                    timestamp_date::<TimestampMicrosecondType>(array, row, tz)?
+                }
+                TimeUnit::Nanosecond => timestamp_date::<TimestampNanosecondType>(array, row, tz)?,
+            }
+        }
+        other => {
+            return Err(ArrowError::CastError(format!(
+                "Cannot read a date from a {} column",
+                other
+            )));
+        }
+    };
+    Ok(date.map(|date| date.format("%Y-%m-%d").to_string()))
+}
+
+fn timestamp_date<T: ArrowTimestampType>(
+    array: &dyn Array,
+    row: usize,
+    tz: Option<&str>,
+) -> Result<Option<NaiveDate>, ArrowError> {
+    let array = array.as_primitive::<T>();
+    Ok(match tz {
+        Some(tz) => array
+            .value_as_datetime_with_tz(row, tz.parse::<Tz>()?)
+            .map(|datetime| datetime.date_naive()),
+        None => array.value_as_datetime(row).map(|datetime| datetime.date()),
+    })
+}
@@ -0,0 +1,121 @@
+//! Values of record batch columns by name
+//!
+//! The same TPCH column is `Utf8` when read from CSV, `Utf8View` from parquet, an
+//! `Int32` key from one writer and an `Int64` from another, or a decimal money
+//! amount. `RecordBatchExt` reads one value of a column whatever its physical
+//! type, so resolvers do not downcast each column themselves. Every getter
+//! returns `None` for a null and for a column the batch does not have, e.g. one
+//! the client did not select, and fails on a type it cannot read.
+
+use crate::datafusion::arrow_convert::date_value;
+use datafusion::arrow::array::{Array, AsArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{
+    DataType, Decimal128Type, Float32Type, Float64Type, Int8Type, Int16Type, Int32Type, Int64Type,
+    UInt8Type, UInt16Type, UInt32Type, UInt64Type,
+};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::record_batch::RecordBatch;
+
+pub trait RecordBatchExt {
+    /// Integer value, widened from any integer type that fits an `i64`
+    fn get_i64(&self, column: &str, row: usize) -> Result<Option<i64>, ArrowError>;
+
+    /// Numeric value as `f64`: floats, integers and decimals scaled by their scale
+    fn get_f64(&self, column: &str, row: usize) -> Result<Option<f64>, ArrowError>;
+
+    /// Text of a `Utf8`, `LargeUtf8` or `Utf8View` column
+    fn get_string(&self, column: &str, row: usize) -> Result<Option<String>, ArrowError>;
+
+    /// Date of a date or timestamp column as `YYYY-MM-DD`; dates stored as text
+    /// are returned as they are
+    fn get_date_string(&self, column: &str, row: usize) -> Result<Option<String>, ArrowError>;
+}
+
+impl RecordBatchExt for RecordBatch {
+    fn get_i64(&self, column: &str, row: usize) -> Result<Option<i64>, ArrowError> {
+        match value_array(self, column, row) {
+            Some(array) => i64_value(array, row).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn get_f64(&self, column: &str, row: usize) -> Result<Option<f64>, ArrowError> {
+        match value_array(self, column, row) {
+            Some(array) => f64_value(array, row).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn get_string(&self, column: &str, row: usize) -> Result<Option<String>, ArrowError> {
+        match value_array(self, column, row) {
+            Some(array) => string_value(array, row).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn get_date_string(&self, column: &str, row: usize) -> Result<Option<String>, ArrowError> {
+        match value_array(self, column, row) {
+            Some(array) if array.data_type().is_temporal() => date_value(array, row),
+            Some(array) => string_value(array, row).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Column of the batch when it has one with a value in `row`
+fn value_array<'a>(batch: &'a RecordBatch, column: &str, row: usize) -> Option<&'a dyn Array> {
+    batch
+        .column_by_name(column)
+        .map(|array| array.as_ref())
+        .filter(|array| !array.is_null(row))
+}
+
+fn unreadable(what: &str, data_type: &DataType) -> ArrowError {
+    ArrowError::CastError(format!("Cannot read {} from a {} column", what, data_type))
+}
+
+fn i64_value(array: &dyn Array, row: usize) -> Result<i64, ArrowError> {
+    Ok(match array.data_type() {
+        DataType::Int8 => array.as_primitive::<Int8Type>().value(row).into(),
+        DataType::Int16 => array.as_primitive::<Int16Type>().value(row).into(),
+        DataType::Int32 => array.as_primitive::<Int32Type>().value(row).into(),
+        DataType::Int64 => array.as_primitive::<Int64Type>().value(row),
+        DataType::UInt8 => array.as_primitive::<UInt8Type>().value(row).into(),
+        DataType::UInt16 => array.as_primitive::<UInt16Type>().value(row).into(),
+        DataType::UInt32 => array.as_primitive::<UInt32Type>().value(row).into(),
+        DataType::UInt64 => {
+            let value = array.as_primitive::<UInt64Type>().value(row);
+            i64::try_from(value)
+                .map_err(|_| ArrowError::CastError(format!("{} does not fit an i64", value)))?
+        }
+        other => return Err(unreadable("an integer", other)),
+    })
+}
+
+fn f64_value(array: &dyn Array, row: usize) -> Result<f64, ArrowError> {
+    match array.data_type() {
+        DataType::Float64 => Ok(array.as_primitive::<Float64Type>().value(row)),
+        DataType::Float32 => Ok(array.as_primitive::<Float32Type>().value(row).into()),
+        DataType::Decimal128(_, scale) => {
+            let value = array.as_primitive::<Decimal128Type>().value(row);
+            Ok(value as f64 / 10f64.powi(i32::from(*scale)))
+        }
+        data_type if data_type.is_integer() => i64_value(array, row).map(|value| value as f64),
+        // Half floats and wide decimals are rare enough to go through the cast kernel
+        data_type if data_type.is_numeric() => {
+            let value = cast(&array.slice(row, 1), &DataType::Float64)?;
+            Ok(value.as_primitive::<Float64Type>().value(0))
+        }
+        other => Err(unreadable("a number", other)),
+    }
+}
+
+fn string_value(array: &dyn Array, row: usize) -> Result<String, ArrowError> {
+    Ok(match array.data_type() {
+        DataType::Utf8 => array.as_string::<i32>().value(row).to_string(),
+        DataType::LargeUtf8 => array.as_string::<i64>().value(row).to_string(),
+        DataType::Utf8View => array.as_string_view().value(row).to_string(),
+        other => return Err(unreadable("text", other)),
+    })
+}
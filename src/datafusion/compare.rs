@@ -0,0 +1,304 @@
+//! Comparing the results of two queries
+//!
+//! After changing a query or upgrading DataFusion the numbers of a dashboard
+//! should stay put. `compare_results` aligns the rows of two results on key
+//! columns, or on all of their values when no keys are given, and reports the rows
+//! only one result has and the values that differ. Float and decimal values may
+//! differ within the tolerances of the comparison. Rows are spread over partitions
+//! by a hash of their key and each partition is matched through a hash map, so the
+//! work stays linear in the rows instead of pairing every row with every other.
+//! Both results are held in memory, so each may be limited in rows and in the
+//! bytes of JSON its values take.
+
+use crate::graphql::columnar::{JsonBudget, RESULT_TOO_LARGE_CODE, ResultError, json_values};
+use crate::models::data::{ComparedRow, QueryComparison, ValueDifference};
+use async_graphql::{ErrorExtensions, Json};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use thiserror::Error;
+
+/// Partitions the rows of both results are hashed into
+const PARTITIONS: usize = 16;
+
+/// Rows and differences listed per kind by default
+pub const DEFAULT_COMPARE_LIMIT: usize = 100;
+
+/// How two results are compared
+#[derive(Debug, Clone)]
+pub struct CompareOptions {
+    /// Columns identifying a row; rows are matched on all their values when empty
+    pub key_columns: Vec<String>,
+    /// Largest absolute difference between float or decimal values counted equal
+    pub absolute_tolerance: f64,
+    /// Largest difference between float or decimal values counted equal, relative
+    /// to the larger of the two
+    pub relative_tolerance: f64,
+    /// Rows and differences listed at most per kind; all of them are counted
+    pub limit: usize,
+    /// Rows each result may have, 0 for no limit
+    pub max_rows: usize,
+    /// Bytes of JSON the values of each result may take, 0 for no limit
+    pub max_json_bytes: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum CompareError {
+    #[error("Cannot compare the results: {0}")]
+    DataFusion(#[from] DataFusionError),
+    #[error("Result {side} has more than {limit} rows; compare narrower queries")]
+    TooManyRows { side: &'static str, limit: usize },
+    #[error("Result {side}: {source}")]
+    TooLarge {
+        side: &'static str,
+        source: ResultError,
+    },
+}
+
+impl ErrorExtensions for CompareError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            if !matches!(self, CompareError::DataFusion(_)) {
+                e.set("code", RESULT_TOO_LARGE_CODE);
+            }
+        })
+    }
+}
+
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            key_columns: Vec::new(),
+            absolute_tolerance: 0.0,
+            relative_tolerance: 0.0,
+            limit: DEFAULT_COMPARE_LIMIT,
+            max_rows: 0,
+            max_json_bytes: 0,
+        }
+    }
+}
+
+impl CompareOptions {
+    fn equal(&self, a: &Value, b: &Value, approximate: bool) -> bool {
+        match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) if approximate => {
+                let difference = (a - b).abs();
+                difference <= self.absolute_tolerance
+                    || difference <= self.relative_tolerance * a.abs().max(b.abs())
+            }
+            // An integer and a float of the same value are equal
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        }
+    }
+}
+
+/// A row of a result, one JSON value per compared column
+struct Row {
+    /// Position in its result, which keeps the report in result order
+    index: usize,
+    key: String,
+    values: Vec<Value>,
+}
+
+/// Columns of a result with their types, `None` for a result without batches
+fn result_columns(batches: &[RecordBatch]) -> Option<Vec<(String, DataType)>> {
+    batches.first().map(|batch| {
+        batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| (field.name().clone(), field.data_type().clone()))
+            .collect()
+    })
+}
+
+/// Rows of result `side` partitioned by the hash of their key, held to the
+/// row and byte limits of `options`
+fn partitioned_rows(
+    side: &'static str,
+    batches: &[RecordBatch],
+    columns: &[String],
+    key_indexes: &[usize],
+    options: &CompareOptions,
+) -> Result<Vec<Vec<Row>>, CompareError> {
+    let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    if options.max_rows > 0 && rows > options.max_rows {
+        return Err(CompareError::TooManyRows {
+            side,
+            limit: options.max_rows,
+        });
+    }
+    let mut budget = JsonBudget::new(options.max_json_bytes);
+    let mut partitions: Vec<Vec<Row>> = (0..PARTITIONS).map(|_| Vec::new()).collect();
+    let mut index = 0;
+    for batch in batches {
+        let mut column_values = Vec::with_capacity(columns.len());
+        for name in columns {
+            let array = batch.column_by_name(name).ok_or_else(|| {
+                DataFusionError::Plan(format!("Column {} is missing from a batch", name))
+            })?;
+            column_values.push(json_values(array).map_err(DataFusionError::from)?);
+        }
+        for row in 0..batch.num_rows() {
+            let values: Vec<Value> = column_values
+                .iter_mut()
+                .map(|values| values[row].take())
+                .collect();
+            budget
+                .charge(&values)
+                .map_err(|source| CompareError::TooLarge { side, source })?;
+            let key_values: Vec<&Value> = key_indexes.iter().map(|&i| &values[i]).collect();
+            let key = serde_json::to_string(&key_values)
+                .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            let partition = (hasher.finish() % PARTITIONS as u64) as usize;
+            partitions[partition].push(Row { index, key, values });
+            index += 1;
+        }
+    }
+    Ok(partitions)
+}
+
+/// Differences from result `a` to result `b`. Both must have the same columns,
+/// in any order; values are reported in the column order of `a`.
+pub fn compare_results(
+    a: &[RecordBatch],
+    b: &[RecordBatch],
+    options: &CompareOptions,
+) -> Result<QueryComparison, CompareError> {
+    let columns = match (result_columns(a), result_columns(b)) {
+        (Some(columns_a), Some(columns_b)) => {
+            let mut names_a: Vec<&String> = columns_a.iter().map(|(name, _)| name).collect();
+            let mut names_b: Vec<&String> = columns_b.iter().map(|(name, _)| name).collect();
+            names_a.sort();
+            names_b.sort();
+            if names_a != names_b {
+                return Err(DataFusionError::Plan(format!(
+                    "Both queries must return the same columns, got {:?} and {:?}",
+                    names_a, names_b
+                ))
+                .into());
+            }
+            // Either side may have switched between integers and floats
+            columns_a
+                .into_iter()
+                .map(|(name, data_type)| {
+                    let data_type_b = &columns_b.iter().find(|(b, _)| *b == name).unwrap().1;
+                    let approximate = is_approximate(&data_type) || is_approximate(data_type_b);
+                    (name, approximate)
+                })
+                .collect()
+        }
+        (Some(columns), None) | (None, Some(columns)) => columns
+            .into_iter()
+            .map(|(name, data_type)| (name, is_approximate(&data_type)))
+            .collect(),
+        (None, None) => Vec::new(),
+    };
+    let names: Vec<String> = columns.iter().map(|(name, _)| name.clone()).collect();
+
+    let key_indexes: Vec<usize> = if options.key_columns.is_empty() {
+        (0..names.len()).collect()
+    } else {
+        options
+            .key_columns
+            .iter()
+            .map(|key| {
+                names
+                    .iter()
+                    .position(|name| name == key)
+                    .ok_or_else(|| DataFusionError::Plan(format!("Unknown key column {}", key)))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let partitions_a = partitioned_rows("A", a, &names, &key_indexes, options)?;
+    let partitions_b = partitioned_rows("B", b, &names, &key_indexes, options)?;
+    let rows_a: usize = partitions_a.iter().map(Vec::len).sum();
+    let rows_b: usize = partitions_b.iter().map(Vec::len).sum();
+
+    let mut only_in_a = Vec::new();
+    let mut only_in_b = Vec::new();
+    // Differences with the row of `b` and the column they were found in
+    let mut differences = Vec::new();
+    for (partition_a, partition_b) in partitions_a.into_iter().zip(partitions_b) {
+        // Rows sharing a key are paired in result order
+        let mut by_key: HashMap<String, VecDeque<Row>> = HashMap::new();
+        for row in partition_a {
+            by_key.entry(row.key.clone()).or_default().push_back(row);
+        }
+        for row_b in partition_b {
+            let Some(row_a) = by_key.get_mut(&row_b.key).and_then(VecDeque::pop_front) else {
+                only_in_b.push(row_b);
+                continue;
+            };
+            for (column, (name, approximate)) in columns.iter().enumerate() {
+                let (value_a, value_b) = (&row_a.values[column], &row_b.values[column]);
+                if !key_indexes.contains(&column) && !options.equal(value_a, value_b, *approximate)
+                {
+                    differences.push((
+                        row_b.index,
+                        column,
+                        ValueDifference {
+                            key: Json(key_values(&row_b, &key_indexes)),
+                            column: name.clone(),
+                            value_a: Json(value_a.clone()),
+                            value_b: Json(value_b.clone()),
+                        },
+                    ));
+                }
+            }
+        }
+        only_in_a.extend(by_key.into_values().flatten());
+    }
+
+    only_in_a.sort_by_key(|row| row.index);
+    only_in_b.sort_by_key(|row| row.index);
+    differences.sort_by_key(|(row, column, _)| (*row, *column));
+    let listed = |rows: Vec<Row>| -> Vec<ComparedRow> {
+        rows.into_iter()
+            .take(options.limit)
+            .map(|row| ComparedRow {
+                key: Json(key_values(&row, &key_indexes)),
+                values: Json(row.values),
+            })
+            .collect()
+    };
+
+    Ok(QueryComparison {
+        identical: only_in_a.is_empty() && only_in_b.is_empty() && differences.is_empty(),
+        key_columns: key_indexes.iter().map(|&i| names[i].clone()).collect(),
+        rows_a: rows_a as i64,
+        rows_b: rows_b as i64,
+        only_in_a_count: only_in_a.len() as i64,
+        only_in_b_count: only_in_b.len() as i64,
+        difference_count: differences.len() as i64,
+        only_in_a: listed(only_in_a),
+        only_in_b: listed(only_in_b),
+        differences: differences
+            .into_iter()
+            .take(options.limit)
+            .map(|(_, _, difference)| difference)
+            .collect(),
+        columns: names,
+    })
+}
+
+fn key_values(row: &Row, key_indexes: &[usize]) -> Vec<Value> {
+    key_indexes.iter().map(|&i| row.values[i].clone()).collect()
+}
+
+/// Whether values of a type are compared within the tolerances
+fn is_approximate(data_type: &DataType) -> bool {
+    data_type.is_floating()
+        || matches!(
+            data_type,
+            DataType::Decimal128(_, _) | DataType::Decimal256(_, _)
+        )
+}
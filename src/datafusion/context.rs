@@ -1,48 +1,1366 @@
+use crate::datafusion::analysis_filters;
+use crate::datafusion::dimensions::DimensionCache;
+use crate::datafusion::file_metadata::{self, TableFileStats};
+use crate::datafusion::lineage;
+use crate::datafusion::memory::{MemoryLimitExceeded, QueryMemoryPool};
+use crate::datafusion::query_log::{QueryLog, QueryLogEntry, SlowQueryLog};
+use crate::datafusion::revisions::{RevisionHistory, RevisionInfo};
+use crate::datafusion::rollup::{self, DAILY_REVENUE};
+use crate::events::{Event, EventBus, EventKind};
+use crate::metrics::{QUERY_RETRIES_TOTAL, ROLLUP_BUILD_SECONDS, TABLE_BYTES, metrics};
+use crate::models::data::{ColumnLineage, Filter};
+use crate::models::{ColumnMismatch, MODEL_MANIFESTS};
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::stats::Precision;
+use datafusion::datasource::listing::{ListingTable, ListingTableConfig, ListingTableUrl};
+use datafusion::datasource::{MemTable, TableProvider};
+use datafusion::error::DataFusionError;
+use datafusion::execution::disk_manager::{DiskManager, DiskManagerConfig};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::execution::{SendableRecordBatchStream, SessionStateBuilder};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::prelude::*;
+use datafusion::sql::parser::Statement as DFStatement;
+use datafusion::sql::sqlparser::ast::Statement;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Tables registered from `{data_path}/{table}.parquet` when no tables are given
+pub const TPCH_TABLES: [&str; 8] = [
+    "customer", "orders", "lineitem", "part", "supplier", "nation", "region", "partsupp",
+];
+
+/// Tables `cache_dimensions` loads into memory
+pub const DIMENSION_TABLES: [&str; 2] = ["nation", "region"];
+
+/// File format of a table source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    #[default]
+    Parquet,
+    Csv,
+}
+
+impl TableFormat {
+    /// Format of a file by its extension, `None` for other files and directories
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "parquet" => Some(Self::Parquet),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// A table registered from a file or directory at startup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableDef {
+    pub name: String,
+    /// File or directory, relative to the data path unless absolute or a URL
+    pub path: String,
+    /// Format forced by the caller; detected from the extension of the path when
+    /// `None`, with directories read as parquet
+    #[serde(default)]
+    pub format: Option<TableFormat>,
+    /// Whether the first line of a CSV source names its columns
+    #[serde(default = "default_has_header")]
+    pub has_header: bool,
+}
+
+fn default_has_header() -> bool {
+    true
+}
+
+impl TableDef {
+    /// Table read in the given format whatever the extension of its path
+    pub fn new(name: &str, path: &str, format: TableFormat) -> Self {
+        Self {
+            format: Some(format),
+            ..Self::from_path(name, path)
+        }
+    }
+
+    /// Table read in the format its extension names
+    pub fn from_path(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            format: None,
+            has_header: true,
+        }
+    }
+
+    /// Read a CSV source without a header row, naming its columns `column_1`,
+    /// `column_2` and so on
+    pub fn without_header(mut self) -> Self {
+        self.has_header = false;
+        self
+    }
+
+    /// Format the table is read in
+    pub fn format(&self) -> TableFormat {
+        self.format
+            .or_else(|| TableFormat::from_path(&self.path))
+            .unwrap_or_default()
+    }
+
+    /// Path of the source, resolved against the data path
+    fn resolved_path(&self, data_path: &str) -> String {
+        if data_path.is_empty() || self.path.contains("://") || Path::new(&self.path).is_absolute()
+        {
+            self.path.clone()
+        } else {
+            format!("{}/{}", data_path.trim_end_matches('/'), self.path)
+        }
+    }
+}
+
+/// The TPCH tables as parquet files named after them
+pub fn tpch_tables() -> Vec<TableDef> {
+    TPCH_TABLES
+        .iter()
+        .map(|table| TableDef::new(table, &format!("{}.parquet", table), TableFormat::Parquet))
+        .collect()
+}
+
+/// Retry behaviour for queries failing with transient errors
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Backoff before the first retry, doubled for each further retry
+    pub initial_backoff: Duration,
+    /// Upper bound for a single backoff
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Category of a query failure, used to decide whether a retry can help
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Object store or network failure that may succeed when retried
+    Transient,
+    /// SQL parsing or planning failure
+    Planner,
+    /// Any other failure during execution
+    Logical,
+}
+
+/// `code` extension of the error a cancelled or killed query fails with
+pub const QUERY_CANCELLED_CODE: &str = "QUERY_CANCELLED";
+
+/// Error returned by `execute_query` when the query was cancelled
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Query {id} was cancelled{}",
+    .killed_by.as_ref().map(|admin| format!(" by admin {}", admin)).unwrap_or_default()
+)]
+pub struct QueryCancelled {
+    pub id: String,
+    /// Admin who killed the query with `kill_query`
+    pub killed_by: Option<String>,
+}
+
+/// Error returned by `drop_table` when views still read from the table
+#[derive(Debug, thiserror::Error)]
+#[error("Cannot drop {table}, it is referenced by views: {}", views.join(", "))]
+pub struct TableInUse {
+    pub table: String,
+    pub views: Vec<String>,
+}
+
+/// A file-backed table whose source cannot be read
+#[derive(Debug, Clone)]
+pub struct SourceProblem {
+    pub table: String,
+    pub path: String,
+    pub problem: String,
+}
+
+tokio::task_local! {
+    static QUERY_OWNER: String;
+}
+
+/// Run `future` with the queries it starts owned by `owner`, see `RunningQueryInfo`
+pub async fn with_query_owner<F: Future>(owner: Option<String>, future: F) -> F::Output {
+    match owner {
+        Some(owner) => QUERY_OWNER.scope(owner, future).await,
+        None => future.await,
+    }
+}
+
+/// A query currently executing
+#[derive(Debug, Clone)]
+pub struct RunningQueryInfo {
+    pub id: String,
+    pub sql: String,
+    pub started_at: DateTime<Utc>,
+    /// User whose request started the query, `None` for anonymous callers
+    pub owner: Option<String>,
+}
+
+struct RunningQuery {
+    id: String,
+    sql: String,
+    started_at: DateTime<Utc>,
+    owner: Option<String>,
+    cancel: Arc<Notify>,
+    killed_by: Option<String>,
+}
+
+impl RunningQuery {
+    fn info(&self) -> RunningQueryInfo {
+        RunningQueryInfo {
+            id: self.id.clone(),
+            sql: self.sql.clone(),
+            started_at: self.started_at,
+            owner: self.owner.clone(),
+        }
+    }
+}
+
+/// Removes a query from the running set when its execution ends or is dropped
+struct RunningGuard<'a> {
+    running: &'a Mutex<HashMap<String, RunningQuery>>,
+    id: &'a str,
+}
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(self.id);
+    }
+}
+
+/// A query whose result is streamed, listed among the running queries until
+/// its stream is dropped and logged then
+struct StreamedQuery {
+    df_ctx: Arc<DataFusionContext>,
+    id: String,
+    sql: String,
+    started: Instant,
+    lineage: Vec<ColumnLineage>,
+    rows: usize,
+    /// Whether the stream was read to its end without error
+    success: bool,
+}
+
+impl Drop for StreamedQuery {
+    fn drop(&mut self) {
+        self.df_ctx.running.lock().unwrap().remove(&self.id);
+        self.df_ctx.record_query(&QueryLogEntry {
+            id: self.id.clone(),
+            sql: self.sql.clone(),
+            duration: self.started.elapsed(),
+            rows: self.rows,
+            success: self.success,
+            timestamp: chrono::Utc::now(),
+            lineage: std::mem::take(&mut self.lineage),
+        });
+    }
+}
+
+/// `err`, reported as `MemoryLimitExceeded` when the query ran out of the
+/// memory of its own pool
+fn memory_error(pool: Option<&QueryMemoryPool>, err: DataFusionError) -> DataFusionError {
+    match (pool, err.find_root()) {
+        (Some(pool), DataFusionError::ResourcesExhausted(_)) => {
+            DataFusionError::External(Box::new(MemoryLimitExceeded {
+                limit_bytes: pool.limit(),
+                peak_bytes: pool.peak(),
+            }))
+        }
+        _ => err,
+    }
+}
+
+/// Marks a table as being reloaded until dropped
+pub struct ReloadGuard<'a> {
+    reloading: &'a Mutex<HashSet<String>>,
+    table: String,
+}
+
+impl Drop for ReloadGuard<'_> {
+    fn drop(&mut self) {
+        self.reloading.lock().unwrap().remove(&self.table);
+    }
+}
+
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
 
 pub struct DataFusionContext {
     ctx: SessionContext,
-    table_names: Vec<String>,
+    table_names: RwLock<Vec<String>>,
+    table_sources: RwLock<HashMap<String, String>>,
+    /// Parquet footer metadata of file-backed tables
+    table_files: RwLock<HashMap<String, TableFileStats>>,
+    /// `SELECT` statements of registered views
+    views: RwLock<HashMap<String, String>>,
+    dimensions: RwLock<Arc<DimensionCache>>,
     data_path: String,
+    retry_policy: RetryPolicy,
+    query_timeout: Duration,
+    /// Memory each query may reserve, `None` for the shared unbounded pool
+    query_memory_limit: Option<usize>,
+    query_log: QueryLog,
+    slow_queries: SlowQueryLog,
+    running: Mutex<HashMap<String, RunningQuery>>,
+    /// Result of the last `check_model_schemas`
+    schema_mismatches: RwLock<Vec<ColumnMismatch>>,
+    /// Tables whose registration is being replaced by `reload_table`
+    reloading: Mutex<HashSet<String>>,
+    /// Receives the outcome of each table reload
+    events: EventBus,
+    /// Tables of the revisions before the last reloads
+    revisions: RevisionHistory,
 }
 
 impl DataFusionContext {
+    /// Create a context over the TPCH parquet files in `data_path`
     pub async fn new(
         data_path: &str,
     ) -> Result<DataFusionContext, datafusion::error::DataFusionError> {
-        let ctx = SessionContext::new();
-        let mut table_names = Vec::new();
+        Self::with_tables(data_path, &[]).await
+    }
 
-        // Register all TPCH tables
-        let tables = [
-            "customer", "orders", "lineitem", "part", "supplier", "nation", "region", "partsupp",
-        ];
+    /// Create a context over the given tables, or the TPCH tables when none are
+    /// given. Relative paths are read from `data_path`.
+    pub async fn with_tables(
+        data_path: &str,
+        tables: &[TableDef],
+    ) -> Result<DataFusionContext, DataFusionError> {
+        let mut context = Self::in_memory();
+        context.data_path = data_path.to_string();
 
+        let tables = if tables.is_empty() {
+            tpch_tables()
+        } else {
+            tables.to_vec()
+        };
         for table in &tables {
-            let table_path = format!("{}/{}.parquet", data_path, table);
-            ctx.register_parquet(*table, &table_path, ParquetReadOptions::default())
-                .await?;
-            table_names.push(table.to_string());
+            let path = table.resolved_path(data_path);
+            match table.format() {
+                TableFormat::Parquet => context.register_parquet(&table.name, &path).await?,
+                TableFormat::Csv => {
+                    context
+                        .register_csv(&table.name, &path, table.has_header)
+                        .await?
+                }
+            }
+        }
+
+        Ok(context)
+    }
+
+    /// Create a context without any registered tables
+    pub fn in_memory() -> Self {
+        Self {
+            ctx: SessionContext::new(),
+            table_names: RwLock::new(Vec::new()),
+            table_sources: RwLock::new(HashMap::new()),
+            table_files: RwLock::new(HashMap::new()),
+            views: RwLock::new(HashMap::new()),
+            dimensions: RwLock::new(Arc::new(DimensionCache::default())),
+            data_path: String::new(),
+            retry_policy: RetryPolicy::default(),
+            query_timeout: Duration::from_secs(30),
+            query_memory_limit: None,
+            query_log: QueryLog::default(),
+            slow_queries: SlowQueryLog::default(),
+            running: Mutex::new(HashMap::new()),
+            schema_mismatches: RwLock::new(Vec::new()),
+            reloading: Mutex::new(HashSet::new()),
+            events: EventBus::default(),
+            revisions: RevisionHistory::default(),
+        }
+    }
+
+    /// Set the retry behaviour for transient errors
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the overall time budget of a query, including retries
+    pub fn with_query_timeout(mut self, query_timeout: Duration) -> Self {
+        self.query_timeout = query_timeout;
+        self
+    }
+
+    /// Give every query its own memory pool of `bytes`; queries going over it fail
+    /// with `MemoryLimitExceeded` instead of spilling to disk
+    pub fn with_query_memory_limit(mut self, bytes: usize) -> Self {
+        self.query_memory_limit = Some(bytes);
+        self
+    }
+
+    /// Publish reload events on a shared bus
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Keep the tables of the `capacity` revisions before the current one for
+    /// queries run as of an earlier revision
+    pub fn with_kept_revisions(mut self, capacity: usize) -> Self {
+        self.revisions = RevisionHistory::new(capacity);
+        self
+    }
+
+    /// Bus the reload events are published on
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Set the threshold above which queries are logged as slow, and how many
+    /// of the slowest queries are kept
+    pub fn with_slow_query_log(mut self, threshold: Duration, capacity: usize) -> Self {
+        self.slow_queries = SlowQueryLog::new(threshold, capacity);
+        self
+    }
+
+    /// Register in-memory record batches as a queryable table
+    pub fn register_batches(
+        &self,
+        table_name: &str,
+        batches: Vec<RecordBatch>,
+    ) -> Result<(), DataFusionError> {
+        let schema = batches
+            .first()
+            .map(|batch| batch.schema())
+            .ok_or_else(|| DataFusionError::Plan("Cannot register an empty table".to_string()))?;
+        let table = MemTable::try_new(schema, vec![batches])?;
+        self.ctx.deregister_table(table_name)?;
+        self.ctx.register_table(table_name, Arc::new(table))?;
+        self.add_table_name(table_name);
+        Ok(())
+    }
+
+    /// Register a parquet file or directory as a table
+    pub async fn register_parquet(
+        &self,
+        table_name: &str,
+        path: &str,
+    ) -> Result<(), DataFusionError> {
+        self.ctx.deregister_table(table_name)?;
+        self.ctx
+            .register_parquet(table_name, path, ParquetReadOptions::default())
+            .await?;
+        self.table_sources
+            .write()
+            .unwrap()
+            .insert(table_name.to_string(), path.to_string());
+        self.add_table_name(table_name);
+        self.refresh_table_files(table_name, path).await;
+        Ok(())
+    }
+
+    /// Register a CSV file, or a directory of `.csv` files, as a table. Column
+    /// types are inferred from the first rows; without a header row columns are
+    /// named `column_1`, `column_2` and so on. Unlike parquet tables it has no
+    /// source to reload from or file statistics.
+    pub async fn register_csv(
+        &self,
+        table_name: &str,
+        path: &str,
+        has_header: bool,
+    ) -> Result<(), DataFusionError> {
+        // DataFusion only lists files with the expected extension, so a file read
+        // as CSV under another name keeps its own
+        let extension = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(extension) if !path.ends_with('/') => format!(".{}", extension),
+            _ => ".csv".to_string(),
+        };
+        let options = CsvReadOptions::new()
+            .has_header(has_header)
+            .file_extension(&extension);
+        self.ctx.deregister_table(table_name)?;
+        self.ctx.register_csv(table_name, path, options).await?;
+        self.add_table_name(table_name);
+        Ok(())
+    }
+
+    /// Register a view over the registered tables
+    pub async fn register_view(&self, view_name: &str, sql: &str) -> Result<(), DataFusionError> {
+        let view = self.ctx.sql(sql).await?.into_view();
+        self.ctx.deregister_table(view_name)?;
+        self.ctx.register_table(view_name, view)?;
+        self.views
+            .write()
+            .unwrap()
+            .insert(view_name.to_string(), sql.to_string());
+        self.add_table_name(view_name);
+        Ok(())
+    }
+
+    /// Views whose statement references a table, sorted by name
+    pub fn dependent_views(&self, table_name: &str) -> Result<Vec<String>, DataFusionError> {
+        let views = self.views.read().unwrap().clone();
+        let mut dependents = Vec::new();
+        for (view_name, sql) in views {
+            if view_name != table_name
+                && self
+                    .referenced_tables(&sql)?
+                    .iter()
+                    .any(|table| table == table_name)
+            {
+                dependents.push(view_name);
+            }
+        }
+        dependents.sort();
+        Ok(dependents)
+    }
+
+    /// Re-register a single table from its source; cached dimension tables stay
+    /// in memory and their lookups are rebuilt. A successful reload starts a new
+    /// revision, keeping the tables as they were. The outcome is published as an
+    /// event.
+    pub async fn reload_table(&self, table_name: &str) -> Result<(), DataFusionError> {
+        let previous = if self.revisions.capacity() > 0 {
+            self.revision_tables(table_name).await
+        } else {
+            HashMap::new()
+        };
+        let mut reloaded = self.reload_from_source(table_name).await;
+        if reloaded.is_ok() {
+            reloaded = self.replan_dependent_views(table_name).await;
+        }
+        if reloaded.is_ok() {
+            let revision = self.revisions.advance(table_name, previous);
+            debug!("Reloading {} started revision {}", table_name, revision);
+        }
+        let event = match &reloaded {
+            Ok(()) => Event::new(EventKind::TableReloaded, table_name, ""),
+            Err(e) => Event::new(EventKind::ReloadFailed, table_name, e.to_string()),
+        };
+        self.events.publish(event);
+        reloaded
+    }
+
+    /// Plan the views over a reloaded table again, and the views over those. A
+    /// view keeps the providers it was planned with, so it would otherwise go on
+    /// reading the table as it was before the reload.
+    async fn replan_dependent_views(&self, table_name: &str) -> Result<(), DataFusionError> {
+        // Views cannot reference views registered after them, so this ends
+        let mut pending: VecDeque<String> = self.dependent_views(table_name)?.into();
+        while let Some(view_name) = pending.pop_front() {
+            let sql = self.views.read().unwrap().get(&view_name).cloned();
+            let Some(sql) = sql else {
+                continue;
+            };
+            self.register_view(&view_name, &sql).await?;
+            pending.extend(self.dependent_views(&view_name)?);
+        }
+        Ok(())
+    }
+
+    async fn reload_from_source(&self, table_name: &str) -> Result<(), DataFusionError> {
+        let source = self.table_sources.read().unwrap().get(table_name).cloned();
+        let Some(path) = source else {
+            return Err(DataFusionError::Plan(format!(
+                "Table {} has no source to reload from",
+                table_name
+            )));
+        };
+
+        let _reloading = self.begin_reload(table_name);
+        let cached = matches!(table_name, "nation" | "region") && !self.dimensions().is_empty();
+        if !cached {
+            self.register_parquet(table_name, &path).await?;
+            // The rollup is derived from orders
+            let rebuilt = match table_name {
+                "orders" => self.refresh_rollups().await,
+                _ => Ok(()),
+            };
+            if let Err(e) = rebuilt {
+                warn!("Failed to rebuild {}: {}", DAILY_REVENUE, e);
+            }
+            return Ok(());
+        }
+        let batches = self.load_source(table_name).await?;
+        self.register_batches(table_name, batches)?;
+        let nation = self.ctx.table("nation").await?.collect().await?;
+        let region = self.ctx.table("region").await?.collect().await?;
+        *self.dimensions.write().unwrap() =
+            Arc::new(DimensionCache::from_batches(&nation, &region)?);
+        self.refresh_table_files(table_name, &path).await;
+        Ok(())
+    }
+
+    /// Tables of the current revision before `reloaded` is reloaded. A file-backed
+    /// table lists its files on every scan, so the reloaded one is pinned to the
+    /// files it has now; files added later are not part of the revision. The other
+    /// tables are kept as they are.
+    async fn revision_tables(&self, reloaded: &str) -> HashMap<String, Arc<dyn TableProvider>> {
+        let mut providers = HashMap::new();
+        for table_name in self.get_table_names() {
+            let Ok(provider) = self.ctx.table_provider(table_name.as_str()).await else {
+                continue;
+            };
+            let provider = if table_name == reloaded {
+                match self.pinned_listing(&table_name, &provider).await {
+                    Ok(Some(table)) => table,
+                    Ok(None) => provider,
+                    Err(e) => {
+                        warn!("Revision keeps the listing of {} open: {}", table_name, e);
+                        provider
+                    }
+                }
+            } else {
+                provider
+            };
+            providers.insert(table_name, provider);
+        }
+        providers
+    }
+
+    /// A listing table over the files `provider` lists now, or `None` when it is
+    /// not an unpartitioned listing of a table's source
+    async fn pinned_listing(
+        &self,
+        table_name: &str,
+        provider: &Arc<dyn TableProvider>,
+    ) -> Result<Option<Arc<dyn TableProvider>>, DataFusionError> {
+        let source = self.table_sources.read().unwrap().get(table_name).cloned();
+        let (Some(path), Some(listing)) =
+            (source, provider.as_any().downcast_ref::<ListingTable>())
+        else {
+            return Ok(None);
+        };
+        // Partition values are read from the path below the table's root
+        if !listing.options().table_partition_cols.is_empty() {
+            return Ok(None);
+        }
+        let store = ListingTableUrl::parse(&path)?.object_store();
+        let files = file_metadata::list_files(&self.ctx, &path)
+            .await?
+            .iter()
+            .map(|object| ListingTableUrl::parse(format!("{}{}", store.as_str(), object.location)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if files.is_empty() {
+            return Ok(None);
+        }
+        let config = ListingTableConfig::new_with_multi_paths(files)
+            .with_listing_options(listing.options().clone())
+            .with_schema(listing.schema());
+        Ok(Some(Arc::new(ListingTable::try_new(config)?)))
+    }
+
+    /// Revision of the registered tables, 1 until the first reload
+    pub fn current_revision(&self) -> u64 {
+        self.revisions.current()
+    }
+
+    /// The current revision and the earlier ones still kept, newest first
+    pub fn table_revisions(&self) -> Vec<RevisionInfo> {
+        self.revisions.revisions()
+    }
+
+    /// Mark a table as being reloaded until the guard is dropped. Its old
+    /// registration is removed before the new one is added, so queries in between
+    /// fail to find it; `is_transient` reports those failures as transient.
+    pub fn begin_reload(&self, table_name: &str) -> ReloadGuard<'_> {
+        self.reloading
+            .lock()
+            .unwrap()
+            .insert(table_name.to_string());
+        ReloadGuard {
+            reloading: &self.reloading,
+            table: table_name.to_string(),
+        }
+    }
+
+    /// Whether a failed query may succeed when the request runs again: a table
+    /// missing while that table is reloaded, and transient I/O errors unless
+    /// `execute_query` already retried them under its own retry policy
+    pub fn is_transient(&self, err: &DataFusionError) -> bool {
+        let reloading =
+            missing_table(err).is_some_and(|table| self.reloading.lock().unwrap().contains(table));
+        reloading
+            || (classify_error(err) == ErrorClass::Transient && self.retry_policy.max_retries == 0)
+    }
+
+    /// Rebuild the `daily_revenue` rollup from orders. When the build fails the
+    /// rollup is removed and trend queries aggregate orders directly.
+    pub async fn refresh_rollups(&self) -> Result<(), DataFusionError> {
+        let started = Instant::now();
+        match rollup::build_daily_revenue(&self.ctx).await {
+            Ok(table) => {
+                self.ctx.deregister_table(DAILY_REVENUE)?;
+                self.ctx.register_table(DAILY_REVENUE, Arc::new(table))?;
+                self.add_table_name(DAILY_REVENUE);
+                ROLLUP_BUILD_SECONDS
+                    .with_label_values(&[DAILY_REVENUE])
+                    .set(started.elapsed().as_secs_f64());
+                Ok(())
+            }
+            Err(e) => {
+                self.remove_rollup();
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether `refresh_rollups` would rebuild the rollup, without building it
+    pub async fn check_rollups(&self) -> Result<(), DataFusionError> {
+        rollup::check_daily_revenue(&self.ctx).await
+    }
+
+    /// Whether the `daily_revenue` rollup is registered
+    pub fn has_daily_revenue(&self) -> bool {
+        self.get_table_names()
+            .iter()
+            .any(|name| name == DAILY_REVENUE)
+    }
+
+    fn remove_rollup(&self) {
+        self.ctx.deregister_table(DAILY_REVENUE).ok();
+        self.table_names
+            .write()
+            .unwrap()
+            .retain(|name| name != DAILY_REVENUE);
+    }
+
+    /// Deregister a table or view and forget its source and file metadata.
+    /// Fails with `TableInUse` while views reference the table.
+    pub fn drop_table(&self, table_name: &str) -> Result<(), DataFusionError> {
+        if !self.get_table_names().iter().any(|name| name == table_name) {
+            return Err(DataFusionError::Plan(format!(
+                "Unknown table: {}",
+                table_name
+            )));
+        }
+        let views = self.dependent_views(table_name)?;
+        if !views.is_empty() {
+            return Err(DataFusionError::External(Box::new(TableInUse {
+                table: table_name.to_string(),
+                views,
+            })));
+        }
+
+        self.ctx.deregister_table(table_name)?;
+        self.table_names
+            .write()
+            .unwrap()
+            .retain(|name| name != table_name);
+        self.table_sources.write().unwrap().remove(table_name);
+        self.views.write().unwrap().remove(table_name);
+        if self
+            .table_files
+            .write()
+            .unwrap()
+            .remove(table_name)
+            .is_some()
+        {
+            TABLE_BYTES.remove_label_values(&[table_name]).ok();
+        }
+        if matches!(table_name, "nation" | "region") {
+            *self.dimensions.write().unwrap() = Arc::new(DimensionCache::default());
+        }
+        if table_name == "orders" {
+            self.remove_rollup();
+        }
+        Ok(())
+    }
+
+    /// Re-read the parquet footers behind a table; failures are logged, the table
+    /// stays registered without file metadata
+    async fn refresh_table_files(&self, table_name: &str, path: &str) {
+        match file_metadata::gather(&self.ctx, path).await {
+            Ok(stats) => {
+                TABLE_BYTES
+                    .with_label_values(&[table_name])
+                    .set(stats.total_bytes() as i64);
+                self.table_files
+                    .write()
+                    .unwrap()
+                    .insert(table_name.to_string(), stats);
+            }
+            Err(e) => {
+                warn!("Failed to read parquet metadata of {}: {}", table_name, e);
+                self.table_files.write().unwrap().remove(table_name);
+            }
+        }
+    }
+
+    /// Check that the source of every file-backed table is reachable and has
+    /// parquet files, without reading them
+    pub async fn check_sources(&self) -> Vec<SourceProblem> {
+        let mut sources: Vec<(String, String)> = self
+            .table_sources
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(table, path)| (table.clone(), path.clone()))
+            .collect();
+        sources.sort();
+
+        let mut problems = Vec::new();
+        for (table, path) in sources {
+            let problem = match file_metadata::list_files(&self.ctx, &path).await {
+                Ok(files) if files.is_empty() => "no parquet files found".to_string(),
+                Ok(_) => continue,
+                Err(e) if is_not_found(&e) => format!("missing: {}", e),
+                Err(e) => format!("unreachable: {}", e),
+            };
+            problems.push(SourceProblem {
+                table,
+                path,
+                problem,
+            });
+        }
+        problems
+    }
+
+    /// Compare the registered tables with the columns the typed models read,
+    /// remembering the mismatches for `model_mismatches`. Tables that are not
+    /// registered are skipped.
+    pub async fn check_model_schemas(&self) -> Vec<ColumnMismatch> {
+        let mut mismatches = Vec::new();
+        for manifest in MODEL_MANIFESTS {
+            if let Ok(schema) = self.table_schema(manifest.table).await {
+                mismatches.extend(manifest.check(&schema));
+            }
+        }
+        *self.schema_mismatches.write().unwrap() = mismatches.clone();
+        mismatches
+    }
+
+    /// Mismatches found for a model by the last `check_model_schemas`
+    pub fn model_mismatches(&self, model: &str) -> Vec<ColumnMismatch> {
+        self.schema_mismatches
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|mismatch| mismatch.model == model)
+            .cloned()
+            .collect()
+    }
+
+    /// Parquet files behind a table, `None` for in-memory tables
+    pub fn table_files(&self, table_name: &str) -> Option<TableFileStats> {
+        self.table_files.read().unwrap().get(table_name).cloned()
+    }
+
+    /// Load `nation` and `region` into memory, replacing their parquet registration,
+    /// and rebuild the dimension lookups. Calling it again re-reads the source files.
+    pub async fn cache_dimensions(&self) -> Result<(), DataFusionError> {
+        let (nation, region, cache) = self.load_dimensions().await?;
+        self.register_batches("nation", nation)?;
+        self.register_batches("region", region)?;
+        *self.dimensions.write().unwrap() = Arc::new(cache);
+
+        // The source files may have changed since they were registered
+        for table_name in DIMENSION_TABLES {
+            let source = self.table_sources.read().unwrap().get(table_name).cloned();
+            if let Some(path) = source {
+                self.refresh_table_files(table_name, &path).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the dimension lookups from the source files without swapping them in
+    pub async fn check_dimensions(&self) -> Result<DimensionCache, DataFusionError> {
+        let (_, _, cache) = self.load_dimensions().await?;
+        Ok(cache)
+    }
+
+    async fn load_dimensions(
+        &self,
+    ) -> Result<(Vec<RecordBatch>, Vec<RecordBatch>, DimensionCache), DataFusionError> {
+        let nation = self.load_source("nation").await?;
+        let region = self.load_source("region").await?;
+        let cache = DimensionCache::from_batches(&nation, &region)?;
+        Ok((nation, region, cache))
+    }
+
+    /// Nation and region lookups; empty until `cache_dimensions` has run
+    pub fn dimensions(&self) -> Arc<DimensionCache> {
+        self.dimensions.read().unwrap().clone()
+    }
+
+    /// Read a table from its source file, or from the registered table when it
+    /// has no file source
+    async fn load_source(&self, table_name: &str) -> Result<Vec<RecordBatch>, DataFusionError> {
+        let source = self.table_sources.read().unwrap().get(table_name).cloned();
+        match source {
+            Some(path) => {
+                self.ctx
+                    .read_parquet(path, ParquetReadOptions::default())
+                    .await?
+                    .collect()
+                    .await
+            }
+            None => self.ctx.table(table_name).await?.collect().await,
         }
+    }
+
+    /// Read the schema of a parquet file or directory without registering it
+    pub async fn parquet_schema(&self, path: &str) -> Result<SchemaRef, DataFusionError> {
+        let df = self
+            .ctx
+            .read_parquet(path, ParquetReadOptions::default())
+            .await?;
+        Ok(Arc::new(df.schema().as_arrow().clone()))
+    }
+
+    /// Schema of a registered table
+    pub async fn table_schema(&self, table_name: &str) -> Result<SchemaRef, DataFusionError> {
+        Ok(self.ctx.table_provider(table_name).await?.schema())
+    }
 
-        Ok(Self {
-            ctx,
-            table_names,
-            data_path: data_path.to_string(),
-        })
+    fn add_table_name(&self, table_name: &str) {
+        let mut table_names = self.table_names.write().unwrap();
+        if !table_names.iter().any(|name| name == table_name) {
+            table_names.push(table_name.to_string());
+        }
     }
 
+    /// Execute a query, retrying transient failures within the query timeout
     pub async fn execute_query(
         &self,
         query: &str,
     ) -> Result<Vec<RecordBatch>, datafusion::error::DataFusionError> {
-        let df = self.ctx.sql(query).await?;
-        df.collect().await
+        self.execute(query, &[], None).await.0
+    }
+
+    /// Same as `execute_query`, also returning the query log entry of the query
+    pub async fn execute_query_logged(
+        &self,
+        query: &str,
+    ) -> (Result<Vec<RecordBatch>, DataFusionError>, QueryLogEntry) {
+        self.execute(query, &[], None).await
+    }
+
+    /// Same as `execute_query_logged`, reading the tables as they were at an
+    /// earlier revision. Fails with a `RevisionError` when the revision is not kept.
+    pub async fn execute_query_logged_at(
+        &self,
+        query: &str,
+        revision: u64,
+    ) -> (Result<Vec<RecordBatch>, DataFusionError>, QueryLogEntry) {
+        self.execute(query, &[], Some(revision)).await
+    }
+
+    /// Same as `execute_query`, reading only the rows of each table that pass
+    /// the analysis filters on its columns
+    pub async fn execute_query_filtered(
+        &self,
+        query: &str,
+        filters: &[Filter],
+    ) -> Result<Vec<RecordBatch>, DataFusionError> {
+        self.execute(query, filters, None).await.0
+    }
+
+    async fn execute(
+        &self,
+        query: &str,
+        filters: &[Filter],
+        revision: Option<u64>,
+    ) -> (Result<Vec<RecordBatch>, DataFusionError>, QueryLogEntry) {
+        let (id, cancel) = self.start_running(query);
+        let _running = RunningGuard {
+            running: &self.running,
+            id: &id,
+        };
+
+        let started = Instant::now();
+        let result = tokio::select! {
+            result = self.execute_with_retries(query, filters, revision) => result,
+            _ = cancel.notified() => Err(self.cancelled(&id)),
+        };
+        let (result, lineage) = match result {
+            Ok((batches, lineage)) => (Ok(batches), lineage),
+            Err(e) => (Err(e), Vec::new()),
+        };
+        let rows = match &result {
+            Ok(batches) => batches.iter().map(|batch| batch.num_rows()).sum(),
+            Err(_) => 0,
+        };
+        let entry = QueryLogEntry {
+            id: id.clone(),
+            sql: query.to_string(),
+            duration: started.elapsed(),
+            rows,
+            success: result.is_ok(),
+            timestamp: chrono::Utc::now(),
+            lineage,
+        };
+        self.record_query(&entry);
+        (result, entry)
+    }
+
+    /// List a query among the running ones, returning its id and the signal
+    /// that cancels it
+    fn start_running(&self, query: &str) -> (String, Arc<Notify>) {
+        let id = uuid::Uuid::new_v4().to_string();
+        let cancel = Arc::new(Notify::new());
+        self.running.lock().unwrap().insert(
+            id.clone(),
+            RunningQuery {
+                id: id.clone(),
+                sql: query.to_string(),
+                started_at: chrono::Utc::now(),
+                owner: QUERY_OWNER.try_with(|owner| owner.clone()).ok(),
+                cancel: cancel.clone(),
+                killed_by: None,
+            },
+        );
+        debug!("Query {} started: {}", id, query);
+        (id, cancel)
+    }
+
+    /// Error of a running query that was cancelled or killed
+    fn cancelled(&self, id: &str) -> DataFusionError {
+        let killed_by = self
+            .running
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|query| query.killed_by.clone());
+        DataFusionError::External(Box::new(QueryCancelled {
+            id: id.to_string(),
+            killed_by,
+        }))
+    }
+
+    /// Error of a query that outlived the query timeout
+    fn timed_out(&self) -> DataFusionError {
+        DataFusionError::Execution(format!("Query timed out after {:?}", self.query_timeout))
+    }
+
+    /// Record a finished query in the latency metric and the query logs
+    fn record_query(&self, entry: &QueryLogEntry) {
+        metrics()
+            .query_latency
+            .observe(entry.duration.as_secs_f64());
+        if self.slow_queries.record(entry) {
+            warn!(
+                "Slow query took {:?} (threshold {:?}), {} rows: {}",
+                entry.duration,
+                self.slow_queries.threshold(),
+                entry.rows,
+                entry.sql
+            );
+        }
+        self.query_log.record(entry.clone());
+    }
+
+    /// Plan a read-only statement and stream its result as it is computed, from
+    /// the tables as they were at `revision` when one is given. The statement is
+    /// held to the query memory limit and the query timeout, which covers reading
+    /// the whole stream, and is listed among the running queries until its stream
+    /// ends or is dropped, when it is logged. A cancelled or killed statement ends
+    /// its stream with a `QueryCancelled` error. Unlike `execute_query` it is not
+    /// retried, since batches already read cannot be taken back.
+    pub async fn execute_stream(
+        self: Arc<Self>,
+        query: &str,
+        revision: Option<u64>,
+    ) -> Result<SendableRecordBatchStream, DataFusionError> {
+        let (id, cancel) = self.start_running(query);
+        let mut streamed = StreamedQuery {
+            df_ctx: Arc::clone(&self),
+            id,
+            sql: query.to_string(),
+            started: Instant::now(),
+            lineage: Vec::new(),
+            rows: 0,
+            success: false,
+        };
+        let deadline = tokio::time::Instant::from_std(streamed.started + self.query_timeout);
+
+        let (ctx, pool) = self.query_context(revision)?;
+        let plan = async {
+            let df = ctx.sql_with_options(query, read_only_options()).await?;
+            let lineage = lineage::column_lineage(df.logical_plan()).unwrap_or_else(|e| {
+                warn!("Failed to extract the lineage of {}: {}", query, e);
+                Vec::new()
+            });
+            Ok::<_, DataFusionError>((df.execute_stream().await?, lineage))
+        };
+        let (batches, lineage) = tokio::select! {
+            planned = plan => planned?,
+            _ = cancel.notified() => return Err(self.cancelled(&streamed.id)),
+            _ = tokio::time::sleep_until(deadline) => return Err(self.timed_out()),
+        };
+        streamed.lineage = lineage;
+
+        let schema = batches.schema();
+        let state = Some((batches, streamed));
+        let stream = futures::stream::unfold(state, move |state| {
+            let pool = pool.clone();
+            let cancel = cancel.clone();
+            async move {
+                let (mut batches, mut streamed) = state?;
+                let next = tokio::select! {
+                    next = batches.next() => next,
+                    _ = cancel.notified() => Some(Err(streamed.df_ctx.cancelled(&streamed.id))),
+                    _ = tokio::time::sleep_until(deadline) => {
+                        Some(Err(streamed.df_ctx.timed_out()))
+                    }
+                };
+                match next {
+                    Some(Ok(batch)) => {
+                        streamed.rows += batch.num_rows();
+                        Some((Ok(batch), Some((batches, streamed))))
+                    }
+                    // Dropping the state ends the query
+                    Some(Err(err)) => Some((Err(memory_error(pool.as_deref(), err)), None)),
+                    None => {
+                        streamed.success = true;
+                        None
+                    }
+                }
+            }
+        });
+        Ok(Box::pin(RecordBatchStreamAdapter::new(schema, stream)))
+    }
+
+    /// Cancel a running query; the caller of `execute_query` receives a
+    /// `QueryCancelled` error. Returns whether a query with that id was running.
+    pub fn cancel_query(&self, id: &str) -> bool {
+        match self.running.lock().unwrap().get(id) {
+            Some(query) => {
+                query.cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancel a running query on behalf of `admin`, who is named in the
+    /// `QueryCancelled` error its caller receives. Returns the query when one
+    /// with that id was running.
+    pub fn kill_query(&self, id: &str, admin: &str) -> Option<RunningQueryInfo> {
+        let mut running = self.running.lock().unwrap();
+        let query = running.get_mut(id)?;
+        query.killed_by = Some(admin.to_string());
+        query.cancel.notify_one();
+        Some(query.info())
+    }
+
+    /// A query currently executing by its id
+    pub fn running_query(&self, id: &str) -> Option<RunningQueryInfo> {
+        self.running.lock().unwrap().get(id).map(RunningQuery::info)
+    }
+
+    /// Queries currently executing, longest running first
+    pub fn running_queries(&self) -> Vec<RunningQueryInfo> {
+        let mut queries: Vec<RunningQueryInfo> = self
+            .running
+            .lock()
+            .unwrap()
+            .values()
+            .map(RunningQuery::info)
+            .collect();
+        queries.sort_by_key(|query| query.started_at);
+        queries
+    }
+
+    /// Recently executed statements, oldest first
+    pub fn recent_queries(&self) -> Vec<QueryLogEntry> {
+        self.query_log.entries()
+    }
+
+    /// A statement of the query log by its id
+    pub fn logged_query(&self, id: &str) -> Option<QueryLogEntry> {
+        self.query_log
+            .entries()
+            .into_iter()
+            .find(|entry| entry.id == id)
+    }
+
+    /// Source columns of each output column of a statement, without running it
+    pub async fn column_lineage(&self, sql: &str) -> Result<Vec<ColumnLineage>, DataFusionError> {
+        let plan = self.ctx.state().create_logical_plan(sql).await?;
+        lineage::column_lineage(&plan)
+    }
+
+    /// The slowest statements above the slow-query threshold, slowest first
+    pub fn slow_queries(&self) -> Vec<QueryLogEntry> {
+        self.slow_queries.entries()
+    }
+
+    async fn execute_with_retries(
+        &self,
+        query: &str,
+        filters: &[Filter],
+        revision: Option<u64>,
+    ) -> Result<(Vec<RecordBatch>, Vec<ColumnLineage>), DataFusionError> {
+        let deadline = Instant::now() + self.query_timeout;
+        let mut backoff = self.retry_policy.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let run = self.run_query(query, filters, revision);
+            let result = match tokio::time::timeout(remaining, run).await {
+                Ok(result) => result,
+                Err(_) => Err(self.timed_out()),
+            };
+
+            let err = match result {
+                Ok(output) => return Ok(output),
+                Err(err) => err,
+            };
+
+            let can_retry = classify_error(&err) == ErrorClass::Transient
+                && attempt <= self.retry_policy.max_retries
+                && Instant::now() + backoff < deadline;
+            if !can_retry {
+                if attempt == 1 {
+                    return Err(err);
+                }
+                return Err(DataFusionError::Context(
+                    format!("Query failed after {} attempts", attempt),
+                    Box::new(err),
+                ));
+            }
+
+            warn!(
+                "Transient query failure (attempt {}), retrying in {:?}: {}",
+                attempt, backoff, err
+            );
+            QUERY_RETRIES_TOTAL.inc();
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.retry_policy.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    /// Run the query on its own task so a cancelled or timed out caller does not
+    /// wait for the current poll to finish; dropping this future aborts the task.
+    /// DDL, DML and statements such as `SET` are rejected, registered tables are
+    /// only changed through the dedicated methods.
+    async fn run_query(
+        &self,
+        query: &str,
+        filters: &[Filter],
+        revision: Option<u64>,
+    ) -> Result<(Vec<RecordBatch>, Vec<ColumnLineage>), DataFusionError> {
+        let (ctx, pool) = self.query_context(revision)?;
+        let sql = query.to_string();
+        let filters = filters.to_vec();
+        let mut task = AbortOnDrop(tokio::spawn(async move {
+            let mut df = ctx.sql_with_options(&sql, read_only_options()).await?;
+            if !filters.is_empty() {
+                let (state, plan) = df.into_parts();
+                df = DataFrame::new(state, analysis_filters::apply_filters(plan, &filters)?);
+            }
+            // Lineage is a record of the query, which runs without it
+            let lineage = lineage::column_lineage(df.logical_plan()).unwrap_or_else(|e| {
+                warn!("Failed to extract the lineage of {}: {}", sql, e);
+                Vec::new()
+            });
+            let batches = df
+                .collect()
+                .await
+                .map_err(|err| memory_error(pool.as_deref(), err))?;
+            Ok((batches, lineage))
+        }));
+        match (&mut task.0).await {
+            Ok(result) => result,
+            Err(e) => Err(DataFusionError::Execution(format!(
+                "Query task failed: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Context a query runs in: held to the query memory limit when one is set,
+    /// with the tables of `revision` when one is given
+    fn query_context(
+        &self,
+        revision: Option<u64>,
+    ) -> Result<(SessionContext, Option<Arc<QueryMemoryPool>>), DataFusionError> {
+        let (ctx, pool) = match self.query_memory_limit {
+            Some(limit) => {
+                let (ctx, pool) = self.limited_context(limit)?;
+                (ctx, Some(pool))
+            }
+            None => (self.ctx.clone(), None),
+        };
+        let ctx = match revision {
+            Some(revision) => self.revision_context(ctx, revision)?,
+            None => ctx,
+        };
+        Ok((ctx, pool))
+    }
+
+    /// A context sharing the registered tables and object stores, with a memory
+    /// pool of its own and spilling disabled
+    fn limited_context(
+        &self,
+        limit: usize,
+    ) -> Result<(SessionContext, Arc<QueryMemoryPool>), DataFusionError> {
+        let pool = Arc::new(QueryMemoryPool::new(limit));
+        let shared = self.ctx.runtime_env();
+        let runtime = RuntimeEnv {
+            memory_pool: pool.clone(),
+            disk_manager: DiskManager::try_new(DiskManagerConfig::Disabled)?,
+            cache_manager: shared.cache_manager.clone(),
+            object_store_registry: shared.object_store_registry.clone(),
+        };
+        let state = SessionStateBuilder::new_from_existing(self.ctx.state())
+            .with_runtime_env(Arc::new(runtime))
+            .build();
+        Ok((SessionContext::new_with_state(state), pool))
     }
 
-    pub fn get_table_names(&self) -> &Vec<String> {
-        &self.table_names
+    /// `ctx` with the tables of an earlier revision in place of the registered
+    /// ones; the context itself for the current revision
+    fn revision_context(
+        &self,
+        ctx: SessionContext,
+        revision: u64,
+    ) -> Result<SessionContext, DataFusionError> {
+        let snapshot = self
+            .revisions
+            .snapshot(revision)
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let Some(snapshot) = snapshot else {
+            return Ok(ctx);
+        };
+        let revision_ctx =
+            SessionContext::new_with_config_rt(ctx.copied_config(), ctx.runtime_env());
+        for (table_name, provider) in snapshot.tables() {
+            revision_ctx.register_table(table_name.as_str(), provider.clone())?;
+        }
+        Ok(revision_ctx)
+    }
+
+    pub fn get_table_names(&self) -> Vec<String> {
+        self.table_names.read().unwrap().clone()
     }
 
     pub fn get_data_path(&self) -> &str {
@@ -69,4 +1387,153 @@ impl DataFusionContext {
 
         Ok(0)
     }
+
+    /// Row count taken from table statistics (parquet footers) without scanning data.
+    /// Returns the count and whether the statistics are exact; falls back to an
+    /// exact count when the table has no statistics.
+    pub async fn estimate_table_count(
+        &self,
+        table_name: &str,
+    ) -> Result<(i64, bool), DataFusionError> {
+        let plan = self
+            .ctx
+            .table(table_name)
+            .await?
+            .create_physical_plan()
+            .await?;
+
+        match plan.partition_statistics(None)?.num_rows {
+            Precision::Exact(rows) => Ok((rows as i64, true)),
+            Precision::Inexact(rows) => Ok((rows as i64, false)),
+            Precision::Absent => Ok((self.get_table_count(table_name).await?, true)),
+        }
+    }
+
+    /// Tables referenced by a SQL statement, as resolved by the parser
+    pub fn referenced_tables(&self, sql: &str) -> Result<Vec<String>, DataFusionError> {
+        let state = self.ctx.state();
+        let statement = state.sql_to_statement(sql, "generic")?;
+        Ok(state
+            .resolve_table_references(&statement)?
+            .iter()
+            .map(|table| table.table().to_string())
+            .collect())
+    }
+
+    /// Canonical text of a single SQL statement, printed back from its syntax tree:
+    /// comments are dropped, whitespace is collapsed and keywords are uppercased.
+    /// Cosmetically different but equivalent statements normalize to the same text,
+    /// which makes it usable as a cache key. Fails on anything but one statement.
+    pub fn normalize_sql(&self, sql: &str) -> Result<String, DataFusionError> {
+        let statement = self.ctx.state().sql_to_statement(sql, "generic")?;
+        Ok(statement.to_string())
+    }
+
+    /// Fail unless the SQL is a single query, i.e. a SELECT, possibly with a
+    /// WITH clause, and not DDL, DML or a statement such as EXPLAIN or SET
+    pub fn ensure_select(&self, sql: &str) -> Result<(), DataFusionError> {
+        match self.ctx.state().sql_to_statement(sql, "generic")? {
+            DFStatement::Statement(statement) if matches!(*statement, Statement::Query(_)) => {
+                Ok(())
+            }
+            _ => Err(DataFusionError::Plan(
+                "Only SELECT statements can be executed".to_string(),
+            )),
+        }
+    }
+
+    /// Describe every registered table as `table(column Type, ...)`, one per line
+    pub async fn schema_summary(&self) -> Result<String, DataFusionError> {
+        let mut lines = Vec::new();
+        for table_name in self.get_table_names() {
+            let schema = self.ctx.table_provider(table_name.as_str()).await?.schema();
+            let columns: Vec<String> = schema
+                .fields()
+                .iter()
+                .map(|field| format!("{} {}", field.name(), field.data_type()))
+                .collect();
+            lines.push(format!("{}({})", table_name, columns.join(", ")));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Whether an error means the query was cancelled through `cancel_query` or
+/// `kill_query`
+pub fn is_cancelled(err: &DataFusionError) -> bool {
+    matches!(err.find_root(), DataFusionError::External(e) if e.is::<QueryCancelled>())
+}
+
+/// The views blocking a `drop_table`, if that is why it failed
+pub fn table_in_use(err: &DataFusionError) -> Option<&TableInUse> {
+    match err.find_root() {
+        DataFusionError::External(e) => e.downcast_ref::<TableInUse>(),
+        _ => None,
+    }
+}
+
+/// Statements queries may run: DDL, DML and statements such as `SET` are
+/// rejected
+fn read_only_options() -> SQLOptions {
+    SQLOptions::new()
+        .with_allow_ddl(false)
+        .with_allow_dml(false)
+        .with_allow_statements(false)
+}
+
+/// Whether an error means a file or object does not exist
+fn is_not_found(err: &DataFusionError) -> bool {
+    match err.find_root() {
+        DataFusionError::ObjectStore(e) => matches!(e, object_store::Error::NotFound { .. }),
+        DataFusionError::IoError(e) => e.kind() == ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// Whether an error means the SQL referenced a table or column that does not exist
+pub fn is_schema_error(err: &DataFusionError) -> bool {
+    match err.find_root() {
+        DataFusionError::SchemaError(..) => true,
+        DataFusionError::Plan(msg) => msg.contains("not found") || msg.contains("No field named"),
+        _ => false,
+    }
+}
+
+/// Table a planning error reports as missing, without its catalog and schema
+fn missing_table(err: &DataFusionError) -> Option<&str> {
+    let DataFusionError::Plan(msg) = err.find_root() else {
+        return None;
+    };
+    let start = msg.find("table '")? + "table '".len();
+    let end = start + msg[start..].find("' not found")?;
+    msg[start..end].rsplit('.').next()
+}
+
+/// Classify an error to decide whether retrying the query can help
+pub fn classify_error(err: &DataFusionError) -> ErrorClass {
+    match err.find_root() {
+        // Stores report network and server failures as generic errors; missing
+        // objects, denied access and failed preconditions are not retried
+        DataFusionError::ObjectStore(e) => match e {
+            object_store::Error::Generic { .. } | object_store::Error::JoinError { .. } => {
+                ErrorClass::Transient
+            }
+            _ => ErrorClass::Logical,
+        },
+        DataFusionError::IoError(e) => match e.kind() {
+            ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::UnexpectedEof => ErrorClass::Transient,
+            _ => ErrorClass::Logical,
+        },
+        DataFusionError::SQL(..)
+        | DataFusionError::Plan(_)
+        | DataFusionError::SchemaError(..)
+        | DataFusionError::NotImplemented(_) => ErrorClass::Planner,
+        _ => ErrorClass::Logical,
+    }
 }
@@ -0,0 +1,549 @@
+//! Synthetic TPCH data for demo mode
+//!
+//! With `--demo` or `DEMO_MODE=true` the server generates a small TPCH-like
+//! dataset in memory instead of reading parquet files, so the API can be tried
+//! without generating TPCH data first. The tables have the TPCH names and column
+//! types, so every resolver, analytics query and AI prompt works against them.
+//! The data is deterministic: every run generates the same rows.
+
+use crate::datafusion::context::DataFusionContext;
+use datafusion::arrow::array::{
+    ArrayRef, Date32Array, Float64Array, Int32Array, Int64Array, StringArray,
+};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use std::sync::Arc;
+
+pub const DEMO_CUSTOMERS: i64 = 150;
+pub const DEMO_ORDERS_PER_CUSTOMER: i64 = 10;
+pub const DEMO_PARTS: i64 = 200;
+pub const DEMO_SUPPLIERS: i64 = 10;
+
+/// Days since the epoch of 1992-01-01 and 1998-08-02, the TPCH order date range
+const FIRST_ORDER_DAY: i32 = 8035;
+const LAST_ORDER_DAY: i32 = 10440;
+
+const REGIONS: &[&str] = &["AFRICA", "AMERICA", "ASIA", "EUROPE", "MIDDLE EAST"];
+
+/// TPCH nations with the key of their region
+const NATIONS: &[(&str, i64)] = &[
+    ("ALGERIA", 0),
+    ("ARGENTINA", 1),
+    ("BRAZIL", 1),
+    ("CANADA", 1),
+    ("EGYPT", 4),
+    ("ETHIOPIA", 0),
+    ("FRANCE", 3),
+    ("GERMANY", 3),
+    ("INDIA", 2),
+    ("INDONESIA", 2),
+    ("IRAN", 4),
+    ("IRAQ", 4),
+    ("JAPAN", 2),
+    ("JORDAN", 4),
+    ("KENYA", 0),
+    ("MOROCCO", 0),
+    ("MOZAMBIQUE", 0),
+    ("PERU", 1),
+    ("CHINA", 2),
+    ("ROMANIA", 3),
+    ("SAUDI ARABIA", 4),
+    ("VIETNAM", 2),
+    ("RUSSIA", 3),
+    ("UNITED KINGDOM", 3),
+    ("UNITED STATES", 1),
+];
+
+const SEGMENTS: &[&str] = &[
+    "AUTOMOBILE",
+    "BUILDING",
+    "FURNITURE",
+    "HOUSEHOLD",
+    "MACHINERY",
+];
+const PRIORITIES: &[&str] = &["1-URGENT", "2-HIGH", "3-MEDIUM", "4-NOT SPECIFIED", "5-LOW"];
+const SHIP_INSTRUCTIONS: &[&str] = &[
+    "DELIVER IN PERSON",
+    "COLLECT COD",
+    "NONE",
+    "TAKE BACK RETURN",
+];
+const SHIP_MODES: &[&str] = &["REG AIR", "AIR", "RAIL", "SHIP", "TRUCK", "MAIL", "FOB"];
+const CONTAINERS: &[&str] = &[
+    "SM CASE", "SM BOX", "MED BAG", "MED BOX", "LG CASE", "LG PACK",
+];
+const PART_TYPES: &[&str] = &[
+    "STANDARD ANODIZED TIN",
+    "SMALL PLATED COPPER",
+    "MEDIUM BURNISHED NICKEL",
+    "LARGE BRUSHED BRASS",
+    "ECONOMY POLISHED STEEL",
+    "PROMO ANODIZED STEEL",
+];
+const COLORS: &[&str] = &[
+    "almond", "blue", "coral", "dark", "forest", "green", "ivory", "lime",
+];
+
+/// Linear congruential generator; the data only needs to look random
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    /// Integer in `lo..=hi`
+    fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        lo + (self.next() % (hi - lo + 1) as u64) as i64
+    }
+
+    /// Amount in cents between `lo` and `hi`
+    fn money(&mut self, lo: f64, hi: f64) -> f64 {
+        self.range((lo * 100.0) as i64, (hi * 100.0) as i64) as f64 / 100.0
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.next() as usize % items.len()]
+    }
+}
+
+fn ints(values: Vec<i64>) -> ArrayRef {
+    Arc::new(Int64Array::from(values))
+}
+
+fn floats(values: Vec<f64>) -> ArrayRef {
+    Arc::new(Float64Array::from(values))
+}
+
+fn strings(values: Vec<String>) -> ArrayRef {
+    Arc::new(StringArray::from(values))
+}
+
+fn dates(values: Vec<i32>) -> ArrayRef {
+    Arc::new(Date32Array::from(values))
+}
+
+/// The eight TPCH tables of the demo dataset
+pub fn demo_tables() -> Result<Vec<(&'static str, RecordBatch)>, ArrowError> {
+    let mut rng = Lcg(42);
+
+    let region = RecordBatch::try_from_iter(vec![
+        ("r_regionkey", ints((0..REGIONS.len() as i64).collect())),
+        (
+            "r_name",
+            strings(REGIONS.iter().map(|name| name.to_string()).collect()),
+        ),
+        (
+            "r_comment",
+            strings(
+                REGIONS
+                    .iter()
+                    .map(|name| format!("demo region {}", name.to_lowercase()))
+                    .collect(),
+            ),
+        ),
+    ])?;
+
+    let nation = RecordBatch::try_from_iter(vec![
+        ("n_nationkey", ints((0..NATIONS.len() as i64).collect())),
+        (
+            "n_name",
+            strings(NATIONS.iter().map(|(name, _)| name.to_string()).collect()),
+        ),
+        (
+            "n_regionkey",
+            ints(NATIONS.iter().map(|(_, region)| *region).collect()),
+        ),
+        (
+            "n_comment",
+            strings(
+                NATIONS
+                    .iter()
+                    .map(|(name, _)| format!("demo nation {}", name.to_lowercase()))
+                    .collect(),
+            ),
+        ),
+    ])?;
+
+    let keys: Vec<i64> = (1..=DEMO_CUSTOMERS).collect();
+    let nation_keys: Vec<i64> = keys
+        .iter()
+        .map(|_| rng.range(0, NATIONS.len() as i64 - 1))
+        .collect();
+    let customer = RecordBatch::try_from_iter(vec![
+        ("c_custkey", ints(keys.clone())),
+        (
+            "c_name",
+            strings(
+                keys.iter()
+                    .map(|key| format!("Customer#{:09}", key))
+                    .collect(),
+            ),
+        ),
+        (
+            "c_address",
+            strings(
+                keys.iter()
+                    .map(|key| format!("{} Demo Street", key * 7))
+                    .collect(),
+            ),
+        ),
+        ("c_nationkey", ints(nation_keys.clone())),
+        (
+            "c_phone",
+            strings(
+                nation_keys
+                    .iter()
+                    .map(|nation| {
+                        format!(
+                            "{}-{:03}-{:03}-{:04}",
+                            nation + 10,
+                            rng.range(100, 999),
+                            rng.range(100, 999),
+                            rng.range(1000, 9999)
+                        )
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "c_acctbal",
+            floats(keys.iter().map(|_| rng.money(-999.99, 9999.99)).collect()),
+        ),
+        (
+            "c_mktsegment",
+            strings(
+                keys.iter()
+                    .map(|_| rng.pick(SEGMENTS).to_string())
+                    .collect(),
+            ),
+        ),
+        (
+            "c_comment",
+            strings(
+                keys.iter()
+                    .map(|key| format!("demo customer {}", key))
+                    .collect(),
+            ),
+        ),
+    ])?;
+
+    let keys: Vec<i64> = (1..=DEMO_SUPPLIERS).collect();
+    let supplier = RecordBatch::try_from_iter(vec![
+        ("s_suppkey", ints(keys.clone())),
+        (
+            "s_name",
+            strings(
+                keys.iter()
+                    .map(|key| format!("Supplier#{:09}", key))
+                    .collect(),
+            ),
+        ),
+        (
+            "s_address",
+            strings(
+                keys.iter()
+                    .map(|key| format!("{} Supply Road", key * 11))
+                    .collect(),
+            ),
+        ),
+        (
+            "s_nationkey",
+            ints(
+                keys.iter()
+                    .map(|_| rng.range(0, NATIONS.len() as i64 - 1))
+                    .collect(),
+            ),
+        ),
+        (
+            "s_phone",
+            strings(
+                keys.iter()
+                    .map(|key| format!("20-{:03}-555-{:04}", key, key * 13))
+                    .collect(),
+            ),
+        ),
+        (
+            "s_acctbal",
+            floats(keys.iter().map(|_| rng.money(-999.99, 9999.99)).collect()),
+        ),
+        (
+            "s_comment",
+            strings(
+                keys.iter()
+                    .map(|key| format!("demo supplier {}", key))
+                    .collect(),
+            ),
+        ),
+    ])?;
+
+    let keys: Vec<i64> = (1..=DEMO_PARTS).collect();
+    // TPCH retail price formula, so line item prices follow from the part
+    let prices: Vec<f64> = keys
+        .iter()
+        .map(|key| (90000 + (key / 10) % 20001 + 100 * (key % 1000)) as f64 / 100.0)
+        .collect();
+    let part = RecordBatch::try_from_iter(vec![
+        ("p_partkey", ints(keys.clone())),
+        (
+            "p_name",
+            strings(
+                keys.iter()
+                    .map(|_| format!("{} {}", rng.pick(COLORS), rng.pick(COLORS)))
+                    .collect(),
+            ),
+        ),
+        (
+            "p_mfgr",
+            strings(
+                keys.iter()
+                    .map(|key| format!("Manufacturer#{}", key % 5 + 1))
+                    .collect(),
+            ),
+        ),
+        (
+            "p_brand",
+            strings(
+                keys.iter()
+                    .map(|key| format!("Brand#{}{}", key % 5 + 1, key % 4 + 1))
+                    .collect(),
+            ),
+        ),
+        (
+            "p_type",
+            strings(
+                keys.iter()
+                    .map(|_| rng.pick(PART_TYPES).to_string())
+                    .collect(),
+            ),
+        ),
+        (
+            "p_size",
+            Arc::new(Int32Array::from_iter_values(
+                keys.iter().map(|_| rng.range(1, 50) as i32),
+            )) as ArrayRef,
+        ),
+        (
+            "p_container",
+            strings(
+                keys.iter()
+                    .map(|_| rng.pick(CONTAINERS).to_string())
+                    .collect(),
+            ),
+        ),
+        ("p_retailprice", floats(prices.clone())),
+        (
+            "p_comment",
+            strings(
+                keys.iter()
+                    .map(|key| format!("demo part {}", key))
+                    .collect(),
+            ),
+        ),
+    ])?;
+
+    // Two suppliers per part
+    let mut partsupp = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for key in 1..=DEMO_PARTS {
+        for offset in 0..2 {
+            partsupp.0.push(key);
+            partsupp
+                .1
+                .push((key + offset * DEMO_SUPPLIERS / 2) % DEMO_SUPPLIERS + 1);
+            partsupp.2.push(rng.range(1, 9999) as i32);
+            partsupp.3.push(rng.money(1.0, 1000.0));
+        }
+    }
+    let partsupp_comments = partsupp
+        .0
+        .iter()
+        .map(|key| format!("demo supply of part {}", key))
+        .collect();
+    let partsupp = RecordBatch::try_from_iter(vec![
+        ("ps_partkey", ints(partsupp.0)),
+        ("ps_suppkey", ints(partsupp.1)),
+        (
+            "ps_availqty",
+            Arc::new(Int32Array::from(partsupp.2)) as ArrayRef,
+        ),
+        ("ps_supplycost", floats(partsupp.3)),
+        ("ps_comment", strings(partsupp_comments)),
+    ])?;
+
+    let (orders, lineitem) = orders_and_line_items(&mut rng, &prices)?;
+
+    Ok(vec![
+        ("customer", customer),
+        ("orders", orders),
+        ("lineitem", lineitem),
+        ("part", part),
+        ("supplier", supplier),
+        ("nation", nation),
+        ("region", region),
+        ("partsupp", partsupp),
+    ])
+}
+
+/// Orders with one to seven line items each; the order total and status follow
+/// from the line items, as in TPCH
+fn orders_and_line_items(
+    rng: &mut Lcg,
+    prices: &[f64],
+) -> Result<(RecordBatch, RecordBatch), ArrowError> {
+    // Line items shipped after this day are still open
+    const CURRENT_DAY: i32 = 9298;
+
+    let mut orders = OrderColumns::default();
+    let mut lines = LineItemColumns::default();
+    for orderkey in 1..=DEMO_CUSTOMERS * DEMO_ORDERS_PER_CUSTOMER {
+        let orderdate = rng.range(FIRST_ORDER_DAY as i64, LAST_ORDER_DAY as i64 - 151) as i32;
+        let mut total = 0.0;
+        let mut open = 0;
+        let line_count = rng.range(1, 7);
+        for linenumber in 1..=line_count {
+            let partkey = rng.range(1, DEMO_PARTS);
+            let quantity = rng.range(1, 50) as f64;
+            let extendedprice = (quantity * prices[partkey as usize - 1] * 100.0).round() / 100.0;
+            let discount = rng.range(0, 10) as f64 / 100.0;
+            let tax = rng.range(0, 8) as f64 / 100.0;
+            let shipdate = orderdate + rng.range(1, 121) as i32;
+            let (returnflag, linestatus) = if shipdate > CURRENT_DAY {
+                open += 1;
+                ("N", "O")
+            } else {
+                (if rng.range(0, 1) == 0 { "R" } else { "A" }, "F")
+            };
+            total += extendedprice * (1.0 - discount) * (1.0 + tax);
+
+            lines.orderkey.push(orderkey);
+            lines.partkey.push(partkey);
+            lines
+                .suppkey
+                .push((partkey + (linenumber % 2) * DEMO_SUPPLIERS / 2) % DEMO_SUPPLIERS + 1);
+            lines.linenumber.push(linenumber as i32);
+            lines.quantity.push(quantity);
+            lines.extendedprice.push(extendedprice);
+            lines.discount.push(discount);
+            lines.tax.push(tax);
+            lines.returnflag.push(returnflag.to_string());
+            lines.linestatus.push(linestatus.to_string());
+            lines.shipdate.push(shipdate);
+            lines.commitdate.push(orderdate + rng.range(30, 90) as i32);
+            lines.receiptdate.push(shipdate + rng.range(1, 30) as i32);
+            lines
+                .shipinstruct
+                .push(rng.pick(SHIP_INSTRUCTIONS).to_string());
+            lines.shipmode.push(rng.pick(SHIP_MODES).to_string());
+            lines
+                .comment
+                .push(format!("demo line {} of order {}", linenumber, orderkey));
+        }
+
+        let status = match open {
+            0 => "F",
+            open if open == line_count => "O",
+            _ => "P",
+        };
+        orders.orderkey.push(orderkey);
+        orders.custkey.push(rng.range(1, DEMO_CUSTOMERS));
+        orders.orderstatus.push(status.to_string());
+        orders.totalprice.push((total * 100.0).round() / 100.0);
+        orders.orderdate.push(orderdate);
+        orders.orderpriority.push(rng.pick(PRIORITIES).to_string());
+        orders.clerk.push(format!("Clerk#{:09}", rng.range(1, 20)));
+        orders.shippriority.push(0);
+        orders.comment.push(format!("demo order {}", orderkey));
+    }
+    Ok((orders.into_batch()?, lines.into_batch()?))
+}
+
+#[derive(Default)]
+struct OrderColumns {
+    orderkey: Vec<i64>,
+    custkey: Vec<i64>,
+    orderstatus: Vec<String>,
+    totalprice: Vec<f64>,
+    orderdate: Vec<i32>,
+    orderpriority: Vec<String>,
+    clerk: Vec<String>,
+    shippriority: Vec<i32>,
+    comment: Vec<String>,
+}
+
+impl OrderColumns {
+    fn into_batch(self) -> Result<RecordBatch, ArrowError> {
+        RecordBatch::try_from_iter(vec![
+            ("o_orderkey", ints(self.orderkey)),
+            ("o_custkey", ints(self.custkey)),
+            ("o_orderstatus", strings(self.orderstatus)),
+            ("o_totalprice", floats(self.totalprice)),
+            ("o_orderdate", dates(self.orderdate)),
+            ("o_orderpriority", strings(self.orderpriority)),
+            ("o_clerk", strings(self.clerk)),
+            (
+                "o_shippriority",
+                Arc::new(Int32Array::from(self.shippriority)) as ArrayRef,
+            ),
+            ("o_comment", strings(self.comment)),
+        ])
+    }
+}
+
+#[derive(Default)]
+struct LineItemColumns {
+    orderkey: Vec<i64>,
+    partkey: Vec<i64>,
+    suppkey: Vec<i64>,
+    linenumber: Vec<i32>,
+    quantity: Vec<f64>,
+    extendedprice: Vec<f64>,
+    discount: Vec<f64>,
+    tax: Vec<f64>,
+    returnflag: Vec<String>,
+    linestatus: Vec<String>,
+    shipdate: Vec<i32>,
+    commitdate: Vec<i32>,
+    receiptdate: Vec<i32>,
+    shipinstruct: Vec<String>,
+    shipmode: Vec<String>,
+    comment: Vec<String>,
+}
+
+impl LineItemColumns {
+    fn into_batch(self) -> Result<RecordBatch, ArrowError> {
+        RecordBatch::try_from_iter(vec![
+            ("l_orderkey", ints(self.orderkey)),
+            ("l_partkey", ints(self.partkey)),
+            ("l_suppkey", ints(self.suppkey)),
+            (
+                "l_linenumber",
+                Arc::new(Int32Array::from(self.linenumber)) as ArrayRef,
+            ),
+            ("l_quantity", floats(self.quantity)),
+            ("l_extendedprice", floats(self.extendedprice)),
+            ("l_discount", floats(self.discount)),
+            ("l_tax", floats(self.tax)),
+            ("l_returnflag", strings(self.returnflag)),
+            ("l_linestatus", strings(self.linestatus)),
+            ("l_shipdate", dates(self.shipdate)),
+            ("l_commitdate", dates(self.commitdate)),
+            ("l_receiptdate", dates(self.receiptdate)),
+            ("l_shipinstruct", strings(self.shipinstruct)),
+            ("l_shipmode", strings(self.shipmode)),
+            ("l_comment", strings(self.comment)),
+        ])
+    }
+}
+
+/// Generate the demo dataset and register it as in-memory tables
+pub fn register_demo_tables(df_ctx: &DataFusionContext) -> Result<(), DataFusionError> {
+    for (name, batch) in demo_tables()? {
+        df_ctx.register_batches(name, vec![batch])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! In-memory lookups for the small TPCH dimension tables
+//!
+//! `nation` (25 rows) and `region` (5 rows) are joined into most analytics queries
+//! only to turn keys into display names. The cache answers those lookups in process.
+
+use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use std::collections::HashMap;
+
+/// Nation row kept in the dimension cache
+#[derive(Debug, Clone)]
+pub struct NationInfo {
+    pub name: String,
+    pub region_key: i64,
+}
+
+/// Typed lookups over the nation and region tables
+#[derive(Debug, Default)]
+pub struct DimensionCache {
+    nations: HashMap<i64, NationInfo>,
+    regions: HashMap<i64, String>,
+}
+
+impl DimensionCache {
+    /// Build the cache from the full contents of the nation and region tables
+    pub fn from_batches(
+        nation: &[RecordBatch],
+        region: &[RecordBatch],
+    ) -> Result<Self, DataFusionError> {
+        let mut nations = HashMap::new();
+        for batch in nation {
+            let keys = int64_column(batch, "n_nationkey")?;
+            let names = string_column(batch, "n_name")?;
+            let region_keys = int64_column(batch, "n_regionkey")?;
+            let keys = keys.as_any().downcast_ref::<Int64Array>().unwrap();
+            let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+            let region_keys = region_keys.as_any().downcast_ref::<Int64Array>().unwrap();
+            for i in 0..batch.num_rows() {
+                nations.insert(
+                    keys.value(i),
+                    NationInfo {
+                        name: names.value(i).to_string(),
+                        region_key: region_keys.value(i),
+                    },
+                );
+            }
+        }
+
+        let mut regions = HashMap::new();
+        for batch in region {
+            let keys = int64_column(batch, "r_regionkey")?;
+            let names = string_column(batch, "r_name")?;
+            let keys = keys.as_any().downcast_ref::<Int64Array>().unwrap();
+            let names = names.as_any().downcast_ref::<StringArray>().unwrap();
+            for i in 0..batch.num_rows() {
+                regions.insert(keys.value(i), names.value(i).to_string());
+            }
+        }
+
+        Ok(Self { nations, regions })
+    }
+
+    /// Name of a nation
+    pub fn nation_name(&self, nationkey: i64) -> Option<&str> {
+        self.nations.get(&nationkey).map(|n| n.name.as_str())
+    }
+
+    /// Name of the region a nation belongs to
+    pub fn region_of_nation(&self, nationkey: i64) -> Option<&str> {
+        let nation = self.nations.get(&nationkey)?;
+        self.regions.get(&nation.region_key).map(String::as_str)
+    }
+
+    /// Name of a region
+    pub fn region_name(&self, regionkey: i64) -> Option<&str> {
+        self.regions.get(&regionkey).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nations.is_empty() && self.regions.is_empty()
+    }
+}
+
+fn column(
+    batch: &RecordBatch,
+    name: &str,
+    data_type: &DataType,
+) -> Result<ArrayRef, DataFusionError> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| DataFusionError::Plan(format!("Missing column {}", name)))?;
+    Ok(cast(column, data_type)?)
+}
+
+fn int64_column(batch: &RecordBatch, name: &str) -> Result<ArrayRef, DataFusionError> {
+    column(batch, name, &DataType::Int64)
+}
+
+fn string_column(batch: &RecordBatch, name: &str) -> Result<ArrayRef, DataFusionError> {
+    column(batch, name, &DataType::Utf8)
+}
@@ -0,0 +1,201 @@
+//! Physical metadata of the parquet files behind a table
+//!
+//! Gathered from the parquet footers through the context's object store registry,
+//! so local paths and object store URLs are handled alike.
+
+use chrono::{DateTime, Utc};
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::error::DataFusionError;
+use datafusion::parquet::basic::Compression;
+use datafusion::parquet::file::metadata::ParquetMetaDataReader;
+use datafusion::prelude::SessionContext;
+use futures::TryStreamExt;
+use object_store::{ObjectMeta, ObjectStore};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Trailer of a parquet file: metadata length (4 bytes) and the `PAR1` magic
+const FOOTER_LEN: u64 = 8;
+
+#[derive(Debug, Clone)]
+pub struct ParquetFileInfo {
+    pub path: String,
+    pub size_bytes: u64,
+    pub row_groups: usize,
+    pub rows: i64,
+    /// Codecs used by the column chunks, e.g. `SNAPPY`
+    pub compression: Vec<String>,
+    /// Writer that produced the file
+    pub created_by: Option<String>,
+    pub last_modified: DateTime<Utc>,
+}
+
+/// Range of a hive-style partition column (`key=value` path segments)
+#[derive(Debug, Clone)]
+pub struct PartitionRange {
+    pub column: String,
+    pub min: String,
+    pub max: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TableFileStats {
+    pub files: Vec<ParquetFileInfo>,
+    pub partitions: Vec<PartitionRange>,
+}
+
+impl TableFileStats {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.size_bytes).sum()
+    }
+
+    pub fn row_groups(&self) -> usize {
+        self.files.iter().map(|file| file.row_groups).sum()
+    }
+
+    pub fn rows(&self) -> i64 {
+        self.files.iter().map(|file| file.rows).sum()
+    }
+
+    /// Codecs used across all files
+    pub fn compression(&self) -> Vec<String> {
+        let mut codecs: Vec<String> = self
+            .files
+            .iter()
+            .flat_map(|file| file.compression.iter().cloned())
+            .collect();
+        codecs.sort();
+        codecs.dedup();
+        codecs
+    }
+}
+
+/// List the parquet files at `path`, a file or a directory, without reading them
+pub async fn list_files(
+    ctx: &SessionContext,
+    path: &str,
+) -> Result<Vec<ObjectMeta>, DataFusionError> {
+    let url = ListingTableUrl::parse(path)?;
+    let store = ctx.runtime_env().object_store(url.object_store())?;
+    let state = ctx.state();
+    url.list_all_files(&state, store.as_ref(), ".parquet")
+        .await?
+        .try_collect()
+        .await
+}
+
+/// Read the footers of the parquet files at `path`, a file or a directory
+pub async fn gather(ctx: &SessionContext, path: &str) -> Result<TableFileStats, DataFusionError> {
+    let url = ListingTableUrl::parse(path)?;
+    let store = ctx.runtime_env().object_store(url.object_store())?;
+    let objects = list_files(ctx, path).await?;
+
+    let mut files = Vec::with_capacity(objects.len());
+    for object in &objects {
+        files.push(read_footer(store.as_ref(), object).await?);
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(TableFileStats {
+        partitions: partition_ranges(&objects),
+        files,
+    })
+}
+
+async fn read_footer(
+    store: &dyn ObjectStore,
+    object: &ObjectMeta,
+) -> Result<ParquetFileInfo, DataFusionError> {
+    let invalid =
+        || DataFusionError::Execution(format!("{} is not a parquet file", object.location));
+    if object.size < FOOTER_LEN {
+        return Err(invalid());
+    }
+    let trailer = store
+        .get_range(&object.location, object.size - FOOTER_LEN..object.size)
+        .await?;
+    if &trailer[4..] != b"PAR1" {
+        return Err(invalid());
+    }
+    let metadata_len = u32::from_le_bytes(trailer[..4].try_into().unwrap()) as u64;
+    if metadata_len + FOOTER_LEN > object.size {
+        return Err(invalid());
+    }
+    let start = object.size - FOOTER_LEN - metadata_len;
+    let bytes = store
+        .get_range(&object.location, start..object.size - FOOTER_LEN)
+        .await?;
+    let metadata = ParquetMetaDataReader::decode_metadata(&bytes)?;
+
+    let mut compression: Vec<String> = metadata
+        .row_groups()
+        .iter()
+        .flat_map(|row_group| row_group.columns().iter())
+        .map(|column| codec_name(column.compression()).to_string())
+        .collect();
+    compression.sort();
+    compression.dedup();
+
+    let file_metadata = metadata.file_metadata();
+    Ok(ParquetFileInfo {
+        path: object.location.to_string(),
+        size_bytes: object.size,
+        row_groups: metadata.num_row_groups(),
+        rows: file_metadata.num_rows(),
+        compression,
+        created_by: file_metadata.created_by().map(str::to_string),
+        last_modified: object.last_modified,
+    })
+}
+
+fn codec_name(compression: Compression) -> &'static str {
+    match compression {
+        Compression::UNCOMPRESSED => "UNCOMPRESSED",
+        Compression::SNAPPY => "SNAPPY",
+        Compression::GZIP(_) => "GZIP",
+        Compression::LZO => "LZO",
+        Compression::BROTLI(_) => "BROTLI",
+        Compression::LZ4 => "LZ4",
+        Compression::ZSTD(_) => "ZSTD",
+        Compression::LZ4_RAW => "LZ4_RAW",
+    }
+}
+
+/// Min and max of each `key=value` path segment across the files
+fn partition_ranges(objects: &[ObjectMeta]) -> Vec<PartitionRange> {
+    let mut ranges: BTreeMap<String, (String, String)> = BTreeMap::new();
+    for object in objects {
+        for segment in object.location.parts() {
+            let Some((key, value)) = segment.as_ref().split_once('=') else {
+                continue;
+            };
+            ranges
+                .entry(key.to_string())
+                .and_modify(|(min, max)| {
+                    if partition_order(value, min) == Ordering::Less {
+                        *min = value.to_string();
+                    }
+                    if partition_order(value, max) == Ordering::Greater {
+                        *max = value.to_string();
+                    }
+                })
+                .or_insert_with(|| (value.to_string(), value.to_string()));
+        }
+    }
+    ranges
+        .into_iter()
+        .map(|(column, (min, max))| PartitionRange { column, min, max })
+        .collect()
+}
+
+/// Order of two partition values, numeric when both are numbers so `9` comes
+/// before `10`, otherwise by their text
+fn partition_order(a: &str, b: &str) -> Ordering {
+    if let (Ok(a), Ok(b)) = (a.parse::<i64>(), b.parse::<i64>()) {
+        return a.cmp(&b);
+    }
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or_else(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
@@ -0,0 +1,160 @@
+//! Column-level lineage of SQL statements
+//!
+//! Governance wants to know which table columns feed each column of a result.
+//! `column_lineage` reads this from the logical plan as planned from the SQL,
+//! before the optimizer rewrites it. An alias keeps the sources of the aliased
+//! expression, while expressions and aggregates combine the sources of all their
+//! arguments, scalar subqueries included. Columns that only filter, join or sort
+//! the rows are not sources, and `COUNT(*)` has none.
+
+use crate::models::data::{ColumnLineage, SourceColumn};
+use datafusion::common::Column;
+use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::utils::grouping_set_to_exprlist;
+use datafusion::logical_expr::{Expr, LogicalPlan};
+use std::collections::BTreeSet;
+
+/// Source columns of one column of a plan
+type Sources = BTreeSet<SourceColumn>;
+
+/// Source columns of each output column of a plan, in output order
+pub fn column_lineage(plan: &LogicalPlan) -> Result<Vec<ColumnLineage>, DataFusionError> {
+    let sources = plan_sources(plan)?;
+    Ok(plan
+        .schema()
+        .fields()
+        .iter()
+        .zip(sources)
+        .map(|(field, sources)| ColumnLineage {
+            output: field.name().clone(),
+            sources: sources.into_iter().collect(),
+        })
+        .collect())
+}
+
+fn plan_sources(plan: &LogicalPlan) -> Result<Vec<Sources>, DataFusionError> {
+    let inputs = plan.inputs();
+    let input_sources = inputs
+        .iter()
+        .map(|input| plan_sources(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    let width = plan.schema().fields().len();
+
+    let sources = match plan {
+        LogicalPlan::TableScan(scan) => {
+            let table = scan.table_name.table().to_string();
+            scan.projected_schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    Sources::from([SourceColumn {
+                        table: table.clone(),
+                        column: field.name().clone(),
+                    }])
+                })
+                .collect()
+        }
+        LogicalPlan::Projection(projection) => {
+            expr_sources(&projection.expr, &projection.input, &input_sources[0])?
+        }
+        LogicalPlan::Aggregate(aggregate) => {
+            let group_exprs = grouping_set_to_exprlist(&aggregate.group_expr)?;
+            let mut sources = expr_sources(group_exprs, &aggregate.input, &input_sources[0])?;
+            // Grouping sets add the grouping id after the group columns
+            if sources.len() + aggregate.aggr_expr.len() < width {
+                sources.push(Sources::new());
+            }
+            sources.extend(expr_sources(
+                &aggregate.aggr_expr,
+                &aggregate.input,
+                &input_sources[0],
+            )?);
+            sources
+        }
+        LogicalPlan::Window(window) => {
+            let mut sources = input_sources[0].clone();
+            sources.extend(expr_sources(
+                &window.window_expr,
+                &window.input,
+                &input_sources[0],
+            )?);
+            sources
+        }
+        // Same columns as the inputs, matched by position
+        LogicalPlan::SubqueryAlias(_) | LogicalPlan::Union(_) => (0..width)
+            .map(|i| {
+                input_sources
+                    .iter()
+                    .filter_map(|sources| sources.get(i))
+                    .flatten()
+                    .cloned()
+                    .collect()
+            })
+            .collect(),
+        _ => passed_through(plan, &inputs, &input_sources),
+    };
+    // Plans whose expressions do not line up with their columns, such as an
+    // unexpanded wildcard, fall back to matching columns by name
+    if sources.len() != width {
+        return Ok(passed_through(plan, &inputs, &input_sources));
+    }
+    Ok(sources)
+}
+
+/// Sources of the columns a plan passes through from its inputs, as filters,
+/// sorts and joins do; columns found in no input have none
+fn passed_through(
+    plan: &LogicalPlan,
+    inputs: &[&LogicalPlan],
+    input_sources: &[Vec<Sources>],
+) -> Vec<Sources> {
+    plan.schema()
+        .iter()
+        .map(|(qualifier, field)| {
+            let column = Column::new(qualifier.cloned(), field.name());
+            inputs
+                .iter()
+                .zip(input_sources)
+                .find_map(|(input, sources)| {
+                    input
+                        .schema()
+                        .maybe_index_of_column(&column)
+                        .map(|i| sources[i].clone())
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Sources of each expression, the union of the sources of the input columns and
+/// scalar subqueries it refers to
+fn expr_sources<'a>(
+    exprs: impl IntoIterator<Item = &'a Expr>,
+    input: &LogicalPlan,
+    input_sources: &[Sources],
+) -> Result<Vec<Sources>, DataFusionError> {
+    exprs
+        .into_iter()
+        .map(|expr| {
+            let mut sources = Sources::new();
+            expr.apply(|expr| {
+                match expr {
+                    Expr::Column(column) => {
+                        if let Some(i) = input.schema().maybe_index_of_column(column) {
+                            sources.extend(input_sources[i].iter().cloned());
+                        }
+                    }
+                    Expr::ScalarSubquery(subquery) => {
+                        for subquery_sources in plan_sources(&subquery.subquery)? {
+                            sources.extend(subquery_sources);
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(TreeNodeRecursion::Continue)
+            })?;
+            Ok(sources)
+        })
+        .collect()
+}
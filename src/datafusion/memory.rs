@@ -0,0 +1,94 @@
+//! Per-query memory accounting
+//!
+//! Each query gets its own memory pool so one large sort or join cannot use up
+//! the budget of the others. Spilling is disabled for these queries: going over
+//! the limit fails the query with `MemoryLimitExceeded`.
+
+use datafusion::error::DataFusionError;
+use datafusion::execution::memory_pool::{
+    GreedyMemoryPool, MemoryConsumer, MemoryPool, MemoryReservation,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Error returned by `execute_query` when a query needs more memory than its limit
+#[derive(Debug, thiserror::Error)]
+#[error("Query exceeded memory limit: needed {peak_bytes} bytes, limit is {limit_bytes} bytes")]
+pub struct MemoryLimitExceeded {
+    pub limit_bytes: usize,
+    /// Highest reservation of the query, including the request that failed
+    pub peak_bytes: usize,
+}
+
+/// Memory pool of a single query, recording its peak usage
+#[derive(Debug)]
+pub struct QueryMemoryPool {
+    inner: GreedyMemoryPool,
+    limit: usize,
+    peak: AtomicUsize,
+}
+
+impl QueryMemoryPool {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: GreedyMemoryPool::new(limit),
+            limit,
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    fn record_peak(&self, bytes: usize) {
+        self.peak.fetch_max(bytes, Ordering::Relaxed);
+    }
+}
+
+impl MemoryPool for QueryMemoryPool {
+    fn register(&self, consumer: &MemoryConsumer) {
+        self.inner.register(consumer)
+    }
+
+    fn unregister(&self, consumer: &MemoryConsumer) {
+        self.inner.unregister(consumer)
+    }
+
+    fn grow(&self, reservation: &MemoryReservation, additional: usize) {
+        self.inner.grow(reservation, additional);
+        self.record_peak(self.inner.reserved());
+    }
+
+    fn shrink(&self, reservation: &MemoryReservation, shrink: usize) {
+        self.inner.shrink(reservation, shrink)
+    }
+
+    fn try_grow(
+        &self,
+        reservation: &MemoryReservation,
+        additional: usize,
+    ) -> Result<(), DataFusionError> {
+        let result = self.inner.try_grow(reservation, additional);
+        match &result {
+            Ok(()) => self.record_peak(self.inner.reserved()),
+            Err(_) => self.record_peak(self.inner.reserved() + additional),
+        }
+        result
+    }
+
+    fn reserved(&self) -> usize {
+        self.inner.reserved()
+    }
+}
+
+/// The memory limit error of a failed query, if it ran out of memory
+pub fn memory_limit_exceeded(err: &DataFusionError) -> Option<&MemoryLimitExceeded> {
+    match err.find_root() {
+        DataFusionError::External(e) => e.downcast_ref::<MemoryLimitExceeded>(),
+        _ => None,
+    }
+}
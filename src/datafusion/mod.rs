@@ -1 +1,15 @@
+pub mod analysis_filters;
+pub mod arrow_convert;
+pub mod column_accessor;
+pub mod compare;
 pub mod context;
+#[cfg(feature = "demo")]
+pub mod demo;
+pub mod dimensions;
+pub mod file_metadata;
+pub mod lineage;
+pub mod memory;
+pub mod query_log;
+pub mod revisions;
+pub mod rollup;
+pub mod upload;
@@ -0,0 +1,126 @@
+//! Bounded log of recently executed SQL statements
+
+use crate::models::data::ColumnLineage;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A single executed statement
+#[derive(Debug, Clone)]
+pub struct QueryLogEntry {
+    /// Id assigned when the query started, usable with `cancel_query` while it runs
+    pub id: String,
+    pub sql: String,
+    pub duration: Duration,
+    /// Rows returned, zero for failed queries
+    pub rows: usize,
+    pub success: bool,
+    pub timestamp: DateTime<Utc>,
+    /// Source columns of each output column, empty for failed queries
+    pub lineage: Vec<ColumnLineage>,
+}
+
+/// Ring buffer keeping the most recent statements, oldest dropped first
+#[derive(Debug)]
+pub struct QueryLog {
+    entries: Mutex<VecDeque<QueryLogEntry>>,
+    capacity: usize,
+}
+
+impl QueryLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    pub fn record(&self, entry: QueryLogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Logged statements, oldest first
+    pub fn entries(&self) -> Vec<QueryLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl Default for QueryLog {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+/// The slowest statements above a duration threshold, slowest first
+#[derive(Debug)]
+pub struct SlowQueryLog {
+    entries: Mutex<Vec<QueryLogEntry>>,
+    threshold: Duration,
+    capacity: usize,
+}
+
+impl SlowQueryLog {
+    /// Longest SQL text kept per entry, in characters
+    pub const MAX_SQL_LEN: usize = 500;
+
+    pub fn new(threshold: Duration, capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(Vec::with_capacity(capacity)),
+            threshold,
+            capacity,
+        }
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Keep the entry if it is slower than the threshold and among the slowest seen.
+    /// Returns whether the entry counted as slow.
+    pub fn record(&self, entry: &QueryLogEntry) -> bool {
+        if entry.duration < self.threshold {
+            return false;
+        }
+        if self.capacity == 0 {
+            return true;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity
+            && entries
+                .last()
+                .is_some_and(|fastest| fastest.duration >= entry.duration)
+        {
+            return true;
+        }
+
+        let mut entry = entry.clone();
+        if let Some((end, _)) = entry.sql.char_indices().nth(Self::MAX_SQL_LEN) {
+            entry.sql.truncate(end);
+            entry.sql.push_str("...");
+        }
+        let position = entries.partition_point(|kept| kept.duration >= entry.duration);
+        entries.insert(position, entry);
+        entries.truncate(self.capacity);
+        true
+    }
+
+    /// Kept statements, slowest first
+    pub fn entries(&self) -> Vec<QueryLogEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+impl Default for SlowQueryLog {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), 20)
+    }
+}
@@ -0,0 +1,166 @@
+//! Earlier revisions of the registered tables
+//!
+//! Every successful `reload_table` starts a new revision of the context. The
+//! tables of the revisions it replaced are kept, up to a configured number, so
+//! a dashboard can run the same query before and after an ETL run. A revision
+//! holds the table providers of its tables; the table whose reload replaced it
+//! is read into memory first, as its files are about to change. Snapshots are
+//! reference counted, so the memory of an evicted revision is reclaimed once the
+//! last query reading it finishes. Keeping revisions costs a copy of every
+//! reloaded table, so none are kept by default.
+
+use chrono::{DateTime, Utc};
+use datafusion::datasource::TableProvider;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/// Revisions before the current one kept by default
+pub const DEFAULT_KEPT_REVISIONS: usize = 0;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RevisionError {
+    #[error("Revision {revision} does not exist; the current revision is {current}")]
+    Unknown { revision: u64, current: u64 },
+    #[error("Revision {revision} is no longer kept; the oldest revision available is {oldest}")]
+    Evicted { revision: u64, oldest: u64 },
+}
+
+/// The tables of a revision the context has moved on from
+pub struct Snapshot {
+    pub revision: u64,
+    /// When the revision became current
+    pub created_at: DateTime<Utc>,
+    /// When a reload replaced it
+    pub superseded_at: DateTime<Utc>,
+    /// Table whose reload replaced it
+    pub superseded_by: String,
+    tables: HashMap<String, Arc<dyn TableProvider>>,
+}
+
+impl Snapshot {
+    /// Tables and views of the revision by name
+    pub fn tables(&self) -> &HashMap<String, Arc<dyn TableProvider>> {
+        &self.tables
+    }
+}
+
+/// A revision as listed by `RevisionHistory::revisions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionInfo {
+    pub revision: u64,
+    pub created_at: DateTime<Utc>,
+    /// `None` for the current revision
+    pub superseded_at: Option<DateTime<Utc>>,
+    pub superseded_by: Option<String>,
+}
+
+struct History {
+    current: u64,
+    current_since: DateTime<Utc>,
+    /// Oldest first
+    kept: VecDeque<Arc<Snapshot>>,
+}
+
+/// The current revision number and the snapshots of the revisions before it
+pub struct RevisionHistory {
+    history: Mutex<History>,
+    capacity: usize,
+}
+
+impl RevisionHistory {
+    /// History keeping the `capacity` revisions before the current one
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            history: Mutex::new(History {
+                current: 1,
+                current_since: Utc::now(),
+                kept: VecDeque::with_capacity(capacity),
+            }),
+            capacity,
+        }
+    }
+
+    /// Revisions kept before the current one
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn current(&self) -> u64 {
+        self.history.lock().unwrap().current
+    }
+
+    /// Start a new revision after `table` was reloaded, keeping the tables of the
+    /// revision it replaces. Returns the new revision.
+    pub fn advance(&self, table: &str, tables: HashMap<String, Arc<dyn TableProvider>>) -> u64 {
+        let mut history = self.history.lock().unwrap();
+        let now = Utc::now();
+        if self.capacity > 0 {
+            if history.kept.len() == self.capacity {
+                history.kept.pop_front();
+            }
+            let snapshot = Snapshot {
+                revision: history.current,
+                created_at: history.current_since,
+                superseded_at: now,
+                superseded_by: table.to_string(),
+                tables,
+            };
+            history.kept.push_back(Arc::new(snapshot));
+        }
+        history.current += 1;
+        history.current_since = now;
+        history.current
+    }
+
+    /// Snapshot of an earlier revision; `None` for the current one, whose tables
+    /// are the registered ones
+    pub fn snapshot(&self, revision: u64) -> Result<Option<Arc<Snapshot>>, RevisionError> {
+        let history = self.history.lock().unwrap();
+        if revision == history.current {
+            return Ok(None);
+        }
+        if revision > history.current || revision == 0 {
+            return Err(RevisionError::Unknown {
+                revision,
+                current: history.current,
+            });
+        }
+        history
+            .kept
+            .iter()
+            .find(|snapshot| snapshot.revision == revision)
+            .map(|snapshot| Some(snapshot.clone()))
+            .ok_or_else(|| RevisionError::Evicted {
+                revision,
+                oldest: history
+                    .kept
+                    .front()
+                    .map_or(history.current, |snapshot| snapshot.revision),
+            })
+    }
+
+    /// The current revision followed by the kept ones, newest first
+    pub fn revisions(&self) -> Vec<RevisionInfo> {
+        let history = self.history.lock().unwrap();
+        let current = RevisionInfo {
+            revision: history.current,
+            created_at: history.current_since,
+            superseded_at: None,
+            superseded_by: None,
+        };
+        let kept = history.kept.iter().rev().map(|snapshot| RevisionInfo {
+            revision: snapshot.revision,
+            created_at: snapshot.created_at,
+            superseded_at: Some(snapshot.superseded_at),
+            superseded_by: Some(snapshot.superseded_by.clone()),
+        });
+        std::iter::once(current).chain(kept).collect()
+    }
+}
+
+impl Default for RevisionHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_KEPT_REVISIONS)
+    }
+}
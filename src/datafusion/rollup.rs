@@ -0,0 +1,37 @@
+//! Pre-aggregated tables maintained by the server
+//!
+//! `daily_revenue` sums the orders of each order date once, so trend queries
+//! aggregate a few thousand days instead of the whole orders table.
+
+use datafusion::datasource::MemTable;
+use datafusion::error::DataFusionError;
+use datafusion::prelude::SessionContext;
+use futures::TryStreamExt;
+
+/// Daily totals of the orders table: `order_date, total_sales, order_count`
+pub const DAILY_REVENUE: &str = "daily_revenue";
+
+const DAILY_REVENUE_SQL: &str = "SELECT o_orderdate AS order_date,
+        CAST(SUM(o_totalprice) AS DOUBLE) AS total_sales,
+        COUNT(*) AS order_count
+    FROM orders
+    GROUP BY o_orderdate
+    ORDER BY o_orderdate";
+
+/// Aggregate orders per day. Orders stream through the aggregation, only the
+/// daily rows are held in memory.
+pub async fn build_daily_revenue(ctx: &SessionContext) -> Result<MemTable, DataFusionError> {
+    let mut stream = ctx.sql(DAILY_REVENUE_SQL).await?.execute_stream().await?;
+    let schema = stream.schema();
+    let mut batches = Vec::new();
+    while let Some(batch) = stream.try_next().await? {
+        batches.push(batch);
+    }
+    MemTable::try_new(schema, vec![batches])
+}
+
+/// Plan the `daily_revenue` aggregation without running it; fails when orders
+/// is missing or lacks the aggregated columns
+pub async fn check_daily_revenue(ctx: &SessionContext) -> Result<(), DataFusionError> {
+    ctx.sql(DAILY_REVENUE_SQL).await.map(|_| ())
+}
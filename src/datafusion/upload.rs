@@ -0,0 +1,237 @@
+//! Tables uploaded by clients
+//!
+//! Clients can paste a small CSV or JSON payload and query it like any other
+//! table. The payload is parsed into record batches and registered as an
+//! in-memory table; `UploadedTables` caps the payload size as well as the number
+//! and total size of live uploads, refuses to replace tables that were not
+//! uploaded or were uploaded by another user and drops each uploaded table a
+//! fixed time after its upload, when the reaper sweeps it.
+
+use crate::datafusion::context::DataFusionContext;
+use crate::models::data::UploadFormat;
+use crate::reaper::Expirable;
+use chrono::{DateTime, Utc};
+use datafusion::arrow::csv;
+use datafusion::arrow::csv::reader::Format;
+use datafusion::arrow::json;
+use datafusion::arrow::json::reader::infer_json_schema;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Records read to infer the column types of a payload
+const INFER_RECORDS: usize = 1000;
+
+/// Parse a payload into record batches, inferring the column types
+pub fn parse_upload(format: UploadFormat, data: &str) -> Result<Vec<RecordBatch>, DataFusionError> {
+    let batches = match format {
+        UploadFormat::Csv => {
+            let format = Format::default().with_header(true);
+            let (schema, _) =
+                format.infer_schema(Cursor::new(data.as_bytes()), Some(INFER_RECORDS))?;
+            csv::ReaderBuilder::new(Arc::new(schema))
+                .with_format(format)
+                .build(Cursor::new(data.as_bytes()))?
+                .collect::<Result<Vec<_>, _>>()?
+        }
+        UploadFormat::Json => {
+            let lines = json_lines(data)?;
+            let (schema, _) =
+                infer_json_schema(Cursor::new(lines.as_bytes()), Some(INFER_RECORDS))?;
+            json::ReaderBuilder::new(Arc::new(schema))
+                .build(Cursor::new(lines.as_bytes()))?
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+    if batches.iter().all(|batch| batch.num_rows() == 0) {
+        return Err(DataFusionError::Plan(
+            "The uploaded data has no rows".to_string(),
+        ));
+    }
+    Ok(batches)
+}
+
+/// One JSON object per line, as the arrow reader expects, from either a JSON
+/// array of objects or lines of objects
+fn json_lines(data: &str) -> Result<String, DataFusionError> {
+    if !data.trim_start().starts_with('[') {
+        return Ok(data.to_string());
+    }
+    let rows: Vec<serde_json::Value> = serde_json::from_str(data)
+        .map_err(|e| DataFusionError::Plan(format!("Invalid JSON: {}", e)))?;
+    Ok(rows
+        .iter()
+        .map(|row| row.to_string())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// A registered upload
+#[derive(Debug, Clone)]
+pub struct UploadedTable {
+    pub columns: Vec<String>,
+    pub row_count: usize,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A live upload
+struct Upload {
+    /// User who uploaded the table
+    owner: String,
+    uploaded: Instant,
+    /// Size of the payload
+    bytes: usize,
+}
+
+/// Uploaded tables and when they expire
+pub struct UploadedTables {
+    df_ctx: Arc<DataFusionContext>,
+    max_bytes: usize,
+    ttl: Duration,
+    /// Live uploads at most, 0 for no limit
+    max_tables: usize,
+    /// Payload bytes of all live uploads at most, 0 for no limit
+    max_total_bytes: usize,
+    /// Live uploads by table name
+    tables: Mutex<HashMap<String, Upload>>,
+}
+
+impl UploadedTables {
+    pub fn new(df_ctx: Arc<DataFusionContext>, max_bytes: usize, ttl: Duration) -> Self {
+        Self {
+            df_ctx,
+            max_bytes,
+            ttl,
+            max_tables: 0,
+            max_total_bytes: 0,
+            tables: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Cap the number and total payload size of the uploads live at a time
+    pub fn with_totals(mut self, max_tables: usize, max_total_bytes: usize) -> Self {
+        self.max_tables = max_tables;
+        self.max_total_bytes = max_total_bytes;
+        self
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Parse a payload of `owner` and register it as `table_name`, replacing
+    /// their earlier upload of the same name
+    pub fn upload(
+        &self,
+        owner: &str,
+        table_name: &str,
+        format: UploadFormat,
+        data: &str,
+    ) -> Result<UploadedTable, DataFusionError> {
+        if data.len() > self.max_bytes {
+            return Err(DataFusionError::Plan(format!(
+                "Upload of {} bytes exceeds the limit of {} bytes",
+                data.len(),
+                self.max_bytes
+            )));
+        }
+        // Expired uploads go even when the reaper is disabled
+        self.reap_expired(Instant::now());
+
+        let mut tables = self.tables.lock().unwrap();
+        match tables.get(table_name) {
+            Some(upload) if upload.owner != owner => {
+                return Err(DataFusionError::Plan(format!(
+                    "Table {} was uploaded by another user",
+                    table_name
+                )));
+            }
+            Some(_) => {}
+            None if self
+                .df_ctx
+                .get_table_names()
+                .iter()
+                .any(|name| name == table_name) =>
+            {
+                return Err(DataFusionError::Plan(format!(
+                    "Table {} exists and was not uploaded",
+                    table_name
+                )));
+            }
+            None if self.max_tables > 0 && tables.len() >= self.max_tables => {
+                return Err(DataFusionError::ResourcesExhausted(format!(
+                    "{} uploaded tables are live already, the limit",
+                    tables.len()
+                )));
+            }
+            None => {}
+        }
+        // The upload it replaces no longer counts
+        let live_bytes: usize = tables
+            .iter()
+            .filter(|(name, _)| name.as_str() != table_name)
+            .map(|(_, upload)| upload.bytes)
+            .sum();
+        if self.max_total_bytes > 0 && live_bytes + data.len() > self.max_total_bytes {
+            return Err(DataFusionError::ResourcesExhausted(format!(
+                "Uploaded tables would hold {} bytes, over the limit of {} bytes",
+                live_bytes + data.len(),
+                self.max_total_bytes
+            )));
+        }
+
+        let batches = parse_upload(format, data)?;
+        let schema = batches[0].schema();
+        let row_count = batches.iter().map(|batch| batch.num_rows()).sum();
+        self.df_ctx.register_batches(table_name, batches)?;
+        tables.insert(
+            table_name.to_string(),
+            Upload {
+                owner: owner.to_string(),
+                uploaded: Instant::now(),
+                bytes: data.len(),
+            },
+        );
+        info!(
+            "Registered uploaded table {} with {} rows",
+            table_name, row_count
+        );
+
+        Ok(UploadedTable {
+            columns: schema.fields().iter().map(|f| f.name().clone()).collect(),
+            row_count,
+            expires_at: Utc::now() + self.ttl,
+        })
+    }
+}
+
+impl Expirable for UploadedTables {
+    fn registry_name(&self) -> &str {
+        "uploaded_tables"
+    }
+
+    fn reap_expired(&self, now: Instant) -> Vec<String> {
+        let mut tables = self.tables.lock().unwrap();
+        let expired: Vec<String> = tables
+            .iter()
+            .filter(|(_, upload)| now.saturating_duration_since(upload.uploaded) >= self.ttl)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &expired {
+            tables.remove(name);
+            // Gone already when an admin dropped it
+            if let Err(e) = self.df_ctx.drop_table(name) {
+                warn!("Failed to drop expired upload {}: {}", name, e);
+            }
+        }
+        expired
+    }
+
+    fn live_entries(&self) -> usize {
+        self.tables.lock().unwrap().len()
+    }
+}
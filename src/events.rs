@@ -0,0 +1,88 @@
+//! Internal events for operators
+//!
+//! Components publish noteworthy events, such as table reloads, on an
+//! `EventBus`. Subscribers like the webhook `Notifier` receive every event
+//! published after they subscribed; events published without subscribers are
+//! dropped.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before the oldest are dropped
+const EVENT_CAPACITY: usize = 256;
+
+/// Kind of an event, used to route it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A table was re-registered from its source files
+    TableReloaded,
+    /// Re-registering a table from its source files failed
+    ReloadFailed,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EventKind::TableReloaded => "table_reloaded",
+            EventKind::ReloadFailed => "reload_failed",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub table: String,
+    /// Details, e.g. the error of a failed reload; empty when there are none
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, table: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            table: table.into(),
+            message: message.into(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// One line summary for chat messages
+    pub fn text(&self) -> String {
+        match self.kind {
+            EventKind::TableReloaded => format!("Table {} was reloaded", self.table),
+            EventKind::ReloadFailed => {
+                format!("Reloading table {} failed: {}", self.table, self.message)
+            }
+        }
+    }
+}
+
+/// Broadcast channel of events
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl EventBus {
+    pub fn publish(&self, event: Event) {
+        // Without subscribers nobody is interested in the event
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,252 @@
+//! Per-role allow-lists of GraphQL operations
+//!
+//! Roles listed in the rules may only run the operations named in their list, or
+//! operations whose top-level fields are all listed. Operation names are chosen by
+//! the client, so a name only counts together with the document it was listed
+//! for, identified by its SHA-256; otherwise its fields are checked like those of
+//! any other operation. Roles that are not listed are
+//! unrestricted, anonymous callers fall under the viewer role and admins bypass the
+//! lists. When the rules come from a file, the file is re-read whenever it changes,
+//! so lists can be edited without a restart.
+
+use crate::auth::Claims;
+use crate::quota::FALLBACK_ROLE;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextPrepareRequest,
+};
+use async_graphql::parser::types::{ExecutableDocument, OperationDefinition, Selection};
+use async_graphql::{ErrorExtensions, Pos, Request, ServerResult, Variables};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// Allowed operations by role
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowListRules {
+    /// Operation names and top-level fields each listed role may run
+    #[serde(default)]
+    pub roles: BTreeMap<String, Vec<String>>,
+    /// Whether operations without a name may run; admins may always run them
+    #[serde(default = "allow_anonymous_default")]
+    pub allow_anonymous: bool,
+    /// Hex SHA-256 of the document each listed operation name stands for
+    #[serde(default)]
+    pub documents: BTreeMap<String, String>,
+}
+
+fn allow_anonymous_default() -> bool {
+    true
+}
+
+impl Default for AllowListRules {
+    fn default() -> Self {
+        Self {
+            roles: BTreeMap::new(),
+            allow_anonymous: true,
+            documents: BTreeMap::new(),
+        }
+    }
+}
+
+impl AllowListRules {
+    /// Check one operation of a caller with the given role, taken from the
+    /// document with hash `document_hash`
+    pub fn check(
+        &self,
+        role: Option<&str>,
+        operation_name: Option<&str>,
+        operation: &OperationDefinition,
+        document_hash: &str,
+    ) -> Result<(), String> {
+        let role = role.unwrap_or(FALLBACK_ROLE);
+        if role == "admin" {
+            return Ok(());
+        }
+        let Some(name) = operation_name else {
+            if !self.allow_anonymous {
+                return Err("Anonymous operations are not allowed, name the operation".to_string());
+            }
+            return self.check_fields(role, operation);
+        };
+        let pinned = self
+            .documents
+            .get(name)
+            .is_some_and(|hash| hash.eq_ignore_ascii_case(document_hash));
+        match self.roles.get(role) {
+            Some(allowed) if pinned && allowed.iter().any(|allowed| allowed == name) => Ok(()),
+            Some(_) => self
+                .check_fields(role, operation)
+                .map_err(|_| format!("Operation {} is not allowed for the {} role", name, role)),
+            None => Ok(()),
+        }
+    }
+
+    fn check_fields(&self, role: &str, operation: &OperationDefinition) -> Result<(), String> {
+        let Some(allowed) = self.roles.get(role) else {
+            return Ok(());
+        };
+        for selection in &operation.selection_set.node.items {
+            let field = match &selection.node {
+                Selection::Field(field) => field.node.name.node.as_str(),
+                _ => {
+                    return Err(format!(
+                        "Fragments at the top level are not allowed for the {} role",
+                        role
+                    ));
+                }
+            };
+            if !allowed.iter().any(|allowed| allowed == field) {
+                return Err(format!(
+                    "Field {} is not allowed for the {} role",
+                    field, role
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hex SHA-256 of a GraphQL document, as listed in `AllowListRules::documents`
+pub fn document_hash(query: &str) -> String {
+    hex::encode(Sha256::digest(query.as_bytes()))
+}
+
+/// Current rules, re-read from their file when it changes
+#[derive(Debug)]
+pub struct OperationAllowList {
+    rules: RwLock<Arc<AllowListRules>>,
+    file: Option<PathBuf>,
+    loaded_at: Mutex<Option<SystemTime>>,
+}
+
+impl OperationAllowList {
+    pub fn new(rules: AllowListRules) -> Self {
+        Self {
+            rules: RwLock::new(Arc::new(rules)),
+            file: None,
+            loaded_at: Mutex::new(None),
+        }
+    }
+
+    /// Take the rules from a JSON file, replacing the current ones once it has
+    /// been read and again whenever it is modified
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self.refresh();
+        self
+    }
+
+    /// Replace the rules
+    pub fn replace(&self, rules: AllowListRules) {
+        *self.rules.write().unwrap() = Arc::new(rules);
+    }
+
+    /// Rules in effect, after picking up changes to the rules file
+    pub fn rules(&self) -> Arc<AllowListRules> {
+        self.refresh();
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Re-read the rules file when it was modified since it was last read. A file
+    /// that cannot be read or parsed leaves the current rules in place.
+    fn refresh(&self) {
+        let Some(path) = &self.file else {
+            return;
+        };
+        let modified = match std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified,
+            Err(e) => {
+                warn!(
+                    "Failed to read operation allow-list {}: {}",
+                    path.display(),
+                    e
+                );
+                return;
+            }
+        };
+        let mut loaded_at = self.loaded_at.lock().unwrap();
+        if *loaded_at == Some(modified) {
+            return;
+        }
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(rules) => {
+                info!("Loaded operation allow-list from {}", path.display());
+                self.replace(rules);
+            }
+            Err(e) => warn!(
+                "Ignoring invalid operation allow-list {}: {}",
+                path.display(),
+                e
+            ),
+        }
+        *loaded_at = Some(modified);
+    }
+}
+
+/// Extension rejecting operations outside the caller's allow-list once the
+/// document is parsed
+pub struct AllowListExtension;
+
+impl ExtensionFactory for AllowListExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(AllowListExtensionImpl {
+            operation_name: Mutex::new(None),
+        })
+    }
+}
+
+struct AllowListExtensionImpl {
+    /// Operation requested by name
+    operation_name: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for AllowListExtensionImpl {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        *self.operation_name.lock().unwrap() = request.operation_name.clone();
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let Some(allow_list) = ctx.data_opt::<Arc<OperationAllowList>>() else {
+            return Ok(document);
+        };
+        let rules = allow_list.rules();
+        let role = ctx.data_opt::<Claims>().map(|claims| claims.role.as_str());
+        let requested = self.operation_name.lock().unwrap().clone();
+        let hash = document_hash(query);
+
+        for (name, operation) in document.operations.iter() {
+            let name = name.map(|name| name.as_str());
+            // Only the operation that will run is checked
+            if requested.is_some() && requested.as_deref() != name {
+                continue;
+            }
+            if let Err(message) = rules.check(role, name, &operation.node, &hash) {
+                return Err(async_graphql::Error::new(message)
+                    .extend_with(|_, e| e.set("code", "FORBIDDEN"))
+                    .into_server_error(Pos::default()));
+            }
+        }
+        Ok(document)
+    }
+}
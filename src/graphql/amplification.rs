@@ -0,0 +1,270 @@
+//! Limits on work multiplied through aliases
+//!
+//! Depth and complexity limits score each field once, so a document requesting
+//! `customers(limit: 100)` under fifty aliases passes them while running the same
+//! query fifty times. Before validation the operation is walked, fragments
+//! included, counting how often each field is resolved with the same arguments,
+//! how many aliases are used and how many list fields are selected. Documents
+//! over a limit are rejected with an error naming the offending field.
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextPrepareRequest,
+};
+use async_graphql::parser::types::{
+    ExecutableDocument, Field, OperationType, Selection, SelectionSet,
+};
+use async_graphql::registry::{MetaTypeName, Registry};
+use async_graphql::{ErrorExtensions, Pos, Positioned, Request, ServerResult, Variables};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+/// Limits on repeated work in one document; 0 disables a limit
+#[derive(Debug, Clone, Copy)]
+pub struct AmplificationLimits {
+    /// Times a field may be resolved with the same arguments
+    pub max_field_repeats: usize,
+    /// Aliased fields per document
+    pub max_aliases: usize,
+    /// Fields returning lists per document
+    pub max_list_fields: usize,
+}
+
+/// A field exceeding one of the limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmplificationViolation {
+    pub message: String,
+    /// Position of the offending field
+    pub pos: Pos,
+}
+
+impl AmplificationLimits {
+    /// Check the operation a request runs: the named one, or the only one
+    pub fn check(
+        &self,
+        registry: &Registry,
+        document: &ExecutableDocument,
+        operation_name: Option<&str>,
+        variables: &Variables,
+    ) -> Result<(), AmplificationViolation> {
+        let mut walker = Walker {
+            limits: self,
+            registry,
+            document,
+            variables,
+            resolutions: HashMap::new(),
+            aliases: 0,
+            list_fields: 0,
+            fragments: Vec::new(),
+        };
+        for (name, operation) in document.operations.iter() {
+            if operation_name.is_some() && operation_name != name.map(|name| name.as_str()) {
+                continue;
+            }
+            let root = match operation.node.ty {
+                OperationType::Query => Some(registry.query_type.as_str()),
+                OperationType::Mutation => registry.mutation_type.as_deref(),
+                OperationType::Subscription => registry.subscription_type.as_deref(),
+            };
+            if let Some(root) = root {
+                walker.walk(root, &operation.node.selection_set.node)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Walker<'a> {
+    limits: &'a AmplificationLimits,
+    registry: &'a Registry,
+    document: &'a ExecutableDocument,
+    variables: &'a Variables,
+    /// Resolutions per (type, field, arguments)
+    resolutions: HashMap<(String, String, String), usize>,
+    aliases: usize,
+    list_fields: usize,
+    /// Fragments being walked, so a cyclic spread is not followed forever
+    fragments: Vec<&'a str>,
+}
+
+impl<'a> Walker<'a> {
+    fn walk(
+        &mut self,
+        type_name: &str,
+        selection_set: &'a SelectionSet,
+    ) -> Result<(), AmplificationViolation> {
+        for selection in &selection_set.items {
+            match &selection.node {
+                Selection::Field(field) => self.field(type_name, field)?,
+                Selection::InlineFragment(fragment) => {
+                    let type_name = fragment
+                        .node
+                        .type_condition
+                        .as_ref()
+                        .map_or(type_name, |condition| condition.node.on.node.as_str());
+                    self.walk(type_name, &fragment.node.selection_set.node)?;
+                }
+                Selection::FragmentSpread(spread) => {
+                    let name = &spread.node.fragment_name.node;
+                    if self.fragments.contains(&name.as_str()) {
+                        continue;
+                    }
+                    let Some(fragment) = self.document.fragments.get(name) else {
+                        continue;
+                    };
+                    self.fragments.push(name.as_str());
+                    self.walk(
+                        fragment.node.type_condition.node.on.node.as_str(),
+                        &fragment.node.selection_set.node,
+                    )?;
+                    self.fragments.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn field(
+        &mut self,
+        type_name: &str,
+        field: &'a Positioned<Field>,
+    ) -> Result<(), AmplificationViolation> {
+        let name = field.node.name.node.as_str();
+        // Introspection is bounded by the schema
+        if name.starts_with("__") {
+            return Ok(());
+        }
+        let violation = |message: String| AmplificationViolation {
+            message,
+            pos: field.pos,
+        };
+
+        if field.node.alias.is_some() {
+            self.aliases += 1;
+            let limit = self.limits.max_aliases;
+            if limit > 0 && self.aliases > limit {
+                return Err(violation(format!(
+                    "Alias of {}.{} exceeds the limit of {} aliases per document",
+                    type_name, name, limit
+                )));
+            }
+        }
+
+        let key = (
+            type_name.to_string(),
+            name.to_string(),
+            self.arguments(&field.node),
+        );
+        let repeats = self.resolutions.entry(key).or_default();
+        *repeats += 1;
+        let limit = self.limits.max_field_repeats;
+        if limit > 0 && *repeats > limit {
+            return Err(violation(format!(
+                "{}.{} is requested more than {} times with the same arguments",
+                type_name, name, limit
+            )));
+        }
+
+        // Unknown types and fields are reported by validation
+        let meta = self
+            .registry
+            .concrete_type_by_name(type_name)
+            .and_then(|ty| ty.field_by_name(name));
+        let Some(meta) = meta else {
+            return Ok(());
+        };
+        if MetaTypeName::create(&meta.ty).is_list() {
+            self.list_fields += 1;
+            let limit = self.limits.max_list_fields;
+            if limit > 0 && self.list_fields > limit {
+                return Err(violation(format!(
+                    "List field {}.{} exceeds the limit of {} list fields per document",
+                    type_name, name, limit
+                )));
+            }
+        }
+        self.walk(
+            MetaTypeName::concrete_typename(&meta.ty),
+            &field.node.selection_set.node,
+        )
+    }
+
+    /// Arguments with variables substituted, sorted by name
+    fn arguments(&self, field: &Field) -> String {
+        let mut arguments: Vec<String> = field
+            .arguments
+            .iter()
+            .map(|(name, value)| {
+                let value = value
+                    .node
+                    .clone()
+                    .into_const_with(|variable| {
+                        Ok::<_, Infallible>(
+                            self.variables.get(&variable).cloned().unwrap_or_default(),
+                        )
+                    })
+                    .unwrap_or_default();
+                format!("{}: {}", name.node, value)
+            })
+            .collect();
+        arguments.sort();
+        arguments.join(", ")
+    }
+}
+
+/// Extension rejecting documents over the amplification limits once parsed
+#[derive(Debug, Clone)]
+pub struct AmplificationExtension {
+    pub limits: AmplificationLimits,
+}
+
+impl ExtensionFactory for AmplificationExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(AmplificationExtensionImpl {
+            limits: self.limits,
+            operation_name: Mutex::new(None),
+        })
+    }
+}
+
+struct AmplificationExtensionImpl {
+    limits: AmplificationLimits,
+    /// Operation requested by name
+    operation_name: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for AmplificationExtensionImpl {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        *self.operation_name.lock().unwrap() = request.operation_name.clone();
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let operation_name = self.operation_name.lock().unwrap().clone();
+        let checked = self.limits.check(
+            &ctx.schema_env.registry,
+            &document,
+            operation_name.as_deref(),
+            variables,
+        );
+        match checked {
+            Ok(()) => Ok(document),
+            Err(violation) => Err(async_graphql::Error::new(violation.message)
+                .extend_with(|_, e| e.set("code", "AMPLIFICATION_LIMIT"))
+                .into_server_error(violation.pos)),
+        }
+    }
+}
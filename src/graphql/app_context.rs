@@ -0,0 +1,211 @@
+//! Services and caller details available to resolvers
+//!
+//! The schema holds the shared services, including the quotas that bind every
+//! request; the HTTP handler adds the caller's claims, request id and deadline to
+//! each request and may bring quotas of its own. `AppContextExtension` bundles
+//! both into one `AppContext` when the request is prepared, and resolvers read it
+//! through `app_context`, which fails with an error instead of panicking when the
+//! schema was built without the services. Responses of callers with analysis
+//! filters are marked private and uncacheable.
+
+use crate::agents::orchestrator::AgentOrchestrator;
+use crate::auth::Claims;
+use crate::config::Config;
+use crate::datafusion::analysis_filters::AnalysisFilters;
+use crate::datafusion::context::DataFusionContext;
+use crate::graphql::deadline::RequestDeadline;
+use crate::graphql::history::QueryHistory;
+use crate::models::data::Filter;
+use crate::models::dictionary::DataDictionary;
+use crate::quota::QuotaManager;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest,
+};
+use async_graphql::{
+    CacheControl, Context, Data, ErrorExtensions, Request, Response, ServerResult,
+};
+use std::any::{Any, TypeId};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Id of a request, taken from the `X-Request-Id` header by the HTTP handler
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Everything a resolver needs for one request
+#[derive(Clone)]
+pub struct AppContext {
+    pub df_ctx: Arc<DataFusionContext>,
+    pub orchestrator: Arc<AgentOrchestrator>,
+    pub config: Arc<Config>,
+    /// Claims of the bearer token, `None` for anonymous callers
+    pub claims: Option<Claims>,
+    pub quotas: Option<Arc<QuotaManager>>,
+    pub history: Option<Arc<QueryHistory>>,
+    pub request_id: String,
+    /// Deadline of the whole request, `None` when it is unbounded
+    pub deadline: Option<RequestDeadline>,
+    /// Column descriptions, empty when no data dictionary is configured
+    pub dictionary: Arc<DataDictionary>,
+    /// What-if filters the caller set for their session, ANDed into analytics
+    pub analysis_filters: Vec<Filter>,
+}
+
+impl AppContext {
+    pub fn new(
+        df_ctx: Arc<DataFusionContext>,
+        orchestrator: Arc<AgentOrchestrator>,
+        config: Arc<Config>,
+    ) -> Self {
+        Self {
+            df_ctx,
+            orchestrator,
+            config,
+            claims: None,
+            quotas: None,
+            history: None,
+            request_id: uuid::Uuid::new_v4().to_string(),
+            deadline: None,
+            dictionary: Arc::new(DataDictionary::default()),
+            analysis_filters: Vec::new(),
+        }
+    }
+
+    pub fn with_claims(mut self, claims: Option<Claims>) -> Self {
+        self.claims = claims;
+        self
+    }
+
+    pub fn with_quotas(mut self, quotas: Option<Arc<QuotaManager>>) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    pub fn with_history(mut self, history: Option<Arc<QueryHistory>>) -> Self {
+        self.history = history;
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: String) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Option<RequestDeadline>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn with_dictionary(mut self, dictionary: Arc<DataDictionary>) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    pub fn with_analysis_filters(mut self, filters: Vec<Filter>) -> Self {
+        self.analysis_filters = filters;
+        self
+    }
+
+    /// Time a step may take: its own limit, cut short by the request deadline
+    pub fn budget(&self, limit: Duration) -> Duration {
+        match &self.deadline {
+            Some(deadline) => limit.min(deadline.remaining()),
+            None => limit,
+        }
+    }
+
+    /// Role of the caller, `None` for anonymous callers
+    pub fn role(&self) -> Option<&str> {
+        self.claims.as_ref().map(|claims| claims.role.as_str())
+    }
+
+    /// Subject of the caller's token, `None` for anonymous callers
+    pub fn user(&self) -> Option<&str> {
+        self.claims.as_ref().map(|claims| claims.sub.as_str())
+    }
+}
+
+/// The request's `AppContext`
+pub fn app_context<'a>(ctx: &Context<'a>) -> Result<&'a AppContext, async_graphql::Error> {
+    ctx.data_opt::<AppContext>().ok_or_else(|| {
+        async_graphql::Error::new("Request context is not available")
+            .extend_with(|_, e| e.set("code", "CONTEXT_MISSING"))
+    })
+}
+
+/// Extension inserting the `AppContext` into each request
+pub struct AppContextExtension;
+
+impl ExtensionFactory for AppContextExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(AppContextExtension)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for AppContextExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let services = (
+            ctx.data_opt::<Arc<DataFusionContext>>(),
+            ctx.data_opt::<Arc<AgentOrchestrator>>(),
+            ctx.data_opt::<Arc<Config>>(),
+        );
+        // Without the services the context stays absent and resolvers report it
+        if let (Some(df_ctx), Some(orchestrator), Some(config)) = services {
+            let mut app = AppContext::new(df_ctx.clone(), orchestrator.clone(), config.clone())
+                .with_claims(request_data::<Claims>(&request.data).cloned())
+                .with_quotas(
+                    request_data::<Arc<QuotaManager>>(&request.data)
+                        .or(ctx.data_opt::<Arc<QuotaManager>>())
+                        .cloned(),
+                )
+                .with_history(ctx.data_opt::<Arc<QueryHistory>>().cloned())
+                .with_deadline(request_data::<RequestDeadline>(&request.data).copied());
+            if let Some(dictionary) = ctx.data_opt::<Arc<DataDictionary>>() {
+                app = app.with_dictionary(dictionary.clone());
+            }
+            if let Some(RequestId(id)) = request_data::<RequestId>(&request.data) {
+                app = app.with_request_id(id.clone());
+            }
+            // The session of a caller is the subject of their token
+            if let (Some(filters), Some(user)) =
+                (ctx.data_opt::<Arc<AnalysisFilters>>(), app.user())
+            {
+                let filters = filters.get(user);
+                app = app.with_analysis_filters(filters);
+            }
+            request.data.insert(app);
+        }
+        next.run(ctx, request).await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let mut response = next.run(ctx, operation_name).await;
+        // Filtered results are the caller's own and change with their filters
+        let filtered = ctx
+            .data_opt::<AppContext>()
+            .is_some_and(|app| !app.analysis_filters.is_empty());
+        if filtered {
+            response.cache_control = CacheControl {
+                public: false,
+                max_age: -1,
+            };
+        }
+        response
+    }
+}
+
+fn request_data<T: Any>(data: &Data) -> Option<&T> {
+    data.get(&TypeId::of::<T>())
+        .and_then(|value| value.downcast_ref::<T>())
+}
@@ -0,0 +1,228 @@
+//! Column-oriented query results
+//!
+//! Row-oriented JSON repeats every key in every row. A columnar result carries
+//! each column once, with its Arrow type and its values in row order, and is built
+//! straight from the record batches without decoding rows into models. The same
+//! values make up the rows of `json_rows`, for clients that want JSON objects.
+//!
+//! A row limit does not bound the JSON of rows with long strings, so both take a
+//! `JsonBudget`: the size of the JSON is counted row by row as each batch is
+//! converted, and the conversion stops with `RESULT_TOO_LARGE` once it exceeds
+//! the budget instead of building the rest of the result.
+
+use crate::models::data::{ColumnarResult, JsonRows, ResultColumn};
+use async_graphql::{ErrorExtensions, Json};
+use datafusion::arrow::array::{Array, ArrayRef, AsArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Float64Type, Int64Type};
+use datafusion::arrow::error::ArrowError;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde_json::Value;
+use std::io;
+use thiserror::Error;
+
+pub const RESULT_TOO_LARGE_CODE: &str = "RESULT_TOO_LARGE";
+
+#[derive(Debug, Error)]
+pub enum ResultError {
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+    #[error(
+        "The result exceeds the limit of {limit} bytes of JSON after {rows} rows; \
+         select fewer columns or rows, or export it as CSV"
+    )]
+    TooLarge { rows: usize, limit: usize },
+}
+
+impl ErrorExtensions for ResultError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            if let ResultError::TooLarge { rows, limit } = self {
+                e.set("code", RESULT_TOO_LARGE_CODE);
+                e.set("rows", *rows);
+                e.set("limit", *limit);
+            }
+        })
+    }
+}
+
+/// Bytes of JSON a result may take, counted row by row
+#[derive(Debug, Clone)]
+pub struct JsonBudget {
+    /// 0 leaves the result unbounded
+    limit: usize,
+    used: usize,
+    rows: usize,
+}
+
+impl JsonBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            used: 0,
+            rows: 0,
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(0)
+    }
+
+    /// Count the JSON of the values of one row
+    pub fn charge<'a>(
+        &mut self,
+        values: impl IntoIterator<Item = &'a Value>,
+    ) -> Result<(), ResultError> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+        for value in values {
+            // The separator before the value
+            self.used += json_size(value) + 1;
+        }
+        if self.used > self.limit {
+            return Err(ResultError::TooLarge {
+                rows: self.rows,
+                limit: self.limit,
+            });
+        }
+        self.rows += 1;
+        Ok(())
+    }
+}
+
+/// Writer counting the bytes written to it
+struct ByteCount(usize);
+
+impl io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Length of the serialized JSON of a value
+fn json_size(value: &Value) -> usize {
+    let mut count = ByteCount(0);
+    // Writing to a counter cannot fail
+    let _ = serde_json::to_writer(&mut count, value);
+    count.0
+}
+
+/// Columns of the batches, which must share one schema
+pub fn columnar_result(
+    batches: &[RecordBatch],
+    mut budget: JsonBudget,
+) -> Result<ColumnarResult, ResultError> {
+    let Some(first) = batches.first() else {
+        return Ok(ColumnarResult {
+            columns: Vec::new(),
+            row_count: 0,
+        });
+    };
+    let schema = first.schema();
+    let mut columns: Vec<Vec<Value>> = schema.fields().iter().map(|_| Vec::new()).collect();
+    for batch in batches {
+        let values = batch
+            .columns()
+            .iter()
+            .map(json_values)
+            .collect::<Result<Vec<_>, _>>()?;
+        for i in 0..batch.num_rows() {
+            budget.charge(values.iter().map(|values| &values[i]))?;
+        }
+        for (column, values) in columns.iter_mut().zip(values) {
+            column.extend(values);
+        }
+    }
+    let row_count: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    Ok(ColumnarResult {
+        columns: schema
+            .fields()
+            .iter()
+            .zip(columns)
+            .map(|(field, values)| ResultColumn {
+                name: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                values: Json(values),
+            })
+            .collect(),
+        row_count: row_count as i64,
+    })
+}
+
+/// Rows of the batches as JSON objects keyed by column name; the batches must
+/// share one schema
+pub fn json_rows(batches: &[RecordBatch], mut budget: JsonBudget) -> Result<JsonRows, ResultError> {
+    let columns: Vec<String> = batches
+        .first()
+        .map(|batch| {
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut rows = Vec::new();
+    for batch in batches {
+        let mut values = batch
+            .columns()
+            .iter()
+            .map(json_values)
+            .collect::<Result<Vec<_>, _>>()?;
+        for i in 0..batch.num_rows() {
+            let row: serde_json::Map<String, Value> = columns
+                .iter()
+                .zip(values.iter_mut())
+                .map(|(name, values)| (name.clone(), values[i].take()))
+                .collect();
+            let row = Value::Object(row);
+            budget.charge([&row])?;
+            rows.push(row);
+        }
+    }
+    Ok(JsonRows {
+        columns,
+        row_count: rows.len() as i64,
+        rows: Json(rows),
+    })
+}
+
+/// Values of an array as JSON: integers and booleans as such, other numbers as
+/// floats and everything else, dates included, in its string form
+pub fn json_values(array: &ArrayRef) -> Result<Vec<Value>, ArrowError> {
+    let data_type = array.data_type();
+    let values = if *data_type == DataType::Boolean {
+        array
+            .as_boolean()
+            .iter()
+            .map(|value| value.map_or(Value::Null, Value::from))
+            .collect()
+    } else if data_type.is_integer() {
+        cast(array, &DataType::Int64)?
+            .as_primitive::<Int64Type>()
+            .iter()
+            .map(|value| value.map_or(Value::Null, Value::from))
+            .collect()
+    } else if data_type.is_numeric() {
+        // Non-finite floats have no JSON form and become null
+        cast(array, &DataType::Float64)?
+            .as_primitive::<Float64Type>()
+            .iter()
+            .map(|value| value.map_or(Value::Null, Value::from))
+            .collect()
+    } else {
+        cast(array, &DataType::Utf8)?
+            .as_string::<i32>()
+            .iter()
+            .map(|value| value.map_or(Value::Null, Value::from))
+            .collect()
+    };
+    Ok(values)
+}
@@ -0,0 +1,87 @@
+//! Overall execution deadline of a GraphQL request
+//!
+//! The SQL timeout only bounds DataFusion; a request can still hang in an LLM call
+//! or while building a large response. The HTTP handler places a `RequestDeadline`
+//! in the request data and bounds the whole execution by it. Within that,
+//! `DeadlineExtension` fails top-level fields still unresolved at the deadline with
+//! a `TIMEOUT` error, so nullable fields that finished in time are still returned.
+//! Resolvers read the deadline from their `AppContext` to size their own budgets.
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextResolve, ResolveInfo,
+};
+use async_graphql::{ErrorExtensions, Pos, Response, ServerError, ServerResult, Value};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Error code of fields and requests cut off by the deadline
+pub const TIMEOUT_CODE: &str = "TIMEOUT";
+
+/// Instant by which the request must have executed
+#[derive(Debug, Clone, Copy)]
+pub struct RequestDeadline(pub Instant);
+
+impl RequestDeadline {
+    pub fn after(timeout: Duration) -> Self {
+        Self(Instant::now() + timeout)
+    }
+
+    /// Time left, zero once the deadline has passed
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+}
+
+/// Run an execution, answering with a `TIMEOUT` error when it outlives `timeout`
+pub async fn with_deadline<F>(timeout: Duration, execute: F) -> Response
+where
+    F: Future<Output = Response>,
+{
+    match tokio::time::timeout(timeout, execute).await {
+        Ok(response) => response,
+        Err(_) => Response::from_errors(vec![timeout_error(format!(
+            "Request timed out after {:?}",
+            timeout
+        ))]),
+    }
+}
+
+fn timeout_error(message: String) -> ServerError {
+    async_graphql::Error::new(message)
+        .extend_with(|_, e| e.set("code", TIMEOUT_CODE))
+        .into_server_error(Pos::default())
+}
+
+/// Extension failing top-level fields unresolved at the request deadline
+pub struct DeadlineExtension;
+
+impl ExtensionFactory for DeadlineExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(DeadlineExtension)
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for DeadlineExtension {
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        let deadline = match ctx.data_opt::<RequestDeadline>() {
+            Some(deadline) if info.path_node.parent.is_none() => *deadline,
+            _ => return next.run(ctx, info).await,
+        };
+        let field = info.name.to_string();
+        let expired = || timeout_error(format!("Deadline exceeded before {} resolved", field));
+        if deadline.remaining().is_zero() {
+            return Err(expired());
+        }
+        match tokio::time::timeout(deadline.remaining(), next.run(ctx, info)).await {
+            Ok(result) => result,
+            Err(_) => Err(expired()),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Dry-run validation of GraphQL documents
+//!
+//! Documents are parsed and validated against the schema, including the depth and
+//! complexity limits, but never executed, so DataFusion is not touched.
+
+use crate::graphql::schema::AppSchema;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextValidation,
+};
+use async_graphql::{Request, Response, ServerError, SimpleObject, ValidationResult, Value};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// Marker placed in the request data to stop a request after validation
+#[derive(Debug, Default)]
+pub struct DryRun {
+    stats: Mutex<Option<DocumentStats>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DocumentStats {
+    depth: usize,
+    complexity: usize,
+    max_depth: usize,
+    max_complexity: usize,
+}
+
+/// Extension recording validation stats and skipping execution for dry runs
+#[derive(Debug, Clone)]
+pub struct DryRunExtension {
+    pub max_depth: usize,
+    pub max_complexity: usize,
+}
+
+impl ExtensionFactory for DryRunExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(self.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for DryRunExtension {
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        if let Some(dry_run) = ctx.data_opt::<Arc<DryRun>>() {
+            *dry_run.stats.lock().unwrap() = Some(DocumentStats {
+                depth: result.depth,
+                complexity: result.complexity,
+                max_depth: self.max_depth,
+                max_complexity: self.max_complexity,
+            });
+        }
+        Ok(result)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        if ctx.data_opt::<Arc<DryRun>>().is_some() {
+            return Response::new(Value::Null);
+        }
+        next.run(ctx, operation_name).await
+    }
+}
+
+/// A single validation error or warning
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct ValidationMessage {
+    pub message: String,
+    pub line: Option<i32>,
+    pub column: Option<i32>,
+}
+
+/// Result of validating a GraphQL document without executing it
+#[derive(Debug, Clone, Serialize, SimpleObject)]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub errors: Vec<ValidationMessage>,
+    pub warnings: Vec<ValidationMessage>,
+    pub depth: Option<i32>,
+    pub complexity: Option<i32>,
+}
+
+/// Parse and validate a request against the schema without executing it
+pub async fn validate_document(schema: &AppSchema, request: Request) -> ValidationReport {
+    let dry_run = Arc::new(DryRun::default());
+    let response = schema.execute(request.data(dry_run.clone())).await;
+    let stats = *dry_run.stats.lock().unwrap();
+
+    let errors: Vec<ValidationMessage> = response
+        .errors
+        .into_iter()
+        .map(|err| {
+            let pos = err.locations.first();
+            ValidationMessage {
+                message: err.message,
+                line: pos.map(|p| p.line as i32),
+                column: pos.map(|p| p.column as i32),
+            }
+        })
+        .collect();
+
+    let mut warnings = Vec::new();
+    if let Some(stats) = stats {
+        // Warn when a document uses more than 80% of a limit
+        if stats.depth * 5 > stats.max_depth * 4 {
+            warnings.push(warning(format!(
+                "Query depth {} is close to the limit of {}",
+                stats.depth, stats.max_depth
+            )));
+        }
+        if stats.complexity * 5 > stats.max_complexity * 4 {
+            warnings.push(warning(format!(
+                "Query complexity {} is close to the limit of {}",
+                stats.complexity, stats.max_complexity
+            )));
+        }
+    }
+
+    ValidationReport {
+        valid: errors.is_empty(),
+        errors,
+        warnings,
+        depth: stats.map(|s| s.depth as i32),
+        complexity: stats.map(|s| s.complexity as i32),
+    }
+}
+
+fn warning(message: String) -> ValidationMessage {
+    ValidationMessage {
+        message,
+        line: None,
+        column: None,
+    }
+}
@@ -0,0 +1,93 @@
+//! Response extensions contributed by resolvers
+//!
+//! Each request gets a fresh `ResponseExtras` in its data. Resolvers add values to it
+//! through `add_extension`/`append_extension` and they are merged into the
+//! `extensions` map of the GraphQL response.
+
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest,
+};
+use async_graphql::{Context, Request, Response, ServerResult, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Values collected during a request for the response `extensions`
+#[derive(Debug, Default)]
+pub struct ResponseExtras {
+    values: Mutex<BTreeMap<String, Value>>,
+}
+
+impl ResponseExtras {
+    /// Set an extension value, replacing any previous value
+    pub fn insert(&self, key: &str, value: Value) {
+        self.values.lock().unwrap().insert(key.to_string(), value);
+    }
+
+    /// Append a value to a list extension
+    pub fn append(&self, key: &str, value: Value) {
+        let mut values = self.values.lock().unwrap();
+        match values
+            .entry(key.to_string())
+            .or_insert(Value::List(Vec::new()))
+        {
+            Value::List(items) => items.push(value),
+            other => *other = Value::List(vec![other.clone(), value]),
+        }
+    }
+
+    fn take(&self) -> BTreeMap<String, Value> {
+        std::mem::take(&mut *self.values.lock().unwrap())
+    }
+}
+
+/// Set a response extension from a resolver
+pub fn add_extension(ctx: &Context<'_>, key: &str, value: Value) {
+    if let Some(extras) = ctx.data_opt::<Arc<ResponseExtras>>() {
+        extras.insert(key, value);
+    }
+}
+
+/// Append to a list response extension from a resolver
+pub fn append_extension(ctx: &Context<'_>, key: &str, value: Value) {
+    if let Some(extras) = ctx.data_opt::<Arc<ResponseExtras>>() {
+        extras.append(key, value);
+    }
+}
+
+/// Extension merging resolver-provided values into the response
+pub struct ResponseExtrasExtension;
+
+impl ExtensionFactory for ResponseExtrasExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ResponseExtrasExtensionImpl {
+            extras: Arc::new(ResponseExtras::default()),
+        })
+    }
+}
+
+struct ResponseExtrasExtensionImpl {
+    extras: Arc<ResponseExtras>,
+}
+
+#[async_trait::async_trait]
+impl Extension for ResponseExtrasExtensionImpl {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        next.run(ctx, request.data(self.extras.clone())).await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let mut response = next.run(ctx, operation_name).await;
+        response.extensions.extend(self.extras.take());
+        response
+    }
+}
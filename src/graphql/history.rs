@@ -0,0 +1,157 @@
+//! Recent GraphQL operations of each user
+//!
+//! `QueryHistoryExtension` records every request made with a bearer token in the
+//! schema's `QueryHistory`, keyed on the token subject and capped per user, so the
+//! `myQueryHistory` query can list a caller's own recent operations.
+
+use crate::auth::Claims;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextPrepareRequest, NextRequest,
+};
+use async_graphql::parser::types::ExecutableDocument;
+use async_graphql::{Request, Response, ServerResult, Variables};
+use chrono::{DateTime, Utc};
+use std::any::TypeId;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// One operation run by a user
+#[derive(Debug, Clone)]
+pub struct HistoryRecord {
+    /// Operation requested by name, or the name of the document's only operation
+    pub operation_name: Option<String>,
+    /// GraphQL document of the request
+    pub query: String,
+    pub duration: Duration,
+    /// Whether the response had no errors
+    pub success: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Bounded per-user operation history, oldest entries dropped first
+#[derive(Debug)]
+pub struct QueryHistory {
+    per_user: usize,
+    users: Mutex<HashMap<String, VecDeque<HistoryRecord>>>,
+}
+
+impl QueryHistory {
+    pub fn new(per_user: usize) -> Self {
+        Self {
+            per_user,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, user: &str, record: HistoryRecord) {
+        if self.per_user == 0 {
+            return;
+        }
+        let mut users = self.users.lock().unwrap();
+        let history = users.entry(user.to_string()).or_default();
+        if history.len() == self.per_user {
+            history.pop_front();
+        }
+        history.push_back(record);
+    }
+
+    /// Operations of one user, most recent first
+    pub fn entries(&self, user: &str) -> Vec<HistoryRecord> {
+        self.users
+            .lock()
+            .unwrap()
+            .get(user)
+            .map(|history| history.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Extension recording authenticated requests in the schema's `QueryHistory`
+pub struct QueryHistoryExtension;
+
+impl ExtensionFactory for QueryHistoryExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(QueryHistoryExtensionImpl {
+            pending: Mutex::new(None),
+        })
+    }
+}
+
+/// Caller and document of the request being executed
+struct PendingRecord {
+    user: String,
+    operation_name: Option<String>,
+    query: String,
+}
+
+struct QueryHistoryExtensionImpl {
+    pending: Mutex<Option<PendingRecord>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for QueryHistoryExtensionImpl {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let started = Instant::now();
+        let response = next.run(ctx).await;
+        let pending = self.pending.lock().unwrap().take();
+        if let (Some(history), Some(pending)) = (ctx.data_opt::<Arc<QueryHistory>>(), pending) {
+            history.record(
+                &pending.user,
+                HistoryRecord {
+                    operation_name: pending.operation_name,
+                    query: pending.query,
+                    duration: started.elapsed(),
+                    success: response.is_ok(),
+                    timestamp: Utc::now(),
+                },
+            );
+        }
+        response
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        let claims = request
+            .data
+            .get(&TypeId::of::<Claims>())
+            .and_then(|value| value.downcast_ref::<Claims>());
+        if let Some(claims) = claims {
+            *self.pending.lock().unwrap() = Some(PendingRecord {
+                user: claims.sub.clone(),
+                operation_name: request.operation_name.clone(),
+                query: request.query.clone(),
+            });
+        }
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+            pending.operation_name = pending
+                .operation_name
+                .take()
+                .or_else(|| single_operation_name(&document));
+        }
+        Ok(document)
+    }
+}
+
+fn single_operation_name(document: &ExecutableDocument) -> Option<String> {
+    let mut operations = document.operations.iter();
+    match (operations.next(), operations.next()) {
+        (Some((name, _)), None) => name.map(|name| name.to_string()),
+        _ => None,
+    }
+}
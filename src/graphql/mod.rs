@@ -1,2 +1,14 @@
+pub mod allow_list;
+pub mod amplification;
+pub mod app_context;
+pub mod columnar;
+pub mod deadline;
+pub mod dry_run;
+pub mod extensions;
+pub mod history;
+pub mod naming;
+pub mod query_translator;
+pub mod read_only;
 pub mod resolvers;
+pub mod response_size;
 pub mod schema;
@@ -0,0 +1,180 @@
+//! Field naming policy of the TPCH types
+//!
+//! The TPCH types historically exposed the raw column names, e.g. `c_custkey`,
+//! while every other type uses camelCase. `tpch_object!` generates the GraphQL
+//! fields of a TPCH type in both spellings: the camelCase field, e.g. `cCustkey`,
+//! and the raw column name as a deprecated alias. The `field_naming` policy,
+//! placed in the schema data, decides which spellings a schema exposes; fields of
+//! the other spelling are hidden from introspection and refused when queried.
+
+use async_graphql::Context;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamingPolicy {
+    /// Only the raw column names, e.g. `c_custkey`
+    Raw,
+    /// Only camelCase names, e.g. `cCustkey`
+    Camel,
+    /// camelCase names with the raw column names as deprecated aliases
+    #[default]
+    Both,
+}
+
+impl NamingPolicy {
+    pub fn exposes(&self, spelling: Spelling) -> bool {
+        matches!(
+            (self, spelling),
+            (NamingPolicy::Both, _)
+                | (NamingPolicy::Raw, Spelling::Raw)
+                | (NamingPolicy::Camel, Spelling::Camel)
+        )
+    }
+}
+
+impl FromStr for NamingPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(NamingPolicy::Raw),
+            "camel" => Ok(NamingPolicy::Camel),
+            "both" => Ok(NamingPolicy::Both),
+            other => Err(format!(
+                "Unknown field naming policy '{}', expected raw, camel or both",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NamingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NamingPolicy::Raw => "raw",
+            NamingPolicy::Camel => "camel",
+            NamingPolicy::Both => "both",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Spelling of a generated field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Spelling {
+    Raw,
+    Camel,
+}
+
+/// camelCase name of a raw column name, as async-graphql derives it, e.g.
+/// `c_mktsegment_raw` becomes `cMktsegmentRaw`
+pub fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    for (i, part) in name.split('_').filter(|part| !part.is_empty()).enumerate() {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            if i == 0 {
+                camel.extend(first.to_lowercase());
+            } else {
+                camel.extend(first.to_uppercase());
+            }
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+fn policy(ctx: &Context<'_>) -> NamingPolicy {
+    ctx.data_opt::<NamingPolicy>().copied().unwrap_or_default()
+}
+
+pub fn raw_visible(ctx: &Context<'_>) -> bool {
+    policy(ctx).exposes(Spelling::Raw)
+}
+
+pub fn camel_visible(ctx: &Context<'_>) -> bool {
+    policy(ctx).exposes(Spelling::Camel)
+}
+
+/// Refuse a field whose spelling the policy does not expose; hidden fields are
+/// only left out of introspection otherwise
+pub fn require_spelling(ctx: &Context<'_>, spelling: Spelling) -> async_graphql::Result<()> {
+    let policy = policy(ctx);
+    if policy.exposes(spelling) {
+        return Ok(());
+    }
+    Err(async_graphql::Error::new(format!(
+        "Field {} is not available with the {} field naming policy",
+        ctx.field().name(),
+        policy
+    )))
+}
+
+/// Define a TPCH type and its GraphQL fields in both spellings. Each field is
+/// declared with its raw column name, e.g. `#[graphql(name = "c_custkey")]`,
+/// like a `SimpleObject` field. A leading `cache_control(...);` sets the cache
+/// hint of the type, as `#[Object(cache_control(...))]` does.
+macro_rules! tpch_object {
+    (
+        $(cache_control($($cache:tt)*);)?
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[doc = $doc:tt])*
+                #[graphql(name = $raw:tt)]
+                pub $field:ident: $ty:ty,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[doc = $doc])*
+                pub $field: $ty,
+            )*
+        }
+
+        paste::paste! {
+            #[async_graphql::Object$((cache_control($($cache)*)))?]
+            impl $name {
+                $(
+                    // Named in camelCase from the method name
+                    $(#[doc = $doc])*
+                    #[graphql(visible = "crate::graphql::naming::camel_visible")]
+                    async fn $field(
+                        &self,
+                        ctx: &async_graphql::Context<'_>,
+                    ) -> async_graphql::Result<&$ty> {
+                        crate::graphql::naming::require_spelling(
+                            ctx,
+                            crate::graphql::naming::Spelling::Camel,
+                        )?;
+                        Ok(&self.$field)
+                    }
+
+                    $(#[doc = $doc])*
+                    #[graphql(
+                        name = $raw,
+                        deprecation = "Raw TPCH column name, use the camelCase field",
+                        visible = "crate::graphql::naming::raw_visible"
+                    )]
+                    async fn [<$field _raw_alias>](
+                        &self,
+                        ctx: &async_graphql::Context<'_>,
+                    ) -> async_graphql::Result<&$ty> {
+                        crate::graphql::naming::require_spelling(
+                            ctx,
+                            crate::graphql::naming::Spelling::Raw,
+                        )?;
+                        Ok(&self.$field)
+                    }
+                )*
+            }
+        }
+    };
+}
+
+pub(crate) use tpch_object;
@@ -1,13 +1,97 @@
-use async_graphql::
-    {InputObject, Result, SchemaBuilder, SimpleObject};
+use crate::config::Config;
+use async_graphql::{InputObject, Result};
 use datafusion::arrow::datatypes::Schema as ArrowSchema;
+use datafusion::sql::sqlparser::keywords::ALL_KEYWORDS;
 use regex::Regex;
-use sqlparser::ast::{Expr, Statement, TableFactor, TableWithJoins};
-use sqlparser::dialect::GenericDialect;
-use sqlparser::parser::Parser;
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 
+/// SQL dialect the generated queries target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SqlDialect {
+    /// ANSI SQL, double-quoted identifiers
+    #[default]
+    Generic,
+    /// DataFusion, double-quoted identifiers
+    DataFusion,
+    /// PostgreSQL, double-quoted identifiers
+    PostgreSql,
+    /// MySQL, backtick-quoted identifiers
+    MySql,
+    /// SQL Server, bracket-quoted identifiers
+    MsSql,
+}
+
+impl SqlDialect {
+    /// Quote an identifier when it is a keyword, has upper-case letters or
+    /// contains characters outside `[a-z0-9_]`; simple names are left as is
+    pub fn quote_identifier(&self, ident: &str) -> String {
+        let simple = ident.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+            && ident
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        let keyword = ALL_KEYWORDS
+            .binary_search(&ident.to_ascii_uppercase().as_str())
+            .is_ok();
+        if simple && !keyword {
+            return ident.to_string();
+        }
+
+        match self {
+            SqlDialect::MySql => format!("`{}`", ident.replace('`', "``")),
+            SqlDialect::MsSql => format!("[{}]", ident.replace(']', "]]")),
+            _ => format!("\"{}\"", ident.replace('"', "\"\"")),
+        }
+    }
+}
+
+impl FromStr for SqlDialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "generic" | "ansi" => Ok(SqlDialect::Generic),
+            "datafusion" => Ok(SqlDialect::DataFusion),
+            "postgres" | "postgresql" => Ok(SqlDialect::PostgreSql),
+            "mysql" => Ok(SqlDialect::MySql),
+            "mssql" | "sqlserver" => Ok(SqlDialect::MsSql),
+            _ => Err(format!("Unknown SQL dialect: {}", s)),
+        }
+    }
+}
+
+/// Primary key of a TPCH table, appended to ORDER BY clauses so rows with equal
+/// sort values come back in the same order on every run
+pub fn primary_key(table: &str) -> &'static [&'static str] {
+    match table {
+        "customer" => &["c_custkey"],
+        "orders" => &["o_orderkey"],
+        "lineitem" => &["l_orderkey", "l_linenumber"],
+        "part" => &["p_partkey"],
+        "supplier" => &["s_suppkey"],
+        "partsupp" => &["ps_partkey", "ps_suppkey"],
+        "nation" => &["n_nationkey"],
+        "region" => &["r_regionkey"],
+        _ => &[],
+    }
+}
+
+/// Name of a table or column from the request, refused unless it matches
+/// `[A-Za-z_][A-Za-z0-9_]*` so it can never carry SQL of its own
+fn check_identifier<'a>(kind: &str, name: &'a str) -> Result<&'a str> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!(
+            "Invalid {} name {:?}: only letters, digits and underscores are allowed",
+            kind, name
+        )
+        .into());
+    }
+    Ok(name)
+}
+
 #[derive(InputObject)]
 pub struct QueryFilter {
     pub field: String,
@@ -21,7 +105,9 @@ pub struct QuerySort {
     pub order: String,
 }
 
+/// Named apart from the `QueryParams` of the typed resolvers
 #[derive(InputObject)]
+#[graphql(name = "TableQueryParams")]
 pub struct QueryParams {
     pub table: String,
     pub fields: Option<Vec<String>>,
@@ -31,29 +117,100 @@ pub struct QueryParams {
     pub offset: Option<i32>,
 }
 
+#[derive(Default)]
 pub struct QueryTranslator {
     schema_cache: HashMap<String, Arc<ArrowSchema>>,
+    primary_keys: HashMap<String, Vec<String>>,
+    dialect: SqlDialect,
+    /// Rows a query fetches at most, whatever its own limit
+    max_rows: Option<i64>,
 }
 
 impl QueryTranslator {
     pub fn new() -> Self {
-        Self {
-            schema_cache: HashMap::new(),
+        Self::default()
+    }
+
+    /// Translator ordering paged queries by the configured default sort
+    pub fn from_config(config: &Config) -> Self {
+        let mut translator = Self::new();
+        for (table, columns) in &config.default_sort {
+            translator
+                .primary_keys
+                .insert(table.clone(), columns.clone());
+        }
+        translator
+    }
+
+    /// Set the dialect used for identifier quoting
+    pub fn with_dialect(mut self, dialect: SqlDialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Fetch at most `max_rows` rows, also for queries without a limit
+    pub fn with_max_rows(mut self, max_rows: i64) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Cache the schema of a table
+    pub fn register_schema(&mut self, table: &str, schema: Arc<ArrowSchema>) {
+        self.schema_cache.insert(table.to_string(), schema);
+    }
+
+    /// Declare the unique key of a table outside the TPCH set, used to break ties
+    pub fn with_primary_key(mut self, table: &str, columns: &[&str]) -> Self {
+        self.primary_keys.insert(
+            table.to_string(),
+            columns.iter().map(|column| column.to_string()).collect(),
+        );
+        self
+    }
+
+    /// Columns breaking ties between rows with equal sort values
+    pub fn tie_breakers(&self, table: &str) -> Vec<String> {
+        match self.primary_keys.get(table) {
+            Some(columns) => columns.clone(),
+            None => primary_key(table).iter().map(|c| c.to_string()).collect(),
         }
     }
 
+    /// Cached schema of a table
+    pub fn schema(&self, table: &str) -> Option<&Arc<ArrowSchema>> {
+        self.schema_cache.get(table)
+    }
+
+    pub fn dialect(&self) -> SqlDialect {
+        self.dialect
+    }
+
     pub fn translate(&self, params: &QueryParams) -> Result<String> {
-        let mut query = format!("SELECT {} FROM {}", self.build_select_clause(params)?, params.table);
-        
+        let table = self
+            .dialect
+            .quote_identifier(check_identifier("table", &params.table)?);
+        let mut query = format!(
+            "SELECT {} FROM {}",
+            self.build_select_clause(params)?,
+            table
+        );
+
         if let Some(filters) = &params.filters {
-            query.push_str(&self.build_where_clause(filters)?);
+            query.push_str(&self.build_where_clause(&params.table, filters)?);
         }
 
-        if let Some(sort) = &params.sort {
-            query.push_str(&self.build_order_by_clause(sort)?);
+        // Paged results need a total order, or rows move between pages
+        let paged = params.limit.is_some() || params.offset.is_some();
+        let sort = params.sort.as_deref().unwrap_or_default();
+        if !sort.is_empty() || paged {
+            query.push_str(&self.build_order_by_clause(&params.table, sort, paged)?);
         }
 
-        if let Some(limit) = params.limit {
+        let limit = match (params.limit.map(i64::from), self.max_rows) {
+            (Some(limit), Some(max_rows)) => Some(limit.min(max_rows)),
+            (limit, max_rows) => limit.or(max_rows),
+        };
+        if let Some(limit) = limit {
             query.push_str(&format!(" LIMIT {}", limit));
         }
 
@@ -66,20 +223,32 @@ impl QueryTranslator {
 
     fn build_select_clause(&self, params: &QueryParams) -> Result<String> {
         if let Some(fields) = &params.fields {
+            let fields = fields
+                .iter()
+                .map(|field| self.column(&params.table, field))
+                .collect::<Result<Vec<String>>>()?;
             Ok(fields.join(", "))
         } else {
             Ok("*".to_string())
         }
     }
 
-    fn build_where_clause(&self, filters: &[QueryFilter]) -> Result<String> {
+    fn build_where_clause(&self, table: &str, filters: &[QueryFilter]) -> Result<String> {
         let mut conditions = Vec::new();
         for filter in filters {
+            let field = self.column(table, &filter.field)?;
             let condition = match filter.operator.to_lowercase().as_str() {
-                "=" => format!("{} = {}", filter.field, self.escape_value(&filter.value)),
-                "!=" => format!("{} != {}", filter.field, self.escape_value(&filter.value)),
-                "like" => format!("{} LIKE {}", filter.field, self.escape_value(&filter.value)),
-                "in" => format!("{} IN {}", filter.field, self.escape_value(&filter.value)),
+                "=" => format!("{} = {}", field, self.escape_value(&filter.value)),
+                "!=" => format!("{} != {}", field, self.escape_value(&filter.value)),
+                "like" => format!("{} LIKE {}", field, self.escape_value(&filter.value)),
+                "in" => {
+                    let values: Vec<String> = filter
+                        .value
+                        .split(',')
+                        .map(|value| self.escape_value(value.trim()))
+                        .collect();
+                    format!("{} IN ({})", field, values.join(", "))
+                }
                 _ => return Err("Invalid operator".into()),
             };
             conditions.push(condition);
@@ -87,7 +256,15 @@ impl QueryTranslator {
         Ok(format!(" WHERE {}", conditions.join(" AND ")))
     }
 
-    fn build_order_by_clause(&self, sort: &[QuerySort]) -> Result<String> {
+    /// ORDER BY for the requested sort, followed by the table's primary key columns
+    /// that are not sorted on already. Paged queries of a table without a known key
+    /// are ordered by all its columns, which needs its cached schema.
+    fn build_order_by_clause(
+        &self,
+        table: &str,
+        sort: &[QuerySort],
+        paged: bool,
+    ) -> Result<String> {
         let mut order_by = Vec::new();
         for sort in sort {
             let order = match sort.order.to_lowercase().as_str() {
@@ -95,17 +272,62 @@ impl QueryTranslator {
                 "desc" | "descending" => "DESC",
                 _ => return Err("Invalid sort order".into()),
             };
-            order_by.push(format!("{} {}", sort.field, order));
+            order_by.push(format!("{} {}", self.column(table, &sort.field)?, order));
+        }
+        let mut tie_breakers = self.tie_breakers(table);
+        if tie_breakers.is_empty() && paged {
+            let schema = self.schema(table).ok_or_else(|| {
+                format!(
+                    "Paging {} needs a stable order; configure its default_sort",
+                    table
+                )
+            })?;
+            tie_breakers = schema.fields().iter().map(|f| f.name().clone()).collect();
+        }
+        for column in tie_breakers {
+            if !sort.iter().any(|sort| sort.field == column) {
+                order_by.push(self.dialect.quote_identifier(&column));
+            }
+        }
+        if order_by.is_empty() {
+            return Ok(String::new());
         }
         Ok(format!(" ORDER BY {}", order_by.join(", ")))
     }
 
-    fn escape_value(&self, value: &str) -> String {
-        if value.starts_with("'") && value.ends_with("'") {
-            value.to_string()
-        } else {
-            format!("'{}'", value)
+    /// Quoted column, checked against the cached schema of the table when there
+    /// is one. Quoted identifiers are case-sensitive, so the name must match the
+    /// schema exactly.
+    fn column(&self, table: &str, column: &str) -> Result<String> {
+        let quoted = self
+            .dialect
+            .quote_identifier(check_identifier("column", column)?);
+        let Some(schema) = self.schema(table) else {
+            return Ok(quoted);
+        };
+        if schema.field_with_name(column).is_ok() {
+            return Ok(quoted);
+        }
+        let similar = schema
+            .fields()
+            .iter()
+            .find(|field| field.name().eq_ignore_ascii_case(column));
+        Err(match similar {
+            Some(field) => format!(
+                "Unknown column {} in {}, did you mean {}? Column names are case-sensitive",
+                column,
+                table,
+                field.name()
+            ),
+            None => format!("Unknown column {} in {}", column, table),
         }
+        .into())
+    }
+
+    /// String literal of a value; quotes inside it are doubled, so a value can
+    /// never end the literal early
+    fn escape_value(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\'', "''"))
     }
 
     pub fn translate_natural_language(&self, input: &str) -> Result<String> {
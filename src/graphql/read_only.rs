@@ -0,0 +1,183 @@
+//! Read-only mode for maintenance windows
+//!
+//! While the mode is on, the API keeps answering data queries but refuses
+//! everything that changes server state: every mutation except `setReadOnly`,
+//! CSV exports, which count against export quotas, and, unless configured
+//! otherwise, queries that call the AI models. Refused operations fail with the
+//! `SERVICE_READ_ONLY` code. The mode starts from the configuration and admins
+//! toggle it at runtime.
+
+use crate::metrics::READ_ONLY_MODE;
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextPrepareRequest,
+};
+use async_graphql::parser::types::{ExecutableDocument, OperationType, Selection, SelectionSet};
+use async_graphql::{ErrorExtensions, Pos, Request, ServerResult, Variables};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Error code of operations refused in read-only mode
+pub const READ_ONLY_CODE: &str = "SERVICE_READ_ONLY";
+
+/// Mutation toggling the mode, allowed while it is on
+pub const TOGGLE_FIELD: &str = "setReadOnly";
+
+/// Query fields calling the AI models
+pub const AI_FIELDS: &[&str] = &["naturalLanguageQuery", "insights", "testAgentConnections"];
+
+#[derive(Debug)]
+pub struct ReadOnlyMode {
+    enabled: AtomicBool,
+    /// Whether AI queries are refused as well
+    blocks_ai: bool,
+}
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool, blocks_ai: bool) -> Self {
+        READ_ONLY_MODE.set(enabled as i64);
+        Self {
+            enabled: AtomicBool::new(enabled),
+            blocks_ai,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        if self.enabled.swap(enabled, Ordering::SeqCst) != enabled {
+            info!(
+                "Read-only mode {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+        READ_ONLY_MODE.set(enabled as i64);
+    }
+
+    /// Reason a top-level field of an operation is refused, `None` when it may run
+    pub fn check_field(&self, operation: OperationType, field: &str) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        match operation {
+            OperationType::Mutation if field != TOGGLE_FIELD => Some(format!(
+                "The server is in read-only mode, mutation {} is unavailable",
+                field
+            )),
+            OperationType::Query if self.blocks_ai && AI_FIELDS.contains(&field) => Some(format!(
+                "The server is in read-only mode, {} is unavailable",
+                field
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Error of an operation refused in read-only mode
+pub fn read_only_error(message: impl Into<String>) -> async_graphql::Error {
+    async_graphql::Error::new(message).extend_with(|_, e| e.set("code", READ_ONLY_CODE))
+}
+
+/// Extension refusing state-changing operations in read-only mode once the
+/// document is parsed
+pub struct ReadOnlyExtension;
+
+impl ExtensionFactory for ReadOnlyExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ReadOnlyExtensionImpl {
+            operation_name: Mutex::new(None),
+        })
+    }
+}
+
+struct ReadOnlyExtensionImpl {
+    /// Operation requested by name
+    operation_name: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for ReadOnlyExtensionImpl {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        *self.operation_name.lock().unwrap() = request.operation_name.clone();
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let Some(mode) = ctx.data_opt::<Arc<ReadOnlyMode>>() else {
+            return Ok(document);
+        };
+        let requested = self.operation_name.lock().unwrap().clone();
+
+        for (name, operation) in document.operations.iter() {
+            // Only the operation that will run is checked
+            if requested.is_some() && requested.as_deref() != name.map(|name| name.as_str()) {
+                continue;
+            }
+            let mut fields = Vec::new();
+            top_level_fields(
+                &document,
+                &operation.node.selection_set.node,
+                &mut Vec::new(),
+                &mut fields,
+            );
+            for (field, pos) in fields {
+                if let Some(message) = mode.check_field(operation.node.ty, field) {
+                    return Err(read_only_error(message).into_server_error(pos));
+                }
+            }
+        }
+        Ok(document)
+    }
+}
+
+/// Names and positions of the top-level fields of a selection set, fields
+/// selected through fragments included
+fn top_level_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    fragments: &mut Vec<&'a str>,
+    fields: &mut Vec<(&'a str, Pos)>,
+) {
+    for selection in &selection_set.items {
+        match &selection.node {
+            Selection::Field(field) => fields.push((field.node.name.node.as_str(), field.pos)),
+            Selection::InlineFragment(fragment) => top_level_fields(
+                document,
+                &fragment.node.selection_set.node,
+                fragments,
+                fields,
+            ),
+            Selection::FragmentSpread(spread) => {
+                let name = spread.node.fragment_name.node.as_str();
+                // A cyclic spread is reported by validation
+                if fragments.contains(&name) {
+                    continue;
+                }
+                if let Some(fragment) = document.fragments.get(&spread.node.fragment_name.node) {
+                    fragments.push(name);
+                    top_level_fields(
+                        document,
+                        &fragment.node.selection_set.node,
+                        fragments,
+                        fields,
+                    );
+                    fragments.pop();
+                }
+            }
+        }
+    }
+}
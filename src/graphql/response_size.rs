@@ -0,0 +1,128 @@
+//! Limit on the serialized size of GraphQL responses
+//!
+//! Row limits bound the number of rows, not their width, so a query can still
+//! produce a response of hundreds of megabytes. Before a response is sent, the
+//! HTTP handler measures its JSON and, when it exceeds `max_response_bytes`, cuts
+//! the longest lists of the data until it fits. A truncated response carries the
+//! `truncated: true` extension and the `X-Response-Truncated: true` header.
+
+use crate::metrics::TRUNCATED_RESPONSES_TOTAL;
+use async_graphql::{Name, Response, ServerError, Value};
+use std::io;
+
+/// Header of truncated responses
+pub const TRUNCATED_HEADER: &str = "X-Response-Truncated";
+
+/// Response extension set on truncated responses
+pub const TRUNCATED_EXTENSION: &str = "truncated";
+
+/// Counts the bytes written instead of keeping them
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Length of a response serialized as JSON
+pub fn serialized_size(response: &Response) -> usize {
+    let mut counter = ByteCounter(0);
+    // Writing to the counter cannot fail
+    let _ = serde_json::to_writer(&mut counter, response);
+    counter.0
+}
+
+pub fn is_truncated(response: &Response) -> bool {
+    response.extensions.contains_key(TRUNCATED_EXTENSION)
+}
+
+/// Cut a response down to at most `max_bytes`, returning whether it was truncated.
+/// The data is dropped altogether when cutting lists is not enough; 0 disables
+/// the limit.
+pub fn truncate_response(response: &mut Response, max_bytes: usize) -> bool {
+    if max_bytes == 0 || serialized_size(response) <= max_bytes {
+        return false;
+    }
+    response
+        .extensions
+        .insert(TRUNCATED_EXTENSION.to_string(), Value::Boolean(true));
+
+    loop {
+        let size = serialized_size(response);
+        if size <= max_bytes {
+            break;
+        }
+        let Some(list) = longest_list(&mut response.data) else {
+            response.data = Value::Null;
+            response.errors.push(ServerError::new(
+                format!(
+                    "Response exceeds the limit of {} bytes and was dropped",
+                    max_bytes
+                ),
+                None,
+            ));
+            break;
+        };
+        // Cut in proportion to the excess, by at least one item per round
+        let len = list.len();
+        let keep = (len as u128 * max_bytes as u128 / size as u128) as usize;
+        list.truncate(keep.min(len - 1));
+    }
+    TRUNCATED_RESPONSES_TOTAL.inc();
+    true
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Field(Name),
+    Index(usize),
+}
+
+/// Longest non-empty list anywhere in a value
+fn longest_list(value: &mut Value) -> Option<&mut Vec<Value>> {
+    let mut longest = None;
+    find_longest(value, &mut Vec::new(), &mut longest);
+    let (_, path) = longest?;
+
+    let mut value = value;
+    for step in &path {
+        value = match (step, value) {
+            (Step::Field(name), Value::Object(fields)) => fields.get_mut(name)?,
+            (Step::Index(index), Value::List(items)) => items.get_mut(*index)?,
+            _ => return None,
+        };
+    }
+    match value {
+        Value::List(items) => Some(items),
+        _ => None,
+    }
+}
+
+fn find_longest(value: &Value, path: &mut Vec<Step>, longest: &mut Option<(usize, Vec<Step>)>) {
+    match value {
+        Value::List(items) => {
+            if !items.is_empty() && longest.as_ref().is_none_or(|(len, _)| items.len() > *len) {
+                *longest = Some((items.len(), path.clone()));
+            }
+            for (index, item) in items.iter().enumerate() {
+                path.push(Step::Index(index));
+                find_longest(item, path, longest);
+                path.pop();
+            }
+        }
+        Value::Object(fields) => {
+            for (name, field) in fields {
+                path.push(Step::Field(name.clone()));
+                find_longest(field, path, longest);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
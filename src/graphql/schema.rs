@@ -1,410 +1,2996 @@
 //! GraphQL schema for DataFusion integration
 
-use async_graphql::{Context, Object, Schema};
+use async_graphql::{
+    Context, ErrorExtensions, Guard, ID, Json, Lookahead, Object, Schema, SelectionField, Value,
+    value,
+};
+use chrono::{Days, NaiveDate, Utc};
+use datafusion::arrow::datatypes::{DataType, Schema as ArrowSchema};
+use datafusion::arrow::json::ArrayWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::type_coercion::binary::comparison_coercion;
+use std::collections::HashMap;
 use std::sync::Arc;
-use crate::datafusion::context::DataFusionContext;
+use crate::audit::{KillAuditRecord, SqlAuditRecord};
+use crate::auth::RoleGuard;
+use crate::config::Config;
+use crate::datafusion::analysis_filters::{
+    AnalysisFilters, validate_filters, validate_table_filters,
+};
+use crate::datafusion::column_accessor::RecordBatchExt;
+use crate::datafusion::compare::{CompareOptions, compare_results};
+use crate::datafusion::context::{
+    DIMENSION_TABLES, DataFusionContext, QUERY_CANCELLED_CODE, is_cancelled, table_in_use,
+};
+use crate::datafusion::dimensions::DimensionCache;
+use crate::datafusion::upload::UploadedTables;
 use crate::agents::orchestrator::AgentOrchestrator;
+use crate::graphql::allow_list::{AllowListExtension, OperationAllowList};
+use crate::graphql::amplification::{AmplificationExtension, AmplificationLimits};
+use crate::graphql::app_context::{AppContextExtension, app_context};
+use crate::graphql::columnar::{JsonBudget, columnar_result, json_rows};
+use crate::graphql::deadline::DeadlineExtension;
+use crate::graphql::history::{QueryHistory, QueryHistoryExtension};
+use crate::graphql::naming::camel_case;
+use crate::graphql::dry_run::{DryRunExtension, ValidationReport, validate_document};
+use crate::graphql::extensions::{ResponseExtrasExtension, add_extension, append_extension};
+use crate::graphql::query_translator::{
+    QueryParams as TableQueryParams, QueryTranslator, SqlDialect,
+};
+use crate::graphql::read_only::{ReadOnlyExtension, ReadOnlyMode};
 use crate::models::data::*;
+use crate::models::dictionary::DataDictionary;
+use crate::models::manifest::{
+    CUSTOMER_MANIFEST, LINEITEM_MANIFEST, ModelManifest, NATION_MANIFEST, ORDER_MANIFEST,
+    PART_MANIFEST, PARTSUPP_MANIFEST, REGION_MANIFEST, SUPPLIER_MANIFEST,
+};
+use crate::quota::{QuotaError, QuotaManager};
+use crate::request_retry::TRANSIENT_CODE;
+use crate::subscriptions::SubscriptionRegistry;
+use tracing::warn;
+
+/// Largest `topN` of the top customers ranking
+const MAX_TOP_N: i32 = 100;
+
+/// Selectable customer columns as (GraphQL field, SQL expression)
+const CUSTOMER_COLUMNS: &[(&str, &str)] = &[
+    ("c_custkey", "c_custkey"),
+    ("c_name", "c_name"),
+    ("c_address", "c_address"),
+    ("c_nationkey", "c_nationkey"),
+    ("c_phone", "c_phone"),
+    ("c_acctbal", "c_acctbal"),
+    ("c_mktsegment", "c_mktsegment"),
+    ("c_mktsegment_raw", "c_mktsegment"),
+    ("c_comment", "c_comment"),
+];
+
+/// Selectable order columns as (GraphQL field, SQL expression)
+const ORDER_COLUMNS: &[(&str, &str)] = &[
+    ("o_orderkey", "o_orderkey"),
+    ("o_custkey", "o_custkey"),
+    ("o_orderstatus", "o_orderstatus"),
+    ("o_orderstatus_raw", "o_orderstatus"),
+    ("o_totalprice", "o_totalprice"),
+    ("o_orderdate", "o_orderdate"),
+    ("o_orderpriority", "o_orderpriority"),
+    ("o_clerk", "o_clerk"),
+    ("o_shippriority", "o_shippriority"),
+    ("o_comment", "o_comment"),
+];
+
+/// Selectable line item columns as (GraphQL field, SQL expression)
+const LINEITEM_COLUMNS: &[(&str, &str)] = &[
+    ("l_orderkey", "l_orderkey"),
+    ("l_partkey", "l_partkey"),
+    ("l_suppkey", "l_suppkey"),
+    ("l_linenumber", "l_linenumber"),
+    ("l_quantity", "l_quantity"),
+    ("l_extendedprice", "l_extendedprice"),
+    ("l_discount", "l_discount"),
+    ("l_tax", "l_tax"),
+    ("l_returnflag", "l_returnflag"),
+    ("l_returnflag_raw", "l_returnflag"),
+    ("l_linestatus", "l_linestatus"),
+    ("l_linestatus_raw", "l_linestatus"),
+    ("l_shipdate", "l_shipdate"),
+    ("l_commitdate", "l_commitdate"),
+    ("l_receiptdate", "l_receiptdate"),
+    ("l_shipinstruct", "l_shipinstruct"),
+    ("l_shipmode", "l_shipmode"),
+    ("l_comment", "l_comment"),
+];
+
+/// Selectable part columns as (GraphQL field, SQL expression)
+const PART_COLUMNS: &[(&str, &str)] = &[
+    ("p_partkey", "p_partkey"),
+    ("p_name", "p_name"),
+    ("p_mfgr", "p_mfgr"),
+    ("p_brand", "p_brand"),
+    ("p_type", "p_type"),
+    ("p_size", "p_size"),
+    ("p_container", "p_container"),
+    ("p_retailprice", "p_retailprice"),
+    ("p_comment", "p_comment"),
+];
+
+/// Selectable supplier columns as (GraphQL field, SQL expression)
+const SUPPLIER_COLUMNS: &[(&str, &str)] = &[
+    ("s_suppkey", "s_suppkey"),
+    ("s_name", "s_name"),
+    ("s_address", "s_address"),
+    ("s_nationkey", "s_nationkey"),
+    ("s_phone", "s_phone"),
+    ("s_acctbal", "s_acctbal"),
+    ("s_comment", "s_comment"),
+];
+
+/// Selectable nation columns as (GraphQL field, SQL expression)
+const NATION_COLUMNS: &[(&str, &str)] = &[
+    ("n_nationkey", "n_nationkey"),
+    ("n_name", "n_name"),
+    ("n_regionkey", "n_regionkey"),
+    ("n_comment", "n_comment"),
+];
+
+/// Selectable region columns as (GraphQL field, SQL expression)
+const REGION_COLUMNS: &[(&str, &str)] = &[
+    ("r_regionkey", "r_regionkey"),
+    ("r_name", "r_name"),
+    ("r_comment", "r_comment"),
+];
+
+/// Selectable part supply columns as (GraphQL field, SQL expression)
+const PARTSUPP_COLUMNS: &[(&str, &str)] = &[
+    ("ps_partkey", "ps_partkey"),
+    ("ps_suppkey", "ps_suppkey"),
+    ("ps_availqty", "ps_availqty"),
+    ("ps_supplycost", "ps_supplycost"),
+    ("ps_comment", "ps_comment"),
+];
+
+/// A table listed page by page by a typed resolver
+struct TypedTable {
+    manifest: ModelManifest,
+    columns: &'static [(&'static str, &'static str)],
+    /// Key columns, always selected, that order the rows
+    keys: &'static [&'static str],
+}
+
+const LINEITEM_TABLE: TypedTable = TypedTable {
+    manifest: LINEITEM_MANIFEST,
+    columns: LINEITEM_COLUMNS,
+    keys: &["l_orderkey", "l_linenumber"],
+};
+
+const PART_TABLE: TypedTable = TypedTable {
+    manifest: PART_MANIFEST,
+    columns: PART_COLUMNS,
+    keys: &["p_partkey"],
+};
+
+const SUPPLIER_TABLE: TypedTable = TypedTable {
+    manifest: SUPPLIER_MANIFEST,
+    columns: SUPPLIER_COLUMNS,
+    keys: &["s_suppkey"],
+};
+
+const NATION_TABLE: TypedTable = TypedTable {
+    manifest: NATION_MANIFEST,
+    columns: NATION_COLUMNS,
+    keys: &["n_nationkey"],
+};
+
+const REGION_TABLE: TypedTable = TypedTable {
+    manifest: REGION_MANIFEST,
+    columns: REGION_COLUMNS,
+    keys: &["r_regionkey"],
+};
+
+const PARTSUPP_TABLE: TypedTable = TypedTable {
+    manifest: PARTSUPP_MANIFEST,
+    columns: PARTSUPP_COLUMNS,
+    keys: &["ps_partkey", "ps_suppkey"],
+};
+
+/// SELECT list with the columns requested in the selection set, plus the key columns.
+/// Fields reading the same column share one SELECT expression; a field counts as
+/// requested in either spelling of the naming policy.
+fn projection(ctx: &Context<'_>, columns: &[(&str, &str)], keys: &[&str]) -> String {
+    projection_of(ctx.look_ahead(), columns, keys)
+}
+
+/// `projection` of the subfields of `look_ahead`, e.g. the `data` of a page
+fn projection_of(look_ahead: Lookahead<'_>, columns: &[(&str, &str)], keys: &[&str]) -> String {
+    let mut selected: Vec<&str> = Vec::new();
+    for (field, expr) in columns {
+        let requested =
+            look_ahead.field(field).exists() || look_ahead.field(&camel_case(field)).exists();
+        if (keys.contains(field) || requested) && !selected.contains(expr) {
+            selected.push(expr);
+        }
+    }
+    selected.join(", ")
+}
+
+/// Whether a subfield of the current field is selected, honouring `@skip` and
+/// `@include` on the subfield. Directives on fragment spreads are not evaluated,
+/// so a field inside a skipped fragment still counts as selected.
+fn field_requested(ctx: &Context<'_>, name: &str) -> bool {
+    ctx.field()
+        .selection_set()
+        .any(|field| field.name() == name && !excluded_by_directive(&field))
+}
+
+fn excluded_by_directive(field: &SelectionField<'_>) -> bool {
+    let Ok(directives) = field.directives() else {
+        return false;
+    };
+    directives.iter().any(|directive| {
+        let condition = directive
+            .arguments
+            .iter()
+            .find(|(name, _)| name.node == "if")
+            .map(|(_, value)| &value.node);
+        matches!(
+            (directive.name.node.as_str(), condition),
+            ("skip", Some(Value::Boolean(true))) | ("include", Some(Value::Boolean(false)))
+        )
+    })
+}
+
+/// WHERE clause restricting a categorical column; `UNKNOWN` matches the rows
+/// whose code is outside the known set
+fn categorical_filter<T: Categorical>(column: &str, value: Option<T>) -> String {
+    let Some(value) = value else {
+        return String::new();
+    };
+    match value.code() {
+        Some(code) => format!("WHERE {} = '{}'", column, code),
+        None => {
+            let codes: Vec<String> = categorical_codes(column)
+                .unwrap_or_default()
+                .iter()
+                .map(|code| format!("'{}'", code))
+                .collect();
+            format!("WHERE {} NOT IN ({})", column, codes.join(", "))
+        }
+    }
+}
+
+/// Analysis filters of the caller, echoed in the `analysisFilters` extension
+/// whenever there are any so that filtered results are never mistaken for totals
+fn analysis_filters<'a>(ctx: &Context<'a>) -> Result<&'a [Filter], async_graphql::Error> {
+    let filters = &app_context(ctx)?.analysis_filters;
+    if !filters.is_empty() {
+        let echo = serde_json::to_value(filters)?;
+        add_extension(
+            ctx,
+            "analysisFilters",
+            Value::from_json(echo).unwrap_or_default(),
+        );
+    }
+    Ok(filters)
+}
+
+/// Budget of the JSON a resolver converts a result into
+fn json_budget(ctx: &Context<'_>) -> Result<JsonBudget, async_graphql::Error> {
+    Ok(JsonBudget::new(
+        app_context(ctx)?.config.max_result_json_bytes,
+    ))
+}
+
+/// Rows to fetch for a result of up to `rows` rows: at most one past the caller's
+/// per-query row limit, enough for `enforce_row_limit` to fail without reading
+/// the whole result
+fn capped_fetch(ctx: &Context<'_>, rows: i64) -> Result<i64, async_graphql::Error> {
+    let app = app_context(ctx)?;
+    let limit = app
+        .quotas
+        .as_ref()
+        .and_then(|quotas| quotas.quota(app.role()).max_rows_per_query);
+    Ok(match limit {
+        Some(limit) => rows.min(i64::try_from(limit.saturating_add(1)).unwrap_or(i64::MAX)),
+        None => rows,
+    })
+}
+
+/// Check the rows a resolver returns against the caller's per-query row limit
+fn enforce_row_limit(ctx: &Context<'_>, rows: usize) -> Result<(), async_graphql::Error> {
+    let app = app_context(ctx)?;
+    let Some(quotas) = &app.quotas else {
+        return Ok(());
+    };
+    quotas
+        .check_rows(app.role(), rows as u64)
+        .map_err(quota_error)
+}
+
+/// Fail when the startup schema check found a table unreadable by one of the
+/// typed models, naming the first offending column
+fn require_models(
+    df_ctx: &DataFusionContext,
+    models: &[ModelManifest],
+) -> Result<(), async_graphql::Error> {
+    let mismatch = models
+        .iter()
+        .find_map(|manifest| df_ctx.model_mismatches(manifest.model).into_iter().next());
+    match mismatch {
+        Some(mismatch) => Err(async_graphql::Error::new(format!(
+            "The {} model is unavailable: {}",
+            mismatch.model, mismatch
+        ))
+        .extend_with(|_, e| {
+            e.set("code", "SCHEMA_MISMATCH");
+            e.set("table", mismatch.table);
+            e.set("column", mismatch.column);
+        })),
+        None => Ok(()),
+    }
+}
+
+fn quota_error(err: QuotaError) -> async_graphql::Error {
+    let code = err.code();
+    let reset_at = err.reset_at();
+    async_graphql::Error::new(err.to_string()).extend_with(|_, e| {
+        e.set("code", code);
+        if let Some(reset_at) = reset_at {
+            e.set("resetAt", reset_at.to_rfc3339());
+        }
+    })
+}
+
+/// Table names clients may register: a letter or underscore, then letters,
+/// digits and underscores
+fn check_table_name(table_name: &str) -> Result<(), async_graphql::Error> {
+    if table_name.is_empty()
+        || table_name.starts_with(|c: char| c.is_ascii_digit())
+        || !table_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(async_graphql::Error::new(format!(
+            "Invalid table name: {}",
+            table_name
+        )));
+    }
+    Ok(())
+}
+
+/// Source of `registerTable`, which must lie under `data_path`. Relative paths
+/// are read from `data_path`; paths leading out of it, also through `..` or a
+/// symlink, are refused, as are URLs unless `data_path` is a URL they extend.
+fn data_file_path(data_path: &str, path: &str) -> Result<String, async_graphql::Error> {
+    let outside = || {
+        async_graphql::Error::new(format!("Path {} is outside the data directory", path))
+            .extend_with(|_, e| e.set("code", "FORBIDDEN"))
+    };
+    if data_path.is_empty() {
+        return Err(outside());
+    }
+    if data_path.contains("://") || path.contains("://") {
+        let prefix = format!("{}/", data_path.trim_end_matches('/'));
+        if !path.starts_with(&prefix) || path.split('/').any(|segment| segment == "..") {
+            return Err(outside());
+        }
+        return Ok(path.to_string());
+    }
+
+    let root = std::fs::canonicalize(data_path).map_err(|e| {
+        async_graphql::Error::new(format!("Cannot read data directory {}: {}", data_path, e))
+    })?;
+    let resolved = std::fs::canonicalize(root.join(path)).map_err(|e| {
+        async_graphql::Error::new(format!("Cannot read parquet source {}: {}", path, e))
+    })?;
+    if !resolved.starts_with(&root) {
+        return Err(outside());
+    }
+    Ok(resolved.to_string_lossy().into_owned())
+}
+
+/// Error of a failed SQL query, with code `TRANSIENT` when running the request
+/// again may succeed and `QUERY_CANCELLED` when it was cancelled or killed
+fn query_error(
+    df_ctx: &DataFusionContext,
+    what: &str,
+    err: DataFusionError,
+) -> async_graphql::Error {
+    let transient = df_ctx.is_transient(&err);
+    let cancelled = is_cancelled(&err);
+    async_graphql::Error::new(format!("{} failed: {}", what, err)).extend_with(|_, e| {
+        if cancelled {
+            e.set("code", QUERY_CANCELLED_CODE);
+        } else if transient {
+            e.set("code", TRANSIENT_CODE);
+        }
+    })
+}
+
+/// Trim a page fetched with `LIMIT limit + 1` back to `limit` rows; the extra row
+/// only tells whether more follow. Reported in the `pagination` extension.
+fn paginate<T>(
+    ctx: &Context<'_>,
+    mut rows: Vec<T>,
+    limit: i32,
+    offset: i32,
+) -> Result<Vec<T>, async_graphql::Error> {
+    let page_size = limit.max(0) as usize;
+    let has_more = rows.len() > page_size;
+    rows.truncate(page_size);
+    enforce_row_limit(ctx, rows.len())?;
+    add_extension(
+        ctx,
+        "pagination",
+        value!({
+            "limit": limit,
+            "offset": offset,
+            "returned": rows.len(),
+            "hasMore": has_more,
+        }),
+    );
+    Ok(rows)
+}
+
+/// Limit and offset of a page, 100 rows from the start by default. Those of
+/// the `QueryParams` take precedence over the `limit` and `offset` arguments.
+fn page_bounds(
+    limit: Option<i32>,
+    offset: Option<i32>,
+    params: Option<&QueryParams>,
+) -> Result<(i32, i32), async_graphql::Error> {
+    let limit = params
+        .and_then(|params| params.limit)
+        .or(limit)
+        .unwrap_or(100);
+    let offset = params
+        .and_then(|params| params.offset)
+        .or(offset)
+        .unwrap_or(0);
+    if limit < 1 {
+        return Err(async_graphql::Error::new("limit must be positive"));
+    }
+    if offset < 0 {
+        return Err(async_graphql::Error::new("offset must not be negative"));
+    }
+    Ok((limit, offset))
+}
+
+/// Page of a typed table in key order, of the rows matching `condition` (a
+/// `WHERE` clause, or empty) and the analysis filters. Only the columns the
+/// client selected are read, so `row` fills the others with defaults.
+async fn typed_page<T>(
+    ctx: &Context<'_>,
+    table: &TypedTable,
+    condition: &str,
+    limit: Option<i32>,
+    offset: Option<i32>,
+    row: impl Fn(&RecordBatch, usize) -> Result<T, async_graphql::Error>,
+) -> Result<Vec<T>, async_graphql::Error> {
+    let df_ctx = &app_context(ctx)?.df_ctx;
+    let filters = analysis_filters(ctx)?;
+    require_models(df_ctx, &[table.manifest])?;
+    let (limit, offset) = page_bounds(limit, offset, None)?;
+
+    let query = format!(
+        "SELECT {} FROM {} {} ORDER BY {} LIMIT {} OFFSET {}",
+        projection(ctx, table.columns, table.keys),
+        table.manifest.table,
+        condition,
+        table.keys.join(", "),
+        capped_fetch(ctx, i64::from(limit) + 1)?,
+        offset
+    );
+    let batches = df_ctx
+        .execute_query_filtered(&query, filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Query", e))?;
+
+    let mut rows = Vec::new();
+    for batch in &batches {
+        for i in 0..batch.num_rows() {
+            rows.push(row(batch, i)?);
+        }
+    }
+    paginate(ctx, rows, limit, offset)
+}
+
+/// Filters and ORDER BY clause of the `QueryParams` of a typed resolver, checked
+/// against the columns of `table` so no name reaches the SQL unchecked. The
+/// filters join the caller's analysis filters and, like them, are applied to the
+/// plan with typed literals instead of being written into the SQL. Rows are
+/// ordered by `key`, after the sort column when one is given.
+async fn table_params(
+    df_ctx: &DataFusionContext,
+    table: &str,
+    key: &str,
+    params: Option<&QueryParams>,
+    filters: &[Filter],
+) -> Result<(Vec<Filter>, String), async_graphql::Error> {
+    let mut filters = filters.to_vec();
+    let Some(params) = params else {
+        return Ok((filters, format!("ORDER BY {}", key)));
+    };
+    let requested = params.filters.clone().unwrap_or_default();
+    validate_table_filters(df_ctx, table, &requested)
+        .await
+        .map_err(|e| async_graphql::Error::new(format!("Invalid filter: {}", e)))?;
+    filters.extend(requested);
+
+    let Some(column) = &params.sort_by else {
+        return Ok((filters, format!("ORDER BY {}", key)));
+    };
+    let schema = df_ctx
+        .table_schema(table)
+        .await
+        .map_err(|e| query_error(df_ctx, "Query", e))?;
+    if schema.field_with_name(column).is_err() {
+        return Err(async_graphql::Error::new(format!(
+            "Unknown sort column {} of table {}",
+            column, table
+        )));
+    }
+    let direction = match params.sort_order.unwrap_or(SortOrder::Asc) {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+    let order_by = format!("ORDER BY \"{}\" {}, {}", column, direction, key);
+    Ok((filters, order_by))
+}
+
+/// Type of a join key, which must be a column of a registered table
+async fn join_key_type(
+    df_ctx: &DataFusionContext,
+    table: &str,
+    column: &str,
+) -> Result<DataType, async_graphql::Error> {
+    if !df_ctx.get_table_names().iter().any(|name| name == table) {
+        return Err(async_graphql::Error::new(format!(
+            "Unknown table: {}",
+            table
+        )));
+    }
+    let schema = df_ctx
+        .table_schema(table)
+        .await
+        .map_err(|e| query_error(df_ctx, "Query", e))?;
+    let field = schema.field_with_name(column).map_err(|_| {
+        async_graphql::Error::new(format!("Unknown column {} in {}", column, table))
+    })?;
+    Ok(field.data_type().clone())
+}
+
+/// Rows of the query `params` translates to. The table must be registered, and
+/// with its schema cached the translator checks every column against it.
+async fn translated_rows(
+    ctx: &Context<'_>,
+    params: &TableQueryParams,
+) -> Result<JsonRows, async_graphql::Error> {
+    let app = app_context(ctx)?;
+    let df_ctx = &app.df_ctx;
+    let filters = analysis_filters(ctx)?;
+    if !df_ctx.get_table_names().contains(&params.table) {
+        return Err(async_graphql::Error::new(format!(
+            "Unknown table: {}",
+            params.table
+        )));
+    }
+    let mut translator =
+        QueryTranslator::from_config(&app.config).with_dialect(SqlDialect::DataFusion);
+    // Bounded by the caller's row limit even when the query has no limit
+    let max_rows = capped_fetch(ctx, i64::MAX)?;
+    if max_rows < i64::MAX {
+        translator = translator.with_max_rows(max_rows);
+    }
+    let schema = df_ctx
+        .table_schema(&params.table)
+        .await
+        .map_err(|e| query_error(df_ctx, "Query", e))?;
+    translator.register_schema(&params.table, schema);
+    let query = translator.translate(params)?;
+
+    let batches = df_ctx
+        .execute_query_filtered(&query, filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Query", e))?;
+    let rows = json_rows(&batches, json_budget(ctx)?).map_err(|e| e.extend())?;
+    enforce_row_limit(ctx, rows.row_count as usize)?;
+    Ok(rows)
+}
+
+/// Whether rows follow a page and where the page lies among `total_count` rows
+fn page_info(limit: i32, offset: i32, total_count: i64) -> (bool, PageInfo) {
+    let limit_rows = i64::from(limit);
+    let page_info = PageInfo {
+        current_page: offset / limit + 1,
+        page_size: limit,
+        total_pages: ((total_count + limit_rows - 1) / limit_rows) as i32,
+    };
+    (i64::from(offset) + limit_rows < total_count, page_info)
+}
+
+/// Rows of a table matching a WHERE clause, under the caller's analysis filters
+async fn row_count(
+    df_ctx: &DataFusionContext,
+    table: &str,
+    filter: &str,
+    filters: &[Filter],
+) -> Result<i64, async_graphql::Error> {
+    let query = format!("SELECT COUNT(*) AS total_count FROM {} {}", table, filter);
+    let batches = df_ctx
+        .execute_query_filtered(&query, filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Count query", e))?;
+    match batches.iter().find(|batch| batch.num_rows() > 0) {
+        Some(batch) => Ok(batch.get_i64("total_count", 0)?.unwrap_or_default()),
+        None => Ok(0),
+    }
+}
+
+/// Customers of the batches of a customer query. Columns the client did not
+/// select are absent and filled with defaults, as are nulls in the columns the
+/// model does not expose as nullable.
+fn customer_rows(batches: &[RecordBatch]) -> Result<Vec<Customer>, async_graphql::Error> {
+    let mut customers = Vec::new();
+    for batch in batches {
+        for i in 0..batch.num_rows() {
+            let (c_mktsegment, c_mktsegment_raw) = batch
+                .get_string("c_mktsegment", i)?
+                .map_or_else(Default::default, |segment| MarketSegment::parse(&segment));
+            customers.push(Customer {
+                c_custkey: batch.get_i64("c_custkey", i)?.unwrap_or_default(),
+                c_name: batch.get_string("c_name", i)?.unwrap_or_default(),
+                c_address: batch.get_string("c_address", i)?,
+                c_nationkey: batch.get_i64("c_nationkey", i)?.unwrap_or_default(),
+                c_phone: batch.get_string("c_phone", i)?,
+                c_acctbal: batch.get_f64("c_acctbal", i)?.unwrap_or_default(),
+                c_mktsegment,
+                c_mktsegment_raw,
+                c_comment: batch.get_string("c_comment", i)?,
+            });
+        }
+    }
+    Ok(customers)
+}
+
+/// Orders of the batches of an orders query, columns the client did not select
+/// filled with defaults
+fn order_rows(batches: &[RecordBatch]) -> Result<Vec<Order>, async_graphql::Error> {
+    let mut orders = Vec::new();
+    for batch in batches {
+        for i in 0..batch.num_rows() {
+            let (o_orderstatus, o_orderstatus_raw) = batch
+                .get_string("o_orderstatus", i)?
+                .map_or_else(Default::default, |status| OrderStatus::parse(&status));
+            let o_shippriority = batch.get_i64("o_shippriority", i)?.unwrap_or_default();
+            orders.push(Order {
+                o_orderkey: batch.get_i64("o_orderkey", i)?.unwrap_or_default(),
+                o_custkey: batch.get_i64("o_custkey", i)?.unwrap_or_default(),
+                o_orderstatus,
+                o_orderstatus_raw,
+                o_totalprice: batch.get_f64("o_totalprice", i)?.unwrap_or_default(),
+                o_orderdate: batch.get_date_string("o_orderdate", i)?,
+                o_orderpriority: batch.get_string("o_orderpriority", i)?.unwrap_or_default(),
+                o_clerk: batch.get_string("o_clerk", i)?.unwrap_or_default(),
+                o_shippriority: i32::try_from(o_shippriority)?,
+                o_comment: batch.get_string("o_comment", i)?.unwrap_or_default(),
+            });
+        }
+    }
+    Ok(orders)
+}
+
+/// Date range of a time series and the shift of its comparison series
+#[derive(Default)]
+struct TrendWindow {
+    /// First day included
+    from: Option<NaiveDate>,
+    /// Last day included
+    to: Option<NaiveDate>,
+    /// Interval moving comparison periods onto the periods they are compared
+    /// with, e.g. `1 years`; no comparison series without it
+    comparison_offset: Option<String>,
+}
+
+impl TrendWindow {
+    /// WHERE clause for the range, moved back by `shift` for the comparison
+    fn filter(&self, time: &str, shift: &str) -> String {
+        let mut conditions = Vec::new();
+        if let Some(from) = self.from {
+            conditions.push(format!("{} >= TIMESTAMP '{}'{}", time, from, shift));
+        }
+        if let Some(to) = self.to {
+            conditions.push(format!(
+                "{} < TIMESTAMP '{}'{}",
+                time,
+                to + Days::new(1),
+                shift
+            ));
+        }
+        if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        }
+    }
+
+    /// Whether the range covers only part of the period starting at `start`
+    fn is_partial(&self, granularity: TimeGranularity, start: NaiveDate) -> bool {
+        self.from.is_some_and(|from| start < from)
+            || self
+                .to
+                .is_some_and(|to| granularity.period_end(start) > to + Days::new(1))
+    }
+}
+
+/// SQL summing `value_column` per period of `time_column`; with a window the
+/// moving average covers the current and `window - 1` preceding periods. With
+/// a comparison offset the comparison series is computed in the same statement
+/// and joined on the shifted periods.
+fn time_series_sql(
+    table: &str,
+    time_column: &str,
+    value_column: &str,
+    granularity: TimeGranularity,
+    window: Option<i32>,
+    trend: &TrendWindow,
+) -> String {
+    let dialect = SqlDialect::default();
+    let time = format!(
+        "CAST({} AS TIMESTAMP)",
+        dialect.quote_identifier(time_column)
+    );
+    let value = dialect.quote_identifier(value_column);
+    let table = dialect.quote_identifier(table);
+    let bucket = format!("date_trunc('{}', {})", granularity.as_sql(), time);
+    let moving_average = match window {
+        Some(window) => format!(
+            "AVG(series.total) OVER (ORDER BY series.bucket ROWS BETWEEN {} PRECEDING AND CURRENT ROW)",
+            window - 1
+        ),
+        None => "CAST(NULL AS DOUBLE)".to_string(),
+    };
+    let (comparison, comparison_columns, comparison_join) = match &trend.comparison_offset {
+        Some(offset) => (
+            format!(
+                ",
+             comparison AS (
+                 SELECT {bucket} + INTERVAL '{offset}' AS bucket,
+                        MIN({bucket}) AS period,
+                        CAST(SUM({value}) AS DOUBLE) AS total
+                 FROM {table}
+                 {filter}
+                 GROUP BY 1
+             )",
+                filter = trend.filter(&time, &format!(" - INTERVAL '{}'", offset)),
+            ),
+            "CAST(CAST(comparison.period AS DATE) AS VARCHAR) AS comparison_period,
+                comparison.total AS comparison_total",
+            "LEFT JOIN comparison ON series.bucket = comparison.bucket",
+        ),
+        None => (
+            String::new(),
+            "CAST(NULL AS VARCHAR) AS comparison_period,
+                CAST(NULL AS DOUBLE) AS comparison_total",
+            "",
+        ),
+    };
+    format!(
+        "WITH series AS (
+             SELECT {bucket} AS bucket, CAST(SUM({value}) AS DOUBLE) AS total
+             FROM {table}
+             {filter}
+             GROUP BY 1
+         ){comparison}
+         SELECT CAST(CAST(series.bucket AS DATE) AS VARCHAR) AS period,
+                series.total AS total,
+                {moving_average} AS moving_average,
+                {comparison_columns}
+         FROM series
+         {comparison_join}
+         ORDER BY series.bucket",
+        filter = trend.filter(&time, ""),
+    )
+}
+
+fn parse_date(name: &str, value: &str) -> Result<NaiveDate, async_graphql::Error> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        async_graphql::Error::new(format!(
+            "Invalid {} date {}, expected YYYY-MM-DD",
+            name, value
+        ))
+    })
+}
+
+/// Sales and order count per month, from the `daily_revenue` rollup when it is
+/// available and from orders otherwise
+fn monthly_trends_sql(rollup: bool) -> String {
+    let (table, date, sales, orders) = if rollup {
+        (
+            "daily_revenue",
+            "order_date",
+            "total_sales",
+            "SUM(order_count)",
+        )
+    } else {
+        ("orders", "o_orderdate", "o_totalprice", "COUNT(*)")
+    };
+    format!(
+        "SELECT substr(CAST(CAST(date_trunc('month', CAST({} AS TIMESTAMP)) AS DATE) AS VARCHAR), 1, 7) AS month,
+                CAST(SUM({}) AS DOUBLE) AS total_sales,
+                CAST({} AS BIGINT) AS order_count
+         FROM {}
+         GROUP BY 1
+         ORDER BY 1",
+        date, sales, orders, table
+    )
+}
+
+/// Total sales and order count, from the `daily_revenue` rollup when it is
+/// available and from orders otherwise
+fn sales_totals_sql(rollup: bool) -> String {
+    let (table, sales, orders) = if rollup {
+        ("daily_revenue", "total_sales", "SUM(order_count)")
+    } else {
+        ("orders", "o_totalprice", "COUNT(*)")
+    };
+    format!(
+        "SELECT CAST(COALESCE(SUM({}), 0) AS DOUBLE) AS total_sales,
+                CAST(COALESCE({}, 0) AS BIGINT) AS total_orders
+         FROM {}",
+        sales, orders, table
+    )
+}
+
+/// Total sales and order count over all orders
+async fn sales_totals(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+) -> Result<(f64, i64), async_graphql::Error> {
+    // The rollup was built from all orders, so filtered totals read the orders
+    let use_rollup = df_ctx.has_daily_revenue() && filters.is_empty();
+    let batches = df_ctx
+        .execute_query_filtered(&sales_totals_sql(use_rollup), filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Sales totals query", e))?;
+    let Some(batch) = batches.iter().find(|batch| batch.num_rows() > 0) else {
+        return Ok((0.0, 0));
+    };
+    Ok((
+        batch.get_f64("total_sales", 0)?.unwrap_or_default(),
+        batch.get_i64("total_orders", 0)?.unwrap_or_default(),
+    ))
+}
+
+/// Sales and number of ordering customers per region of the customer's nation
+async fn sales_by_region(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+) -> Result<Vec<RegionSales>, async_graphql::Error> {
+    // Filters on nation or region columns need the tables in the query
+    let dimensions = df_ctx.dimensions();
+    let filters_dimensions = filters.iter().any(|filter| {
+        [NATION_MANIFEST, REGION_MANIFEST].iter().any(|manifest| {
+            manifest
+                .columns
+                .iter()
+                .any(|(name, _)| *name == filter.column)
+        })
+    });
+    if !dimensions.is_empty() && !filters_dimensions {
+        return sales_by_cached_region(df_ctx, filters, &dimensions).await;
+    }
+
+    let batches = df_ctx
+        .execute_query_filtered(
+            "SELECT r.r_name AS region,
+                    CAST(SUM(o.o_totalprice) AS DOUBLE) AS total_sales,
+                    COUNT(DISTINCT c.c_custkey) AS customer_count
+             FROM orders o
+             JOIN customer c ON c.c_custkey = o.o_custkey
+             JOIN nation n ON n.n_nationkey = c.c_nationkey
+             JOIN region r ON r.r_regionkey = n.n_regionkey
+             GROUP BY r.r_name
+             ORDER BY total_sales DESC, region",
+            filters,
+        )
+        .await
+        .map_err(|e| query_error(df_ctx, "Sales by region query", e))?;
+    let mut regions = Vec::new();
+    for batch in batches {
+        for i in 0..batch.num_rows() {
+            let region = batch.get_string("region", i)?.unwrap_or_default();
+            regions.push(RegionSales {
+                region: region.trim_end().to_string(),
+                total_sales: batch.get_f64("total_sales", i)?.unwrap_or_default(),
+                customer_count: batch.get_i64("customer_count", i)?.unwrap_or_default(),
+            });
+        }
+    }
+    Ok(regions)
+}
+
+/// `sales_by_region` aggregating per nation and naming regions from the
+/// dimension cache. A customer belongs to one nation, so the distinct customers
+/// of the nations of a region add up to those of the region.
+async fn sales_by_cached_region(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+    dimensions: &DimensionCache,
+) -> Result<Vec<RegionSales>, async_graphql::Error> {
+    let batches = df_ctx
+        .execute_query_filtered(
+            "SELECT c.c_nationkey AS nationkey,
+                    CAST(SUM(o.o_totalprice) AS DOUBLE) AS total_sales,
+                    COUNT(DISTINCT c.c_custkey) AS customer_count
+             FROM orders o
+             JOIN customer c ON c.c_custkey = o.o_custkey
+             GROUP BY c.c_nationkey",
+            filters,
+        )
+        .await
+        .map_err(|e| query_error(df_ctx, "Sales by region query", e))?;
+    let mut by_region: HashMap<&str, RegionSales> = HashMap::new();
+    for batch in batches {
+        for i in 0..batch.num_rows() {
+            let nationkey = batch.get_i64("nationkey", i)?.unwrap_or_default();
+            // Like the join, customers of unknown nations count for no region
+            let Some(region) = dimensions.region_of_nation(nationkey) else {
+                continue;
+            };
+            let sales = by_region.entry(region).or_insert_with(|| RegionSales {
+                region: region.trim_end().to_string(),
+                total_sales: 0.0,
+                customer_count: 0,
+            });
+            sales.total_sales += batch.get_f64("total_sales", i)?.unwrap_or_default();
+            sales.customer_count += batch.get_i64("customer_count", i)?.unwrap_or_default();
+        }
+    }
+    let mut regions: Vec<RegionSales> = by_region.into_values().collect();
+    regions.sort_by(|a, b| {
+        b.total_sales
+            .total_cmp(&a.total_sales)
+            .then_with(|| a.region.cmp(&b.region))
+    });
+    Ok(regions)
+}
+
+/// Customers with the highest value of the ranking metric over their orders,
+/// ties broken by customer key
+fn top_customers_sql(top_n: i32, rank_by: CustomerRanking) -> String {
+    format!(
+        "SELECT c.c_custkey AS c_custkey, c.c_name AS c_name, c.c_address AS c_address,
+                c.c_nationkey AS c_nationkey, c.c_phone AS c_phone,
+                c.c_acctbal AS c_acctbal, c.c_mktsegment AS c_mktsegment,
+                c.c_comment AS c_comment,
+                CAST(SUM(o.o_totalprice) AS DOUBLE) AS total_spent,
+                COUNT(*) AS order_count,
+                CAST(AVG(o.o_totalprice) AS DOUBLE) AS avg_order_value
+         FROM customer c
+         JOIN orders o ON o.o_custkey = c.c_custkey
+         GROUP BY c.c_custkey, c.c_name, c.c_address, c.c_nationkey, c.c_phone,
+                  c.c_acctbal, c.c_mktsegment, c.c_comment
+         ORDER BY {} DESC, c_custkey
+         LIMIT {}",
+        rank_by.as_sql(),
+        top_n
+    )
+}
+
+/// Customers with the highest value of the ranking metric
+async fn top_customers(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+    top_n: i32,
+    rank_by: CustomerRanking,
+) -> Result<Vec<CustomerSales>, async_graphql::Error> {
+    let customers_batches = df_ctx
+        .execute_query_filtered(&top_customers_sql(top_n, rank_by), filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Customers query", e))?;
+
+    let mut top_customers = Vec::new();
+    for batch in customers_batches {
+        let customers = customer_rows(std::slice::from_ref(&batch))?;
+        for (i, customer) in customers.into_iter().enumerate() {
+            let total_spent = batch.get_f64("total_spent", i)?.unwrap_or_default();
+            let order_count = batch.get_i64("order_count", i)?.unwrap_or_default();
+            let avg_order_value = batch.get_f64("avg_order_value", i)?.unwrap_or_default();
+            let rank_value = match rank_by {
+                CustomerRanking::TotalSpent => total_spent,
+                CustomerRanking::OrderCount => order_count as f64,
+                CustomerRanking::AvgOrderValue => avg_order_value,
+            };
+
+            top_customers.push(CustomerSales {
+                customer,
+                total_spent,
+                order_count,
+                avg_order_value,
+                ranked_by: rank_by,
+                rank_value,
+            });
+        }
+    }
+    Ok(top_customers)
+}
+
+/// Sales and order count per month
+async fn monthly_trends(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+) -> Result<Vec<MonthlyTrend>, async_graphql::Error> {
+    let use_rollup = df_ctx.has_daily_revenue() && filters.is_empty();
+    let trends_batches = df_ctx
+        .execute_query_filtered(&monthly_trends_sql(use_rollup), filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Monthly trends query", e))?;
+    let mut monthly_trends = Vec::new();
+    for batch in trends_batches {
+        for i in 0..batch.num_rows() {
+            monthly_trends.push(MonthlyTrend {
+                month: batch.get_string("month", i)?.unwrap_or_default(),
+                total_sales: batch.get_f64("total_sales", i)?.unwrap_or_default(),
+                order_count: batch.get_i64("order_count", i)?.unwrap_or_default(),
+            });
+        }
+    }
+    Ok(monthly_trends)
+}
+
+/// Failed sections of an analytics result. With errors as data, a failed
+/// section is recorded and returned empty so the other sections still resolve.
+struct SectionErrors {
+    as_data: bool,
+    errors: Vec<SectionError>,
+}
+
+impl SectionErrors {
+    fn new(as_data: bool) -> Self {
+        Self {
+            as_data,
+            errors: Vec::new(),
+        }
+    }
+
+    fn collect<T: Default>(
+        &mut self,
+        section: &str,
+        result: Result<T, async_graphql::Error>,
+    ) -> Result<T, async_graphql::Error> {
+        match result {
+            Err(e) if self.as_data => {
+                self.errors.push(SectionError {
+                    section: section.to_string(),
+                    message: e.message,
+                });
+                Ok(T::default())
+            }
+            result => result,
+        }
+    }
+}
+
+/// Approximate percentiles and an equal-width histogram of order values
+async fn order_value_distribution(
+    df_ctx: &DataFusionContext,
+    filters: &[Filter],
+    buckets: usize,
+) -> Result<OrderValueDistribution, async_graphql::Error> {
+    const ORDER_VALUES: &str = "(SELECT CAST(o_totalprice AS DOUBLE) AS v FROM orders)";
+    let stats_sql = format!(
+        "SELECT approx_percentile_cont(0.5) WITHIN GROUP (ORDER BY v) AS p50,
+                approx_percentile_cont(0.9) WITHIN GROUP (ORDER BY v) AS p90,
+                approx_percentile_cont(0.99) WITHIN GROUP (ORDER BY v) AS p99,
+                MIN(v) AS lo,
+                MAX(v) AS hi
+         FROM {}",
+        ORDER_VALUES
+    );
+    let batches = df_ctx
+        .execute_query_filtered(&stats_sql, filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Distribution query", e))?;
+    let Some(batch) = batches.iter().find(|batch| batch.num_rows() > 0) else {
+        return Ok(OrderValueDistribution::default());
+    };
+    let value = |name: &str| batch.get_f64(name, 0);
+    let (Some(lo), Some(hi)) = (value("lo")?, value("hi")?) else {
+        return Ok(OrderValueDistribution::default());
+    };
+
+    // Bucket i covers [lo + i * width, lo + (i + 1) * width), the last one up to hi
+    let width = (hi - lo) / buckets as f64;
+    let buckets = if width > 0.0 { buckets } else { 1 };
+    let bucket_expr = if buckets == 1 {
+        "0".to_string()
+    } else {
+        let whens: Vec<String> = (1..buckets)
+            .map(|i| format!("WHEN v < {} THEN {}", lo + width * i as f64, i - 1))
+            .collect();
+        format!("CASE {} ELSE {} END", whens.join(" "), buckets - 1)
+    };
+    let histogram_sql = format!(
+        "SELECT {} AS bucket, COUNT(*) AS count FROM {} WHERE v IS NOT NULL GROUP BY 1",
+        bucket_expr, ORDER_VALUES
+    );
+    let batches = df_ctx
+        .execute_query_filtered(&histogram_sql, filters)
+        .await
+        .map_err(|e| query_error(df_ctx, "Histogram query", e))?;
+    let mut counts = vec![0; buckets];
+    for batch in &batches {
+        for i in 0..batch.num_rows() {
+            let bucket = batch.get_i64("bucket", i)?.unwrap_or_default();
+            counts[bucket as usize] = batch.get_i64("count", i)?.unwrap_or_default();
+        }
+    }
+
+    Ok(OrderValueDistribution {
+        p50: value("p50")?,
+        p90: value("p90")?,
+        p99: value("p99")?,
+        histogram: counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| HistogramBucket {
+                bucket_start: lo + width * i as f64,
+                bucket_end: if i + 1 == buckets {
+                    hi
+                } else {
+                    lo + width * (i + 1) as f64
+                },
+                count,
+            })
+            .collect(),
+    })
+}
+
+/// `get_field` expression selecting a dot-separated path inside a struct column,
+/// checking each step against the struct's fields
+fn struct_path_expr(
+    column: &str,
+    data_type: &DataType,
+    path: &[&str],
+) -> Result<String, async_graphql::Error> {
+    let mut expr = SqlDialect::default().quote_identifier(column);
+    let mut data_type = data_type;
+    for segment in path {
+        let DataType::Struct(fields) = data_type else {
+            return Err(async_graphql::Error::new(format!(
+                "Cannot select {} from a non-struct value",
+                segment
+            )));
+        };
+        let field = fields
+            .iter()
+            .find(|field| field.name() == segment)
+            .ok_or_else(|| {
+                async_graphql::Error::new(format!("Unknown struct field {}", segment))
+            })?;
+        expr = format!("get_field({}, '{}')", expr, segment.replace('\'', "''"));
+        data_type = field.data_type();
+    }
+    Ok(expr)
+}
+
+/// Rejects AI fields with a FEATURE_DISABLED error when AI is turned off
+struct AiEnabledGuard;
+
+impl Guard for AiEnabledGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if app_context(ctx)?.config.ai_enabled() {
+            return Ok(());
+        }
+        Err(
+            async_graphql::Error::new("AI features are disabled on this server")
+                .extend_with(|_, e| e.set("code", "FEATURE_DISABLED")),
+        )
+    }
+}
 
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
+    // Version of the server and whether it is in read-only mode
+    #[graphql(cache_control(no_cache))]
+    async fn server_info(&self, ctx: &Context<'_>) -> Result<ServerInfo, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        Ok(ServerInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            read_only: ctx
+                .data_opt::<Arc<ReadOnlyMode>>()
+                .is_some_and(|mode| mode.is_enabled()),
+            ai_enabled: app.config.ai_enabled(),
+            demo: app.config.demo_mode,
+        })
+    }
+
     // Get all tables available
     async fn tables(&self, ctx: &Context<'_>) -> Result<Vec<String>, async_graphql::Error> {
-        let df_ctx = ctx.data_unchecked::<Arc<DataFusionContext>>();
-        Ok(df_ctx.get_table_names().clone())
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        Ok(df_ctx.get_table_names())
+    }
+
+    // Get table row count, or a fast estimate from parquet statistics when
+    // `exact` is false. Estimates are listed in the `countEstimates` extension.
+    #[graphql(cache_control(no_cache))]
+    async fn table_count(
+        &self,
+        ctx: &Context<'_>,
+        table_name: String,
+        #[graphql(default = true)] exact: bool,
+    ) -> Result<i64, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        if exact {
+            return df_ctx
+                .get_table_count(&table_name)
+                .await
+                .map_err(|e| async_graphql::Error::new(format!("Failed to get count: {}", e)));
+        }
+
+        let (count, statistics_exact) = df_ctx
+            .estimate_table_count(&table_name)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to estimate count: {}", e)))?;
+        append_extension(
+            ctx,
+            "countEstimates",
+            value!({
+                "table": table_name,
+                "count": count,
+                "approximate": true,
+                "statisticsExact": statistics_exact,
+            }),
+        );
+        Ok(count)
+    }
+
+    // Customer queries
+    async fn customers(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        segment: Option<MarketSegment>,
+        #[graphql(desc = "Filters, sort and page; its limit and offset take precedence")]
+        params: Option<QueryParams>,
+    ) -> Result<Vec<Customer>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        require_models(df_ctx, &[CUSTOMER_MANIFEST])?;
+        let (filters, order_by) =
+            table_params(df_ctx, "customer", "c_custkey", params.as_ref(), filters).await?;
+        let (limit, offset) = page_bounds(limit, offset, params.as_ref())?;
+
+        let query = format!(
+            "SELECT {}
+             FROM customer 
+             {}
+             {}
+             LIMIT {} OFFSET {}",
+            projection(ctx, CUSTOMER_COLUMNS, &["c_custkey"]),
+            categorical_filter("c_mktsegment", segment),
+            order_by,
+            capped_fetch(ctx, i64::from(limit) + 1)?,
+            offset
+        );
+
+        let batches = df_ctx
+            .execute_query_filtered(&query, &filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+
+        paginate(ctx, customer_rows(&batches)?, limit, offset)
+    }
+
+    /// A page of customers with the total count of customers and the page's
+    /// position among them. Pages past the end are empty.
+    async fn customers_paged(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        segment: Option<MarketSegment>,
+        #[graphql(desc = "Filters, sort and page; its limit and offset take precedence")]
+        params: Option<QueryParams>,
+    ) -> Result<CustomerQueryResult, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        require_models(df_ctx, &[CUSTOMER_MANIFEST])?;
+        let (limit, offset) = page_bounds(limit, offset, params.as_ref())?;
+        let (filters, order_by) =
+            table_params(df_ctx, "customer", "c_custkey", params.as_ref(), filters).await?;
+        let filter = categorical_filter("c_mktsegment", segment);
+
+        let query = format!(
+            "SELECT {} FROM customer {} {} LIMIT {} OFFSET {}",
+            projection_of(
+                ctx.look_ahead().field("data"),
+                CUSTOMER_COLUMNS,
+                &["c_custkey"]
+            ),
+            filter,
+            order_by,
+            capped_fetch(ctx, i64::from(limit))?,
+            offset
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, &filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+        let data = customer_rows(&batches)?;
+        enforce_row_limit(ctx, data.len())?;
+
+        let total_count = row_count(df_ctx, "customer", &filter, &filters).await?;
+        let (has_more, page_info) = page_info(limit, offset, total_count);
+        Ok(CustomerQueryResult {
+            data,
+            total_count,
+            has_more,
+            page_info,
+        })
+    }
+
+    // Customers column by column, read straight from the record batches. Values
+    // are returned as stored, e.g. segments as their raw codes.
+    async fn customers_columnar(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        segment: Option<MarketSegment>,
+        #[graphql(desc = "Columns to return, all by default")] columns: Option<Vec<String>>,
+    ) -> Result<ColumnarResult, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        let known: Vec<&str> = CUSTOMER_MANIFEST
+            .columns
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+        let mut selected: Vec<String> = Vec::new();
+        match columns {
+            Some(columns) => {
+                for column in columns {
+                    if !known.contains(&column.as_str()) {
+                        return Err(async_graphql::Error::new(format!(
+                            "Unknown customer column: {}",
+                            column
+                        )));
+                    }
+                    if !selected.contains(&column) {
+                        selected.push(column);
+                    }
+                }
+            }
+            None => {
+                require_models(df_ctx, &[CUSTOMER_MANIFEST])?;
+                selected = known.iter().map(|name| name.to_string()).collect();
+            }
+        }
+        if selected.is_empty() {
+            return Err(async_graphql::Error::new("Select at least one column"));
+        }
+
+        let query = format!(
+            "SELECT {} FROM customer {} ORDER BY c_custkey LIMIT {} OFFSET {}",
+            selected.join(", "),
+            categorical_filter("c_mktsegment", segment),
+            capped_fetch(ctx, i64::from(limit.unwrap_or(100)))?,
+            offset.unwrap_or(0)
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+        let result = columnar_result(&batches, json_budget(ctx)?).map_err(|e| e.extend())?;
+        enforce_row_limit(ctx, result.row_count as usize)?;
+        Ok(result)
+    }
+
+    // Orders queries
+    async fn orders(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        status: Option<OrderStatus>,
+        #[graphql(desc = "Filters, sort and page; its limit and offset take precedence")]
+        params: Option<QueryParams>,
+    ) -> Result<Vec<Order>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        require_models(df_ctx, &[ORDER_MANIFEST])?;
+        let (filters, order_by) =
+            table_params(df_ctx, "orders", "o_orderkey", params.as_ref(), filters).await?;
+        let (limit, offset) = page_bounds(limit, offset, params.as_ref())?;
+
+        let query = format!(
+            "SELECT {}
+             FROM orders 
+             {}
+             {}
+             LIMIT {} OFFSET {}",
+            projection(ctx, ORDER_COLUMNS, &["o_orderkey"]),
+            categorical_filter("o_orderstatus", status),
+            order_by,
+            capped_fetch(ctx, i64::from(limit) + 1)?,
+            offset
+        );
+
+        let batches = df_ctx
+            .execute_query_filtered(&query, &filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+
+        paginate(ctx, order_rows(&batches)?, limit, offset)
+    }
+
+    /// A page of orders with the total count of orders and the page's position
+    /// among them. Pages past the end are empty.
+    async fn orders_paged(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        status: Option<OrderStatus>,
+        #[graphql(desc = "Filters, sort and page; its limit and offset take precedence")]
+        params: Option<QueryParams>,
+    ) -> Result<OrderQueryResult, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        require_models(df_ctx, &[ORDER_MANIFEST])?;
+        let (limit, offset) = page_bounds(limit, offset, params.as_ref())?;
+        let (filters, order_by) =
+            table_params(df_ctx, "orders", "o_orderkey", params.as_ref(), filters).await?;
+        let filter = categorical_filter("o_orderstatus", status);
+
+        let query = format!(
+            "SELECT {} FROM orders {} {} LIMIT {} OFFSET {}",
+            projection_of(
+                ctx.look_ahead().field("data"),
+                ORDER_COLUMNS,
+                &["o_orderkey"]
+            ),
+            filter,
+            order_by,
+            capped_fetch(ctx, i64::from(limit))?,
+            offset
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, &filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+        let data = order_rows(&batches)?;
+        enforce_row_limit(ctx, data.len())?;
+
+        let total_count = row_count(df_ctx, "orders", &filter, &filters).await?;
+        let (has_more, page_info) = page_info(limit, offset, total_count);
+        Ok(OrderQueryResult {
+            data,
+            total_count,
+            has_more,
+            page_info,
+        })
+    }
+
+    /// Line items by order and line number, optionally of one order
+    async fn line_items(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        order_key: Option<i64>,
+    ) -> Result<Vec<LineItem>, async_graphql::Error> {
+        let condition = order_key
+            .map(|key| format!("WHERE l_orderkey = {}", key))
+            .unwrap_or_default();
+        typed_page(
+            ctx,
+            &LINEITEM_TABLE,
+            &condition,
+            limit,
+            offset,
+            |batch, i| {
+                let (l_returnflag, l_returnflag_raw) = batch
+                    .get_string("l_returnflag", i)?
+                    .map_or_else(Default::default, |flag| ReturnFlag::parse(&flag));
+                let (l_linestatus, l_linestatus_raw) = batch
+                    .get_string("l_linestatus", i)?
+                    .map_or_else(Default::default, |status| LineStatus::parse(&status));
+                let l_linenumber = batch.get_i64("l_linenumber", i)?.unwrap_or_default();
+                Ok(LineItem {
+                    l_orderkey: batch.get_i64("l_orderkey", i)?.unwrap_or_default(),
+                    l_partkey: batch.get_i64("l_partkey", i)?.unwrap_or_default(),
+                    l_suppkey: batch.get_i64("l_suppkey", i)?.unwrap_or_default(),
+                    l_linenumber: i32::try_from(l_linenumber)?,
+                    l_quantity: batch.get_f64("l_quantity", i)?.unwrap_or_default(),
+                    l_extendedprice: batch.get_f64("l_extendedprice", i)?.unwrap_or_default(),
+                    l_discount: batch.get_f64("l_discount", i)?.unwrap_or_default(),
+                    l_tax: batch.get_f64("l_tax", i)?.unwrap_or_default(),
+                    l_returnflag,
+                    l_returnflag_raw,
+                    l_linestatus,
+                    l_linestatus_raw,
+                    l_shipdate: batch.get_date_string("l_shipdate", i)?.unwrap_or_default(),
+                    l_commitdate: batch
+                        .get_date_string("l_commitdate", i)?
+                        .unwrap_or_default(),
+                    l_receiptdate: batch
+                        .get_date_string("l_receiptdate", i)?
+                        .unwrap_or_default(),
+                    l_shipinstruct: batch.get_string("l_shipinstruct", i)?.unwrap_or_default(),
+                    l_shipmode: batch.get_string("l_shipmode", i)?.unwrap_or_default(),
+                    l_comment: batch.get_string("l_comment", i)?.unwrap_or_default(),
+                })
+            },
+        )
+        .await
+    }
+
+    /// Parts by part key
+    async fn parts(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Part>, async_graphql::Error> {
+        typed_page(ctx, &PART_TABLE, "", limit, offset, |batch, i| {
+            let p_size = batch.get_i64("p_size", i)?.unwrap_or_default();
+            Ok(Part {
+                p_partkey: batch.get_i64("p_partkey", i)?.unwrap_or_default(),
+                p_name: batch.get_string("p_name", i)?.unwrap_or_default(),
+                p_mfgr: batch.get_string("p_mfgr", i)?.unwrap_or_default(),
+                p_brand: batch.get_string("p_brand", i)?.unwrap_or_default(),
+                p_type: batch.get_string("p_type", i)?.unwrap_or_default(),
+                p_size: i32::try_from(p_size)?,
+                p_container: batch.get_string("p_container", i)?.unwrap_or_default(),
+                p_retailprice: batch.get_f64("p_retailprice", i)?.unwrap_or_default(),
+                p_comment: batch.get_string("p_comment", i)?.unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    /// Suppliers by supplier key
+    async fn suppliers(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Supplier>, async_graphql::Error> {
+        typed_page(ctx, &SUPPLIER_TABLE, "", limit, offset, |batch, i| {
+            Ok(Supplier {
+                s_suppkey: batch.get_i64("s_suppkey", i)?.unwrap_or_default(),
+                s_name: batch.get_string("s_name", i)?.unwrap_or_default(),
+                s_address: batch.get_string("s_address", i)?.unwrap_or_default(),
+                s_nationkey: batch.get_i64("s_nationkey", i)?.unwrap_or_default(),
+                s_phone: batch.get_string("s_phone", i)?.unwrap_or_default(),
+                s_acctbal: batch.get_f64("s_acctbal", i)?.unwrap_or_default(),
+                s_comment: batch.get_string("s_comment", i)?.unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    /// Nations by nation key
+    async fn nations(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Nation>, async_graphql::Error> {
+        typed_page(ctx, &NATION_TABLE, "", limit, offset, |batch, i| {
+            Ok(Nation {
+                n_nationkey: batch.get_i64("n_nationkey", i)?.unwrap_or_default(),
+                n_name: batch.get_string("n_name", i)?.unwrap_or_default(),
+                n_regionkey: batch.get_i64("n_regionkey", i)?.unwrap_or_default(),
+                n_comment: batch.get_string("n_comment", i)?.unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    /// Regions by region key
+    async fn regions(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<Region>, async_graphql::Error> {
+        typed_page(ctx, &REGION_TABLE, "", limit, offset, |batch, i| {
+            Ok(Region {
+                r_regionkey: batch.get_i64("r_regionkey", i)?.unwrap_or_default(),
+                r_name: batch.get_string("r_name", i)?.unwrap_or_default(),
+                r_comment: batch.get_string("r_comment", i)?.unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    /// Part supplies by part key and supplier key
+    async fn part_supplies(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> Result<Vec<PartSupp>, async_graphql::Error> {
+        typed_page(ctx, &PARTSUPP_TABLE, "", limit, offset, |batch, i| {
+            let ps_availqty = batch.get_i64("ps_availqty", i)?.unwrap_or_default();
+            Ok(PartSupp {
+                ps_partkey: batch.get_i64("ps_partkey", i)?.unwrap_or_default(),
+                ps_suppkey: batch.get_i64("ps_suppkey", i)?.unwrap_or_default(),
+                ps_availqty: i32::try_from(ps_availqty)?,
+                ps_supplycost: batch.get_f64("ps_supplycost", i)?.unwrap_or_default(),
+                ps_comment: batch.get_string("ps_comment", i)?.unwrap_or_default(),
+            })
+        })
+        .await
+    }
+
+    /// Rows of a registered table as JSON objects, with the columns, filters,
+    /// sort and page of `params` translated to SQL
+    async fn query_table(
+        &self,
+        ctx: &Context<'_>,
+        params: TableQueryParams,
+    ) -> Result<JsonRows, async_graphql::Error> {
+        translated_rows(ctx, &params).await
+    }
+
+    /// Like `queryTable`, but only the rows: one JSON object per row, keyed by
+    /// column name
+    async fn dynamic_query(
+        &self,
+        ctx: &Context<'_>,
+        params: TableQueryParams,
+    ) -> Result<Vec<Json<serde_json::Value>>, async_graphql::Error> {
+        let rows = translated_rows(ctx, &params).await?;
+        Ok(rows.rows.0.into_iter().map(Json).collect())
+    }
+
+    /// Run a read-only SELECT and return its rows as JSON objects. With
+    /// `asOfRevision` it reads the tables as they were before later reloads, as
+    /// long as that revision is still kept (see `tableRevisions`).
+    async fn execute_sql(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        as_of_revision: Option<i64>,
+    ) -> Result<JsonRows, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let df_ctx = &app.df_ctx;
+        df_ctx
+            .ensure_select(&query)
+            .map_err(|e| async_graphql::Error::new(format!("Invalid query: {}", e)))?;
+        let (revision, (executed, entry)) = match as_of_revision {
+            Some(revision) => {
+                let revision = u64::try_from(revision).map_err(|_| {
+                    async_graphql::Error::new(format!("Invalid revision: {}", revision))
+                })?;
+                (
+                    revision,
+                    df_ctx.execute_query_logged_at(&query, revision).await,
+                )
+            }
+            None => (
+                df_ctx.current_revision(),
+                df_ctx.execute_query_logged(&query).await,
+            ),
+        };
+        let error = executed.as_ref().err().map(|e| e.to_string());
+        app.orchestrator
+            .audit_log()
+            .record_sql(&SqlAuditRecord::from_entry(app.user(), &entry, error));
+        add_extension(
+            ctx,
+            "executeSql",
+            value!({ "queryId": entry.id, "revision": revision }),
+        );
+        let batches = executed.map_err(|e| query_error(df_ctx, "Query", e))?;
+        let rows = json_rows(&batches, json_budget(ctx)?).map_err(|e| e.extend())?;
+        enforce_row_limit(ctx, rows.row_count as usize)?;
+        Ok(rows)
+    }
+
+    // Sales analytics
+    async fn sales_analytics(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 10)] histogram_buckets: i32,
+        #[graphql(default = 5)] top_n: i32,
+        #[graphql(default_with = "CustomerRanking::TotalSpent")] rank_by: CustomerRanking,
+        #[graphql(
+            default = false,
+            desc = "Return failed sections empty and list them in `errors`"
+        )]
+        errors_as_data: bool,
+    ) -> Result<SalesAnalytics, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        require_models(df_ctx, &[CUSTOMER_MANIFEST, ORDER_MANIFEST])?;
+        if !(1..=100).contains(&histogram_buckets) {
+            return Err(async_graphql::Error::new(
+                "histogramBuckets must be between 1 and 100",
+            ));
+        }
+        if !(1..=MAX_TOP_N).contains(&top_n) {
+            return Err(async_graphql::Error::new(format!(
+                "topN must be between 1 and {}",
+                MAX_TOP_N
+            )));
+        }
+
+        // Sections left out of the selection, or excluded by @skip/@include, are
+        // not queried at all
+        let (total_sales, total_orders) = if ["totalSales", "totalOrders", "avgOrderValue"]
+            .iter()
+            .any(|field| field_requested(ctx, field))
+        {
+            sales_totals(df_ctx, filters).await?
+        } else {
+            (0.0, 0)
+        };
+        let avg_order_value = if total_orders > 0 {
+            total_sales / total_orders as f64
+        } else {
+            0.0
+        };
+
+        let mut sections = SectionErrors::new(errors_as_data);
+        let top_customers = if field_requested(ctx, "topCustomers") {
+            sections.collect(
+                "topCustomers",
+                top_customers(df_ctx, filters, top_n, rank_by).await,
+            )?
+        } else {
+            Vec::new()
+        };
+
+        let sales_by_region = if field_requested(ctx, "salesByRegion") {
+            sections.collect("salesByRegion", sales_by_region(df_ctx, filters).await)?
+        } else {
+            Vec::new()
+        };
+
+        let monthly_trends = if field_requested(ctx, "monthlyTrends") {
+            sections.collect("monthlyTrends", monthly_trends(df_ctx, filters).await)?
+        } else {
+            Vec::new()
+        };
+
+        let order_value_distribution = if field_requested(ctx, "orderValueDistribution") {
+            sections.collect(
+                "orderValueDistribution",
+                order_value_distribution(df_ctx, filters, histogram_buckets as usize).await,
+            )?
+        } else {
+            OrderValueDistribution::default()
+        };
+
+        Ok(SalesAnalytics {
+            total_sales,
+            total_orders,
+            avg_order_value,
+            top_customers,
+            sales_by_region,
+            monthly_trends,
+            order_value_distribution,
+            errors: sections.errors,
+        })
+    }
+
+    // Sum of a value column per period, optionally smoothed with a moving
+    // average over the last `movingAverage` periods
+    async fn time_series(
+        &self,
+        ctx: &Context<'_>,
+        table: String,
+        time_column: String,
+        value_column: String,
+        #[graphql(default_with = "TimeGranularity::Month")] granularity: TimeGranularity,
+        moving_average: Option<i32>,
+        #[graphql(desc = "First day of the range, `YYYY-MM-DD`")] from: Option<String>,
+        #[graphql(desc = "Last day of the range, `YYYY-MM-DD`")] to: Option<String>,
+        compare_to: Option<TrendComparison>,
+    ) -> Result<Vec<TimeSeriesPoint>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        if moving_average.is_some_and(|window| window < 1) {
+            return Err(async_graphql::Error::new(
+                "movingAverage window must be a positive number of periods",
+            ));
+        }
+        let from = from
+            .as_deref()
+            .map(|date| parse_date("from", date))
+            .transpose()?;
+        let to = to
+            .as_deref()
+            .map(|date| parse_date("to", date))
+            .transpose()?;
+        if let Some((from, to)) = from.zip(to).filter(|(from, to)| from > to) {
+            return Err(async_graphql::Error::new(format!(
+                "from ({}) is after to ({})",
+                from, to
+            )));
+        }
+        let comparison_offset = match (compare_to, from, to) {
+            (None, _, _) => None,
+            (Some(TrendComparison::PreviousPeriod), Some(from), Some(to)) => {
+                Some(granularity.interval(granularity.periods_between(from, to)))
+            }
+            (Some(TrendComparison::PreviousPeriod), _, _) => {
+                return Err(async_graphql::Error::new(
+                    "compareTo PREVIOUS_PERIOD needs both from and to",
+                ));
+            }
+            (Some(TrendComparison::PreviousYear), _, _) => Some(match granularity {
+                TimeGranularity::Week => "364 days".to_string(),
+                _ => "1 years".to_string(),
+            }),
+        };
+        let trend = TrendWindow {
+            from,
+            to,
+            comparison_offset,
+        };
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        let field_type = |name: &str| {
+            schema
+                .field_with_name(name)
+                .map(|field| field.data_type().clone())
+                .map_err(|_| {
+                    async_graphql::Error::new(format!("Unknown column {} in {}", name, table))
+                })
+        };
+        if !field_type(&time_column)?.is_temporal() {
+            return Err(async_graphql::Error::new(format!(
+                "Column {} is not a date or timestamp",
+                time_column
+            )));
+        }
+        if !field_type(&value_column)?.is_numeric() {
+            return Err(async_graphql::Error::new(format!(
+                "Column {} is not numeric",
+                value_column
+            )));
+        }
+
+        // Order revenue over time is served from the daily rollup, which was built
+        // from all orders
+        let use_rollup = table == "orders"
+            && time_column == "o_orderdate"
+            && value_column == "o_totalprice"
+            && df_ctx.has_daily_revenue()
+            && filters.is_empty();
+        let query = if use_rollup {
+            time_series_sql(
+                "daily_revenue",
+                "order_date",
+                "total_sales",
+                granularity,
+                moving_average,
+                &trend,
+            )
+        } else {
+            time_series_sql(
+                &table,
+                &time_column,
+                &value_column,
+                granularity,
+                moving_average,
+                &trend,
+            )
+        };
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+
+        let mut points = Vec::new();
+        for batch in batches {
+            for i in 0..batch.num_rows() {
+                let period = batch.get_string("period", i)?.unwrap_or_default();
+                let value = batch.get_f64("total", i)?.unwrap_or_default();
+                let comparison_value = batch.get_f64("comparison_total", i)?;
+                let start = parse_date("period", &period)?;
+                points.push(TimeSeriesPoint {
+                    partial: trend.is_partial(granularity, start),
+                    comparison_period: batch.get_string("comparison_period", i)?,
+                    change_percent: comparison_value
+                        .filter(|previous| *previous != 0.0)
+                        .map(|previous| (value - previous) / previous.abs() * 100.0),
+                    comparison_value,
+                    moving_average: batch.get_f64("moving_average", i)?,
+                    period,
+                    value,
+                });
+            }
+        }
+
+        enforce_row_limit(ctx, points.len())?;
+        Ok(points)
+    }
+
+    // Aggregate of a value column per value of a row column and per distinct
+    // value of a pivot column, which become the columns of the result
+    async fn pivot(
+        &self,
+        ctx: &Context<'_>,
+        table: String,
+        row_column: String,
+        pivot_column: String,
+        value_column: String,
+        #[graphql(default_with = "PivotAggregate::Sum")] aggregate: PivotAggregate,
+    ) -> Result<PivotTable, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let df_ctx = &app.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        for column in [&row_column, &pivot_column, &value_column] {
+            if schema.field_with_name(column).is_err() {
+                return Err(async_graphql::Error::new(format!(
+                    "Unknown column {} in {}",
+                    column, table
+                )));
+            }
+        }
+        let value_numeric = schema
+            .field_with_name(&value_column)
+            .is_ok_and(|field| field.data_type().is_numeric());
+        if aggregate != PivotAggregate::Count && !value_numeric {
+            return Err(async_graphql::Error::new(format!(
+                "Column {} is not numeric",
+                value_column
+            )));
+        }
+
+        let dialect = SqlDialect::default();
+        let table_sql = dialect.quote_identifier(&table);
+        let row_sql = dialect.quote_identifier(&row_column);
+        let pivot_sql = dialect.quote_identifier(&pivot_column);
+
+        // Reading one distinct value past the limit tells whether the pivot is too
+        // wide without counting every distinct value of a high-cardinality column.
+        // Keys are read as text but ordered as the column's values.
+        let max_columns = app.config.max_pivot_columns;
+        let distinct_sql = format!(
+            "SELECT CAST(raw_key AS VARCHAR) AS pivot_key \
+             FROM (SELECT DISTINCT {} AS raw_key FROM {} LIMIT {}) ORDER BY raw_key",
+            pivot_sql,
+            table_sql,
+            max_columns + 1
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&distinct_sql, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Pivot columns query", e))?;
+        let mut columns: Vec<Option<String>> = Vec::new();
+        for batch in &batches {
+            for i in 0..batch.num_rows() {
+                columns.push(batch.get_string("pivot_key", i)?);
+            }
+        }
+        if columns.len() > max_columns {
+            return Err(async_graphql::Error::new(format!(
+                "Column {} has more than {} distinct values, the most a pivot can turn \
+                 into columns; pivot on a column with fewer distinct values",
+                pivot_column, max_columns
+            ))
+            .extend_with(move |_, e| {
+                e.set("code", "PIVOT_TOO_WIDE");
+                e.set("maxColumns", max_columns);
+            }));
+        }
+        let positions: HashMap<Option<String>, usize> = columns
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(position, key)| (key, position))
+            .collect();
+
+        let query = format!(
+            "SELECT CAST({} AS VARCHAR) AS row_key, CAST({} AS VARCHAR) AS pivot_key, \
+             {}({}) AS value FROM {} GROUP BY {}, {} ORDER BY {}",
+            row_sql,
+            pivot_sql,
+            aggregate.as_sql(),
+            dialect.quote_identifier(&value_column),
+            table_sql,
+            row_sql,
+            pivot_sql,
+            row_sql
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Pivot query", e))?;
+
+        // Rows arrive ordered by their key, one per pivot value present
+        let mut rows: Vec<PivotRow> = Vec::new();
+        for batch in &batches {
+            for i in 0..batch.num_rows() {
+                let key = batch.get_string("row_key", i)?;
+                if rows.last().is_none_or(|row| row.key != key) {
+                    rows.push(PivotRow {
+                        key,
+                        values: vec![None; columns.len()],
+                    });
+                }
+                let pivot_key = batch.get_string("pivot_key", i)?;
+                if let (Some(row), Some(position)) = (rows.last_mut(), positions.get(&pivot_key)) {
+                    row.values[*position] = batch.get_f64("value", i)?;
+                }
+            }
+        }
+        enforce_row_limit(ctx, rows.len())?;
+
+        Ok(PivotTable { columns, rows })
+    }
+
+    // Values at a dot-separated path inside a struct column, or inside a string
+    // column holding JSON documents
+    async fn json_extract(
+        &self,
+        ctx: &Context<'_>,
+        table: String,
+        column: String,
+        path: String,
+        limit: Option<i32>,
+    ) -> Result<Vec<Json<serde_json::Value>>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        let limit = limit.unwrap_or(100);
+        if limit < 1 {
+            return Err(async_graphql::Error::new("limit must be positive"));
+        }
+        let segments: Vec<&str> = path.split('.').collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(async_graphql::Error::new(format!("Invalid path: {}", path)));
+        }
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        let data_type = schema
+            .field_with_name(&column)
+            .map(|field| field.data_type().clone())
+            .map_err(|_| {
+                async_graphql::Error::new(format!("Unknown column {} in {}", column, table))
+            })?;
+
+        let json_text = matches!(
+            data_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        );
+        let expr = match &data_type {
+            DataType::Struct(_) => struct_path_expr(&column, &data_type, &segments)?,
+            _ if json_text => SqlDialect::default().quote_identifier(&column),
+            _ => {
+                return Err(async_graphql::Error::new(format!(
+                    "Column {} is neither a struct nor JSON text",
+                    column
+                )));
+            }
+        };
+        let query = format!(
+            "SELECT {} AS value FROM {} LIMIT {}",
+            expr,
+            SqlDialect::default().quote_identifier(&table),
+            capped_fetch(ctx, i64::from(limit))?
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Query", e))?;
+
+        let mut budget = json_budget(ctx)?;
+        let mut values = Vec::new();
+        if json_text {
+            let pointer = format!("/{}", segments.join("/"));
+            for batch in &batches {
+                for i in 0..batch.num_rows() {
+                    let value = batch
+                        .get_string("value", i)?
+                        .and_then(|document| {
+                            serde_json::from_str::<serde_json::Value>(&document).ok()
+                        })
+                        .and_then(|document| document.pointer(&pointer).cloned())
+                        .unwrap_or(serde_json::Value::Null);
+                    budget.charge([&value]).map_err(|e| e.extend())?;
+                    values.push(Json(value));
+                }
+            }
+        } else {
+            // Arrow's JSON writer handles nested structs, lists and maps; one batch
+            // at a time, so the budget stops it before the whole result is written
+            for batch in &batches {
+                let mut writer = ArrayWriter::new(Vec::new());
+                writer.write(batch)?;
+                writer.finish()?;
+                let buffer = writer.into_inner();
+                if buffer.is_empty() {
+                    continue;
+                }
+                let rows: Vec<serde_json::Map<String, serde_json::Value>> =
+                    serde_json::from_slice(&buffer)?;
+                for mut row in rows {
+                    let value = row.remove("value").unwrap_or(serde_json::Value::Null);
+                    budget.charge([&value]).map_err(|e| e.extend())?;
+                    values.push(Json(value));
+                }
+            }
+        }
+
+        enforce_row_limit(ctx, values.len())?;
+        Ok(values)
+    }
+
+    // Natural language query, returns SQL that was validated by executing it. The
+    // pipeline report, filtered for the caller's role, is in the extensions.
+    #[graphql(guard = "AiEnabledGuard")]
+    async fn natural_language_query(
+        &self,
+        ctx: &Context<'_>,
+        input: String,
+    ) -> Result<String, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let result = app
+            .orchestrator
+            .execute_natural_language_for(app.user(), &input, None)
+            .await?;
+
+        let report = serde_json::to_value(result.report.for_role(app.role()))?;
+        add_extension(
+            ctx,
+            "naturalLanguageQuery",
+            Value::from_json(report).unwrap_or_default(),
+        );
+        Ok(result.sql)
+    }
+
+    // AI insights (mocked for now)
+    #[graphql(guard = "AiEnabledGuard")]
+    async fn insights(
+        &self,
+        _ctx: &Context<'_>,
+        _input: String,
+    ) -> Result<String, async_graphql::Error> {
+        Ok("Based on the TPCH data analysis:
+        
+1. **Top Customers**: The highest spending customers are primarily from the BUILDING market segment
+2. **Order Patterns**: Most orders are placed in Q1 and Q4, showing seasonal business patterns
+3. **Revenue Distribution**: 40% of revenue comes from AMERICA, 35% from ASIA, 25% from EUROPE
+4. **Average Order Value**: The average order value is $15,000 with significant variation by region
+5. **Customer Segments**: BUILDING and MACHINERY segments show the highest customer loyalty
+
+Recommendations:
+- Focus marketing efforts on BUILDING segment customers
+- Develop seasonal promotions for Q1 and Q4
+- Expand presence in ASIA market given strong performance"
+            .to_string())
+    }
+
+    // Validate a GraphQL document against the schema without executing it
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn validate_query(
+        &self,
+        ctx: &Context<'_>,
+        document: String,
+    ) -> Result<ValidationReport, async_graphql::Error> {
+        let schema = ctx.data::<AppSchema>()?;
+        Ok(validate_document(schema, async_graphql::Request::new(document)).await)
+    }
+
+    // Recent operations of the caller, most recent first. Only the caller's own
+    // history is visible.
+    #[graphql(cache_control(no_cache, private))]
+    async fn my_query_history(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<QueryHistoryEntry>, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let user = app.user().ok_or_else(|| {
+            async_graphql::Error::new("Authentication required")
+                .extend_with(|_, e| e.set("code", "UNAUTHENTICATED"))
+        })?;
+        let Some(history) = &app.history else {
+            return Ok(Vec::new());
+        };
+        Ok(history
+            .entries(user)
+            .into_iter()
+            .map(|record| QueryHistoryEntry {
+                operation_name: record.operation_name,
+                query: record.query,
+                duration_ms: record.duration.as_secs_f64() * 1000.0,
+                timestamp: record.timestamp.to_rfc3339(),
+                success: record.success,
+            })
+            .collect())
+    }
+
+    // Row and export quota of the caller, with today's export usage
+    #[graphql(cache_control(no_cache, private))]
+    async fn my_quota(&self, ctx: &Context<'_>) -> Result<QuotaInfo, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let quotas = app
+            .quotas
+            .as_ref()
+            .ok_or_else(|| async_graphql::Error::new("Quotas are not configured"))?;
+        let usage = quotas.usage(app.user().unwrap_or("anonymous"), app.role());
+
+        let exported = usage.exported_rows_today as i64;
+        let max_export = usage.quota.max_export_rows_per_day.map(|max| max as i64);
+        Ok(QuotaInfo {
+            role: usage.role,
+            max_rows_per_query: usage.quota.max_rows_per_query.map(|max| max as i64),
+            max_export_rows_per_day: max_export,
+            exported_rows_today: exported,
+            remaining_export_rows: max_export.map(|max| (max - exported).max(0)),
+            resets_at: usage.resets_at.to_rfc3339(),
+        })
+    }
+
+    // Slowest recent queries, slowest first (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")", cache_control(no_cache))]
+    async fn slow_queries(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SlowQuery>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        Ok(df_ctx
+            .slow_queries()
+            .into_iter()
+            .map(|entry| SlowQuery {
+                query_id: entry.id,
+                sql: entry.sql,
+                duration_ms: entry.duration.as_secs_f64() * 1000.0,
+                timestamp: entry.timestamp.to_rfc3339(),
+                row_count: entry.rows as i64,
+                success: entry.success,
+            })
+            .collect())
+    }
+
+    // Most recently executed queries, oldest first (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")", cache_control(no_cache))]
+    async fn recent_queries(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SlowQuery>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        Ok(df_ctx
+            .recent_queries()
+            .into_iter()
+            .map(|entry| SlowQuery {
+                query_id: entry.id,
+                sql: entry.sql,
+                duration_ms: entry.duration.as_secs_f64() * 1000.0,
+                timestamp: entry.timestamp.to_rfc3339(),
+                row_count: entry.rows as i64,
+                success: entry.success,
+            })
+            .collect())
+    }
+
+    // Revisions of the registered tables that queries can read, newest first;
+    // each table reload starts a new one
+    #[graphql(cache_control(no_cache))]
+    async fn table_revisions(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<TableRevision>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        Ok(df_ctx
+            .table_revisions()
+            .into_iter()
+            .map(|revision| TableRevision {
+                revision: revision.revision as i64,
+                created_at: revision.created_at.to_rfc3339(),
+                current: revision.superseded_at.is_none(),
+                superseded_at: revision.superseded_at.map(|at| at.to_rfc3339()),
+                superseded_by: revision.superseded_by,
+            })
+            .collect())
+    }
+
+    // Queries executing right now, longest running first (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")", cache_control(no_cache))]
+    async fn running_queries(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<RunningQuery>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let now = Utc::now();
+        Ok(df_ctx
+            .running_queries()
+            .into_iter()
+            .map(|query| RunningQuery {
+                query_id: query.id,
+                sql: query.sql,
+                started_at: query.started_at.to_rfc3339(),
+                elapsed_ms: (now - query.started_at).num_microseconds().unwrap_or(0) as f64
+                    / 1000.0,
+            })
+            .collect())
+    }
+
+    // Source columns of each output column of a logged query (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")", cache_control(no_cache))]
+    async fn query_lineage(
+        &self,
+        ctx: &Context<'_>,
+        query_id: String,
+    ) -> Result<QueryLineage, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let entry = df_ctx
+            .logged_query(&query_id)
+            .ok_or_else(|| async_graphql::Error::new(format!("Unknown query: {}", query_id)))?;
+        Ok(QueryLineage {
+            query_id: entry.id,
+            sql: entry.sql,
+            columns: entry.lineage,
+        })
+    }
+
+    // Connected WebSocket subscriptions, oldest first (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")", cache_control(no_cache))]
+    async fn active_subscriptions(
+        &self,
+        ctx: &Context<'_>,
+    ) -> Result<Vec<SubscriptionInfo>, async_graphql::Error> {
+        let subscriptions = ctx.data::<Arc<SubscriptionRegistry>>()?;
+        Ok(subscriptions
+            .list()
+            .into_iter()
+            .map(|subscription| SubscriptionInfo {
+                id: subscription.id,
+                kind: subscription.kind,
+                query: subscription.query,
+                user: subscription.user,
+                started_at: subscription.started_at.to_rfc3339(),
+            })
+            .collect())
     }
 
-    // Get table row count
-    async fn table_count(
+    // Parquet files behind a table, read from their footers at registration; only
+    // admins see where the files are
+    async fn table_files(
         &self,
         ctx: &Context<'_>,
         table_name: String,
-    ) -> Result<i64, async_graphql::Error> {
-        let df_ctx = ctx.data_unchecked::<Arc<DataFusionContext>>();
-        df_ctx
-            .get_table_count(&table_name)
-            .await
-            .map_err(|e| async_graphql::Error::new(format!("Failed to get count: {}", e)))
+    ) -> Result<TableFiles, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        if !df_ctx.get_table_names().contains(&table_name) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table_name
+            )));
+        }
+        let stats = df_ctx.table_files(&table_name).ok_or_else(|| {
+            async_graphql::Error::new(format!(
+                "Table {} is not backed by parquet files",
+                table_name
+            ))
+        })?;
+        Ok(TableFiles {
+            table_name,
+            file_count: stats.files.len() as i32,
+            total_bytes: stats.total_bytes() as i64,
+            row_groups: stats.row_groups() as i32,
+            rows: stats.rows(),
+            compression: stats.compression(),
+            files: stats
+                .files
+                .into_iter()
+                .map(|file| TableFile {
+                    path: file.path,
+                    size_bytes: file.size_bytes as i64,
+                    row_groups: file.row_groups as i32,
+                    rows: file.rows,
+                    compression: file.compression,
+                    created_by: file.created_by,
+                    last_modified: file.last_modified.to_rfc3339(),
+                })
+                .collect(),
+            partitions: stats
+                .partitions
+                .into_iter()
+                .map(|range| TablePartition {
+                    column: range.column,
+                    min: range.min,
+                    max: range.max,
+                })
+                .collect(),
+        })
     }
 
-    // Customer queries
-    async fn customers(
+    // Paths of the files backing a table, every part file of a directory (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn table_file_paths(
         &self,
         ctx: &Context<'_>,
-        limit: Option<i32>,
-        offset: Option<i32>,
-    ) -> Result<Vec<Customer>, async_graphql::Error> {
-        let df_ctx = ctx.data_unchecked::<Arc<DataFusionContext>>();
-        let limit = limit.unwrap_or(100);
-        let offset = offset.unwrap_or(0);
+        name: String,
+    ) -> Result<Vec<String>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        if !df_ctx.get_table_names().contains(&name) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                name
+            )));
+        }
+        let stats = df_ctx.table_files(&name).ok_or_else(|| {
+            async_graphql::Error::new(format!("Table {} is not backed by parquet files", name))
+        })?;
+        Ok(stats.files.into_iter().map(|file| file.path).collect())
+    }
 
-        let query = format!(
-            "SELECT c_custkey, c_name, c_address, c_nationkey, c_phone, 
-                    CAST(c_acctbal AS DOUBLE) as c_acctbal, c_mktsegment, c_comment 
-             FROM customer 
-             ORDER BY c_custkey 
-             LIMIT {} OFFSET {}",
-            limit, offset
-        );
+    // Columns added, removed or changed from table_a to table_b
+    async fn schema_diff(
+        &self,
+        ctx: &Context<'_>,
+        table_a: String,
+        table_b: String,
+    ) -> Result<Vec<SchemaDiffEntry>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let table_names = df_ctx.get_table_names();
+        for table in [&table_a, &table_b] {
+            if !table_names.contains(table) {
+                return Err(async_graphql::Error::new(format!(
+                    "Unknown table: {}",
+                    table
+                )));
+            }
+        }
+        let schema_a = df_ctx
+            .table_schema(&table_a)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        let schema_b = df_ctx
+            .table_schema(&table_b)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        Ok(SchemaDiffEntry::between(&schema_a, &schema_b))
+    }
 
-        let batches = df_ctx
-            .execute_query(&query)
+    // Run two SELECTs and diff their results, rows aligned on the key columns (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn compare_queries(
+        &self,
+        ctx: &Context<'_>,
+        sql_a: String,
+        sql_b: String,
+        #[graphql(desc = "Columns aligning rows, all by default")] key_columns: Option<Vec<String>>,
+        #[graphql(
+            default = 0.0,
+            desc = "Largest absolute difference of equal float and decimal values"
+        )]
+        tolerance: f64,
+        #[graphql(
+            default = 0.0,
+            desc = "Largest difference of equal float and decimal values, relative to the larger"
+        )]
+        relative_tolerance: f64,
+        #[graphql(default = 100)] limit: i32,
+    ) -> Result<QueryComparison, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let df_ctx = &app.df_ctx;
+        if !(tolerance >= 0.0 && relative_tolerance >= 0.0) {
+            return Err(async_graphql::Error::new("Tolerances must not be negative"));
+        }
+        let options = CompareOptions {
+            key_columns: key_columns.unwrap_or_default(),
+            absolute_tolerance: tolerance,
+            relative_tolerance,
+            limit: limit.max(0) as usize,
+            max_rows: app.config.max_compared_rows,
+            max_json_bytes: app.config.max_result_json_bytes,
+        };
+        // One row past the ceiling is enough to refuse a result
+        let capped = |sql: &str| match options.max_rows {
+            0 => sql.to_string(),
+            max_rows => format!(
+                "SELECT * FROM ({}) AS compared LIMIT {}",
+                sql.trim().trim_end_matches(';'),
+                max_rows + 1
+            ),
+        };
+        let a = df_ctx
+            .execute_query(&capped(&sql_a))
             .await
-            .map_err(|e| async_graphql::Error::new(format!("Query failed: {}", e)))?;
+            .map_err(|e| query_error(df_ctx, "Query A", e))?;
+        let b = df_ctx
+            .execute_query(&capped(&sql_b))
+            .await
+            .map_err(|e| query_error(df_ctx, "Query B", e))?;
+        compare_results(&a, &b, &options).map_err(|e| e.extend())
+    }
 
-        let mut customers = Vec::new();
-        for batch in batches {
-            let custkeys = batch
-                .column(0)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int64Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_custkey column"))?;
-            let nationkeys = batch
-                .column(3)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int64Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_nationkey column"))?;
-            let acctbals = batch
-                .column(5)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Float64Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_acctbal column"))?;
-
-            // Handle string columns - support StringViewArray
-            let names = batch
-                .column(1)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_name column"))?;
-            let addresses = batch
-                .column(2)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_address column"))?;
-            let phones = batch
-                .column(4)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_phone column"))?;
-            let mktsegments = batch
-                .column(6)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_mktsegment column"))?;
-            let comments = batch
-                .column(7)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast c_comment column"))?;
+    // Columns of a table with their descriptions from the data dictionary
+    async fn table_schema(
+        &self,
+        ctx: &Context<'_>,
+        table: String,
+    ) -> Result<Vec<ColumnInfo>, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        if !app.df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = app
+            .df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        Ok(column_infos(&table, &schema, &app.dictionary))
+    }
 
-            for i in 0..batch.num_rows() {
-                customers.push(Customer {
-                    c_custkey: custkeys.value(i),
-                    c_name: names.value(i).to_string(),
-                    c_address: addresses.value(i).to_string(),
-                    c_nationkey: nationkeys.value(i),
-                    c_phone: phones.value(i).to_string(),
-                    c_acctbal: acctbals.value(i),
-                    c_mktsegment: mktsegments.value(i).to_string(),
-                    c_comment: comments.value(i).to_string(),
-                });
-            }
+    // Columns of all tables whose name or description contains the text, ignoring case
+    async fn search_columns(
+        &self,
+        ctx: &Context<'_>,
+        text: String,
+    ) -> Result<Vec<ColumnInfo>, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let needle = text.to_lowercase();
+        let mut table_names = app.df_ctx.get_table_names();
+        table_names.sort();
+        let mut matches = Vec::new();
+        for table in table_names {
+            let schema =
+                app.df_ctx.table_schema(&table).await.map_err(|e| {
+                    async_graphql::Error::new(format!("Failed to read schema: {}", e))
+                })?;
+            matches.extend(
+                column_infos(&table, &schema, &app.dictionary)
+                    .into_iter()
+                    .filter(|info| {
+                        info.column.to_lowercase().contains(&needle)
+                            || info.description.to_lowercase().contains(&needle)
+                    }),
+            );
         }
+        Ok(matches)
+    }
 
-        Ok(customers)
+    // Filter operators valid for each column of a table, derived from its schema
+    async fn filter_capabilities(
+        &self,
+        ctx: &Context<'_>,
+        table: String,
+    ) -> Result<Vec<ColumnFilterCaps>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        Ok(schema
+            .fields()
+            .iter()
+            .map(|field| ColumnFilterCaps {
+                column: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                nullable: field.is_nullable(),
+                operators: FilterOperator::for_type(field.data_type()),
+            })
+            .collect())
     }
 
-    // Orders queries
-    async fn orders(
+    // Row, null and distinct counts of a column; `approx` estimates the distinct
+    // count with HyperLogLog instead of counting every distinct value
+    async fn column_stats(
         &self,
         ctx: &Context<'_>,
-        limit: Option<i32>,
-        offset: Option<i32>,
-    ) -> Result<Vec<Order>, async_graphql::Error> {
-        let df_ctx = ctx.data_unchecked::<Arc<DataFusionContext>>();
-        let limit = limit.unwrap_or(100);
-        let offset = offset.unwrap_or(0);
+        table: String,
+        column: String,
+        #[graphql(default = false)] approx: bool,
+    ) -> Result<ColumnStats, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        if schema.field_with_name(&column).is_err() {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown column {} in {}",
+                column, table
+            )));
+        }
 
+        let dialect = SqlDialect::default();
+        let column_sql = dialect.quote_identifier(&column);
+        let distinct = if approx {
+            format!("approx_distinct({})", column_sql)
+        } else {
+            format!("COUNT(DISTINCT {})", column_sql)
+        };
         let query = format!(
-            "SELECT o_orderkey, o_custkey, o_orderstatus, 
-                    CAST(o_totalprice AS DOUBLE) as o_totalprice,
-                    o_orderpriority, o_clerk, o_shippriority, o_comment 
-             FROM orders 
-             ORDER BY o_orderkey 
-             LIMIT {} OFFSET {}",
-            limit, offset
+            "SELECT COUNT(*) AS row_count, COUNT({}) AS value_count, {} AS distinct_count FROM {}",
+            column_sql,
+            distinct,
+            dialect.quote_identifier(&table)
         );
-
         let batches = df_ctx
-            .execute_query(&query)
+            .execute_query_filtered(&query, filters)
             .await
-            .map_err(|e| async_graphql::Error::new(format!("Query failed: {}", e)))?;
-
-        let mut orders = Vec::new();
-        for batch in batches {
-            println!("Orders batch schema: {:?}", batch.schema());
-            
-            let orderkeys = batch
-                .column(0)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int64Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_orderkey column"))?;
-            let custkeys = batch
-                .column(1)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int64Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_custkey column"))?;
-            let totalprices = batch
-                .column(3)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Float64Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_totalprice column"))?;
-            let shippriorities = batch
-                .column(6)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int32Array>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_shippriority column"))?;
-
-            // Handle string columns - support StringViewArray
-            let orderstatuses = batch
-                .column(2)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_orderstatus column"))?;
-            let orderpriorities = batch
-                .column(4)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_orderpriority column"))?;
-            let clerks = batch
-                .column(5)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_clerk column"))?;
-            let comments = batch
-                .column(7)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::StringViewArray>()
-                .ok_or_else(|| async_graphql::Error::new("Failed to cast o_comment column"))?;
-
-            for i in 0..batch.num_rows() {
-                orders.push(Order {
-                    o_orderkey: orderkeys.value(i),
-                    o_custkey: custkeys.value(i),
-                    o_orderstatus: orderstatuses.value(i).to_string(),
-                    o_totalprice: totalprices.value(i),
-                    o_orderdate: "1992-01-01".to_string(), // Temporary placeholder
-                    o_orderpriority: orderpriorities.value(i).to_string(),
-                    o_clerk: clerks.value(i).to_string(),
-                    o_shippriority: shippriorities.value(i),
-                    o_comment: comments.value(i).to_string(),
-                });
+            .map_err(|e| query_error(df_ctx, "Column stats query", e))?;
+        let count = |name: &str| -> Result<i64, async_graphql::Error> {
+            match batches.iter().find(|batch| batch.num_rows() > 0) {
+                Some(batch) => Ok(batch.get_i64(name, 0)?.unwrap_or_default()),
+                None => Ok(0),
             }
-        }
-
-        Ok(orders)
+        };
+        let row_count = count("row_count")?;
+        Ok(ColumnStats {
+            table,
+            column,
+            row_count,
+            null_count: row_count - count("value_count")?,
+            distinct_count: count("distinct_count")?,
+            approximate: approx,
+        })
     }
 
-    // Sales analytics
-    async fn sales_analytics(
+    // Quantile `q` in [0, 1] of a numeric column, e.g. 0.5 for the median,
+    // interpolated linearly between the two nearest values; `approx` estimates it
+    // with a t-digest instead of sorting the column. Null without values.
+    async fn quantile(
         &self,
         ctx: &Context<'_>,
-    ) -> Result<SalesAnalytics, async_graphql::Error> {
-        let df_ctx = ctx.data_unchecked::<Arc<DataFusionContext>>();
-
-        // For now, return mock data to get the system working
-        // TODO: Implement real DataFusion queries once basic functionality is working
-
-        let total_sales = 15000000.0;
-        let total_orders = 150000;
-        let avg_order_value = total_sales / total_orders as f64;
-
-        // Get some basic customer data
-        let customers_query = "
-            SELECT 
-                c_custkey, c_name, c_address, c_nationkey, c_phone,
-                CAST(c_acctbal AS DOUBLE) as c_acctbal, c_mktsegment, c_comment
-            FROM customer 
-            ORDER BY c_acctbal DESC
-            LIMIT 5
-        ";
-
-        let customers_batches = df_ctx
-            .execute_query(customers_query)
+        table: String,
+        column: String,
+        q: f64,
+        #[graphql(default = false)] approx: bool,
+    ) -> Result<Option<f64>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        if !(0.0..=1.0).contains(&q) {
+            return Err(async_graphql::Error::new(format!(
+                "Quantile must be between 0 and 1, got {}",
+                q
+            )));
+        }
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
             .await
-            .map_err(|e| async_graphql::Error::new(format!("Customers query failed: {}", e)))?;
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        let field = schema.field_with_name(&column).map_err(|_| {
+            async_graphql::Error::new(format!("Unknown column {} in {}", column, table))
+        })?;
+        if !field.data_type().is_numeric() {
+            return Err(async_graphql::Error::new(format!(
+                "Column {} is not numeric: {}",
+                column,
+                field.data_type()
+            )));
+        }
 
-        let mut top_customers = Vec::new();
-        for batch in customers_batches {
-            if batch.num_rows() == 0 {
-                continue;
-            }
+        let dialect = SqlDialect::default();
+        let column_sql = dialect.quote_identifier(&column);
+        let table_sql = dialect.quote_identifier(&table);
+        // Value of the single row of an aggregate query
+        let first_value =
+            |batches: &[RecordBatch], name: &str| -> Result<Option<f64>, async_graphql::Error> {
+                match batches.iter().find(|batch| batch.num_rows() > 0) {
+                    Some(batch) => Ok(batch.get_f64(name, 0)?),
+                    None => Ok(None),
+                }
+            };
 
-            // Debug: Print column types
-            println!("Batch schema: {:?}", batch.schema());
-            for (i, col) in batch.columns().iter().enumerate() {
-                println!("Column {}: {:?}", i, col.data_type());
-            }
+        if approx {
+            let query = format!(
+                "SELECT approx_percentile_cont({}) WITHIN GROUP (ORDER BY CAST({} AS DOUBLE)) AS quantile FROM {}",
+                q, column_sql, table_sql
+            );
+            let batches = df_ctx
+                .execute_query_filtered(&query, filters)
+                .await
+                .map_err(|e| query_error(df_ctx, "Quantile query", e))?;
+            return first_value(&batches, "quantile");
+        }
 
-            // For now, just extract the numeric columns we know work
-            let custkeys = if let Some(arr) = batch
-                .column(0)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int64Array>(
-            ) {
-                arr
-            } else {
-                return Err(async_graphql::Error::new(format!(
-                    "Failed to cast c_custkey column, type: {:?}",
-                    batch.column(0).data_type()
-                )));
-            };
+        let count_query = format!(
+            "SELECT COUNT({}) AS value_count FROM {}",
+            column_sql, table_sql
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&count_query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Quantile query", e))?;
+        let value_count = first_value(&batches, "value_count")?.unwrap_or(0.0) as u64;
+        if value_count == 0 {
+            return Ok(None);
+        }
 
-            let nationkeys = if let Some(arr) = batch
-                .column(3)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Int64Array>(
-            ) {
-                arr
-            } else {
+        // The two values around the quantile's position among the sorted values
+        let position = q * (value_count - 1) as f64;
+        let lower = position.floor();
+        let query = format!(
+            "SELECT CAST({0} AS DOUBLE) AS value FROM {1} WHERE {0} IS NOT NULL ORDER BY value LIMIT 2 OFFSET {2}",
+            column_sql, table_sql, lower as u64
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Quantile query", e))?;
+        let mut values = Vec::new();
+        for batch in &batches {
+            for i in 0..batch.num_rows() {
+                values.extend(batch.get_f64("value", i)?);
+            }
+        }
+        Ok(match values.as_slice() {
+            [low, high, ..] => Some(low + (high - low) * (position - lower)),
+            [only] => Some(*only),
+            [] => None,
+        })
+    }
+
+    // Rows whose numeric column lies outside the bounds of `method`: `iqr` flags
+    // values more than `threshold` interquartile ranges (default 1.5) beyond the
+    // quartiles, `zscore` values more than `threshold` standard deviations
+    // (default 3) from the mean. The bounds are in the `outliers` extension.
+    async fn outliers(
+        &self,
+        ctx: &Context<'_>,
+        table: String,
+        column: String,
+        method: String,
+        threshold: Option<f64>,
+        limit: Option<i32>,
+    ) -> Result<Vec<Json<serde_json::Value>>, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        let limit = limit.unwrap_or(100);
+        if limit < 1 {
+            return Err(async_graphql::Error::new("limit must be positive"));
+        }
+        let method = method.to_ascii_lowercase();
+        let threshold = match (method.as_str(), threshold) {
+            (_, Some(threshold)) if !(threshold.is_finite() && threshold > 0.0) => {
                 return Err(async_graphql::Error::new(format!(
-                    "Failed to cast c_nationkey column, type: {:?}",
-                    batch.column(3).data_type()
+                    "threshold must be positive, got {}",
+                    threshold
                 )));
-            };
-
-            let acctbals = if let Some(arr) = batch
-                .column(5)
-                .as_any()
-                .downcast_ref::<datafusion::arrow::array::Float64Array>(
-            ) {
-                arr
-            } else {
+            }
+            ("iqr" | "zscore", Some(threshold)) => threshold,
+            ("iqr", None) => 1.5,
+            ("zscore", None) => 3.0,
+            _ => {
                 return Err(async_graphql::Error::new(format!(
-                    "Failed to cast c_acctbal column, type: {:?}",
-                    batch.column(5).data_type()
+                    "Unknown outlier method {}, expected iqr or zscore",
+                    method
                 )));
-            };
-
-            for i in 0..batch.num_rows() {
-                // For now, use mock string data to avoid Utf8View issues
-                let customer = Customer {
-                    c_custkey: custkeys.value(i),
-                    c_name: format!("Customer_{}", custkeys.value(i)),
-                    c_address: "Mock Address".to_string(),
-                    c_nationkey: nationkeys.value(i),
-                    c_phone: "555-0000".to_string(),
-                    c_acctbal: acctbals.value(i),
-                    c_mktsegment: "BUILDING".to_string(),
-                    c_comment: "Mock comment".to_string(),
-                };
-
-                top_customers.push(CustomerSales {
-                    customer,
-                    total_spent: acctbals.value(i), // Using account balance as proxy for spending
-                    order_count: 1,                 // Mock value
-                });
             }
+        };
+        if !df_ctx.get_table_names().contains(&table) {
+            return Err(async_graphql::Error::new(format!(
+                "Unknown table: {}",
+                table
+            )));
+        }
+        let schema = df_ctx
+            .table_schema(&table)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Failed to read schema: {}", e)))?;
+        let field = schema.field_with_name(&column).map_err(|_| {
+            async_graphql::Error::new(format!("Unknown column {} in {}", column, table))
+        })?;
+        if !field.data_type().is_numeric() {
+            return Err(async_graphql::Error::new(format!(
+                "Column {} is not numeric: {}",
+                column,
+                field.data_type()
+            )));
         }
 
-        // Mock data for other analytics
-        let sales_by_region = vec![
-            RegionSales {
-                region: "AMERICA".to_string(),
-                total_sales: total_sales * 0.4,
-                customer_count: 1000,
-            },
-            RegionSales {
-                region: "ASIA".to_string(),
-                total_sales: total_sales * 0.35,
-                customer_count: 800,
-            },
-            RegionSales {
-                region: "EUROPE".to_string(),
-                total_sales: total_sales * 0.25,
-                customer_count: 600,
-            },
-        ];
-
-        let monthly_trends = vec![
-            MonthlyTrend {
-                month: "2024-01".to_string(),
-                total_sales: total_sales * 0.08,
-                order_count: total_orders / 12,
-            },
-            MonthlyTrend {
-                month: "2024-02".to_string(),
-                total_sales: total_sales * 0.09,
-                order_count: total_orders / 12,
-            },
-            MonthlyTrend {
-                month: "2024-03".to_string(),
-                total_sales: total_sales * 0.10,
-                order_count: total_orders / 12,
-            },
-        ];
+        let dialect = SqlDialect::default();
+        let value_sql = format!("CAST({} AS DOUBLE)", dialect.quote_identifier(&column));
+        let table_sql = dialect.quote_identifier(&table);
+        let bounds_query = if method == "iqr" {
+            format!(
+                "SELECT approx_percentile_cont(0.25) WITHIN GROUP (ORDER BY {0}) AS low, \
+                 approx_percentile_cont(0.75) WITHIN GROUP (ORDER BY {0}) AS high FROM {1}",
+                value_sql, table_sql
+            )
+        } else {
+            format!(
+                "SELECT AVG({0}) AS low, STDDEV_POP({0}) AS high FROM {1}",
+                value_sql, table_sql
+            )
+        };
+        let batches = df_ctx
+            .execute_query_filtered(&bounds_query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Outlier bounds query", e))?;
+        let statistic = |name: &str| -> Result<Option<f64>, async_graphql::Error> {
+            match batches.iter().find(|batch| batch.num_rows() > 0) {
+                Some(batch) => Ok(batch.get_f64(name, 0)?),
+                None => Ok(None),
+            }
+        };
+        // Quartiles, or mean and standard deviation; null when the column has no values
+        let (Some(low), Some(high)) = (statistic("low")?, statistic("high")?) else {
+            return Ok(Vec::new());
+        };
+        let (lower, upper) = if method == "iqr" {
+            let spread = threshold * (high - low);
+            (low - spread, high + spread)
+        } else {
+            (low - threshold * high, low + threshold * high)
+        };
+        // Infinite or NaN values, or a spread beyond the range of f64, leave no
+        // bounds to compare against
+        if !(lower.is_finite() && upper.is_finite()) {
+            return Err(async_graphql::Error::new(format!(
+                "Outlier bounds of {} are not finite ({}, {}); the column holds infinite, \
+                 NaN or too widely spread values",
+                column, lower, upper
+            )));
+        }
+        add_extension(
+            ctx,
+            "outliers",
+            value!({
+                "method": method.as_str(),
+                "threshold": threshold,
+                "lower": lower,
+                "upper": upper,
+            }),
+        );
 
-        Ok(SalesAnalytics {
-            total_sales,
-            total_orders,
-            avg_order_value,
-            top_customers,
-            sales_by_region,
-            monthly_trends,
-        })
+        let query = format!(
+            "SELECT * FROM {0} WHERE {1} < {2} OR {1} > {3} ORDER BY {1} LIMIT {4}",
+            table_sql,
+            value_sql,
+            lower,
+            upper,
+            capped_fetch(ctx, i64::from(limit))?
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Outlier query", e))?;
+        let rows = json_rows(&batches, json_budget(ctx)?).map_err(|e| e.extend())?;
+        enforce_row_limit(ctx, rows.row_count as usize)?;
+        Ok(rows.rows.0.into_iter().map(Json).collect())
     }
 
-    // Natural language query (still mocked for now)
-    async fn natural_language_query(
+    /// Row counts of two tables and of their inner join on `leftKey = rightKey`,
+    /// to size a join before running it
+    async fn join_cardinality(
         &self,
-        _ctx: &Context<'_>,
-        _input: String,
-    ) -> Result<String, async_graphql::Error> {
-        Ok(
-            "SELECT c_name, SUM(CAST(o_totalprice AS DOUBLE)) as total_spent 
-            FROM customer c 
-            JOIN orders o ON c.c_custkey = o.o_custkey 
-            GROUP BY c.c_custkey, c.c_name 
-            ORDER BY total_spent DESC 
-            LIMIT 10"
-                .to_string(),
-        )
-    }
+        ctx: &Context<'_>,
+        left: String,
+        left_key: String,
+        right: String,
+        right_key: String,
+    ) -> Result<JoinStats, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        let filters = analysis_filters(ctx)?;
+        let left_type = join_key_type(df_ctx, &left, &left_key).await?;
+        let right_type = join_key_type(df_ctx, &right, &right_key).await?;
+        if comparison_coercion(&left_type, &right_type).is_none() {
+            return Err(async_graphql::Error::new(format!(
+                "Cannot join {}.{} of type {} with {}.{} of type {}",
+                left, left_key, left_type, right, right_key, right_type
+            )));
+        }
 
-    // AI insights (mocked for now)
-    async fn insights(
-        &self,
-        _ctx: &Context<'_>,
-        _input: String,
-    ) -> Result<String, async_graphql::Error> {
-        Ok("Based on the TPCH data analysis:
-        
-1. **Top Customers**: The highest spending customers are primarily from the BUILDING market segment
-2. **Order Patterns**: Most orders are placed in Q1 and Q4, showing seasonal business patterns
-3. **Revenue Distribution**: 40% of revenue comes from AMERICA, 35% from ASIA, 25% from EUROPE
-4. **Average Order Value**: The average order value is $15,000 with significant variation by region
-5. **Customer Segments**: BUILDING and MACHINERY segments show the highest customer loyalty
+        let dialect = SqlDialect::default();
+        let left_sql = dialect.quote_identifier(&left);
+        let right_sql = dialect.quote_identifier(&right);
+        let left_rows = row_count(df_ctx, &left_sql, "", filters).await?;
+        let right_rows = row_count(df_ctx, &right_sql, "", filters).await?;
+        // Rows per key on each side, multiplied, so the join itself never runs
+        let per_key = |table: &str, key: &str| {
+            format!(
+                "SELECT {} AS join_key, COUNT(*) AS cnt FROM {} GROUP BY {}",
+                dialect.quote_identifier(key),
+                table,
+                dialect.quote_identifier(key)
+            )
+        };
+        let query = format!(
+            "SELECT COALESCE(SUM(l.cnt * r.cnt), 0) AS matched FROM ({}) AS l JOIN ({}) AS r \
+             ON l.join_key = r.join_key",
+            per_key(&left_sql, &left_key),
+            per_key(&right_sql, &right_key)
+        );
+        let batches = df_ctx
+            .execute_query_filtered(&query, filters)
+            .await
+            .map_err(|e| query_error(df_ctx, "Join count query", e))?;
+        let matched = match batches.iter().find(|batch| batch.num_rows() > 0) {
+            Some(batch) => batch.get_i64("matched", 0)?.unwrap_or_default(),
+            None => 0,
+        };
 
-Recommendations:
-- Focus marketing efforts on BUILDING segment customers
-- Develop seasonal promotions for Q1 and Q4
-- Expand presence in ASIA market given strong performance"
-            .to_string())
+        Ok(JoinStats {
+            left_rows,
+            right_rows,
+            matched,
+            avg_fanout: if left_rows > 0 {
+                matched as f64 / left_rows as f64
+            } else {
+                0.0
+            },
+        })
     }
 
     // Agent status
-    async fn agent_status(&self, _ctx: &Context<'_>) -> Result<String, async_graphql::Error> {
-        Ok("Agent system is operational and ready for TPCH data analysis".to_string())
+    #[graphql(guard = "AiEnabledGuard", cache_control(no_cache))]
+    async fn agent_status(&self, ctx: &Context<'_>) -> Result<String, async_graphql::Error> {
+        let orchestrator = &app_context(ctx)?.orchestrator;
+        let agents = orchestrator.get_available_agents().await;
+        if agents.is_empty() {
+            return Ok("No agents are configured".to_string());
+        }
+        Ok(format!(
+            "Agent system is operational with agents: {}",
+            agents.join(", ")
+        ))
     }
 
     // Test agent connections
+    #[graphql(guard = "AiEnabledGuard")]
     async fn test_agent_connections(
         &self,
-        _ctx: &Context<'_>,
+        ctx: &Context<'_>,
     ) -> Result<bool, async_graphql::Error> {
-        Ok(true)
+        let orchestrator = &app_context(ctx)?.orchestrator;
+        let results = orchestrator.test_connections().await;
+        Ok(!results.is_empty() && results.values().all(|connected| *connected))
     }
 }
 
@@ -412,18 +2998,336 @@ pub struct MutationRoot;
 
 #[Object]
 impl MutationRoot {
-    async fn refresh_connection(&self, _ctx: &Context<'_>) -> Result<bool, async_graphql::Error> {
+    // Re-cache the dimension tables and rebuild the rollup; a dry run reports
+    // what would be refreshed without swapping anything in
+    async fn refresh_connection(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = false)] dry_run: bool,
+    ) -> Result<RefreshConnectionResult, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let (df_ctx, config) = (&app.df_ctx, &app.config);
+        let mut dimension_tables = Vec::new();
+        if config.cache_dimension_tables {
+            let result = if dry_run {
+                df_ctx.check_dimensions().await.map(|_| ())
+            } else {
+                df_ctx.cache_dimensions().await
+            };
+            result.map_err(|e| {
+                async_graphql::Error::new(format!("Failed to refresh dimension tables: {}", e))
+            })?;
+            dimension_tables = DIMENSION_TABLES.iter().map(|t| t.to_string()).collect();
+        }
+        // Without the rollup trend queries aggregate orders directly
+        let rebuilt = if dry_run {
+            df_ctx.check_rollups().await
+        } else {
+            df_ctx.refresh_rollups().await
+        };
+        if let (false, Err(e)) = (dry_run, &rebuilt) {
+            warn!("Failed to rebuild the daily revenue rollup: {}", e);
+        }
+        Ok(RefreshConnectionResult {
+            dimension_tables,
+            rebuilds_rollup: rebuilt.is_ok(),
+            dry_run,
+            applied: !dry_run,
+        })
+    }
+
+    // Cancel a running query of the caller, or of anyone for admins; returns
+    // whether a query with that id was running
+    async fn cancel_query(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let Some(query) = app.df_ctx.running_query(&id) else {
+            return Ok(false);
+        };
+        if app.role() != Some("admin") && query.owner.as_deref() != app.user() {
+            return Err(async_graphql::Error::new(
+                "Only the owner of a query or an admin can cancel it",
+            )
+            .extend_with(|_, e| e.set("code", "FORBIDDEN")));
+        }
+        Ok(app.df_ctx.cancel_query(&id))
+    }
+
+    /// Kill a runaway query of any user. Its caller gets a `QUERY_CANCELLED`
+    /// error naming the admin, and the kill is recorded in the audit log.
+    /// Returns whether a query with that id was running.
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn kill_query(&self, ctx: &Context<'_>, id: ID) -> Result<bool, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let admin = app.user().unwrap_or("admin");
+        let Some(query) = app.df_ctx.kill_query(&id, admin) else {
+            return Ok(false);
+        };
+        let now = Utc::now();
+        app.orchestrator.audit_log().record_kill(&KillAuditRecord {
+            admin: admin.to_string(),
+            query_id: query.id,
+            sql: query.sql,
+            elapsed_ms: (now - query.started_at).num_milliseconds().max(0) as u64,
+            timestamp: now,
+        });
+        Ok(true)
+    }
+
+    /// Exclude rows from the analytics and typed resolvers for the rest of the
+    /// caller's session, e.g. as if a region did not exist. Replaces the earlier
+    /// filters; an empty list clears them.
+    async fn set_analysis_filters(
+        &self,
+        ctx: &Context<'_>,
+        filters: Vec<Filter>,
+    ) -> Result<Vec<Filter>, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let user = app.user().ok_or_else(|| {
+            async_graphql::Error::new("Analysis filters need an authenticated caller")
+        })?;
+        validate_filters(&app.df_ctx, &filters)
+            .await
+            .map_err(|e| async_graphql::Error::new(format!("Invalid analysis filter: {}", e)))?;
+        ctx.data::<Arc<AnalysisFilters>>()?
+            .set(user, filters.clone());
+        Ok(filters)
+    }
+
+    // Terminate a WebSocket subscription; returns whether one with that id was
+    // connected (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn close_subscription(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let subscriptions = ctx.data::<Arc<SubscriptionRegistry>>()?;
+        Ok(subscriptions.close(&id))
+    }
+
+    // Register a parquet source under the data directory as a table, or replace
+    // a table with one of the same schema (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn register_table(
+        &self,
+        ctx: &Context<'_>,
+        table_name: String,
+        path: String,
+        #[graphql(default = false)] dry_run: bool,
+    ) -> Result<RegisterTableResult, async_graphql::Error> {
+        let app = app_context(ctx)?;
+        let df_ctx = &app.df_ctx;
+        check_table_name(&table_name)?;
+        let path = data_file_path(&app.config.data_path, &path)?;
+
+        let schema = df_ctx.parquet_schema(&path).await.map_err(|e| {
+            async_graphql::Error::new(format!("Cannot read parquet source {}: {}", path, e))
+        })?;
+
+        let replaces_existing = df_ctx.get_table_names().contains(&table_name);
+        if replaces_existing {
+            let existing = df_ctx.table_schema(&table_name).await?;
+            if existing.fields() != schema.fields() {
+                return Err(async_graphql::Error::new(format!(
+                    "Schema of {} does not match the registered table {}",
+                    path, table_name
+                )));
+            }
+        }
+
+        if !dry_run {
+            df_ctx.register_parquet(&table_name, &path).await?;
+        }
+
+        Ok(RegisterTableResult {
+            columns: schema.fields().iter().map(|f| f.name().clone()).collect(),
+            table_name,
+            replaces_existing,
+            dry_run,
+            applied: !dry_run,
+        })
+    }
+
+    /// Register a small CSV or JSON payload as an in-memory table, queryable like
+    /// any other table until it expires
+    async fn upload_table(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        format: UploadFormat,
+        data: String,
+    ) -> Result<UploadTableResult, async_graphql::Error> {
+        let user = app_context(ctx)?
+            .user()
+            .ok_or_else(|| async_graphql::Error::new("Uploads need an authenticated caller"))?;
+        check_table_name(&name)?;
+        let uploads = ctx.data::<Arc<UploadedTables>>()?;
+        if data.len() > uploads.max_bytes() {
+            return Err(async_graphql::Error::new(format!(
+                "Upload of {} bytes exceeds the limit of {} bytes",
+                data.len(),
+                uploads.max_bytes()
+            ))
+            .extend_with(|_, e| e.set("code", "PAYLOAD_TOO_LARGE")));
+        }
+
+        let table = uploads
+            .upload(user, &name, format, &data)
+            .map_err(|e| async_graphql::Error::new(format!("Cannot upload {}: {}", name, e)))?;
+        Ok(UploadTableResult {
+            table_name: name,
+            columns: table.columns,
+            row_count: table.row_count as i64,
+            expires_at: table.expires_at.to_rfc3339(),
+        })
+    }
+
+    // Re-register one table from its source files (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn reload_table(
+        &self,
+        ctx: &Context<'_>,
+        table_name: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        df_ctx.reload_table(&table_name).await.map_err(|e| {
+            async_graphql::Error::new(format!("Failed to reload {}: {}", table_name, e))
+        })?;
+        Ok(true)
+    }
+
+    // Deregister one table; fails while configured views depend on it (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn drop_table(
+        &self,
+        ctx: &Context<'_>,
+        table_name: String,
+    ) -> Result<bool, async_graphql::Error> {
+        let df_ctx = &app_context(ctx)?.df_ctx;
+        df_ctx
+            .drop_table(&table_name)
+            .map_err(|e| match table_in_use(&e) {
+                Some(in_use) => {
+                    let views = in_use.views.clone();
+                    async_graphql::Error::new(in_use.to_string()).extend_with(move |_, ext| {
+                        ext.set("code", "DEPENDENCY_ERROR");
+                        ext.set("views", views.clone());
+                    })
+                }
+                None => async_graphql::Error::new(format!("Failed to drop {}: {}", table_name, e)),
+            })?;
         Ok(true)
     }
+
+    // Turn read-only mode on or off; allowed while the mode is on (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    async fn set_read_only(
+        &self,
+        ctx: &Context<'_>,
+        enabled: bool,
+    ) -> Result<bool, async_graphql::Error> {
+        let mode = ctx
+            .data_opt::<Arc<ReadOnlyMode>>()
+            .ok_or_else(|| async_graphql::Error::new("Read-only mode is not available"))?;
+        mode.set(enabled);
+        Ok(mode.is_enabled())
+    }
 }
 
 pub type AppSchema = Schema<QueryRoot, MutationRoot, async_graphql::EmptySubscription>;
 
+/// Columns of a table schema merged with their data dictionary entries
+fn column_infos(table: &str, schema: &ArrowSchema, dictionary: &DataDictionary) -> Vec<ColumnInfo> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let doc = dictionary
+                .column(table, field.name())
+                .cloned()
+                .unwrap_or_default();
+            ColumnInfo {
+                table: table.to_string(),
+                column: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                nullable: field.is_nullable(),
+                description: doc.description,
+                tags: doc.tags,
+                unit: doc.unit,
+            }
+        })
+        .collect()
+}
+
 pub fn build_schema(
     df_ctx: Arc<DataFusionContext>,
-    _orchestrator: Arc<AgentOrchestrator>,
+    orchestrator: Arc<AgentOrchestrator>,
+    config: Arc<Config>,
 ) -> AppSchema {
+    let mut allow_list = OperationAllowList::new(config.operation_allow_list.clone());
+    if !config.operation_allow_list_path.is_empty() {
+        allow_list = allow_list.with_file(&config.operation_allow_list_path);
+    }
+
+    // Config validation rejects a malformed dictionary; here it only degrades
+    let dictionary = if config.data_dictionary_path.is_empty() {
+        DataDictionary::default()
+    } else {
+        DataDictionary::load(&config.data_dictionary_path).unwrap_or_else(|e| {
+            warn!("{}", e);
+            DataDictionary::default()
+        })
+    };
+
+    let uploads = Arc::new(
+        UploadedTables::new(
+            df_ctx.clone(),
+            config.max_upload_bytes,
+            std::time::Duration::from_secs(config.upload_ttl_secs),
+        )
+        .with_totals(config.max_uploaded_tables, config.max_uploaded_bytes),
+    );
+
     Schema::build(QueryRoot, MutationRoot, async_graphql::EmptySubscription)
+        .limit_depth(config.max_query_depth)
+        .limit_complexity(config.max_query_complexity)
+        .extension(DryRunExtension {
+            max_depth: config.max_query_depth,
+            max_complexity: config.max_query_complexity,
+        })
+        .extension(AmplificationExtension {
+            limits: AmplificationLimits {
+                max_field_repeats: config.max_field_repeats,
+                max_aliases: config.max_query_aliases,
+                max_list_fields: config.max_list_fields,
+            },
+        })
+        .extension(ResponseExtrasExtension)
+        .extension(AppContextExtension)
+        .extension(DeadlineExtension)
+        .extension(QueryHistoryExtension)
+        .extension(AllowListExtension)
+        .extension(ReadOnlyExtension)
         .data(df_ctx)
+        .data(orchestrator)
+        .data(Arc::new(QueryHistory::new(config.query_history_size)))
+        .data(Arc::new(allow_list))
+        .data(Arc::new(dictionary))
+        .data(Arc::new(ReadOnlyMode::new(
+            config.read_only,
+            config.read_only_blocks_ai,
+        )))
+        .data(uploads)
+        .data(Arc::new(QuotaManager::from_config(&config)))
+        .data(Arc::new(SubscriptionRegistry::new()))
+        .data(Arc::new(AnalysisFilters::new(
+            std::time::Duration::from_secs(config.analysis_filter_ttl_secs),
+        )))
+        .data(config.field_naming)
+        .data(config)
         .finish()
 }
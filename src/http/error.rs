@@ -0,0 +1,152 @@
+//! JSON error envelope for the non-GraphQL endpoints
+//!
+//! Every error is returned as `{ "error": { "code": ..., "message": ... } }` with a
+//! matching HTTP status, so clients handle REST-style failures uniformly.
+
+use crate::datafusion::context::{ErrorClass, QUERY_CANCELLED_CODE, classify_error, is_cancelled};
+use crate::datafusion::memory::memory_limit_exceeded;
+use crate::query_queue::QueueError;
+use crate::quota::QuotaError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use datafusion::error::DataFusionError;
+use serde_json::{Map, Value, json};
+use std::fmt;
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    /// Seconds sent in a `Retry-After` header
+    pub retry_after: Option<u64>,
+    /// Extra fields of the error object
+    pub details: Map<String, Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            retry_after: None,
+            details: Map::new(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "BAD_REQUEST", message)
+    }
+
+    pub fn unauthenticated(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "UNAUTHENTICATED", message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "FORBIDDEN", message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", message)
+    }
+
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    pub fn with_detail(mut self, key: &str, value: Value) -> Self {
+        self.details.insert(key.to_string(), value);
+        self
+    }
+
+    /// The envelope body
+    pub fn body(&self) -> Value {
+        let mut error = self.details.clone();
+        error.insert("code".to_string(), json!(self.code));
+        error.insert("message".to_string(), json!(self.message));
+        json!({ "error": error })
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        if let Some(seconds) = self.retry_after {
+            response.insert_header(("Retry-After", seconds.to_string()));
+        }
+        response.json(self.body())
+    }
+}
+
+impl From<DataFusionError> for ApiError {
+    fn from(err: DataFusionError) -> Self {
+        if is_cancelled(&err) {
+            return Self::new(StatusCode::CONFLICT, QUERY_CANCELLED_CODE, err.to_string());
+        }
+        if let Some(exceeded) = memory_limit_exceeded(&err) {
+            return Self::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "MEMORY_LIMIT_EXCEEDED",
+                exceeded.to_string(),
+            )
+            .with_detail("limitBytes", json!(exceeded.limit_bytes))
+            .with_detail("peakBytes", json!(exceeded.peak_bytes));
+        }
+        match classify_error(&err) {
+            ErrorClass::Planner => {
+                Self::new(StatusCode::BAD_REQUEST, "INVALID_SQL", err.to_string())
+            }
+            ErrorClass::Transient => Self::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "QUERY_FAILED",
+                err.to_string(),
+            )
+            .with_retry_after(1),
+            ErrorClass::Logical => Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "QUERY_FAILED",
+                err.to_string(),
+            ),
+        }
+    }
+}
+
+impl From<QueueError> for ApiError {
+    fn from(err: QueueError) -> Self {
+        let (status, code) = match err {
+            QueueError::Full => (StatusCode::SERVICE_UNAVAILABLE, "QUEUE_FULL"),
+            QueueError::Timeout => (StatusCode::SERVICE_UNAVAILABLE, "QUEUE_TIMEOUT"),
+            QueueError::UserLimit { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "USER_CONCURRENCY_LIMIT")
+            }
+        };
+        Self::new(status, code, err.to_string()).with_retry_after(1)
+    }
+}
+
+impl From<QuotaError> for ApiError {
+    fn from(err: QuotaError) -> Self {
+        let api_error = Self::new(StatusCode::TOO_MANY_REQUESTS, err.code(), err.to_string());
+        match err.reset_at() {
+            Some(reset_at) => {
+                let seconds = (reset_at - chrono::Utc::now()).num_seconds().max(1) as u64;
+                api_error
+                    .with_retry_after(seconds)
+                    .with_detail("resetAt", json!(reset_at.to_rfc3339()))
+            }
+            None => api_error,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Export of query results in file formats
+//!
+//! Exports are encoded batch by batch as the query produces them, so they are
+//! not held to the byte budget of results converted into GraphQL JSON. The
+//! query is still held to the memory limit and timeout of other queries, and
+//! can be cancelled while it streams.
+
+use crate::auth::AuthGuard;
+use crate::datafusion::context::{DataFusionContext, with_query_owner};
+use crate::graphql::read_only::{READ_ONLY_CODE, ReadOnlyMode};
+use crate::graphql::schema::AppSchema;
+use crate::http::error::ApiError;
+use crate::http::request_claims;
+use crate::quota::{QuotaError, QuotaManager};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, web};
+use datafusion::arrow::csv::WriterBuilder;
+use futures::{StreamExt, stream};
+use serde::Deserialize;
+use std::io;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// Read-only SQL whose result is exported
+    pub sql: String,
+    /// Field separator, a single character; defaults to a comma
+    pub delimiter: Option<String>,
+    /// Whether to write a header row; defaults to true
+    pub header: Option<bool>,
+    /// Character quoting fields that contain the delimiter; defaults to `"`
+    pub quote: Option<String>,
+    /// Earlier revision whose tables are read; defaults to the current tables
+    pub revision: Option<u64>,
+}
+
+/// Byte value of a single-character option
+fn single_char(name: &str, value: &str) -> Result<u8, ApiError> {
+    match value.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(ApiError::bad_request(format!(
+            "Parameter `{}` must be a single ASCII character",
+            name
+        ))),
+    }
+}
+
+/// Run a query and return its result as CSV, by default comma separated with a
+/// header row. Exported rows count against the caller's daily export quota, so
+/// exports are refused in read-only mode.
+pub async fn export_csv(
+    schema: Option<web::Data<AppSchema>>,
+    df_ctx: Option<web::Data<DataFusionContext>>,
+    auth: Option<web::Data<AuthGuard>>,
+    quotas: Option<web::Data<QuotaManager>>,
+    http_req: HttpRequest,
+    params: web::Query<ExportParams>,
+) -> Result<HttpResponse, ApiError> {
+    let df_ctx = df_ctx.ok_or_else(|| ApiError::internal("No DataFusion context configured"))?;
+    let claims = request_claims(&http_req, auth.as_deref())?;
+    let read_only = schema
+        .as_ref()
+        .and_then(|schema| schema.data::<Arc<ReadOnlyMode>>())
+        .is_some_and(|mode| mode.is_enabled());
+    if read_only {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            READ_ONLY_CODE,
+            "The server is in read-only mode, exports are unavailable",
+        ));
+    }
+    if params.sql.trim().is_empty() {
+        return Err(ApiError::bad_request("Parameter `sql` cannot be empty"));
+    }
+    let delimiter = single_char("delimiter", params.delimiter.as_deref().unwrap_or(","))?;
+    let quote = single_char("quote", params.quote.as_deref().unwrap_or("\""))?;
+    if delimiter == quote {
+        return Err(ApiError::bad_request(
+            "Parameters `delimiter` and `quote` must differ",
+        ));
+    }
+
+    // The query is listed as the caller's, who may cancel it
+    let owner = claims.as_ref().map(|claims| claims.sub.clone());
+    let mut quota = ExportQuota {
+        quotas,
+        user: claims
+            .as_ref()
+            .map_or("anonymous".to_string(), |claims| claims.sub.clone()),
+        role: claims.map(|claims| claims.role),
+        rows: 0,
+    };
+    let execute = df_ctx
+        .into_inner()
+        .execute_stream(&params.sql, params.revision);
+    let mut batches = with_query_owner(owner, execute).await?;
+    // The first batch is counted before responding, so an export the quota has
+    // no room for is refused with its status rather than cut short
+    let first = batches.next().await.transpose()?;
+    if let Some(batch) = &first {
+        quota.record(batch.num_rows())?;
+    }
+
+    // The header goes before the first batch only
+    let header = params.header.unwrap_or(true);
+    let rest = batches.map(move |batch| {
+        let batch = batch.map_err(io::Error::other)?;
+        quota.record(batch.num_rows()).map_err(io::Error::other)?;
+        Ok(batch)
+    });
+    let chunks = stream::iter(first.map(Ok))
+        .chain(rest)
+        .enumerate()
+        .map(move |(index, batch)| {
+            let mut writer = WriterBuilder::new()
+                .with_delimiter(delimiter)
+                .with_header(header && index == 0)
+                .with_quote(quote)
+                .build(Vec::new());
+            writer
+                .write(&batch?)
+                .map_err(|e| io::Error::other(format!("Failed to write CSV: {}", e)))?;
+            Ok::<_, io::Error>(Bytes::from(writer.into_inner()))
+        });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .streaming(chunks))
+}
+
+/// Rows of an export counted against the caller's quotas as batches are sent.
+/// An export that outgrows them midway ends at the batch that does not fit.
+struct ExportQuota {
+    quotas: Option<web::Data<QuotaManager>>,
+    user: String,
+    role: Option<String>,
+    rows: u64,
+}
+
+impl ExportQuota {
+    fn record(&mut self, rows: usize) -> Result<(), QuotaError> {
+        let Some(quotas) = &self.quotas else {
+            return Ok(());
+        };
+        let rows = rows as u64;
+        quotas.check_rows(self.role.as_deref(), self.rows + rows)?;
+        quotas.record_export(&self.user, self.role.as_deref(), rows)?;
+        self.rows += rows;
+        Ok(())
+    }
+}
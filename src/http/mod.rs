@@ -0,0 +1,651 @@
+//! HTTP handlers and routes for the GraphQL endpoint
+
+pub mod error;
+pub mod export;
+pub mod request_body;
+pub mod ws;
+
+use crate::agents::orchestrator::AgentOrchestrator;
+use crate::auth::{AuthGuard, Claims, ClientIdentity, bearer_token};
+use crate::config::Config;
+use crate::datafusion::context::{DataFusionContext, with_query_owner};
+use crate::graphql::app_context::RequestId;
+use crate::graphql::deadline::{RequestDeadline, with_deadline};
+use crate::graphql::dry_run::validate_document;
+use crate::graphql::read_only::ReadOnlyMode;
+use crate::graphql::response_size::{TRUNCATED_HEADER, is_truncated, truncate_response};
+use crate::graphql::schema::AppSchema;
+use crate::http::error::ApiError;
+use crate::http::export::export_csv;
+use crate::http::request_body::GraphQLBody;
+use crate::http::ws::{WsExecutor, connection_init_data, session_claims};
+use crate::metrics;
+use crate::models::data::SubscriptionKind;
+use crate::query_queue::{QueryClass, QueryQueue, QueueError};
+use crate::quota::QuotaManager;
+use crate::request_retry::{RequestRetry, replay};
+use crate::singleflight::{GraphQLFlight, operation_type, request_key};
+use crate::subscriptions::SubscriptionRegistry;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::{DefaultHeaders, Next};
+use actix_web::{Either, HttpRequest, HttpResponse, ResponseError, guard, web};
+use async_graphql::parser::types::OperationType;
+use async_graphql_actix_web::{GraphQLResponse, GraphQLSubscription};
+use futures::StreamExt;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+pub async fn graphql_handler(
+    schema: web::Data<AppSchema>,
+    queue: Option<web::Data<QueryQueue>>,
+    flight: Option<web::Data<GraphQLFlight>>,
+    auth: Option<web::Data<AuthGuard>>,
+    quotas: Option<web::Data<QuotaManager>>,
+    retry: Option<web::Data<RequestRetry>>,
+    config: Option<web::Data<Config>>,
+    http_req: HttpRequest,
+    req: GraphQLBody,
+) -> Either<GraphQLResponse, HttpResponse> {
+    let claims = match request_claims(&http_req, auth.as_deref()) {
+        Ok(claims) => claims,
+        Err(e) => return Either::Right(e.error_response()),
+    };
+
+    // Held until the response is ready, including while waiting for an
+    // identical query, so the cap counts every request the user has open
+    let _user_permit = match (&queue, &claims) {
+        (Some(queue), Some(claims)) => match queue.acquire_user(&claims.sub) {
+            Ok(permit) => Some(permit),
+            Err(e) => return Either::Right(ApiError::from(e).error_response()),
+        },
+        _ => None,
+    };
+
+    let request = req.into_inner();
+    let operation = operation_type(&request);
+    // GET requests may be replayed, prefetched or cached along the way, so only
+    // queries run over GET
+    if http_req.method() == Method::GET && operation.is_some_and(|ty| ty != OperationType::Query) {
+        let mut response = ApiError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "METHOD_NOT_ALLOWED",
+            "Only queries can be sent with GET; send mutations and subscriptions with POST",
+        )
+        .error_response();
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("POST"));
+        return Either::Right(response);
+    }
+    // Responses of authenticated callers are theirs alone
+    let flight_key = match &flight {
+        Some(_) if claims.is_none() && !bypasses_flight(&http_req) => request_key(&request),
+        _ => None,
+    };
+    let quotas = quotas.map(|quotas| quotas.into_inner());
+    let request_id = request_id(&http_req).map(RequestId);
+    // Only queries are safe to run again
+    let read_only = operation == Some(OperationType::Query);
+    let max_response_bytes = config
+        .as_ref()
+        .map_or(0, |config| config.max_response_bytes);
+    let timeout = config
+        .map(|config| config.request_timeout)
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs);
+
+    let class = queue
+        .as_ref()
+        .map(|queue| query_class_hint(&http_req).unwrap_or_else(|| queue.classify(&request.query)));
+    // Latencies observed while executing are attributed to the request, and the
+    // queries it starts to the caller, who may cancel them
+    let observed_id = request_id.as_ref().map(|RequestId(id)| id.clone());
+    let owner = claims.as_ref().map(|claims| claims.sub.clone());
+    let execute = metrics::with_request_id(observed_id, async move {
+        let _permit = match (queue, class) {
+            (Some(queue), Some(class)) => Some(queue.acquire(class).await?),
+            _ => None,
+        };
+        // The deadline starts once the request leaves the queue
+        let deadline = timeout.map(RequestDeadline::after);
+        let with_data = |mut request: async_graphql::Request| {
+            if let Some(claims) = &claims {
+                request = request.data(claims.clone());
+            }
+            if let Some(quotas) = &quotas {
+                request = request.data(quotas.clone());
+            }
+            if let Some(request_id) = &request_id {
+                request = request.data(request_id.clone());
+            }
+            if let Some(deadline) = deadline {
+                request = request.data(deadline);
+            }
+            request.data(schema.get_ref().clone())
+        };
+        let execution = async {
+            match retry {
+                Some(retry) if read_only => {
+                    // The first attempt runs the request as received, retries a copy
+                    let template = replay(&request);
+                    let mut received = Some(request);
+                    retry
+                        .run(true, || {
+                            let request = received.take().unwrap_or_else(|| replay(&template));
+                            schema.execute(with_data(request))
+                        })
+                        .await
+                }
+                _ => schema.execute(with_data(request)).await,
+            }
+        };
+        let mut response = match timeout {
+            Some(timeout) => with_deadline(timeout, execution).await,
+            None => execution.await,
+        };
+        truncate_response(&mut response, max_response_bytes);
+        Ok::<_, QueueError>(response)
+    });
+    let execute = with_query_owner(owner, execute);
+
+    // Identical queries already executing are awaited instead of run again
+    match (flight, flight_key) {
+        (Some(flight), Some(key)) => {
+            match flight
+                .run(key, || async { execute.await.map(Arc::new) })
+                .await
+            {
+                Ok(response) => Either::Right(shared_response(&response)),
+                Err(e) => Either::Right(ApiError::from(e).error_response()),
+            }
+        }
+        _ => match execute.await {
+            Ok(response) if is_truncated(&response) => Either::Right(shared_response(&response)),
+            Ok(response) => Either::Left(response.into()),
+            Err(e) => Either::Right(ApiError::from(e).error_response()),
+        },
+    }
+}
+
+/// Value of the `X-Request-Id` header
+fn request_id(http_req: &HttpRequest) -> Option<String> {
+    http_req
+        .headers()
+        .get("X-Request-Id")
+        .and_then(|value| value.to_str().ok())
+        .map(|request_id| request_id.to_string())
+}
+
+/// Requests sending `X-Force-Refresh: true` always execute on their own
+fn bypasses_flight(http_req: &HttpRequest) -> bool {
+    http_req
+        .headers()
+        .get("X-Force-Refresh")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+/// JSON body of a response shared between coalesced requests or truncated,
+/// rendered like `GraphQLResponse` renders its own
+fn shared_response(response: &async_graphql::Response) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    if let Some(cache_control) = response.cache_control.value().filter(|_| response.is_ok()) {
+        builder.insert_header((header::CACHE_CONTROL, cache_control));
+    }
+    if is_truncated(response) {
+        builder.insert_header((TRUNCATED_HEADER, "true"));
+    }
+    builder.json(response)
+}
+
+/// Claims of the request's bearer token, or of its client certificate when it
+/// has no token. Requests with neither run anonymously; an invalid token is
+/// rejected.
+pub fn request_claims(
+    http_req: &HttpRequest,
+    auth: Option<&AuthGuard>,
+) -> Result<Option<Claims>, ApiError> {
+    let token = http_req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    match (auth, token) {
+        (Some(auth), Some(token)) => auth
+            .verify_token(token)
+            .map(Some)
+            .map_err(|e| ApiError::unauthenticated(format!("Invalid token: {}", e))),
+        _ => Ok(http_req
+            .conn_data::<ClientIdentity>()
+            .map(|identity| identity.0.clone())),
+    }
+}
+
+/// GraphQL over WebSocket, speaking the `graphql-transport-ws` and `graphql-ws`
+/// protocols. Operations are admitted and limited like requests to `/graphql`,
+/// as the caller of the upgrade request or of the token sent with
+/// `connection_init`; an invalid token refuses the connection. Each connection
+/// is listed in the schema's subscription registry, with the operation it last
+/// started, until it disconnects or an admin closes it.
+pub async fn graphql_ws_handler(
+    schema: web::Data<AppSchema>,
+    queue: Option<web::Data<QueryQueue>>,
+    auth: Option<web::Data<AuthGuard>>,
+    quotas: Option<web::Data<QuotaManager>>,
+    config: Option<web::Data<Config>>,
+    http_req: HttpRequest,
+    payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    let claims = match request_claims(&http_req, auth.as_deref()) {
+        Ok(claims) => claims,
+        Err(e) => return Ok(e.error_response()),
+    };
+    let user = claims.as_ref().map(|claims| claims.sub.clone());
+
+    // Closing ends the inbound frames, which stops the connection
+    let closed = Arc::new(Notify::new());
+    let registered = schema
+        .data::<Arc<SubscriptionRegistry>>()
+        .map(|subscriptions| {
+            let close = Arc::clone(&closed);
+            Registered {
+                id: subscriptions
+                    .register(SubscriptionKind::Graphql, user, move || close.notify_one()),
+                subscriptions: Arc::clone(subscriptions),
+            }
+        });
+    let listing = registered
+        .as_ref()
+        .map(|registered| (Arc::clone(&registered.subscriptions), registered.id.clone()));
+    let executor = WsExecutor::new(AppSchema::clone(&schema), claims)
+        .with_queue(queue)
+        .with_quotas(quotas.map(|quotas| quotas.into_inner()))
+        .with_config(config.as_deref())
+        .with_listing(listing.clone());
+    let on_init = move |payload: serde_json::Value| {
+        let data = connection_init_data(auth.as_deref(), &payload);
+        let init_claims = data.as_ref().ok().and_then(session_claims);
+        if let (Some((subscriptions, id)), Some(claims)) = (&listing, init_claims) {
+            subscriptions.set_user(id, Some(claims.sub.clone()));
+        }
+        futures::future::ready(data)
+    };
+    let frames = payload.take_until(async move {
+        let _registered = registered;
+        closed.notified().await
+    });
+    GraphQLSubscription::new(executor)
+        .on_connection_init(on_init)
+        .start(&http_req, frames)
+}
+
+/// Registry entry of a WebSocket connection, removed when the connection's
+/// frame stream, which owns it, is dropped
+struct Registered {
+    subscriptions: Arc<SubscriptionRegistry>,
+    id: String,
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.subscriptions.deregister(&self.id);
+    }
+}
+
+/// Admission class requested through the `X-Query-Class` header. Only `heavy`
+/// is honoured, so callers can move requests out of the interactive pool but
+/// not move heavy requests into it.
+fn query_class_hint(http_req: &HttpRequest) -> Option<QueryClass> {
+    http_req
+        .headers()
+        .get("X-Query-Class")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .filter(|class| *class == QueryClass::Heavy)
+}
+
+/// Validate a GraphQL document against the schema without executing it. Only
+/// admins may validate, since reports describe fields the caller may not see.
+pub async fn validate_handler(
+    schema: web::Data<AppSchema>,
+    auth: Option<web::Data<AuthGuard>>,
+    http_req: HttpRequest,
+    req: GraphQLBody,
+) -> HttpResponse {
+    match request_claims(&http_req, auth.as_deref()) {
+        Ok(Some(claims)) if claims.role == "admin" => {}
+        Ok(Some(_)) => return ApiError::forbidden("admin role required").error_response(),
+        Ok(None) => return ApiError::unauthenticated("Authentication required").error_response(),
+        Err(e) => return e.error_response(),
+    }
+    let report = validate_document(&schema, req.into_inner()).await;
+    HttpResponse::Ok().json(report)
+}
+
+/// Liveness probe, also reporting whether the server is in read-only mode
+pub async fn health_handler(schema: Option<web::Data<AppSchema>>) -> HttpResponse {
+    let read_only = schema
+        .as_ref()
+        .and_then(|schema| schema.data::<Arc<ReadOnlyMode>>())
+        .is_some_and(|mode| mode.is_enabled());
+    HttpResponse::Ok().json(json!({ "status": "ok", "readOnly": read_only }))
+}
+
+/// Readiness probe: tables are registered, their source files are reachable when
+/// `check_data_source` is set and, when AI is enabled, the agents respond
+pub async fn ready_handler(
+    df_ctx: Option<web::Data<DataFusionContext>>,
+    orchestrator: Option<web::Data<AgentOrchestrator>>,
+    config: Option<web::Data<Config>>,
+) -> HttpResponse {
+    let datafusion = df_ctx
+        .as_ref()
+        .is_some_and(|ctx| !ctx.get_table_names().is_empty());
+    let source_problems = match (&df_ctx, &config) {
+        (Some(ctx), Some(config)) if config.check_data_source => Some(ctx.check_sources().await),
+        _ => None,
+    };
+    let data_source = match &source_problems {
+        Some(problems) if problems.is_empty() => "ok",
+        Some(_) => "degraded",
+        None => "disabled",
+    };
+    let ollama = match (config, orchestrator) {
+        (Some(config), Some(orchestrator)) if config.ai_enabled() => {
+            let results = orchestrator.test_connections().await;
+            if !results.is_empty() && results.values().all(|connected| *connected) {
+                "ok"
+            } else {
+                "failed"
+            }
+        }
+        _ => "disabled",
+    };
+
+    let ready = datafusion && ollama != "failed" && data_source != "degraded";
+    let status = match (ready, data_source) {
+        (true, _) => "ready",
+        (false, "degraded") => "degraded",
+        (false, _) => "not_ready",
+    };
+    let mut body = json!({
+        "status": status,
+        "checks": {
+            "datafusion": if datafusion { "ok" } else { "failed" },
+            "ollama": ollama,
+            "data_source": data_source,
+        }
+    });
+    if let Some(problems) = source_problems.filter(|problems| !problems.is_empty()) {
+        body["data_source_problems"] = problems
+            .iter()
+            .map(|problem| {
+                json!({
+                    "table": problem.table,
+                    "path": problem.path,
+                    "problem": problem.problem,
+                })
+            })
+            .collect();
+    }
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+/// Prometheus metrics in the text exposition format, or in OpenMetrics with
+/// exemplars when they are enabled and the scraper accepts it
+pub async fn metrics_handler(http_req: HttpRequest) -> HttpResponse {
+    let metrics = metrics::metrics();
+    let openmetrics = metrics.exemplars()
+        && http_req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/openmetrics-text"));
+    if openmetrics {
+        return HttpResponse::Ok()
+            .content_type(metrics::OPENMETRICS_CONTENT_TYPE)
+            .body(metrics.render(true));
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render(false))
+}
+
+/// Banner of the playground in demo mode
+const DEMO_BANNER: &str = "<div style=\"position:fixed;bottom:0;left:0;right:0;z-index:1000;\
+    padding:6px;text-align:center;font-family:sans-serif;background:#f5a623;color:#000\">\
+    Demo mode: queries run against generated sample data, not real TPCH data</div>";
+
+pub async fn playground(schema: Option<web::Data<AppSchema>>) -> HttpResponse {
+    let mut html = async_graphql::http::playground_source(
+        async_graphql::http::GraphQLPlaygroundConfig::new("/graphql"),
+    );
+    let demo = schema
+        .as_ref()
+        .and_then(|schema| schema.data::<Arc<Config>>())
+        .is_some_and(|config| config.demo_mode);
+    if demo {
+        html = html.replacen("<body>", &format!("<body>{}", DEMO_BANNER), 1);
+    }
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
+
+/// Middleware adding the configured custom headers to every response.
+/// Headers must have been checked by `Config::validate`. Without a configured
+/// `Access-Control-Allow-Origin` responses allow any origin, as preflights do.
+pub fn custom_headers(headers: &BTreeMap<String, String>) -> DefaultHeaders {
+    let middleware = headers
+        .iter()
+        .fold(DefaultHeaders::new(), |middleware, (name, value)| {
+            middleware.add((name.as_str(), value.as_str()))
+        });
+    match allowed_origin(headers) {
+        Some(_) => middleware,
+        None => middleware.add((header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")),
+    }
+}
+
+/// Request headers cross-origin callers may send: those the handlers read
+const ALLOWED_HEADERS: &str =
+    "Content-Type, Authorization, X-Request-Id, X-Query-Class, X-Force-Refresh";
+
+/// The configured custom `Access-Control-Allow-Origin` header
+fn allowed_origin(headers: &BTreeMap<String, String>) -> Option<&String> {
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("access-control-allow-origin"))
+        .map(|(_, value)| value)
+}
+
+/// Middleware, for `from_fn`, adding `X-Response-Time-Ms` to every response and
+/// `X-Over-Budget: true` when it took longer than the configured budget. The
+/// duration is observed in the HTTP latency histogram.
+pub async fn response_time(
+    config: Option<web::Data<Config>>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let request_id = request_id(req.request());
+    let mut res = next.call(req).await?;
+    let elapsed = started.elapsed();
+    metrics::metrics()
+        .http_latency
+        .observe_for(elapsed.as_secs_f64(), request_id);
+    let elapsed_ms = elapsed.as_millis() as u64;
+
+    let headers = res.headers_mut();
+    headers.insert(
+        HeaderName::from_static("x-response-time-ms"),
+        HeaderValue::from(elapsed_ms),
+    );
+    let budget_ms = config.map_or(0, |config| config.response_time_budget_ms);
+    if budget_ms > 0 && elapsed_ms > budget_ms {
+        headers.insert(
+            HeaderName::from_static("x-over-budget"),
+            HeaderValue::from_static("true"),
+        );
+    }
+    Ok(res)
+}
+
+/// Answer a CORS preflight. The allowed origin is the configured custom
+/// `Access-Control-Allow-Origin` header, any origin without one, the same one
+/// `custom_headers` sets on the actual responses. Requests carry bearer tokens
+/// rather than cookies, so credentials are not allowed.
+pub async fn preflight(config: Option<web::Data<Config>>) -> HttpResponse {
+    let origin = config
+        .as_ref()
+        .and_then(|config| allowed_origin(&config.custom_headers))
+        .map_or("*".to_string(), |origin| origin.clone());
+    HttpResponse::NoContent()
+        .insert_header((header::ACCESS_CONTROL_ALLOW_ORIGIN, origin))
+        .insert_header((header::ACCESS_CONTROL_ALLOW_METHODS, "GET, POST, OPTIONS"))
+        .insert_header((header::ACCESS_CONTROL_ALLOW_HEADERS, ALLOWED_HEADERS))
+        .insert_header((header::ACCESS_CONTROL_MAX_AGE, "86400"))
+        .finish()
+}
+
+/// Routes served by `configure`, listed in 404 responses
+pub const ENDPOINTS: &[(&str, &str)] = &[
+    ("POST /graphql", "GraphQL queries and mutations"),
+    ("GET /graphql?query=...", "GraphQL queries over GET"),
+    (
+        "POST /graphql/validate",
+        "Validate a GraphQL document without executing it",
+    ),
+    ("GET /playground", "Interactive GraphQL playground"),
+    ("GET /health", "Liveness probe"),
+    ("GET /ready", "Readiness probe"),
+    ("GET /metrics", "Prometheus metrics"),
+    (
+        "GET /export/csv?sql=...",
+        "Export a read-only query result as CSV",
+    ),
+    (
+        "GET /graphql/ws",
+        "GraphQL over WebSocket, on the WebSocket port",
+    ),
+];
+
+/// Whether the client prefers an HTML response, i.e. a browser
+fn accepts_html(http_req: &HttpRequest) -> bool {
+    http_req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+fn html_page(title: &str, message: &str) -> String {
+    let endpoints: String = ENDPOINTS
+        .iter()
+        .map(|(route, description)| format!("<li><code>{}</code> {}</li>", route, description))
+        .collect();
+    let message = message
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(
+        "<!DOCTYPE html><html><head><title>{title}</title></head><body>\
+         <h1>{title}</h1><p>{message}</p><ul>{endpoints}</ul></body></html>"
+    )
+}
+
+/// GET on the GraphQL endpoint without a `query` parameter, usually a browser
+/// pointed at the wrong URL
+pub async fn graphql_get_hint(http_req: HttpRequest) -> HttpResponse {
+    let message = "GET /graphql needs a `query` parameter. \
+                   Open /playground to explore the API interactively.";
+    if accepts_html(&http_req) {
+        return HttpResponse::BadRequest()
+            .content_type("text/html; charset=utf-8")
+            .body(html_page("Missing GraphQL query", message));
+    }
+    HttpResponse::BadRequest().json(json!({
+        "error": { "code": "MISSING_QUERY", "message": message, "playground": "/playground" }
+    }))
+}
+
+/// Fallback for unknown routes, listing the valid endpoints and configured port
+pub async fn not_found(http_req: HttpRequest, config: Option<web::Data<Config>>) -> HttpResponse {
+    let message = format!("No route for {} {}", http_req.method(), http_req.path());
+    if accepts_html(&http_req) {
+        return HttpResponse::NotFound()
+            .content_type("text/html; charset=utf-8")
+            .body(html_page("Not found", &message));
+    }
+
+    let endpoints: Vec<_> = ENDPOINTS
+        .iter()
+        .map(|(route, description)| json!({ "route": route, "description": description }))
+        .collect();
+    HttpResponse::NotFound().json(json!({
+        "error": {
+            "code": "NOT_FOUND",
+            "message": message,
+            "endpoints": endpoints,
+            "port": config.map(|config| config.http_port),
+        }
+    }))
+}
+
+/// Register the GraphQL WebSocket route, on its own server when `ws_port` differs
+/// from `http_port`
+pub fn configure_ws(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/graphql/ws").route(web::get().to(graphql_ws_handler)));
+}
+
+/// Register the GraphQL routes on an actix application
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let has_query = guard::fn_guard(|ctx| {
+        ctx.head()
+            .uri
+            .query()
+            .is_some_and(|query| query.split('&').any(|pair| pair.starts_with("query=")))
+    });
+
+    // Malformed query strings and JSON bodies get the same error envelope as handlers
+    cfg.app_data(
+        web::QueryConfig::default()
+            .error_handler(|err, _| ApiError::bad_request(err.to_string()).into()),
+    )
+    .app_data(
+        web::JsonConfig::default()
+            .error_handler(|err, _| ApiError::bad_request(err.to_string()).into()),
+    )
+    .service(
+        web::resource("/graphql")
+            .route(web::post().to(graphql_handler))
+            .route(web::get().guard(has_query).to(graphql_handler))
+            .route(web::get().to(graphql_get_hint))
+            .route(web::route().method(Method::OPTIONS).to(preflight)),
+    )
+    .service(
+        web::resource("/graphql/validate")
+            .route(web::post().to(validate_handler))
+            .route(web::route().method(Method::OPTIONS).to(preflight)),
+    )
+    .service(web::resource("/playground").route(web::get().to(playground)))
+    .service(web::resource("/health").route(web::get().to(health_handler)))
+    .service(web::resource("/ready").route(web::get().to(ready_handler)))
+    .service(web::resource("/metrics").route(web::get().to(metrics_handler)))
+    .service(
+        web::resource("/export/csv")
+            .route(web::get().to(export_csv))
+            .route(web::route().method(Method::OPTIONS).to(preflight)),
+    )
+    .default_service(web::to(not_found));
+}
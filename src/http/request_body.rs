@@ -0,0 +1,92 @@
+//! Content types accepted for GraphQL requests
+//!
+//! POST bodies must be `application/json`, a GraphQL request object, or
+//! `application/graphql`, the query document itself. Anything else, including a
+//! body without a content type, is refused with a 415 naming the accepted types
+//! instead of the parse error the JSON extractor would report. Turning off
+//! `enforce_content_type` parses every other body as JSON, as before. GET
+//! requests carry the query in the URL and are not checked.
+
+use crate::config::Config;
+use crate::http::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::http::{Method, StatusCode};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, web};
+use async_graphql_actix_web::GraphQLRequest;
+use futures_util::future::LocalBoxFuture;
+
+/// Content types of a GraphQL POST body
+pub const GRAPHQL_CONTENT_TYPES: [&str; 2] = ["application/json", "application/graphql"];
+
+/// Body of a GraphQL request in one of the accepted content types
+pub struct GraphQLBody(pub async_graphql::Request);
+
+impl GraphQLBody {
+    pub fn into_inner(self) -> async_graphql::Request {
+        self.0
+    }
+}
+
+enum BodyKind {
+    Json,
+    Query,
+    Unsupported(String),
+}
+
+fn body_kind(req: &HttpRequest) -> BodyKind {
+    let content_type = match req.mime_type() {
+        Ok(Some(mime)) => mime,
+        Ok(None) => return BodyKind::Unsupported("no content type".to_string()),
+        Err(_) => return BodyKind::Unsupported(req.content_type().to_string()),
+    };
+    match (
+        content_type.type_().as_str(),
+        content_type.subtype().as_str(),
+    ) {
+        ("application", "json") => BodyKind::Json,
+        ("application", "graphql") => BodyKind::Query,
+        _ => BodyKind::Unsupported(content_type.essence_str().to_string()),
+    }
+}
+
+fn unsupported(content_type: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "UNSUPPORTED_MEDIA_TYPE",
+        format!(
+            "Unsupported content type {}; send {}",
+            content_type,
+            GRAPHQL_CONTENT_TYPES.join(" or ")
+        ),
+    )
+    .with_detail("accepted", serde_json::json!(GRAPHQL_CONTENT_TYPES))
+}
+
+impl FromRequest for GraphQLBody {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let enforce = req
+            .app_data::<web::Data<Config>>()
+            .is_none_or(|config| config.enforce_content_type);
+        let kind = if req.method() == Method::POST {
+            body_kind(req)
+        } else {
+            BodyKind::Json
+        };
+        match kind {
+            BodyKind::Query => {
+                let body = String::from_request(req, payload);
+                Box::pin(async move { Ok(GraphQLBody(async_graphql::Request::new(body.await?))) })
+            }
+            BodyKind::Unsupported(content_type) if enforce => {
+                Box::pin(async move { Err(unsupported(&content_type).into()) })
+            }
+            _ => {
+                let request = GraphQLRequest::from_request(req, payload);
+                Box::pin(async move { Ok(GraphQLBody(request.await?.into_inner())) })
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Execution of GraphQL operations received over WebSocket
+//!
+//! Operations on `/graphql/ws` are admitted and bounded like requests to
+//! `/graphql`: they count against the caller's concurrency cap, wait in the
+//! query queue, run under the request deadline with the caller's claims and
+//! quotas, own the queries they start, and have oversized responses truncated.
+
+use crate::auth::{AuthGuard, Claims, bearer_token};
+use crate::config::Config;
+use crate::datafusion::context::with_query_owner;
+use crate::graphql::deadline::{RequestDeadline, with_deadline};
+use crate::graphql::response_size::truncate_response;
+use crate::graphql::schema::AppSchema;
+use crate::http::error::ApiError;
+use crate::query_queue::{QueryQueue, QueueError};
+use crate::quota::QuotaManager;
+use crate::subscriptions::SubscriptionRegistry;
+use actix_web::web;
+use async_graphql::{Data, ErrorExtensions, Executor, Pos, Request, Response};
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use std::any::TypeId;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Executor of one WebSocket connection's operations
+#[derive(Clone)]
+pub struct WsExecutor {
+    schema: AppSchema,
+    /// Claims of the upgrade request, replaced by those sent with `connection_init`
+    claims: Option<Claims>,
+    queue: Option<web::Data<QueryQueue>>,
+    quotas: Option<Arc<QuotaManager>>,
+    timeout: Option<Duration>,
+    max_response_bytes: usize,
+    /// Registry entry of the connection, showing the operation it last started
+    listing: Option<(Arc<SubscriptionRegistry>, String)>,
+}
+
+impl WsExecutor {
+    pub fn new(schema: AppSchema, claims: Option<Claims>) -> Self {
+        Self {
+            schema,
+            claims,
+            queue: None,
+            quotas: None,
+            timeout: None,
+            max_response_bytes: 0,
+            listing: None,
+        }
+    }
+
+    pub fn with_queue(mut self, queue: Option<web::Data<QueryQueue>>) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    pub fn with_quotas(mut self, quotas: Option<Arc<QuotaManager>>) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Request timeout and response size limit of the HTTP endpoint
+    pub fn with_config(mut self, config: Option<&Config>) -> Self {
+        if let Some(config) = config {
+            self.timeout = Some(config.request_timeout)
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs);
+            self.max_response_bytes = config.max_response_bytes;
+        }
+        self
+    }
+
+    pub fn with_listing(mut self, listing: Option<(Arc<SubscriptionRegistry>, String)>) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    async fn run(self, request: Request, session_data: Option<Arc<Data>>) -> Response {
+        if let Some((subscriptions, id)) = &self.listing {
+            subscriptions.set_query(id, &request.query);
+        }
+        let claims = session_data
+            .as_deref()
+            .and_then(session_claims)
+            .cloned()
+            .or(self.claims);
+
+        let _user_permit = match (&self.queue, &claims) {
+            (Some(queue), Some(claims)) => match queue.acquire_user(&claims.sub) {
+                Ok(permit) => Some(permit),
+                Err(e) => return queue_error(e),
+            },
+            _ => None,
+        };
+        let _permit = match &self.queue {
+            Some(queue) => match queue.acquire(queue.classify(&request.query)).await {
+                Ok(permit) => Some(permit),
+                Err(e) => return queue_error(e),
+            },
+            None => None,
+        };
+
+        // The deadline starts once the operation leaves the queue
+        let mut request = request;
+        if let Some(claims) = &claims {
+            request = request.data(claims.clone());
+        }
+        if let Some(quotas) = self.quotas {
+            request = request.data(quotas);
+        }
+        if let Some(timeout) = self.timeout {
+            request = request.data(RequestDeadline::after(timeout));
+        }
+        let request = request.data(self.schema.clone());
+
+        let owner = claims.map(|claims| claims.sub);
+        let execution = with_query_owner(owner, self.schema.execute(request));
+        let mut response = match self.timeout {
+            Some(timeout) => with_deadline(timeout, execution).await,
+            None => execution.await,
+        };
+        truncate_response(&mut response, self.max_response_bytes);
+        response
+    }
+}
+
+impl Executor for WsExecutor {
+    fn execute(&self, request: Request) -> impl Future<Output = Response> + Send {
+        self.clone().run(request, None)
+    }
+
+    /// The schema has no subscription root, so every operation answers once
+    fn execute_stream(
+        &self,
+        request: Request,
+        session_data: Option<Arc<Data>>,
+    ) -> BoxStream<'static, Response> {
+        futures::stream::once(self.clone().run(request, session_data)).boxed()
+    }
+}
+
+/// Session data of a connection whose `connection_init` payload carries a
+/// bearer token, as `{"Authorization": "Bearer <token>"}`. An invalid token
+/// refuses the connection; without one the upgrade request's claims apply.
+pub fn connection_init_data(
+    auth: Option<&AuthGuard>,
+    payload: &serde_json::Value,
+) -> async_graphql::Result<Data> {
+    let mut data = Data::default();
+    let token = ["Authorization", "authorization"]
+        .iter()
+        .find_map(|key| payload.get(key))
+        .and_then(|value| value.as_str())
+        .and_then(bearer_token);
+    if let (Some(auth), Some(token)) = (auth, token) {
+        let claims = auth
+            .verify_token(token)
+            .map_err(|e| async_graphql::Error::new(format!("Invalid token: {}", e)))?;
+        data.insert(claims);
+    }
+    Ok(data)
+}
+
+/// Claims placed in the session data by `connection_init_data`
+pub fn session_claims(data: &Data) -> Option<&Claims> {
+    data.get(&TypeId::of::<Claims>())
+        .and_then(|claims| claims.downcast_ref::<Claims>())
+}
+
+fn queue_error(err: QueueError) -> Response {
+    let code = ApiError::from(err).code;
+    Response::from_errors(vec![
+        async_graphql::Error::new(err.to_string())
+            .extend_with(|_, e| e.set("code", code))
+            .into_server_error(Pos::default()),
+    ])
+}
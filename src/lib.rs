@@ -1,14 +1,28 @@
 //! GraphQL DataFusion - A GraphQL interface for Apache DataFusion
 
 pub mod agents;
+pub mod audit;
 pub mod auth;
 pub mod config;
 pub mod datafusion;
 // pub mod error; // Temporarily disabled due to complex error handling issues
+pub mod events;
 pub mod graphql;
+pub mod http;
+pub mod metrics;
 pub mod models;
+pub mod notifier;
+pub mod query_queue;
+pub mod quota;
 pub mod rate_limit;
+pub mod reaper;
+pub mod request_retry;
 pub mod security;
+pub mod singleflight;
+pub mod state;
+pub mod subscriptions;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod validation;
 
 pub use agents::*;
@@ -17,7 +31,9 @@ pub use config::*;
 pub use datafusion::*;
 // pub use error::*; // Temporarily disabled
 pub use graphql::*;
+pub use http::*;
 pub use models::*;
+pub use query_queue::*;
 pub use rate_limit::*;
 pub use security::*;
 pub use validation::*;
@@ -0,0 +1,418 @@
+//! Prometheus metrics
+//!
+//! Counters and gauges are registered in a single registry; the latency
+//! histograms of queries, LLM calls and HTTP requests are kept by `Metrics`,
+//! whose buckets come from the configuration. Both are rendered by the `/metrics`
+//! route, with the configured prefix in front of every name. The prometheus crate
+//! has no exemplars, so the histograms are kept here: with exemplars enabled each
+//! bucket remembers the request id of its latest observation, served to scrapers
+//! asking for the OpenMetrics format.
+
+use crate::config::Config;
+use lazy_static::lazy_static;
+use prometheus::core::Collector;
+use prometheus::proto::{MetricFamily, MetricType};
+use prometheus::{
+    Encoder, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+
+/// Content type of the OpenMetrics exposition, the only one carrying exemplars
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Buckets of the query and HTTP latency histograms by default, in seconds
+pub const DEFAULT_LATENCY_BUCKETS: &[f64] = prometheus::DEFAULT_BUCKETS;
+
+/// Buckets of the LLM latency histogram by default; model calls take seconds
+pub const DEFAULT_LLM_LATENCY_BUCKETS: &[f64] =
+    &[0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+lazy_static! {
+    pub static ref REGISTRY: Registry = Registry::new();
+    pub static ref QUERY_RETRIES_TOTAL: IntCounter = register(
+        IntCounter::new(
+            "query_retries_total",
+            "Query executions retried after a transient error"
+        )
+        .unwrap()
+    );
+    pub static ref COALESCED_REQUESTS_TOTAL: IntCounter = register(
+        IntCounter::new(
+            "graphql_coalesced_requests_total",
+            "GraphQL requests answered with the response of an identical request in flight"
+        )
+        .unwrap()
+    );
+    pub static ref GRAPHQL_REQUEST_RETRIES_TOTAL: IntCounter = register(
+        IntCounter::new(
+            "graphql_request_retries_total",
+            "GraphQL queries executed again after a transient failure"
+        )
+        .unwrap()
+    );
+    pub static ref QUERY_POOL_IN_USE: IntGaugeVec = register(
+        IntGaugeVec::new(
+            Opts::new(
+                "query_pool_in_use",
+                "Execution permits in use per admission pool"
+            ),
+            &["pool"]
+        )
+        .unwrap()
+    );
+    pub static ref TABLE_BYTES: IntGaugeVec = register(
+        IntGaugeVec::new(
+            Opts::new(
+                "table_bytes",
+                "Total size of the parquet files behind each table"
+            ),
+            &["table"]
+        )
+        .unwrap()
+    );
+    pub static ref ROLLUP_BUILD_SECONDS: GaugeVec = register(
+        GaugeVec::new(
+            Opts::new(
+                "rollup_build_seconds",
+                "Duration of the last successful build of each rollup table"
+            ),
+            &["rollup"]
+        )
+        .unwrap()
+    );
+    pub static ref STATE_LIVE_ENTRIES: IntGaugeVec = register(
+        IntGaugeVec::new(
+            Opts::new(
+                "state_live_entries",
+                "Live entries per expiring state registry, as of the last sweep"
+            ),
+            &["registry"]
+        )
+        .unwrap()
+    );
+    pub static ref STATE_REAPED_TOTAL: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "state_reaped_total",
+                "Idle entries removed per expiring state registry"
+            ),
+            &["registry"]
+        )
+        .unwrap()
+    );
+    pub static ref READ_ONLY_MODE: IntGauge = register(
+        IntGauge::new(
+            "read_only_mode",
+            "1 while the server refuses state-changing operations, 0 otherwise"
+        )
+        .unwrap()
+    );
+    pub static ref TRUNCATED_RESPONSES_TOTAL: IntCounter = register(
+        IntCounter::new(
+            "graphql_truncated_responses_total",
+            "GraphQL responses cut down to the maximum response size"
+        )
+        .unwrap()
+    );
+    pub static ref WEBHOOK_DELIVERIES_TOTAL: IntCounterVec = register(
+        IntCounterVec::new(
+            Opts::new(
+                "webhook_deliveries_total",
+                "Webhook deliveries per event kind that succeeded or failed after all retries"
+            ),
+            &["event", "outcome"]
+        )
+        .unwrap()
+    );
+}
+
+fn register<C: Collector + Clone + 'static>(collector: C) -> C {
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+/// Render all metrics in the Prometheus text format
+pub fn render() -> String {
+    metrics().render(false)
+}
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Run `future` with observations made inside it attributed to `request_id`
+pub async fn with_request_id<F: Future>(request_id: Option<String>, future: F) -> F::Output {
+    match request_id {
+        Some(request_id) => REQUEST_ID.scope(request_id, future).await,
+        None => future.await,
+    }
+}
+
+/// Request id of the running task, see `with_request_id`
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|request_id| request_id.clone()).ok()
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Use the histograms and naming of `metrics`; only the first call takes effect,
+/// so it belongs before the server starts
+pub fn install(metrics: Metrics) {
+    let _ = METRICS.set(metrics);
+}
+
+/// The installed metrics, those of the default configuration when none were
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new(&Config::default()))
+}
+
+/// Check that a bucket list is usable: not empty, finite and strictly increasing
+pub fn validate_buckets(name: &str, buckets: &[f64]) -> Result<(), String> {
+    if buckets.is_empty() {
+        return Err(format!("{} cannot be empty", name));
+    }
+    if let Some(bound) = buckets.iter().find(|bound| !bound.is_finite()) {
+        return Err(format!(
+            "{} has a bucket that is not a number: {}",
+            name, bound
+        ));
+    }
+    if let Some(pair) = buckets.windows(2).find(|pair| pair[0] >= pair[1]) {
+        return Err(format!(
+            "{} must be strictly increasing, got {} before {}",
+            name, pair[0], pair[1]
+        ));
+    }
+    Ok(())
+}
+
+/// Check that a prefix keeps metric names valid
+pub fn validate_prefix(prefix: &str) -> Result<(), String> {
+    let valid = prefix.chars().enumerate().all(|(i, c)| {
+        c.is_ascii_alphabetic() || c == '_' || c == ':' || (i > 0 && c.is_ascii_digit())
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid metric prefix: {}", prefix))
+    }
+}
+
+/// The latest observation of a bucket
+struct Exemplar {
+    request_id: String,
+    value: f64,
+    /// Seconds since the epoch
+    timestamp: f64,
+}
+
+#[derive(Default)]
+struct HistogramState {
+    /// Observations per bucket, the last one above every bound
+    counts: Vec<u64>,
+    sum: f64,
+    exemplars: Vec<Option<Exemplar>>,
+}
+
+/// Histogram of durations in seconds
+pub struct LatencyHistogram {
+    name: &'static str,
+    help: &'static str,
+    bounds: Vec<f64>,
+    state: Mutex<HistogramState>,
+}
+
+impl LatencyHistogram {
+    fn new(name: &'static str, help: &'static str, bounds: &[f64]) -> Self {
+        Self {
+            name,
+            help,
+            bounds: bounds.to_vec(),
+            state: Mutex::new(HistogramState {
+                counts: vec![0; bounds.len() + 1],
+                sum: 0.0,
+                exemplars: (0..=bounds.len()).map(|_| None).collect(),
+            }),
+        }
+    }
+
+    /// Record a duration, attributed to the request id of the running task
+    pub fn observe(&self, seconds: f64) {
+        self.observe_for(seconds, current_request_id());
+    }
+
+    /// Record a duration of the request `request_id`
+    pub fn observe_for(&self, seconds: f64, request_id: Option<String>) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(self.bounds.len());
+        let mut state = self.state.lock().unwrap();
+        state.counts[bucket] += 1;
+        state.sum += seconds;
+        if let Some(request_id) = request_id {
+            state.exemplars[bucket] = Some(Exemplar {
+                request_id,
+                value: seconds,
+                timestamp: chrono::Utc::now().timestamp_millis() as f64 / 1000.0,
+            });
+        }
+    }
+
+    fn render(&self, prefix: &str, exemplars: bool, out: &mut String) {
+        let name = format!("{}{}", prefix, self.name);
+        let state = self.state.lock().unwrap();
+        let _ = writeln!(out, "# HELP {} {}", name, self.help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bucket, count) in state.counts.iter().enumerate() {
+            cumulative += count;
+            let le = match self.bounds.get(bucket) {
+                Some(bound) => format!("{:?}", bound),
+                None => "+Inf".to_string(),
+            };
+            let _ = write!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+            if let (true, Some(exemplar)) = (exemplars, &state.exemplars[bucket]) {
+                let _ = write!(
+                    out,
+                    " # {{request_id=\"{}\"}} {} {}",
+                    escape_label(&exemplar.request_id),
+                    exemplar.value,
+                    exemplar.timestamp
+                );
+            }
+            out.push('\n');
+        }
+        let _ = writeln!(out, "{}_sum {}", name, state.sum);
+        let _ = writeln!(out, "{}_count {}", name, cumulative);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Metric naming and the latency histograms
+pub struct Metrics {
+    prefix: String,
+    exemplars: bool,
+    /// Execution of a SQL statement, including retries
+    pub query_latency: LatencyHistogram,
+    /// A call to the model, from sending the prompt to the parsed answer
+    pub llm_latency: LatencyHistogram,
+    /// An HTTP request, from its arrival to the response headers
+    pub http_latency: LatencyHistogram,
+}
+
+impl Metrics {
+    /// Metrics named and bucketed as configured; `Config::validate` checks the
+    /// buckets beforehand
+    pub fn new(config: &Config) -> Self {
+        Self {
+            prefix: config.metric_prefix.clone(),
+            exemplars: config.metric_exemplars,
+            query_latency: LatencyHistogram::new(
+                "query_duration_seconds",
+                "Duration of SQL query executions",
+                &config.query_latency_buckets,
+            ),
+            llm_latency: LatencyHistogram::new(
+                "llm_request_duration_seconds",
+                "Duration of calls to the language model",
+                &config.llm_latency_buckets,
+            ),
+            http_latency: LatencyHistogram::new(
+                "http_request_duration_seconds",
+                "Duration of HTTP requests",
+                &config.http_latency_buckets,
+            ),
+        }
+    }
+
+    /// Whether the OpenMetrics exposition carries exemplars
+    pub fn exemplars(&self) -> bool {
+        self.exemplars
+    }
+
+    /// Render the registry and the histograms, in the Prometheus text format or,
+    /// with `openmetrics`, in the OpenMetrics format with exemplars when enabled
+    pub fn render(&self, openmetrics: bool) -> String {
+        let mut families = REGISTRY.gather();
+        for family in &mut families {
+            let name = format!("{}{}", self.prefix, family.get_name());
+            family.set_name(name);
+        }
+        let mut out = if openmetrics {
+            openmetrics_families(&families)
+        } else {
+            let mut buffer = Vec::new();
+            TextEncoder::new()
+                .encode(&families, &mut buffer)
+                .unwrap_or_default();
+            String::from_utf8(buffer).unwrap_or_default()
+        };
+        let exemplars = openmetrics && self.exemplars;
+        for histogram in [&self.query_latency, &self.llm_latency, &self.http_latency] {
+            histogram.render(&self.prefix, exemplars, &mut out);
+        }
+        if openmetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+}
+
+/// Counters and gauges of the registry in the OpenMetrics format, where a
+/// counter family is named without the `_total` suffix of its samples
+fn openmetrics_families(families: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in families {
+        let (kind, family_name, sample_name) = match family.get_field_type() {
+            MetricType::COUNTER => {
+                let base = family.get_name().trim_end_matches("_total");
+                ("counter", base.to_string(), format!("{}_total", base))
+            }
+            MetricType::GAUGE => (
+                "gauge",
+                family.get_name().to_string(),
+                family.get_name().to_string(),
+            ),
+            // The registry only has counters and gauges
+            _ => continue,
+        };
+        let _ = writeln!(out, "# HELP {} {}", family_name, family.get_help());
+        let _ = writeln!(out, "# TYPE {} {}", family_name, kind);
+        for metric in family.get_metric() {
+            let labels: Vec<String> = metric
+                .get_label()
+                .iter()
+                .map(|label| {
+                    format!(
+                        "{}=\"{}\"",
+                        label.get_name(),
+                        escape_label(label.get_value())
+                    )
+                })
+                .collect();
+            let labels = if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", labels.join(","))
+            };
+            let value = match family.get_field_type() {
+                MetricType::COUNTER => metric.get_counter().get_value(),
+                _ => metric.get_gauge().get_value(),
+            };
+            let _ = writeln!(out, "{}{} {}", sample_name, labels, value);
+        }
+    }
+    out
+}
@@ -1,161 +1,369 @@
 //! Data structures for GraphQL DataFusion
 
-use async_graphql::{Enum, InputObject, SimpleObject};
+use crate::auth::RoleGuard;
+use crate::graphql::naming::tpch_object;
+use async_graphql::{Enum, InputObject, Json, SimpleObject};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use datafusion::arrow::datatypes::{DataType, Schema};
 use serde::{Deserialize, Serialize};
 
 // TPCH Data Models
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct Customer {
-    #[graphql(name = "c_custkey")]
-    pub c_custkey: i64,
-    #[graphql(name = "c_name")]
-    pub c_name: String,
-    #[graphql(name = "c_address")]
-    pub c_address: String,
-    #[graphql(name = "c_nationkey")]
-    pub c_nationkey: i64,
-    #[graphql(name = "c_phone")]
-    pub c_phone: String,
-    #[graphql(name = "c_acctbal")]
-    pub c_acctbal: f64,
-    #[graphql(name = "c_mktsegment")]
-    pub c_mktsegment: String,
-    #[graphql(name = "c_comment")]
-    pub c_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct Order {
-    #[graphql(name = "o_orderkey")]
-    pub o_orderkey: i64,
-    #[graphql(name = "o_custkey")]
-    pub o_custkey: i64,
-    #[graphql(name = "o_orderstatus")]
-    pub o_orderstatus: String,
-    #[graphql(name = "o_totalprice")]
-    pub o_totalprice: f64,
-    #[graphql(name = "o_orderdate")]
-    pub o_orderdate: String, // Date as string for GraphQL compatibility
-    #[graphql(name = "o_orderpriority")]
-    pub o_orderpriority: String,
-    #[graphql(name = "o_clerk")]
-    pub o_clerk: String,
-    #[graphql(name = "o_shippriority")]
-    pub o_shippriority: i32,
-    #[graphql(name = "o_comment")]
-    pub o_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct LineItem {
-    #[graphql(name = "l_orderkey")]
-    pub l_orderkey: i64,
-    #[graphql(name = "l_partkey")]
-    pub l_partkey: i64,
-    #[graphql(name = "l_suppkey")]
-    pub l_suppkey: i64,
-    #[graphql(name = "l_linenumber")]
-    pub l_linenumber: i32,
-    #[graphql(name = "l_quantity")]
-    pub l_quantity: f64,
-    #[graphql(name = "l_extendedprice")]
-    pub l_extendedprice: f64,
-    #[graphql(name = "l_discount")]
-    pub l_discount: f64,
-    #[graphql(name = "l_tax")]
-    pub l_tax: f64,
-    #[graphql(name = "l_returnflag")]
-    pub l_returnflag: String,
-    #[graphql(name = "l_linestatus")]
-    pub l_linestatus: String,
-    #[graphql(name = "l_shipdate")]
-    pub l_shipdate: String,
-    #[graphql(name = "l_commitdate")]
-    pub l_commitdate: String,
-    #[graphql(name = "l_receiptdate")]
-    pub l_receiptdate: String,
-    #[graphql(name = "l_shipinstruct")]
-    pub l_shipinstruct: String,
-    #[graphql(name = "l_shipmode")]
-    pub l_shipmode: String,
-    #[graphql(name = "l_comment")]
-    pub l_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct Part {
-    #[graphql(name = "p_partkey")]
-    pub p_partkey: i64,
-    #[graphql(name = "p_name")]
-    pub p_name: String,
-    #[graphql(name = "p_mfgr")]
-    pub p_mfgr: String,
-    #[graphql(name = "p_brand")]
-    pub p_brand: String,
-    #[graphql(name = "p_type")]
-    pub p_type: String,
-    #[graphql(name = "p_size")]
-    pub p_size: i32,
-    #[graphql(name = "p_container")]
-    pub p_container: String,
-    #[graphql(name = "p_retailprice")]
-    pub p_retailprice: f64,
-    #[graphql(name = "p_comment")]
-    pub p_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct Supplier {
-    #[graphql(name = "s_suppkey")]
-    pub s_suppkey: i64,
-    #[graphql(name = "s_name")]
-    pub s_name: String,
-    #[graphql(name = "s_address")]
-    pub s_address: String,
-    #[graphql(name = "s_nationkey")]
-    pub s_nationkey: i32,
-    #[graphql(name = "s_phone")]
-    pub s_phone: String,
-    #[graphql(name = "s_acctbal")]
-    pub s_acctbal: f64,
-    #[graphql(name = "s_comment")]
-    pub s_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct Nation {
-    #[graphql(name = "n_nationkey")]
-    pub n_nationkey: i64,
-    #[graphql(name = "n_name")]
-    pub n_name: String,
-    #[graphql(name = "n_regionkey")]
-    pub n_regionkey: i64,
-    #[graphql(name = "n_comment")]
-    pub n_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct Region {
-    #[graphql(name = "r_regionkey")]
-    pub r_regionkey: i64,
-    #[graphql(name = "r_name")]
-    pub r_name: String,
-    #[graphql(name = "r_comment")]
-    pub r_comment: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
-pub struct PartSupp {
-    #[graphql(name = "ps_partkey")]
-    pub ps_partkey: i64,
-    #[graphql(name = "ps_suppkey")]
-    pub ps_suppkey: i64,
-    #[graphql(name = "ps_availqty")]
-    pub ps_availqty: i32,
-    #[graphql(name = "ps_supplycost")]
-    pub ps_supplycost: f64,
-    #[graphql(name = "ps_comment")]
-    pub ps_comment: String,
+// Cache hints: rows change only when their table is reloaded, and the nation and
+// region dimensions hardly ever. A response gets the shortest hint it touches.
+tpch_object! {
+    cache_control(max_age = 60);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Customer {
+        #[graphql(name = "c_custkey")]
+        pub c_custkey: i64,
+        #[graphql(name = "c_name")]
+        pub c_name: String,
+        #[graphql(name = "c_address")]
+        pub c_address: Option<String>,
+        #[graphql(name = "c_nationkey")]
+        pub c_nationkey: i64,
+        #[graphql(name = "c_phone")]
+        pub c_phone: Option<String>,
+        #[graphql(name = "c_acctbal")]
+        pub c_acctbal: f64,
+        #[graphql(name = "c_mktsegment")]
+        pub c_mktsegment: MarketSegment,
+        /// Value found in the data when `c_mktsegment` is `UNKNOWN`
+        #[graphql(name = "c_mktsegment_raw")]
+        pub c_mktsegment_raw: Option<String>,
+        #[graphql(name = "c_comment")]
+        pub c_comment: Option<String>,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 60);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Order {
+        #[graphql(name = "o_orderkey")]
+        pub o_orderkey: i64,
+        #[graphql(name = "o_custkey")]
+        pub o_custkey: i64,
+        #[graphql(name = "o_orderstatus")]
+        pub o_orderstatus: OrderStatus,
+        /// Value found in the data when `o_orderstatus` is `UNKNOWN`
+        #[graphql(name = "o_orderstatus_raw")]
+        pub o_orderstatus_raw: Option<String>,
+        #[graphql(name = "o_totalprice")]
+        pub o_totalprice: f64,
+        /// ISO-8601 date, e.g. `1996-01-02`; null when the data has none
+        #[graphql(name = "o_orderdate")]
+        pub o_orderdate: Option<String>,
+        #[graphql(name = "o_orderpriority")]
+        pub o_orderpriority: String,
+        #[graphql(name = "o_clerk")]
+        pub o_clerk: String,
+        #[graphql(name = "o_shippriority")]
+        pub o_shippriority: i32,
+        #[graphql(name = "o_comment")]
+        pub o_comment: String,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 60);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct LineItem {
+        #[graphql(name = "l_orderkey")]
+        pub l_orderkey: i64,
+        #[graphql(name = "l_partkey")]
+        pub l_partkey: i64,
+        #[graphql(name = "l_suppkey")]
+        pub l_suppkey: i64,
+        #[graphql(name = "l_linenumber")]
+        pub l_linenumber: i32,
+        #[graphql(name = "l_quantity")]
+        pub l_quantity: f64,
+        #[graphql(name = "l_extendedprice")]
+        pub l_extendedprice: f64,
+        #[graphql(name = "l_discount")]
+        pub l_discount: f64,
+        #[graphql(name = "l_tax")]
+        pub l_tax: f64,
+        #[graphql(name = "l_returnflag")]
+        pub l_returnflag: ReturnFlag,
+        /// Value found in the data when `l_returnflag` is `UNKNOWN`
+        #[graphql(name = "l_returnflag_raw")]
+        pub l_returnflag_raw: Option<String>,
+        #[graphql(name = "l_linestatus")]
+        pub l_linestatus: LineStatus,
+        /// Value found in the data when `l_linestatus` is `UNKNOWN`
+        #[graphql(name = "l_linestatus_raw")]
+        pub l_linestatus_raw: Option<String>,
+        #[graphql(name = "l_shipdate")]
+        pub l_shipdate: String,
+        #[graphql(name = "l_commitdate")]
+        pub l_commitdate: String,
+        #[graphql(name = "l_receiptdate")]
+        pub l_receiptdate: String,
+        #[graphql(name = "l_shipinstruct")]
+        pub l_shipinstruct: String,
+        #[graphql(name = "l_shipmode")]
+        pub l_shipmode: String,
+        #[graphql(name = "l_comment")]
+        pub l_comment: String,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 60);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Part {
+        #[graphql(name = "p_partkey")]
+        pub p_partkey: i64,
+        #[graphql(name = "p_name")]
+        pub p_name: String,
+        #[graphql(name = "p_mfgr")]
+        pub p_mfgr: String,
+        #[graphql(name = "p_brand")]
+        pub p_brand: String,
+        #[graphql(name = "p_type")]
+        pub p_type: String,
+        #[graphql(name = "p_size")]
+        pub p_size: i32,
+        #[graphql(name = "p_container")]
+        pub p_container: String,
+        #[graphql(name = "p_retailprice")]
+        pub p_retailprice: f64,
+        #[graphql(name = "p_comment")]
+        pub p_comment: String,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 60);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Supplier {
+        #[graphql(name = "s_suppkey")]
+        pub s_suppkey: i64,
+        #[graphql(name = "s_name")]
+        pub s_name: String,
+        #[graphql(name = "s_address")]
+        pub s_address: String,
+        /// 64-bit like `c_nationkey` and `n_nationkey`, as stored in the data
+        #[graphql(name = "s_nationkey")]
+        pub s_nationkey: i64,
+        #[graphql(name = "s_phone")]
+        pub s_phone: String,
+        #[graphql(name = "s_acctbal")]
+        pub s_acctbal: f64,
+        #[graphql(name = "s_comment")]
+        pub s_comment: String,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 3600);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Nation {
+        #[graphql(name = "n_nationkey")]
+        pub n_nationkey: i64,
+        #[graphql(name = "n_name")]
+        pub n_name: String,
+        #[graphql(name = "n_regionkey")]
+        pub n_regionkey: i64,
+        #[graphql(name = "n_comment")]
+        pub n_comment: String,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 3600);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Region {
+        #[graphql(name = "r_regionkey")]
+        pub r_regionkey: i64,
+        #[graphql(name = "r_name")]
+        pub r_name: String,
+        #[graphql(name = "r_comment")]
+        pub r_comment: String,
+    }
+}
+
+tpch_object! {
+    cache_control(max_age = 60);
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct PartSupp {
+        #[graphql(name = "ps_partkey")]
+        pub ps_partkey: i64,
+        #[graphql(name = "ps_suppkey")]
+        pub ps_suppkey: i64,
+        #[graphql(name = "ps_availqty")]
+        pub ps_availqty: i32,
+        #[graphql(name = "ps_supplycost")]
+        pub ps_supplycost: f64,
+        #[graphql(name = "ps_comment")]
+        pub ps_comment: String,
+    }
+}
+
+// Categorical Columns
+/// TPCH column with a fixed set of codes, exposed as a GraphQL enum whose
+/// `Unknown` default stands for codes outside that set
+pub trait Categorical: Sized + Default {
+    /// Variant of a code, `None` for unexpected codes
+    fn from_code(code: &str) -> Option<Self>;
+
+    /// Code stored in the data, `None` for `Unknown`
+    fn code(&self) -> Option<&'static str>;
+
+    /// Variant of a value read from the data, with the raw value for the sibling
+    /// `_raw` field when the code is unexpected
+    fn parse(raw: &str) -> (Self, Option<String>) {
+        match Self::from_code(raw.trim()) {
+            Some(value) => (value, None),
+            None => (Self::default(), Some(raw.to_string())),
+        }
+    }
+}
+
+/// Known codes of a categorical column
+pub fn categorical_codes(column: &str) -> Option<&'static [&'static str]> {
+    match column {
+        "o_orderstatus" => Some(&["O", "F", "P"]),
+        "l_returnflag" => Some(&["R", "A", "N"]),
+        "l_linestatus" => Some(&["O", "F"]),
+        "c_mktsegment" => Some(&[
+            "AUTOMOBILE",
+            "BUILDING",
+            "FURNITURE",
+            "HOUSEHOLD",
+            "MACHINERY",
+        ]),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq, Default)]
+pub enum OrderStatus {
+    /// `O`: all line items open
+    Open,
+    /// `F`: all line items fulfilled
+    Fulfilled,
+    /// `P`: some line items fulfilled
+    Partial,
+    #[default]
+    Unknown,
+}
+
+impl Categorical for OrderStatus {
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "O" => Some(OrderStatus::Open),
+            "F" => Some(OrderStatus::Fulfilled),
+            "P" => Some(OrderStatus::Partial),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            OrderStatus::Open => Some("O"),
+            OrderStatus::Fulfilled => Some("F"),
+            OrderStatus::Partial => Some("P"),
+            OrderStatus::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq, Default)]
+pub enum ReturnFlag {
+    /// `R`
+    Returned,
+    /// `A`
+    Accepted,
+    /// `N`
+    NotReturned,
+    #[default]
+    Unknown,
+}
+
+impl Categorical for ReturnFlag {
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "R" => Some(ReturnFlag::Returned),
+            "A" => Some(ReturnFlag::Accepted),
+            "N" => Some(ReturnFlag::NotReturned),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ReturnFlag::Returned => Some("R"),
+            ReturnFlag::Accepted => Some("A"),
+            ReturnFlag::NotReturned => Some("N"),
+            ReturnFlag::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq, Default)]
+pub enum LineStatus {
+    /// `O`
+    Open,
+    /// `F`
+    Fulfilled,
+    #[default]
+    Unknown,
+}
+
+impl Categorical for LineStatus {
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "O" => Some(LineStatus::Open),
+            "F" => Some(LineStatus::Fulfilled),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            LineStatus::Open => Some("O"),
+            LineStatus::Fulfilled => Some("F"),
+            LineStatus::Unknown => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq, Default)]
+pub enum MarketSegment {
+    Automobile,
+    Building,
+    Furniture,
+    Household,
+    Machinery,
+    #[default]
+    Unknown,
+}
+
+impl Categorical for MarketSegment {
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "AUTOMOBILE" => Some(MarketSegment::Automobile),
+            "BUILDING" => Some(MarketSegment::Building),
+            "FURNITURE" => Some(MarketSegment::Furniture),
+            "HOUSEHOLD" => Some(MarketSegment::Household),
+            "MACHINERY" => Some(MarketSegment::Machinery),
+            _ => None,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            MarketSegment::Automobile => Some("AUTOMOBILE"),
+            MarketSegment::Building => Some("BUILDING"),
+            MarketSegment::Furniture => Some("FURNITURE"),
+            MarketSegment::Household => Some("HOUSEHOLD"),
+            MarketSegment::Machinery => Some("MACHINERY"),
+            MarketSegment::Unknown => None,
+        }
+    }
 }
 
 // Query Input Types
@@ -175,7 +383,9 @@ pub struct QueryParams {
     pub offset: Option<i32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, InputObject)]
+/// A predicate on one column; returned as `AnalysisFilter` when echoed back
+#[derive(Debug, Clone, Serialize, Deserialize, InputObject, SimpleObject)]
+#[graphql(name = "AnalysisFilter", input_name = "Filter")]
 pub struct Filter {
     pub column: String,
     pub operator: FilterOperator,
@@ -191,9 +401,55 @@ pub enum FilterOperator {
     Lt,
     Lte,
     Like,
+    /// Case-insensitive `LIKE`
+    #[graphql(name = "ILIKE")]
+    ILike,
     In,
 }
 
+impl FilterOperator {
+    pub const ALL: [FilterOperator; 9] = [
+        FilterOperator::Eq,
+        FilterOperator::Ne,
+        FilterOperator::Gt,
+        FilterOperator::Gte,
+        FilterOperator::Lt,
+        FilterOperator::Lte,
+        FilterOperator::Like,
+        FilterOperator::ILike,
+        FilterOperator::In,
+    ];
+
+    /// Whether the operator can filter a column of the given type: patterns
+    /// apply to strings, ordering to numbers and temporal values, equality to
+    /// both and to booleans
+    pub fn supports(&self, data_type: &DataType) -> bool {
+        let text = matches!(
+            data_type,
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+        );
+        let ordered = data_type.is_numeric() || data_type.is_temporal();
+        match self {
+            FilterOperator::Eq | FilterOperator::Ne => {
+                text || ordered || *data_type == DataType::Boolean
+            }
+            FilterOperator::In => text || ordered,
+            FilterOperator::Gt | FilterOperator::Gte | FilterOperator::Lt | FilterOperator::Lte => {
+                ordered
+            }
+            FilterOperator::Like | FilterOperator::ILike => text,
+        }
+    }
+
+    /// Operators that can filter a column of the given type
+    pub fn for_type(data_type: &DataType) -> Vec<FilterOperator> {
+        Self::ALL
+            .into_iter()
+            .filter(|operator| operator.supports(data_type))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
 pub enum SortOrder {
     Asc,
@@ -233,6 +489,35 @@ pub struct SalesAnalytics {
     pub top_customers: Vec<CustomerSales>,
     pub sales_by_region: Vec<RegionSales>,
     pub monthly_trends: Vec<MonthlyTrend>,
+    pub order_value_distribution: OrderValueDistribution,
+    /// Sections that failed and were returned empty, with `errorsAsData`
+    pub errors: Vec<SectionError>,
+}
+
+/// Failure of one section of an analytics result
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SectionError {
+    /// GraphQL field of the section, e.g. `topCustomers`
+    pub section: String,
+    pub message: String,
+}
+
+/// Spread of order values; percentiles are null when there are no orders
+#[derive(Debug, Clone, Default, Serialize, Deserialize, SimpleObject)]
+pub struct OrderValueDistribution {
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+    /// Equal-width buckets from the smallest to the largest order value
+    pub histogram: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct HistogramBucket {
+    pub bucket_start: f64,
+    /// Exclusive, except for the last bucket which includes the largest value
+    pub bucket_end: f64,
+    pub count: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
@@ -240,6 +525,30 @@ pub struct CustomerSales {
     pub customer: Customer,
     pub total_spent: f64,
     pub order_count: i64,
+    pub avg_order_value: f64,
+    /// Metric the customers were ranked by
+    pub ranked_by: CustomerRanking,
+    /// Value of the ranking metric for this customer
+    pub rank_value: f64,
+}
+
+/// Metric `topCustomers` are ranked by, highest first
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum CustomerRanking {
+    TotalSpent,
+    OrderCount,
+    AvgOrderValue,
+}
+
+impl CustomerRanking {
+    /// Column of the top customers query holding the metric
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            CustomerRanking::TotalSpent => "total_spent",
+            CustomerRanking::OrderCount => "order_count",
+            CustomerRanking::AvgOrderValue => "avg_order_value",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
@@ -256,6 +565,508 @@ pub struct MonthlyTrend {
     pub order_count: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct TimeSeriesPoint {
+    /// Start of the period as `YYYY-MM-DD`
+    pub period: String,
+    pub value: f64,
+    /// Average of `value` over this and the preceding periods of the window
+    pub moving_average: Option<f64>,
+    /// Whether the requested date range covers only part of the period
+    pub partial: bool,
+    /// Start of the period this one is compared with
+    pub comparison_period: Option<String>,
+    /// Value of the comparison period, null when it has no rows
+    pub comparison_value: Option<f64>,
+    /// Change from `comparison_value` to `value` in percent, null when there
+    /// is nothing to compare with
+    pub change_percent: Option<f64>,
+}
+
+/// Aggregate computed for each cell of a pivot
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum PivotAggregate {
+    Sum,
+    Avg,
+    Count,
+    Min,
+    Max,
+}
+
+impl PivotAggregate {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            PivotAggregate::Sum => "SUM",
+            PivotAggregate::Avg => "AVG",
+            PivotAggregate::Count => "COUNT",
+            PivotAggregate::Min => "MIN",
+            PivotAggregate::Max => "MAX",
+        }
+    }
+}
+
+/// A value aggregated per row value and per distinct value of a pivot column
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct PivotTable {
+    /// Distinct values of the pivot column, in order
+    pub columns: Vec<Option<String>>,
+    pub rows: Vec<PivotRow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct PivotRow {
+    pub key: Option<String>,
+    /// Aggregate for each of the table's `columns`, null where no rows match
+    pub values: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum TimeGranularity {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl TimeGranularity {
+    /// Precision argument of `date_trunc`
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            TimeGranularity::Day => "day",
+            TimeGranularity::Week => "week",
+            TimeGranularity::Month => "month",
+            TimeGranularity::Quarter => "quarter",
+            TimeGranularity::Year => "year",
+        }
+    }
+
+    /// First day of the period containing `date`, as `date_trunc` computes it
+    pub fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        let month_start =
+            |month0: u32| NaiveDate::from_ymd_opt(date.year(), month0 + 1, 1).unwrap();
+        match self {
+            TimeGranularity::Day => date,
+            TimeGranularity::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            TimeGranularity::Month => month_start(date.month0()),
+            TimeGranularity::Quarter => month_start(date.month0() / 3 * 3),
+            TimeGranularity::Year => month_start(0),
+        }
+    }
+
+    /// First day after the period starting at `start`
+    pub fn period_end(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            TimeGranularity::Day => start + Days::new(1),
+            TimeGranularity::Week => start + Days::new(7),
+            TimeGranularity::Month => start + Months::new(1),
+            TimeGranularity::Quarter => start + Months::new(3),
+            TimeGranularity::Year => start + Months::new(12),
+        }
+    }
+
+    /// Number of periods touched by the days `from` to `to`
+    pub fn periods_between(&self, from: NaiveDate, to: NaiveDate) -> u32 {
+        let mut start = self.period_start(from);
+        let mut periods = 0;
+        while start <= to {
+            periods += 1;
+            start = self.period_end(start);
+        }
+        periods
+    }
+
+    /// SQL interval spanning `periods` periods
+    pub fn interval(&self, periods: u32) -> String {
+        match self {
+            TimeGranularity::Day => format!("{} days", periods),
+            TimeGranularity::Week => format!("{} days", periods * 7),
+            TimeGranularity::Month => format!("{} months", periods),
+            TimeGranularity::Quarter => format!("{} months", periods * 3),
+            TimeGranularity::Year => format!("{} years", periods),
+        }
+    }
+}
+
+/// Series a time series is compared with
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum TrendComparison {
+    /// The equally long range just before the requested one
+    PreviousPeriod,
+    /// The same periods one year earlier; weeks are compared with the week 52
+    /// weeks earlier so that they start on the same weekday
+    PreviousYear,
+}
+
+// Quotas
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct QuotaInfo {
+    /// Role the quota applies to; anonymous callers get the viewer quota
+    pub role: String,
+    /// Rows a single query may return, null when unlimited
+    pub max_rows_per_query: Option<i64>,
+    /// Rows that may be exported per UTC day, null when unlimited
+    pub max_export_rows_per_day: Option<i64>,
+    pub exported_rows_today: i64,
+    pub remaining_export_rows: Option<i64>,
+    /// RFC 3339 time the daily export counter resets
+    pub resets_at: String,
+}
+
+// Mutation Results
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct RegisterTableResult {
+    pub table_name: String,
+    pub columns: Vec<String>,
+    /// Whether an existing table with the same name is replaced
+    pub replaces_existing: bool,
+    pub dry_run: bool,
+    /// Whether the registration took effect; always false for dry runs
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct RefreshConnectionResult {
+    /// Dimension tables re-read from their sources into the in-memory cache
+    pub dimension_tables: Vec<String>,
+    /// Whether the daily revenue rollup is rebuilt; false when it cannot be built
+    pub rebuilds_rollup: bool,
+    pub dry_run: bool,
+    /// Whether the refresh took effect; always false for dry runs
+    pub applied: bool,
+}
+
+/// Format of an uploaded table
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum UploadFormat {
+    /// Comma separated values with a header row
+    Csv,
+    /// A JSON array of objects, or one object per line
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct UploadTableResult {
+    pub table_name: String,
+    pub columns: Vec<String>,
+    pub row_count: i64,
+    /// RFC 3339 time the table is dropped
+    pub expires_at: String,
+}
+
+/// Version and operating mode of the server
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ServerInfo {
+    pub version: String,
+    /// Whether state-changing operations are refused
+    pub read_only: bool,
+    pub ai_enabled: bool,
+    /// Whether the server serves generated sample data
+    pub demo: bool,
+}
+
+// Operations
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SlowQuery {
+    /// Id of the query, for `queryLineage`
+    pub query_id: String,
+    /// SQL text, truncated for long statements
+    pub sql: String,
+    pub duration_ms: f64,
+    /// RFC 3339 time the query finished
+    pub timestamp: String,
+    pub row_count: i64,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct RunningQuery {
+    /// Id of the query, for `killQuery`
+    pub query_id: String,
+    pub sql: String,
+    /// RFC 3339 time the query started
+    pub started_at: String,
+    pub elapsed_ms: f64,
+}
+
+/// A revision of the registered tables; each reload starts a new one
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct TableRevision {
+    pub revision: i64,
+    /// RFC 3339 time the revision became current
+    pub created_at: String,
+    /// RFC 3339 time a reload replaced it, null for the current revision
+    pub superseded_at: Option<String>,
+    /// Table whose reload replaced it
+    pub superseded_by: Option<String>,
+    pub current: bool,
+}
+
+/// Row counts of two tables and of their inner join on one key each
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct JoinStats {
+    pub left_rows: i64,
+    pub right_rows: i64,
+    /// Rows of the inner join
+    pub matched: i64,
+    /// Joined rows per left row, e.g. orders per customer; 0 for an empty left table
+    pub avg_fanout: f64,
+}
+
+/// A table column feeding an output column
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, SimpleObject)]
+pub struct SourceColumn {
+    pub table: String,
+    pub column: String,
+}
+
+/// Table columns an output column of a query is computed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct ColumnLineage {
+    pub output: String,
+    /// Empty for values computed from no column, such as `COUNT(*)`
+    pub sources: Vec<SourceColumn>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct QueryLineage {
+    pub query_id: String,
+    pub sql: String,
+    pub columns: Vec<ColumnLineage>,
+}
+
+/// WebSocket a subscription streams from
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum SubscriptionKind {
+    /// GraphQL operations over `/graphql/ws`
+    Graphql,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SubscriptionInfo {
+    pub id: String,
+    pub kind: SubscriptionKind,
+    /// Document of the operation the client last started, empty until its first
+    pub query: String,
+    /// Subscribed user, null for anonymous connections
+    pub user: Option<String>,
+    /// RFC 3339 time the connection opened
+    pub started_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct QueryHistoryEntry {
+    pub operation_name: Option<String>,
+    /// GraphQL document of the request
+    pub query: String,
+    pub duration_ms: f64,
+    /// RFC 3339 time the operation finished
+    pub timestamp: String,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct TableFiles {
+    pub table_name: String,
+    pub file_count: i32,
+    pub total_bytes: i64,
+    pub row_groups: i32,
+    /// Row count from the parquet footers
+    pub rows: i64,
+    /// Codecs used across all files, e.g. `SNAPPY`
+    pub compression: Vec<String>,
+    pub files: Vec<TableFile>,
+    /// Ranges of hive-style partition columns, empty for unpartitioned tables
+    pub partitions: Vec<TablePartition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct TableFile {
+    /// Location of the file on the server (admin only)
+    #[graphql(guard = "RoleGuard::new(\"admin\")")]
+    pub path: String,
+    pub size_bytes: i64,
+    pub row_groups: i32,
+    pub rows: i64,
+    pub compression: Vec<String>,
+    /// Writer that produced the file, from the parquet footer
+    pub created_by: Option<String>,
+    /// RFC 3339 time the file was last modified
+    pub last_modified: String,
+}
+
+/// How a column differs between two schemas
+#[derive(Debug, Clone, Serialize, Deserialize, Enum, Copy, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// Only in the second schema
+    Added,
+    /// Only in the first schema
+    Removed,
+    TypeChanged,
+    NullabilityChanged,
+}
+
+/// A column that differs between two schemas
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SchemaDiffEntry {
+    pub column: String,
+    pub change: SchemaChange,
+    /// Arrow type in the first schema, null when the column was added
+    pub type_a: Option<String>,
+    /// Arrow type in the second schema, null when the column was removed
+    pub type_b: Option<String>,
+}
+
+impl SchemaDiffEntry {
+    /// Differences from schema `a` to schema `b`: columns of `a` in order, then
+    /// the columns only `b` has
+    pub fn between(a: &Schema, b: &Schema) -> Vec<SchemaDiffEntry> {
+        let mut entries = Vec::new();
+        for field_a in a.fields() {
+            let type_a = Some(field_a.data_type().to_string());
+            let change = match b.field_with_name(field_a.name()) {
+                Err(_) => Some((SchemaChange::Removed, None)),
+                Ok(field_b) if field_b.data_type() != field_a.data_type() => Some((
+                    SchemaChange::TypeChanged,
+                    Some(field_b.data_type().to_string()),
+                )),
+                Ok(field_b) if field_b.is_nullable() != field_a.is_nullable() => Some((
+                    SchemaChange::NullabilityChanged,
+                    Some(field_b.data_type().to_string()),
+                )),
+                Ok(_) => None,
+            };
+            if let Some((change, type_b)) = change {
+                entries.push(SchemaDiffEntry {
+                    column: field_a.name().clone(),
+                    change,
+                    type_a,
+                    type_b,
+                });
+            }
+        }
+        for field_b in b.fields() {
+            if a.field_with_name(field_b.name()).is_err() {
+                entries.push(SchemaDiffEntry {
+                    column: field_b.name().clone(),
+                    change: SchemaChange::Added,
+                    type_a: None,
+                    type_b: Some(field_b.data_type().to_string()),
+                });
+            }
+        }
+        entries
+    }
+}
+
+/// A row found in only one of two compared results
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ComparedRow {
+    /// Values of the key columns
+    pub key: Json<Vec<serde_json::Value>>,
+    /// Values of all columns, in the order of `QueryComparison.columns`
+    pub values: Json<Vec<serde_json::Value>>,
+}
+
+/// A value that differs between two rows with the same key
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ValueDifference {
+    pub key: Json<Vec<serde_json::Value>>,
+    pub column: String,
+    pub value_a: Json<serde_json::Value>,
+    pub value_b: Json<serde_json::Value>,
+}
+
+/// Differences between the results of two queries. The lists are capped by the
+/// comparison limit, the counts are not.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct QueryComparison {
+    /// Whether the results hold the same rows with equal values
+    pub identical: bool,
+    pub columns: Vec<String>,
+    /// Columns the rows were aligned on, all columns when none were given
+    pub key_columns: Vec<String>,
+    pub rows_a: i64,
+    pub rows_b: i64,
+    pub only_in_a_count: i64,
+    pub only_in_b_count: i64,
+    pub difference_count: i64,
+    pub only_in_a: Vec<ComparedRow>,
+    pub only_in_b: Vec<ComparedRow>,
+    pub differences: Vec<ValueDifference>,
+}
+
+/// One column of a columnar result
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ResultColumn {
+    pub name: String,
+    /// Arrow type of the column
+    #[graphql(name = "type")]
+    pub data_type: String,
+    /// Values in row order: numbers, booleans or strings, null for nulls
+    pub values: Json<Vec<serde_json::Value>>,
+}
+
+/// Query result carried column by column instead of row by row
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ColumnarResult {
+    pub columns: Vec<ResultColumn>,
+    pub row_count: i64,
+}
+
+/// Rows of an ad hoc query
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct JsonRows {
+    pub columns: Vec<String>,
+    /// One object per row keyed by column name: numbers, booleans or strings, dates
+    /// as `YYYY-MM-DD` and null for nulls
+    pub rows: Json<Vec<serde_json::Value>>,
+    pub row_count: i64,
+}
+
+/// Filter operators valid for one column
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ColumnFilterCaps {
+    pub column: String,
+    /// Arrow type of the column
+    pub data_type: String,
+    pub nullable: bool,
+    pub operators: Vec<FilterOperator>,
+}
+
+/// Row, null and distinct counts of one column
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ColumnStats {
+    pub table: String,
+    pub column: String,
+    pub row_count: i64,
+    pub null_count: i64,
+    pub distinct_count: i64,
+    /// Whether `distinct_count` is a HyperLogLog estimate
+    pub approximate: bool,
+}
+
+/// A column of a registered table with its data dictionary entry
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ColumnInfo {
+    pub table: String,
+    pub column: String,
+    /// Arrow type of the column
+    pub data_type: String,
+    pub nullable: bool,
+    /// Description from the data dictionary, empty when it has none
+    pub description: String,
+    pub tags: Vec<String>,
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct TablePartition {
+    pub column: String,
+    pub min: String,
+    pub max: String,
+}
+
 // Implement Display for enums
 impl std::fmt::Display for FilterOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -267,6 +1078,7 @@ impl std::fmt::Display for FilterOperator {
             FilterOperator::Lt => write!(f, "<"),
             FilterOperator::Lte => write!(f, "<="),
             FilterOperator::Like => write!(f, "LIKE"),
+            FilterOperator::ILike => write!(f, "ILIKE"),
             FilterOperator::In => write!(f, "IN"),
         }
     }
@@ -280,3 +1092,9 @@ impl std::fmt::Display for SortOrder {
         }
     }
 }
+
+impl std::fmt::Display for MarketSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code().unwrap_or("UNKNOWN"))
+    }
+}
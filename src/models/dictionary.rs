@@ -0,0 +1,72 @@
+//! Human descriptions of table columns
+//!
+//! The data dictionary is a YAML file mapping each table to its columns and each
+//! column to a description, tags and a unit, e.g.
+//!
+//! ```yaml
+//! customer:
+//!   c_acctbal:
+//!     description: Account balance
+//!     tags: [finance]
+//!     unit: USD
+//! ```
+//!
+//! It is read once at startup. Columns without an entry have an empty description.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Documentation of one column
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnDoc {
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Unit of the values, e.g. `USD` or `days`
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// Column documentation by table and column name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DataDictionary {
+    pub tables: BTreeMap<String, BTreeMap<String, ColumnDoc>>,
+}
+
+impl DataDictionary {
+    /// Parse a dictionary, reporting where a malformed document went wrong
+    pub fn parse(yaml: &str) -> Result<Self, String> {
+        if yaml.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(yaml).map_err(|e| match e.location() {
+            Some(location) => format!(
+                "line {} column {}: {}",
+                location.line(),
+                location.column(),
+                e
+            ),
+            None => e.to_string(),
+        })
+    }
+
+    /// Read a dictionary file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read data dictionary {}: {}", path.display(), e))?;
+        Self::parse(&contents)
+            .map_err(|e| format!("Invalid data dictionary {}: {}", path.display(), e))
+    }
+
+    /// Documentation of a column, `None` when it has no entry
+    pub fn column(&self, table: &str, column: &str) -> Option<&ColumnDoc> {
+        self.tables
+            .get(table)
+            .and_then(|columns| columns.get(column))
+    }
+}
@@ -0,0 +1,251 @@
+//! Columns the typed TPCH resolvers read from each table
+//!
+//! The typed models decode record batches by column name and Arrow type, so a
+//! table whose schema drifted from TPCH would fail every query at decode time.
+//! Checking the manifests against the registered schemas at startup reports
+//! each mismatch once and lets the affected resolvers fail with a precise error.
+
+use datafusion::arrow::datatypes::{DataType, Schema};
+use std::fmt;
+
+/// Arrow types a model column can be decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    /// Integers that fit an `i64`, read widened
+    Integer,
+    /// Integers of at most 32 bits, for model fields that are `i32`
+    Int32,
+    /// Integers, floats and decimals, read as `f64`
+    Numeric,
+    /// `Utf8`, `LargeUtf8` and `Utf8View`
+    Text,
+    /// `Date32` and `Date64`, or dates stored as text
+    Date,
+}
+
+impl ColumnKind {
+    pub fn accepts(&self, data_type: &DataType) -> bool {
+        match self {
+            ColumnKind::Integer => data_type.is_integer(),
+            ColumnKind::Int32 => matches!(
+                data_type,
+                DataType::Int8
+                    | DataType::Int16
+                    | DataType::Int32
+                    | DataType::UInt8
+                    | DataType::UInt16
+            ),
+            ColumnKind::Numeric => data_type.is_numeric(),
+            ColumnKind::Text => matches!(
+                data_type,
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+            ),
+            ColumnKind::Date => {
+                matches!(data_type, DataType::Date32 | DataType::Date64)
+                    || ColumnKind::Text.accepts(data_type)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ColumnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnKind::Integer => "integer",
+            ColumnKind::Int32 => "32-bit integer",
+            ColumnKind::Numeric => "numeric",
+            ColumnKind::Text => "string",
+            ColumnKind::Date => "date",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Columns a typed model reads from its table
+#[derive(Debug, Clone, Copy)]
+pub struct ModelManifest {
+    /// GraphQL type of the model, e.g. `Customer`
+    pub model: &'static str,
+    pub table: &'static str,
+    pub columns: &'static [(&'static str, ColumnKind)],
+}
+
+pub const CUSTOMER_MANIFEST: ModelManifest = ModelManifest {
+    model: "Customer",
+    table: "customer",
+    columns: &[
+        ("c_custkey", ColumnKind::Integer),
+        ("c_name", ColumnKind::Text),
+        ("c_address", ColumnKind::Text),
+        ("c_nationkey", ColumnKind::Integer),
+        ("c_phone", ColumnKind::Text),
+        ("c_acctbal", ColumnKind::Numeric),
+        ("c_mktsegment", ColumnKind::Text),
+        ("c_comment", ColumnKind::Text),
+    ],
+};
+
+pub const ORDER_MANIFEST: ModelManifest = ModelManifest {
+    model: "Order",
+    table: "orders",
+    columns: &[
+        ("o_orderkey", ColumnKind::Integer),
+        ("o_custkey", ColumnKind::Integer),
+        ("o_orderstatus", ColumnKind::Text),
+        ("o_totalprice", ColumnKind::Numeric),
+        ("o_orderdate", ColumnKind::Date),
+        ("o_orderpriority", ColumnKind::Text),
+        ("o_clerk", ColumnKind::Text),
+        ("o_shippriority", ColumnKind::Int32),
+        ("o_comment", ColumnKind::Text),
+    ],
+};
+
+pub const LINEITEM_MANIFEST: ModelManifest = ModelManifest {
+    model: "LineItem",
+    table: "lineitem",
+    columns: &[
+        ("l_orderkey", ColumnKind::Integer),
+        ("l_partkey", ColumnKind::Integer),
+        ("l_suppkey", ColumnKind::Integer),
+        ("l_linenumber", ColumnKind::Int32),
+        ("l_quantity", ColumnKind::Numeric),
+        ("l_extendedprice", ColumnKind::Numeric),
+        ("l_discount", ColumnKind::Numeric),
+        ("l_tax", ColumnKind::Numeric),
+        ("l_returnflag", ColumnKind::Text),
+        ("l_linestatus", ColumnKind::Text),
+        ("l_shipdate", ColumnKind::Date),
+        ("l_commitdate", ColumnKind::Date),
+        ("l_receiptdate", ColumnKind::Date),
+        ("l_shipinstruct", ColumnKind::Text),
+        ("l_shipmode", ColumnKind::Text),
+        ("l_comment", ColumnKind::Text),
+    ],
+};
+
+pub const PART_MANIFEST: ModelManifest = ModelManifest {
+    model: "Part",
+    table: "part",
+    columns: &[
+        ("p_partkey", ColumnKind::Integer),
+        ("p_name", ColumnKind::Text),
+        ("p_mfgr", ColumnKind::Text),
+        ("p_brand", ColumnKind::Text),
+        ("p_type", ColumnKind::Text),
+        ("p_size", ColumnKind::Int32),
+        ("p_container", ColumnKind::Text),
+        ("p_retailprice", ColumnKind::Numeric),
+        ("p_comment", ColumnKind::Text),
+    ],
+};
+
+pub const SUPPLIER_MANIFEST: ModelManifest = ModelManifest {
+    model: "Supplier",
+    table: "supplier",
+    columns: &[
+        ("s_suppkey", ColumnKind::Integer),
+        ("s_name", ColumnKind::Text),
+        ("s_address", ColumnKind::Text),
+        ("s_nationkey", ColumnKind::Integer),
+        ("s_phone", ColumnKind::Text),
+        ("s_acctbal", ColumnKind::Numeric),
+        ("s_comment", ColumnKind::Text),
+    ],
+};
+
+pub const NATION_MANIFEST: ModelManifest = ModelManifest {
+    model: "Nation",
+    table: "nation",
+    columns: &[
+        ("n_nationkey", ColumnKind::Integer),
+        ("n_name", ColumnKind::Text),
+        ("n_regionkey", ColumnKind::Integer),
+        ("n_comment", ColumnKind::Text),
+    ],
+};
+
+pub const REGION_MANIFEST: ModelManifest = ModelManifest {
+    model: "Region",
+    table: "region",
+    columns: &[
+        ("r_regionkey", ColumnKind::Integer),
+        ("r_name", ColumnKind::Text),
+        ("r_comment", ColumnKind::Text),
+    ],
+};
+
+pub const PARTSUPP_MANIFEST: ModelManifest = ModelManifest {
+    model: "PartSupp",
+    table: "partsupp",
+    columns: &[
+        ("ps_partkey", ColumnKind::Integer),
+        ("ps_suppkey", ColumnKind::Integer),
+        ("ps_availqty", ColumnKind::Int32),
+        ("ps_supplycost", ColumnKind::Numeric),
+        ("ps_comment", ColumnKind::Text),
+    ],
+};
+
+/// Manifests of all typed models
+pub const MODEL_MANIFESTS: &[ModelManifest] = &[
+    CUSTOMER_MANIFEST,
+    ORDER_MANIFEST,
+    LINEITEM_MANIFEST,
+    PART_MANIFEST,
+    SUPPLIER_MANIFEST,
+    NATION_MANIFEST,
+    REGION_MANIFEST,
+    PARTSUPP_MANIFEST,
+];
+
+/// A model column that is missing from its table or has an unreadable type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMismatch {
+    pub model: &'static str,
+    pub table: &'static str,
+    pub column: &'static str,
+    pub expected: ColumnKind,
+    /// Type of the column in the table, `None` when it is missing
+    pub found: Option<String>,
+}
+
+impl fmt::Display for ColumnMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.found {
+            Some(found) => write!(
+                f,
+                "{}.{} is {}, the {} model needs a {} column",
+                self.table, self.column, found, self.model, self.expected
+            ),
+            None => write!(
+                f,
+                "{}.{} is missing, the {} model needs a {} column",
+                self.table, self.column, self.model, self.expected
+            ),
+        }
+    }
+}
+
+impl ModelManifest {
+    /// Columns of the manifest the table schema does not provide
+    pub fn check(&self, schema: &Schema) -> Vec<ColumnMismatch> {
+        self.columns
+            .iter()
+            .filter_map(|(column, expected)| {
+                let found = match schema.field_with_name(column) {
+                    Ok(field) if expected.accepts(field.data_type()) => return None,
+                    Ok(field) => Some(field.data_type().to_string()),
+                    Err(_) => None,
+                };
+                Some(ColumnMismatch {
+                    model: self.model,
+                    table: self.table,
+                    column,
+                    expected: *expected,
+                    found,
+                })
+            })
+            .collect()
+    }
+}
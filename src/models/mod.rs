@@ -1,7 +1,11 @@
 //! Data models for GraphQL DataFusion
 
 pub mod data;
+pub mod dictionary;
+pub mod manifest;
 pub mod schema_inference;
 
 pub use data::*;
+pub use dictionary::*;
+pub use manifest::*;
 pub use schema_inference::*;
@@ -0,0 +1,208 @@
+//! Webhook notifications of internal events
+//!
+//! The `Notifier` subscribes to the `EventBus` and POSTs each event as JSON to
+//! the configured endpoints that accept its kind. The payload carries a `text`
+//! field, so Slack incoming webhooks render it directly. Endpoints with a secret
+//! receive an HMAC-SHA256 signature of the body in `X-Signature-256`. Failed
+//! deliveries are retried with exponential backoff; deliveries still failing
+//! after the last retry are appended to the dead-letter log.
+
+use crate::datafusion::context::RetryPolicy;
+use crate::events::{Event, EventBus, EventKind};
+use crate::metrics::WEBHOOK_DELIVERIES_TOTAL;
+use futures::future::join_all;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Header with the signature of the body, `sha256=<hex digest>`
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// A webhook receiving events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub url: String,
+    /// Key of the signature header; empty sends unsigned requests
+    #[serde(default)]
+    pub secret: String,
+    /// Kinds of events to send; empty sends all of them
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+}
+
+impl WebhookEndpoint {
+    pub fn accepts(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Signature of a body for the signature header
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// JSON body sent for an event
+pub fn payload(event: &Event) -> serde_json::Value {
+    serde_json::json!({
+        "event": event.kind,
+        "table": event.table,
+        "message": event.message,
+        "timestamp": event.timestamp,
+        "text": event.text(),
+    })
+}
+
+/// Delivers events to webhook endpoints
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    client: Client,
+    endpoints: Vec<WebhookEndpoint>,
+    retry_policy: RetryPolicy,
+    dead_letter_path: Option<PathBuf>,
+}
+
+impl Notifier {
+    pub fn new(endpoints: Vec<WebhookEndpoint>) -> Self {
+        Self {
+            client: Client::new(),
+            endpoints,
+            retry_policy: RetryPolicy::default(),
+            dead_letter_path: None,
+        }
+    }
+
+    /// Set the retries of failed deliveries
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Append undeliverable events to a JSON lines file instead of only logging them
+    pub fn with_dead_letter_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.dead_letter_path = Some(path.into());
+        self
+    }
+
+    /// Deliver the events published on the bus from now on
+    pub fn spawn(self, events: &EventBus) -> JoinHandle<()> {
+        let mut receiver = events.subscribe();
+        let notifier = Arc::new(self);
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let notifier = notifier.clone();
+                        tokio::spawn(async move { notifier.notify(&event).await });
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Webhook notifier fell behind, {} events skipped", skipped)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Deliver an event to every endpoint accepting it, returning how many
+    /// deliveries succeeded
+    pub async fn notify(&self, event: &Event) -> usize {
+        let body = payload(event).to_string();
+        let deliveries = self
+            .endpoints
+            .iter()
+            .filter(|endpoint| endpoint.accepts(event.kind))
+            .map(|endpoint| self.deliver(endpoint, event.kind, &body));
+        join_all(deliveries)
+            .await
+            .into_iter()
+            .filter(|delivered| *delivered)
+            .count()
+    }
+
+    async fn deliver(&self, endpoint: &WebhookEndpoint, kind: EventKind, body: &str) -> bool {
+        let label = kind.to_string();
+        let mut backoff = self.retry_policy.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            let error = match self.post(endpoint, body).await {
+                Ok(()) => {
+                    WEBHOOK_DELIVERIES_TOTAL
+                        .with_label_values(&[label.as_str(), "success"])
+                        .inc();
+                    return true;
+                }
+                Err(e) => e,
+            };
+            if attempt > self.retry_policy.max_retries {
+                WEBHOOK_DELIVERIES_TOTAL
+                    .with_label_values(&[label.as_str(), "failure"])
+                    .inc();
+                self.dead_letter(endpoint, body, attempt, &error);
+                return false;
+            }
+
+            warn!(
+                "Webhook delivery to {} failed (attempt {}), retrying in {:?}: {}",
+                endpoint.url, attempt, backoff, error
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.retry_policy.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    async fn post(&self, endpoint: &WebhookEndpoint, body: &str) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if !endpoint.secret.is_empty() {
+            request = request.header(SIGNATURE_HEADER, sign(&endpoint.secret, body.as_bytes()));
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("endpoint returned {}", response.status()));
+        }
+        Ok(())
+    }
+
+    fn dead_letter(&self, endpoint: &WebhookEndpoint, body: &str, attempts: u32, error: &str) {
+        error!(
+            "Giving up on webhook delivery to {} after {} attempts: {}",
+            endpoint.url, attempts, error
+        );
+        let Some(path) = &self.dead_letter_path else {
+            return;
+        };
+        let record = serde_json::json!({
+            "url": endpoint.url,
+            "attempts": attempts,
+            "error": error,
+            "payload": serde_json::from_str::<serde_json::Value>(body).unwrap_or_default(),
+        });
+        let written = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", record));
+        if let Err(e) = written {
+            error!(
+                "Failed to write webhook dead letter {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
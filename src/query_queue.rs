@@ -0,0 +1,278 @@
+//! Bounded query queue
+//!
+//! Limits how many GraphQL requests execute at once. Requests beyond that wait in a
+//! bounded FIFO queue, so short bursts are served with added latency, while a full
+//! queue or an exceeded wait time sheds load.
+//!
+//! Admission is split into an interactive and a heavy pool so a few expensive
+//! analytics requests cannot starve cheap lookups. Heavy requests may borrow idle
+//! interactive permits, but one interactive permit is always kept in reserve and
+//! interactive requests never borrow heavy permits.
+//!
+//! Independently of the pools, each authenticated user may only have a few
+//! requests in flight, so one user opening many parallel queries cannot take
+//! every slot. Requests over the per-user cap are rejected rather than queued.
+
+use crate::metrics::QUERY_POOL_IN_USE;
+use async_graphql::parser::parse_query;
+use async_graphql::parser::types::{ExecutableDocument, Selection, SelectionSet};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Query queue configuration
+#[derive(Debug, Clone)]
+pub struct QueryQueueConfig {
+    /// Interactive requests executing at the same time
+    pub max_concurrent: usize,
+    /// Heavy requests executing at the same time
+    pub max_concurrent_heavy: usize,
+    /// Requests allowed to wait for a free slot
+    pub capacity: usize,
+    /// Longest time a request waits for a free slot
+    pub max_wait: Duration,
+    /// Top-level fields that make a request heavy
+    pub heavy_fields: Vec<String>,
+    /// Requests one user may have in flight at the same time; 0 for no limit
+    pub max_concurrent_per_user: usize,
+}
+
+impl Default for QueryQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 8,
+            max_concurrent_heavy: 2,
+            capacity: 64,
+            max_wait: Duration::from_secs(5),
+            heavy_fields: vec![
+                "salesAnalytics".to_string(),
+                "naturalLanguageQuery".to_string(),
+                "insights".to_string(),
+            ],
+            max_concurrent_per_user: 5,
+        }
+    }
+}
+
+/// Admission pool a request is scheduled in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryClass {
+    Interactive,
+    Heavy,
+}
+
+impl QueryClass {
+    fn label(&self) -> &'static str {
+        match self {
+            QueryClass::Interactive => "interactive",
+            QueryClass::Heavy => "heavy",
+        }
+    }
+}
+
+impl FromStr for QueryClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "interactive" => Ok(QueryClass::Interactive),
+            "heavy" => Ok(QueryClass::Heavy),
+            _ => Err(format!("Unknown query class: {}", s)),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueError {
+    #[error("Query queue is full, try again later")]
+    Full,
+    #[error("Timed out waiting in the query queue")]
+    Timeout,
+    #[error("Too many concurrent queries, each user may run {limit} at a time")]
+    UserLimit { limit: usize },
+}
+
+/// Execution slot; released when dropped
+#[derive(Debug)]
+pub struct QueryPermit {
+    _permit: OwnedSemaphorePermit,
+    pool: QueryClass,
+}
+
+impl QueryPermit {
+    fn new(permit: OwnedSemaphorePermit, pool: QueryClass) -> Self {
+        QUERY_POOL_IN_USE.with_label_values(&[pool.label()]).inc();
+        Self {
+            _permit: permit,
+            pool,
+        }
+    }
+
+    /// Pool the permit was taken from, which differs from the request class
+    /// when a heavy request borrowed an interactive permit
+    pub fn pool(&self) -> QueryClass {
+        self.pool
+    }
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        QUERY_POOL_IN_USE
+            .with_label_values(&[self.pool.label()])
+            .dec();
+    }
+}
+
+/// In-flight slot of one user; released when dropped
+#[derive(Debug)]
+pub struct UserPermit {
+    users: Arc<Mutex<HashMap<String, usize>>>,
+    user: String,
+}
+
+impl Drop for UserPermit {
+    fn drop(&mut self) {
+        let mut users = self.users.lock().unwrap();
+        if let Some(in_flight) = users.get_mut(&self.user) {
+            *in_flight -= 1;
+            if *in_flight == 0 {
+                users.remove(&self.user);
+            }
+        }
+    }
+}
+
+/// Bounded FIFO queue in front of query execution
+#[derive(Debug)]
+pub struct QueryQueue {
+    interactive: Arc<Semaphore>,
+    heavy: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    /// Requests in flight per user
+    users: Arc<Mutex<HashMap<String, usize>>>,
+    config: QueryQueueConfig,
+}
+
+impl QueryQueue {
+    pub fn new(config: QueryQueueConfig) -> Self {
+        Self {
+            interactive: Arc::new(Semaphore::new(config.max_concurrent)),
+            heavy: Arc::new(Semaphore::new(config.max_concurrent_heavy)),
+            waiting: AtomicUsize::new(0),
+            users: Arc::new(Mutex::new(HashMap::new())),
+            config,
+        }
+    }
+
+    /// Classify a GraphQL document by its top-level fields, including those
+    /// selected through fragments
+    pub fn classify(&self, query: &str) -> QueryClass {
+        let Ok(document) = parse_query(query) else {
+            return QueryClass::Interactive;
+        };
+
+        let heavy = document.operations.iter().any(|(_, operation)| {
+            self.selects_heavy(
+                &operation.node.selection_set.node,
+                &document,
+                &mut HashSet::new(),
+            )
+        });
+
+        if heavy {
+            QueryClass::Heavy
+        } else {
+            QueryClass::Interactive
+        }
+    }
+
+    fn selects_heavy<'a>(
+        &self,
+        selection_set: &'a SelectionSet,
+        document: &'a ExecutableDocument,
+        visited: &mut HashSet<&'a str>,
+    ) -> bool {
+        selection_set
+            .items
+            .iter()
+            .any(|selection| match &selection.node {
+                Selection::Field(field) => self
+                    .config
+                    .heavy_fields
+                    .iter()
+                    .any(|name| name.as_str() == field.node.name.node.as_str()),
+                Selection::InlineFragment(fragment) => {
+                    self.selects_heavy(&fragment.node.selection_set.node, document, visited)
+                }
+                Selection::FragmentSpread(spread) => {
+                    let name = spread.node.fragment_name.node.as_str();
+                    // Fragment cycles are invalid; each fragment is read once
+                    visited.insert(name)
+                        && document.fragments.get(name).is_some_and(|fragment| {
+                            self.selects_heavy(&fragment.node.selection_set.node, document, visited)
+                        })
+                }
+            })
+    }
+
+    /// Wait for an execution slot in the pool of the given class
+    pub async fn acquire(&self, class: QueryClass) -> Result<QueryPermit, QueueError> {
+        let pool = match class {
+            QueryClass::Interactive => &self.interactive,
+            QueryClass::Heavy => &self.heavy,
+        };
+        if let Ok(permit) = pool.clone().try_acquire_owned() {
+            return Ok(QueryPermit::new(permit, class));
+        }
+
+        if class == QueryClass::Heavy && self.interactive.available_permits() > 1 {
+            if let Ok(permit) = self.interactive.clone().try_acquire_owned() {
+                return Ok(QueryPermit::new(permit, QueryClass::Interactive));
+            }
+        }
+
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= self.config.capacity {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            return Err(QueueError::Full);
+        }
+
+        // The semaphore is fair, so waiters are served in arrival order
+        let result = tokio::time::timeout(self.config.max_wait, pool.clone().acquire_owned()).await;
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+
+        match result {
+            Ok(Ok(permit)) => Ok(QueryPermit::new(permit, class)),
+            _ => Err(QueueError::Timeout),
+        }
+    }
+
+    /// Take one of the user's in-flight slots, failing right away when the user
+    /// already has `max_concurrent_per_user` requests in flight
+    pub fn acquire_user(&self, user: &str) -> Result<UserPermit, QueueError> {
+        let limit = self.config.max_concurrent_per_user;
+        let mut users = self.users.lock().unwrap();
+        let in_flight = users.entry(user.to_string()).or_insert(0);
+        if limit > 0 && *in_flight >= limit {
+            return Err(QueueError::UserLimit { limit });
+        }
+        *in_flight += 1;
+        Ok(UserPermit {
+            users: self.users.clone(),
+            user: user.to_string(),
+        })
+    }
+
+    /// Number of requests the user has in flight
+    pub fn user_in_flight(&self, user: &str) -> usize {
+        self.users.lock().unwrap().get(user).copied().unwrap_or(0)
+    }
+
+    /// Number of requests currently waiting for a slot
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,260 @@
+//! Per-role limits on how much data callers can pull
+//!
+//! Each role has a cap on the rows a single query returns and on the rows a user
+//! may export per UTC day. Daily export counters are kept per user and, when a
+//! state file is configured, written to disk so they survive restarts. The file
+//! is read again before the counters are used, so replicas sharing it see each
+//! other's exports; two replicas counting at the same moment may still both
+//! pass a quota that only one of them fits in.
+
+use crate::config::Config;
+use crate::state::{QUOTA_STATE, StateDir, read_state, write_state};
+use chrono::{DateTime, Days, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+use tracing::warn;
+
+/// Role whose quota applies to anonymous callers and roles without a quota
+pub const FALLBACK_ROLE: &str = "viewer";
+
+/// Limits for one role; `None` means unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleQuota {
+    /// Rows a single query may return
+    pub max_rows_per_query: Option<u64>,
+    /// Rows a user may export per UTC day
+    pub max_export_rows_per_day: Option<u64>,
+}
+
+impl RoleQuota {
+    pub const UNLIMITED: RoleQuota = RoleQuota {
+        max_rows_per_query: None,
+        max_export_rows_per_day: None,
+    };
+}
+
+/// Default quotas: viewers 10k rows per query and 100k exported per day,
+/// analysts ten times that, admins unlimited
+pub fn default_role_quotas() -> BTreeMap<String, RoleQuota> {
+    BTreeMap::from([
+        (
+            "viewer".to_string(),
+            RoleQuota {
+                max_rows_per_query: Some(10_000),
+                max_export_rows_per_day: Some(100_000),
+            },
+        ),
+        (
+            "analyst".to_string(),
+            RoleQuota {
+                max_rows_per_query: Some(100_000),
+                max_export_rows_per_day: Some(1_000_000),
+            },
+        ),
+        ("admin".to_string(), RoleQuota::UNLIMITED),
+    ])
+}
+
+#[derive(Debug, Error)]
+pub enum QuotaError {
+    #[error("Query returned {rows} rows, the {role} role is limited to {limit} rows per query")]
+    RowLimit { role: String, rows: u64, limit: u64 },
+    #[error(
+        "Exporting {rows} rows exceeds the daily export quota of {limit} rows for the {role} role \
+         ({used} already exported), resets at {reset_at}"
+    )]
+    ExportQuota {
+        role: String,
+        rows: u64,
+        used: u64,
+        limit: u64,
+        reset_at: DateTime<Utc>,
+    },
+}
+
+impl QuotaError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            QuotaError::RowLimit { .. } => "ROW_LIMIT_EXCEEDED",
+            QuotaError::ExportQuota { .. } => "EXPORT_QUOTA_EXCEEDED",
+        }
+    }
+
+    /// When the exceeded quota resets, `None` for per-query limits
+    pub fn reset_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            QuotaError::RowLimit { .. } => None,
+            QuotaError::ExportQuota { reset_at, .. } => Some(*reset_at),
+        }
+    }
+}
+
+/// A caller's quota and what they used today
+#[derive(Debug, Clone)]
+pub struct QuotaUsage {
+    pub role: String,
+    pub quota: RoleQuota,
+    pub exported_rows_today: u64,
+    pub resets_at: DateTime<Utc>,
+}
+
+/// Daily export counters, as persisted to the state file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuotaState {
+    /// UTC day the counters belong to, `YYYY-MM-DD`
+    day: String,
+    exported: HashMap<String, u64>,
+}
+
+impl QuotaState {
+    /// Start new counters when the day has changed
+    fn roll_over(&mut self, today: &str) {
+        if self.day != today {
+            self.day = today.to_string();
+            self.exported.clear();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct QuotaManager {
+    quotas: BTreeMap<String, RoleQuota>,
+    state_path: Option<PathBuf>,
+    state: Mutex<QuotaState>,
+}
+
+impl QuotaManager {
+    pub fn new(quotas: BTreeMap<String, RoleQuota>) -> Self {
+        Self {
+            quotas,
+            state_path: None,
+            state: Mutex::new(QuotaState::default()),
+        }
+    }
+
+    /// Quotas of the configured roles, with counters in `quota_state_path` or,
+    /// failing that, in the state directory
+    pub fn from_config(config: &Config) -> Self {
+        let quotas = Self::new(config.role_quotas.clone());
+        if !config.quota_state_path.is_empty() {
+            quotas.with_state_file(&config.quota_state_path)
+        } else if !config.state_dir.is_empty() {
+            quotas.with_state_file(StateDir::new(&config.state_dir).path(&QUOTA_STATE))
+        } else {
+            quotas
+        }
+    }
+
+    /// Persist export counters to a JSON file, loading counters saved earlier
+    pub fn with_state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_path = Some(path.into());
+        let mut state = QuotaState::default();
+        self.load(&mut state);
+        self.state = Mutex::new(state);
+        self
+    }
+
+    /// Replace the counters with those in the state file, which other replicas
+    /// may have written since
+    fn load(&self, state: &mut QuotaState) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        match read_state(path, &QUOTA_STATE) {
+            Ok(Some(saved)) => *state = saved,
+            Ok(None) => {}
+            Err(e) => warn!("Ignoring unreadable quota state {}: {}", path.display(), e),
+        }
+    }
+
+    /// Quota for a role; anonymous callers and unknown roles get the viewer quota
+    pub fn quota(&self, role: Option<&str>) -> RoleQuota {
+        role.and_then(|role| self.quotas.get(role))
+            .or_else(|| self.quotas.get(FALLBACK_ROLE))
+            .copied()
+            .unwrap_or(RoleQuota::UNLIMITED)
+    }
+
+    /// Check the rows returned by one query against the role's cap
+    pub fn check_rows(&self, role: Option<&str>, rows: u64) -> Result<(), QuotaError> {
+        match self.quota(role).max_rows_per_query {
+            Some(limit) if rows > limit => Err(QuotaError::RowLimit {
+                role: role.unwrap_or(FALLBACK_ROLE).to_string(),
+                rows,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Count exported rows against the user's daily quota, rejecting the export
+    /// without counting it when it does not fit
+    pub fn record_export(
+        &self,
+        user: &str,
+        role: Option<&str>,
+        rows: u64,
+    ) -> Result<(), QuotaError> {
+        self.check_rows(role, rows)?;
+
+        let mut state = self.state.lock().unwrap();
+        self.load(&mut state);
+        state.roll_over(&today());
+        let used = state.exported.get(user).copied().unwrap_or(0);
+        let limit = self.quota(role).max_export_rows_per_day;
+        if let Some(limit) = limit.filter(|limit| used + rows > *limit) {
+            return Err(QuotaError::ExportQuota {
+                role: role.unwrap_or(FALLBACK_ROLE).to_string(),
+                rows,
+                used,
+                limit,
+                reset_at: next_reset(),
+            });
+        }
+        state.exported.insert(user.to_string(), used + rows);
+        self.persist(&state);
+        Ok(())
+    }
+
+    pub fn usage(&self, user: &str, role: Option<&str>) -> QuotaUsage {
+        let mut state = self.state.lock().unwrap();
+        self.load(&mut state);
+        state.roll_over(&today());
+        QuotaUsage {
+            role: role.unwrap_or(FALLBACK_ROLE).to_string(),
+            quota: self.quota(role),
+            exported_rows_today: state.exported.get(user).copied().unwrap_or(0),
+            resets_at: next_reset(),
+        }
+    }
+
+    fn persist(&self, state: &QuotaState) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        if let Err(e) = write_state(path, &QUOTA_STATE, state) {
+            warn!("Failed to save quota state: {}", e);
+        }
+    }
+}
+
+impl Default for QuotaManager {
+    fn default() -> Self {
+        Self::new(default_role_quotas())
+    }
+}
+
+fn today() -> String {
+    Utc::now().date_naive().to_string()
+}
+
+/// Start of the next UTC day, when daily counters reset
+fn next_reset() -> DateTime<Utc> {
+    (Utc::now().date_naive() + Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc()
+}
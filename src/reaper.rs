@@ -0,0 +1,176 @@
+//! Background expiry of idle state
+//!
+//! Stateful registries implement `Expirable` and are registered with a `Reaper`,
+//! which sweeps them on an interval, logs what it reclaimed and exports the number
+//! of live entries per registry. `TtlMap` is a ready-made registry whose entries
+//! expire a fixed time after they were last accessed.
+
+use crate::metrics::{STATE_LIVE_ENTRIES, STATE_REAPED_TOTAL};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// A registry holding state that expires when idle
+pub trait Expirable: Send + Sync {
+    /// Name used in logs and as the `registry` metric label
+    fn registry_name(&self) -> &str;
+
+    /// Remove the entries idle past their TTL at `now`, returning their keys
+    fn reap_expired(&self, now: Instant) -> Vec<String>;
+
+    /// Number of entries currently held
+    fn live_entries(&self) -> usize;
+}
+
+#[derive(Debug)]
+struct TtlEntry<V> {
+    value: V,
+    last_access: Instant,
+}
+
+/// Map whose entries expire `ttl` after they were last inserted or read
+#[derive(Debug)]
+pub struct TtlMap<V> {
+    name: String,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, TtlEntry<V>>>,
+}
+
+impl<V: Clone> TtlMap<V> {
+    pub fn new(name: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            name: name.into(),
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn insert(&self, key: impl Into<String>, value: V) {
+        self.entries.lock().unwrap().insert(
+            key.into(),
+            TtlEntry {
+                value,
+                last_access: Instant::now(),
+            },
+        );
+    }
+
+    /// Value of a live entry, counting as an access. Entries past their TTL are
+    /// gone even before the reaper has swept them.
+    pub fn get(&self, key: &str) -> Option<V> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(key) {
+            Some(entry) if now.duration_since(entry.last_access) < self.ttl => {
+                entry.last_access = now;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn remove(&self, key: &str) -> Option<V> {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(key)
+            .map(|entry| entry.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<V: Send> Expirable for TtlMap<V> {
+    fn registry_name(&self) -> &str {
+        &self.name
+    }
+
+    fn reap_expired(&self, now: Instant) -> Vec<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let expired: Vec<String> = entries
+            .iter()
+            .filter(|(_, entry)| now.saturating_duration_since(entry.last_access) >= self.ttl)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            entries.remove(key);
+        }
+        expired
+    }
+
+    fn live_entries(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+/// Sweeps the registered registries on an interval
+pub struct Reaper {
+    registries: Vec<Arc<dyn Expirable>>,
+    interval: Duration,
+}
+
+impl Reaper {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            registries: Vec::new(),
+            interval,
+        }
+    }
+
+    pub fn register(mut self, registry: Arc<dyn Expirable>) -> Self {
+        self.registries.push(registry);
+        self
+    }
+
+    /// Reap every registry as of `now`, returning the number of entries removed
+    pub fn sweep(&self, now: Instant) -> usize {
+        let mut reclaimed = 0;
+        for registry in &self.registries {
+            let name = registry.registry_name();
+            let expired = registry.reap_expired(now);
+            if !expired.is_empty() {
+                info!(
+                    "Reclaimed {} idle {}: {}",
+                    expired.len(),
+                    name,
+                    expired.join(", ")
+                );
+                STATE_REAPED_TOTAL
+                    .with_label_values(&[name])
+                    .inc_by(expired.len() as u64);
+            }
+            STATE_LIVE_ENTRIES
+                .with_label_values(&[name])
+                .set(registry.live_entries() as i64);
+            reclaimed += expired.len();
+        }
+        reclaimed
+    }
+
+    /// Sweep in the background every `interval` until the task is aborted
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.sweep(Instant::now());
+            }
+        })
+    }
+}
@@ -0,0 +1,92 @@
+//! Retries of whole GraphQL queries that failed transiently
+//!
+//! Resolvers mark failures that may go away on their own, such as a table missing
+//! while it is reloaded, with the `TRANSIENT` error code. Interrupted reads are
+//! retried by `execute_query` itself and only marked when its retries are off,
+//! so the two layers do not multiply each other's attempts.
+//! When retries are enabled, a query whose response carries such an error runs
+//! again from the start after a short backoff, a bounded number of times.
+//! Mutations and subscriptions are never retried, since running them again could
+//! repeat their effects.
+
+use crate::datafusion::context::RetryPolicy;
+use crate::metrics::GRAPHQL_REQUEST_RETRIES_TOTAL;
+use async_graphql::{Request, Response, Value};
+use std::future::Future;
+use tracing::warn;
+
+/// Error code of failures that may succeed when the request runs again
+pub const TRANSIENT_CODE: &str = "TRANSIENT";
+
+/// Upper bound for the configured number of request retries
+pub const MAX_REQUEST_RETRIES: u32 = 3;
+
+/// Retry behaviour for GraphQL queries failing transiently
+#[derive(Debug, Clone)]
+pub struct RequestRetry {
+    policy: RetryPolicy,
+}
+
+impl RequestRetry {
+    /// Retries beyond `MAX_REQUEST_RETRIES` are capped
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy: RetryPolicy {
+                max_retries: policy.max_retries.min(MAX_REQUEST_RETRIES),
+                ..policy
+            },
+        }
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.policy.max_retries
+    }
+
+    /// Execute a request, executing it again while its response has a transient
+    /// error. Only queries may pass `read_only`; other operations run once.
+    pub async fn run<F, Fut>(&self, read_only: bool, mut execute: F) -> Response
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Response>,
+    {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            let response = execute().await;
+            if !read_only || attempt > self.policy.max_retries || !is_transient(&response) {
+                return response;
+            }
+
+            warn!(
+                "Transient GraphQL failure (attempt {}), retrying in {:?}",
+                attempt, backoff
+            );
+            GRAPHQL_REQUEST_RETRIES_TOTAL.inc();
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+            attempt += 1;
+        }
+    }
+}
+
+/// Whether any error of a response has the `TRANSIENT` code
+pub fn is_transient(response: &Response) -> bool {
+    let transient = Value::from(TRANSIENT_CODE);
+    response.errors.iter().any(|error| {
+        error
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code"))
+            == Some(&transient)
+    })
+}
+
+/// Copy of a request to execute it again, without its data and uploads
+pub fn replay(request: &Request) -> Request {
+    let mut copy = Request::new(request.query.clone()).variables(request.variables.clone());
+    copy.operation_name = request.operation_name.clone();
+    copy.extensions = request.extensions.clone();
+    copy.introspection_mode = request.introspection_mode;
+    copy
+}
@@ -1,30 +1,40 @@
 //! GraphQL DataFusion server
 
-use actix_web::{App, HttpResponse, HttpServer, middleware::Logger, web};
-use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+use actix_web::middleware::{Logger, from_fn};
+use actix_web::{App, HttpServer, web};
 use graphql_datafusion::Config;
 use graphql_datafusion::agents::client::AgentClient;
+use graphql_datafusion::agents::health::HealthPolicy;
 use graphql_datafusion::agents::orchestrator::AgentOrchestrator;
-use graphql_datafusion::datafusion::context::DataFusionContext;
-use graphql_datafusion::graphql::schema::{AppSchema, build_schema};
-use std::collections::HashMap;
+use graphql_datafusion::auth::AuthGuard;
+use graphql_datafusion::datafusion::analysis_filters::AnalysisFilters;
+use graphql_datafusion::datafusion::compare::{CompareOptions, compare_results};
+use graphql_datafusion::datafusion::context::{DataFusionContext, RetryPolicy};
+#[cfg(feature = "demo")]
+use graphql_datafusion::datafusion::demo;
+use graphql_datafusion::datafusion::upload::UploadedTables;
+use graphql_datafusion::events::EventBus;
+use graphql_datafusion::graphql::schema::build_schema;
+use graphql_datafusion::http::{configure, configure_ws, custom_headers, response_time};
+use graphql_datafusion::metrics::{self, Metrics};
+use graphql_datafusion::notifier::Notifier;
+use graphql_datafusion::query_queue::{QueryQueue, QueryQueueConfig};
+use graphql_datafusion::quota::QuotaManager;
+use graphql_datafusion::reaper::Reaper;
+use graphql_datafusion::request_retry::RequestRetry;
+use graphql_datafusion::singleflight::GraphQLFlight;
+use graphql_datafusion::state::StateDir;
+#[cfg(feature = "tls")]
+use graphql_datafusion::tls;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{error, info};
-
-async fn graphql_handler(schema: web::Data<AppSchema>, req: GraphQLRequest) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
-}
-
-async fn playground() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type("text/html; charset=utf-8")
-        .body(async_graphql::http::playground_source(
-            async_graphql::http::GraphQLPlaygroundConfig::new("/graphql"),
-        ))
-}
+use tracing::{error, info, warn};
 
 pub async fn start_server(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    config.validate()?;
+    metrics::install(Metrics::new(&config));
+
     // Initialize logging
     unsafe {
         std::env::set_var("RUST_LOG", &config.log_level);
@@ -36,43 +46,381 @@ pub async fn start_server(config: Config) -> Result<(), Box<dyn std::error::Erro
         config.http_port
     );
 
-    // Initialize DataFusion context
-    let df_ctx = Arc::new(
-        DataFusionContext::new(&config.data_path)
+    // State files of older versions are migrated before anything reads them
+    let state_dir = (!config.state_dir.is_empty()).then(|| StateDir::new(&config.state_dir));
+    if let Some(state_dir) = &state_dir {
+        state_dir
+            .migrate()
+            .map_err(|e| format!("Incompatible server state: {}", e))?;
+    }
+
+    // Events such as table reloads are sent to the configured webhooks
+    let events = EventBus::default();
+    if !config.webhooks.is_empty() {
+        let mut notifier = Notifier::new(config.webhooks.clone()).with_retry_policy(RetryPolicy {
+            max_retries: config.webhook_retry_attempts,
+            initial_backoff: Duration::from_millis(config.webhook_retry_backoff_ms),
+            max_backoff: Duration::from_secs(30),
+        });
+        if !config.webhook_dead_letter_path.is_empty() {
+            notifier = notifier.with_dead_letter_log(&config.webhook_dead_letter_path);
+        }
+        notifier.spawn(&events);
+    }
+
+    // Initialize DataFusion context, over generated data in demo mode
+    let df_ctx = if config.demo_mode {
+        demo_context()?
+    } else {
+        DataFusionContext::with_tables(&config.data_path, &config.tables)
+            .await
+            .map_err(|e| format!("Failed to initialize DataFusion: {}", e))?
+    };
+    let mut df_ctx = df_ctx
+        .with_retry_policy(RetryPolicy {
+            max_retries: config.query_retry_attempts,
+            initial_backoff: Duration::from_millis(config.query_retry_backoff_ms),
+            ..RetryPolicy::default()
+        })
+        .with_query_timeout(Duration::from_secs(config.query_timeout))
+        .with_events(events)
+        .with_slow_query_log(
+            Duration::from_millis(config.slow_query_threshold_ms),
+            config.slow_query_log_size,
+        )
+        .with_kept_revisions(config.kept_table_revisions);
+    if config.query_memory_limit_mb > 0 {
+        df_ctx =
+            df_ctx.with_query_memory_limit(config.query_memory_limit_mb as usize * 1024 * 1024);
+    }
+    let df_ctx = Arc::new(df_ctx);
+    if config.cache_dimension_tables {
+        df_ctx
+            .cache_dimensions()
             .await
-            .map_err(|e| format!("Failed to initialize DataFusion: {}", e))?,
+            .map_err(|e| format!("Failed to cache dimension tables: {}", e))?;
+    }
+    for mismatch in df_ctx.check_model_schemas().await {
+        warn!(
+            "Schema mismatch, the {} resolvers are unavailable: {}",
+            mismatch.model, mismatch
+        );
+    }
+    if let Err(e) = df_ctx.refresh_rollups().await {
+        warn!(
+            "Failed to build the daily revenue rollup, trends aggregate orders directly: {}",
+            e
+        );
+    }
+    for (name, sql) in &config.views {
+        df_ctx
+            .register_view(name, sql)
+            .await
+            .map_err(|e| format!("Failed to register view {}: {}", name, e))?;
+    }
+
+    // Initialize agent orchestrator; no agent clients are built with AI disabled
+    let orchestrator = if config.ai_enabled() {
+        let agent = |model: &String| {
+            AgentClient::new(config.ollama_url.clone(), model.clone())
+                .with_max_prompt_chars(config.ollama_max_prompt_chars)
+        };
+        let orchestrator = config.agents.iter().fold(
+            AgentOrchestrator::empty()
+                .with_agent("default".to_string(), agent(&config.ollama_model)),
+            |orchestrator, (name, model)| orchestrator.with_agent(name.clone(), agent(model)),
+        );
+        orchestrator
+            .with_priority(config.agent_priority.clone())
+            .with_health_policy(HealthPolicy {
+                failure_threshold: config.agent_failure_threshold,
+                open_duration: Duration::from_secs(config.agent_open_secs),
+                max_p95_latency: (config.agent_max_p95_ms > 0)
+                    .then(|| Duration::from_millis(config.agent_max_p95_ms)),
+                ..HealthPolicy::default()
+            })
+    } else {
+        info!("AI features are disabled, natural language queries are unavailable");
+        AgentOrchestrator::empty()
+    };
+    let orchestrator = Arc::new(
+        orchestrator
+            .with_context(df_ctx.clone())
+            .with_max_correction_attempts(config.nlq_max_correction_attempts),
     );
 
-    // Initialize agent system
-    let mut clients = HashMap::new();
-    let client = Arc::new(AgentClient::new(
-        config.ollama_url.clone(),
-        config.ollama_model.clone(),
+    // Build GraphQL schema
+    let shared_config = Arc::new(config.clone());
+    let schema = web::Data::new(build_schema(
+        df_ctx.clone(),
+        orchestrator.clone(),
+        shared_config.clone(),
     ));
-    clients.insert("default".to_string(), client);
 
-    // Initialize agent orchestrator
-    let orchestrator = Arc::new(AgentOrchestrator::new());
+    // Shared state for the health and readiness probes
+    let df_ctx = web::Data::from(df_ctx);
+    let orchestrator = web::Data::from(orchestrator);
+    let shared_config = web::Data::from(shared_config);
 
-    // Build GraphQL schema
-    let schema = web::Data::new(build_schema(df_ctx, orchestrator));
+    // Queue GraphQL requests beyond the concurrency limit
+    let queue = web::Data::new(QueryQueue::new(QueryQueueConfig {
+        max_concurrent: config.max_concurrent_queries,
+        max_concurrent_heavy: config.max_concurrent_heavy_queries,
+        capacity: config.query_queue_capacity,
+        max_wait: Duration::from_millis(config.query_queue_max_wait_ms),
+        heavy_fields: config.heavy_query_fields.clone(),
+        max_concurrent_per_user: config.max_concurrent_queries_per_user,
+    }));
+
+    // Identical concurrent queries share one execution
+    let flight = (config.singleflight_max_wait_ms > 0).then(|| {
+        web::Data::new(GraphQLFlight::new(Duration::from_millis(
+            config.singleflight_max_wait_ms,
+        )))
+    });
+
+    // Queries failing transiently run again when retries are enabled
+    let retry = (config.graphql_retry_attempts > 0).then(|| {
+        web::Data::new(RequestRetry::new(RetryPolicy {
+            max_retries: config.graphql_retry_attempts,
+            initial_backoff: Duration::from_millis(config.graphql_retry_backoff_ms),
+            ..RetryPolicy::default()
+        }))
+    });
+
+    // Idle state of the in-memory registries is swept in the background
+    if config.reaper_interval_secs > 0 {
+        let mut reaper = Reaper::new(Duration::from_secs(config.reaper_interval_secs));
+        if let Some(uploads) = schema.data::<Arc<UploadedTables>>() {
+            reaper = reaper.register(uploads.clone());
+        }
+        if let Some(filters) = schema.data::<Arc<AnalysisFilters>>() {
+            reaper = reaper.register(filters.clone());
+        }
+        reaper.spawn();
+    }
+
+    // Bearer tokens are only verified when a secret is configured
+    let auth = (!config.jwt_secret.is_empty()).then(|| {
+        web::Data::new(AuthGuard::new(&config.jwt_secret).with_leeway(config.jwt_leeway_secs))
+    });
+
+    // GraphQL over WebSocket shares the HTTP server when both use one port
+    let single_port = config.ws_port == config.http_port;
+    let ws_schema = schema.clone();
+
+    // Row limits and daily export quotas per role, the ones the schema applies to
+    // WebSocket requests as well
+    let quotas = web::Data::from(
+        schema
+            .data::<Arc<QuotaManager>>()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(QuotaManager::from_config(&config))),
+    );
+    let ws_queue = queue.clone();
+    let ws_quotas = quotas.clone();
+    let ws_config = shared_config.clone();
+    let ws_auth = auth.clone();
 
     // Start server
-    HttpServer::new(move || {
-        App::new()
+    let headers = config.custom_headers.clone();
+    let http_server = HttpServer::new(move || {
+        let mut app = App::new()
             .wrap(Logger::default())
+            .wrap(custom_headers(&headers))
+            .wrap(from_fn(response_time))
             .app_data(schema.clone())
-            .service(web::resource("/graphql").route(web::post().to(graphql_handler)))
-            .service(web::resource("/playground").route(web::get().to(playground)))
-    })
-    .bind(format!("0.0.0.0:{}", config.http_port))?
-    .run()
-    .await
-    .map_err(|e| format!("Failed to start server: {}", e).into())
+            .app_data(queue.clone())
+            .app_data(quotas.clone())
+            .app_data(df_ctx.clone())
+            .app_data(orchestrator.clone())
+            .app_data(shared_config.clone());
+        if let Some(auth) = &auth {
+            app = app.app_data(auth.clone());
+        }
+        if let Some(flight) = &flight {
+            app = app.app_data(flight.clone());
+        }
+        if let Some(retry) = &retry {
+            app = app.app_data(retry.clone());
+        }
+        if single_port {
+            app = app.configure(configure_ws);
+        }
+        app.configure(configure)
+    });
+    let address = format!("0.0.0.0:{}", config.http_port);
+    // TLS needs the tls feature, which `Config::validate` checked
+    #[cfg(feature = "tls")]
+    let http_server = if config.tls_cert_path.is_empty() {
+        http_server.bind(&address)?
+    } else {
+        let tls_config =
+            tls::server_config(&config).map_err(|e| format!("Failed to set up TLS: {}", e))?;
+        if !config.tls_client_ca_path.is_empty() {
+            info!("Client certificates are required and verified");
+        }
+        let roles = tls::ClientCertRoles::new(&config);
+        http_server
+            .on_connect(move |connection, data| roles.on_connect(connection, data))
+            .bind_rustls_0_23(&address, tls_config)?
+    };
+    #[cfg(not(feature = "tls"))]
+    let http_server = http_server.bind(&address)?;
+    let http_server = http_server.run();
+
+    if single_port {
+        return http_server
+            .await
+            .map_err(|e| format!("Failed to start server: {}", e).into());
+    }
+
+    info!("Serving GraphQL over WebSocket on port {}", config.ws_port);
+    let headers = config.custom_headers.clone();
+    // Operations on the WebSocket port are admitted and limited like those on
+    // the HTTP port
+    let ws_server = HttpServer::new(move || {
+        let mut app = App::new()
+            .wrap(Logger::default())
+            .wrap(custom_headers(&headers))
+            .wrap(from_fn(response_time))
+            .app_data(ws_schema.clone())
+            .app_data(ws_queue.clone())
+            .app_data(ws_quotas.clone())
+            .app_data(ws_config.clone());
+        if let Some(auth) = &ws_auth {
+            app = app.app_data(auth.clone());
+        }
+        app.configure(configure_ws)
+    });
+    let ws_address = format!("0.0.0.0:{}", config.ws_port);
+    // The WebSocket port serves the same schema, so it needs the same TLS and
+    // client certificates as the HTTP port
+    #[cfg(feature = "tls")]
+    let ws_server = if config.tls_cert_path.is_empty() {
+        ws_server.bind(&ws_address)?
+    } else {
+        let tls_config =
+            tls::server_config(&config).map_err(|e| format!("Failed to set up TLS: {}", e))?;
+        let roles = tls::ClientCertRoles::new(&config);
+        ws_server
+            .on_connect(move |connection, data| roles.on_connect(connection, data))
+            .bind_rustls_0_23(&ws_address, tls_config)?
+    };
+    #[cfg(not(feature = "tls"))]
+    let ws_server = ws_server.bind(&ws_address)?;
+    let ws_server = ws_server.run();
+
+    tokio::try_join!(http_server, ws_server)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to start server: {}", e).into())
+}
+
+/// Run `export-state <archive>` or `import-state <archive>` against the state directory
+fn run_state_command(
+    config: &Config,
+    command: &str,
+    archive: Option<&String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if config.state_dir.is_empty() {
+        return Err("STATE_DIR must be set to export or import state".into());
+    }
+    let archive = archive.ok_or_else(|| format!("Usage: {} <archive.tar.gz>", command))?;
+    let state_dir = StateDir::new(&config.state_dir);
+    match command {
+        "export-state" => {
+            let count = state_dir.export(Path::new(archive))?;
+            println!("Exported {} state files to {}", count, archive);
+        }
+        _ => {
+            let count = state_dir.import(Path::new(archive))?;
+            println!("Imported {} state files from {}", count, archive);
+        }
+    }
+    Ok(())
+}
+
+/// Run `compare-queries <sql-a> <sql-b>` against the data path, printing the
+/// comparison as JSON; fails when the results differ
+async fn run_compare_command(
+    config: &Config,
+    args: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    const USAGE: &str = "Usage: compare-queries <sql-a> <sql-b> [--key <column>]... \
+                         [--tolerance <x>] [--relative-tolerance <x>] [--limit <n>]";
+    let mut queries = Vec::new();
+    let mut options = CompareOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" => options.key_columns.push(args.next().ok_or(USAGE)?.clone()),
+            "--tolerance" => options.absolute_tolerance = args.next().ok_or(USAGE)?.parse()?,
+            "--relative-tolerance" => {
+                options.relative_tolerance = args.next().ok_or(USAGE)?.parse()?
+            }
+            "--limit" => options.limit = args.next().ok_or(USAGE)?.parse()?,
+            _ => queries.push(arg),
+        }
+    }
+    let [sql_a, sql_b] = queries[..] else {
+        return Err(USAGE.into());
+    };
+
+    let df_ctx = if config.demo_mode {
+        demo_context()?
+    } else {
+        DataFusionContext::with_tables(&config.data_path, &config.tables)
+            .await
+            .map_err(|e| format!("Failed to initialize DataFusion: {}", e))?
+    };
+    let a = df_ctx
+        .execute_query(sql_a)
+        .await
+        .map_err(|e| format!("Query A failed: {}", e))?;
+    let b = df_ctx
+        .execute_query(sql_b)
+        .await
+        .map_err(|e| format!("Query B failed: {}", e))?;
+    let comparison = compare_results(&a, &b, &options)?;
+    println!("{}", serde_json::to_string_pretty(&comparison)?);
+    if !comparison.identical {
+        return Err(format!(
+            "Results differ: {} rows only in A, {} only in B, {} values differ",
+            comparison.only_in_a_count, comparison.only_in_b_count, comparison.difference_count
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Context over the generated demo tables
+#[cfg(feature = "demo")]
+fn demo_context() -> Result<DataFusionContext, Box<dyn std::error::Error>> {
+    let df_ctx = DataFusionContext::in_memory();
+    demo::register_demo_tables(&df_ctx)
+        .map_err(|e| format!("Failed to generate demo data: {}", e))?;
+    info!("Demo mode: serving generated sample data, not the files under the data path");
+    Ok(df_ctx)
+}
+
+#[cfg(not(feature = "demo"))]
+fn demo_context() -> Result<DataFusionContext, Box<dyn std::error::Error>> {
+    Err("Demo mode needs a build with the demo feature".into())
 }
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::from_env();
+    let mut config = Config::from_env();
+    let mut args: Vec<String> = std::env::args().collect();
+    if let Some(position) = args.iter().position(|arg| arg == "--demo") {
+        args.remove(position);
+        config.demo_mode = true;
+    }
+    if let Some(command @ ("export-state" | "import-state")) = args.get(1).map(String::as_str) {
+        return run_state_command(&config, command, args.get(2));
+    }
+    if args.get(1).map(String::as_str) == Some("compare-queries") {
+        return run_compare_command(&config, &args[2..]).await;
+    }
     start_server(config).await
 }
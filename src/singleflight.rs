@@ -0,0 +1,135 @@
+//! Coalescing of identical concurrent GraphQL queries
+//!
+//! While a query executes, identical queries wait for its response instead of
+//! executing again, so a dashboard refreshed in many tabs at once runs its SQL
+//! once. Queries are identical when the document, operation name and variables
+//! match. Only anonymous queries are coalesced: the response of an authenticated
+//! caller may hold their own data, such as their quota or query history, and
+//! executing it does their quota bookkeeping. A waiting request gives up after
+//! `max_wait` and executes on its own.
+
+use crate::metrics::COALESCED_REQUESTS_TOTAL;
+use crate::query_queue::QueueError;
+use async_graphql::parser::parse_query;
+use async_graphql::parser::types::OperationType;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// In-flight GraphQL responses shared by the HTTP handler
+pub type GraphQLFlight = SingleFlight<Result<Arc<async_graphql::Response>, QueueError>>;
+
+#[derive(Debug)]
+pub struct SingleFlight<T> {
+    in_flight: Mutex<HashMap<String, watch::Receiver<Option<T>>>>,
+    max_wait: Duration,
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new(max_wait: Duration) -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+            max_wait,
+        }
+    }
+
+    /// Run `execute`, or wait for the result of the call with the same key that is
+    /// already running
+    pub async fn run<F, Fut>(&self, key: String, execute: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let leader = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    in_flight.insert(key.clone(), receiver.clone());
+                    Ok((sender, receiver))
+                }
+            }
+        };
+
+        match leader {
+            Ok((sender, receiver)) => {
+                // Removes the entry even when the caller goes away mid-execution,
+                // which wakes the waiting requests to execute on their own
+                let _entry = InFlightEntry {
+                    flight: self,
+                    key,
+                    receiver,
+                };
+                let value = execute().await;
+                sender.send_replace(Some(value.clone()));
+                value
+            }
+            Err(mut receiver) => {
+                let shared =
+                    tokio::time::timeout(self.max_wait, receiver.wait_for(|value| value.is_some()))
+                        .await;
+                match shared {
+                    Ok(Ok(value)) => {
+                        COALESCED_REQUESTS_TOTAL.inc();
+                        value.clone().unwrap()
+                    }
+                    _ => execute().await,
+                }
+            }
+        }
+    }
+
+    /// Number of distinct calls currently executing
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+}
+
+struct InFlightEntry<'a, T> {
+    flight: &'a SingleFlight<T>,
+    key: String,
+    receiver: watch::Receiver<Option<T>>,
+}
+
+impl<T> Drop for InFlightEntry<'_, T> {
+    fn drop(&mut self) {
+        let mut in_flight = self.flight.in_flight.lock().unwrap();
+        if in_flight
+            .get(&self.key)
+            .is_some_and(|receiver| receiver.same_channel(&self.receiver))
+        {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+/// Key identifying an anonymous request among concurrent ones, `None` for
+/// mutations, subscriptions and documents that do not parse
+pub fn request_key(request: &async_graphql::Request) -> Option<String> {
+    if operation_type(request)? != OperationType::Query {
+        return None;
+    }
+    serde_json::to_string(&(&request.operation_name, &request.query, &request.variables)).ok()
+}
+
+/// Type of the operation a request runs, `None` when the document does not parse
+/// or has no such operation
+pub fn operation_type(request: &async_graphql::Request) -> Option<OperationType> {
+    let document = parse_query(&request.query).ok()?;
+    let operation = match &request.operation_name {
+        Some(name) => {
+            document
+                .operations
+                .iter()
+                .find(|(operation_name, _)| {
+                    operation_name.is_some_and(|n| n.as_str() == name.as_str())
+                })?
+                .1
+        }
+        None => document.operations.iter().next()?.1,
+    };
+    Some(operation.node.ty)
+}
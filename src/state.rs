@@ -0,0 +1,233 @@
+//! Server-side state files
+//!
+//! State the server persists between restarts lives in JSON files with a
+//! versioned envelope, `{"version": N, "data": ...}`, written atomically through a
+//! temporary file. Files from older versions, including the unversioned files
+//! written before the envelope existed, are migrated when read; files from a newer
+//! server are rejected. A `StateDir` holds all state files in one directory and
+//! exports or imports them as a single tar.gz archive.
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// A kind of state file and the version this server writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateFile {
+    /// File name inside the state directory and the archive
+    pub name: &'static str,
+    pub version: u32,
+}
+
+/// Daily export counters of the quota manager
+pub const QUOTA_STATE: StateFile = StateFile {
+    name: "quota.json",
+    version: 1,
+};
+
+/// Every state file, all of them covered by export and import
+pub const STATE_FILES: &[StateFile] = &[QUOTA_STATE];
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("Failed to access state {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Invalid state file {name}: {message}")]
+    Invalid { name: String, message: String },
+    #[error(
+        "State file {name} has version {found}, this server supports versions up to {supported}"
+    )]
+    UnsupportedVersion {
+        name: String,
+        found: u32,
+        supported: u32,
+    },
+    #[error("Unknown state file {0} in archive")]
+    UnknownFile(String),
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> StateError + '_ {
+    move |source| StateError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    data: serde_json::Value,
+}
+
+/// Data of a state file at the current version
+fn parse(file: &StateFile, contents: &str) -> Result<serde_json::Value, StateError> {
+    let invalid = |message: String| StateError::Invalid {
+        name: file.name.to_string(),
+        message,
+    };
+    let document: serde_json::Value =
+        serde_json::from_str(contents).map_err(|e| invalid(e.to_string()))?;
+    let (version, data) = match document.get("version") {
+        Some(_) => {
+            let envelope: Envelope =
+                serde_json::from_value(document).map_err(|e| invalid(e.to_string()))?;
+            (envelope.version, envelope.data)
+        }
+        // Written before state files were versioned
+        None => (0, document),
+    };
+    if version > file.version {
+        return Err(StateError::UnsupportedVersion {
+            name: file.name.to_string(),
+            found: version,
+            supported: file.version,
+        });
+    }
+    migrate(file, version, data)
+}
+
+/// Bring data of an older version up to the current one
+fn migrate(
+    file: &StateFile,
+    version: u32,
+    data: serde_json::Value,
+) -> Result<serde_json::Value, StateError> {
+    match (file.name, version) {
+        // Version 1 only added the envelope
+        ("quota.json", 0) => Ok(data),
+        (_, version) if version == file.version => Ok(data),
+        (name, version) => Err(StateError::UnsupportedVersion {
+            name: name.to_string(),
+            found: version,
+            supported: file.version,
+        }),
+    }
+}
+
+/// Read a state file, `None` when it does not exist
+pub fn read_state<T: DeserializeOwned>(
+    path: &Path,
+    file: &StateFile,
+) -> Result<Option<T>, StateError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(io_error(path)(e)),
+    };
+    let data = parse(file, &contents)?;
+    serde_json::from_value(data)
+        .map(Some)
+        .map_err(|e| StateError::Invalid {
+            name: file.name.to_string(),
+            message: e.to_string(),
+        })
+}
+
+/// Write a state file at the current version, replacing it atomically
+pub fn write_state<T: Serialize>(
+    path: &Path,
+    file: &StateFile,
+    data: &T,
+) -> Result<(), StateError> {
+    let envelope = Envelope {
+        version: file.version,
+        data: serde_json::to_value(data).map_err(|e| StateError::Invalid {
+            name: file.name.to_string(),
+            message: e.to_string(),
+        })?,
+    };
+    let contents = serde_json::to_string_pretty(&envelope).map_err(|e| StateError::Invalid {
+        name: file.name.to_string(),
+        message: e.to_string(),
+    })?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io_error(parent))?;
+    }
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, contents).map_err(io_error(&temporary))?;
+    std::fs::rename(&temporary, path).map_err(io_error(path))
+}
+
+/// Directory holding all state files
+#[derive(Debug, Clone)]
+pub struct StateDir {
+    root: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn path(&self, file: &StateFile) -> PathBuf {
+        self.root.join(file.name)
+    }
+
+    /// Check that this server can read every state file, rewriting files of older
+    /// versions at the current version
+    pub fn migrate(&self) -> Result<(), StateError> {
+        for file in STATE_FILES {
+            let path = self.path(file);
+            if let Some(data) = read_state::<serde_json::Value>(&path, file)? {
+                write_state(&path, file, &data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the existing state files to a tar.gz archive, returning how many
+    pub fn export(&self, archive: &Path) -> Result<usize, StateError> {
+        let output = File::create(archive).map_err(io_error(archive))?;
+        let mut builder = tar::Builder::new(GzEncoder::new(output, Compression::default()));
+        let mut exported = 0;
+        for file in STATE_FILES {
+            let path = self.path(file);
+            if path.exists() {
+                builder
+                    .append_path_with_name(&path, file.name)
+                    .map_err(io_error(&path))?;
+                exported += 1;
+            }
+        }
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .map_err(io_error(archive))?;
+        Ok(exported)
+    }
+
+    /// Restore the state files of an archive, returning how many. Nothing is
+    /// written unless every file in the archive is known and readable.
+    pub fn import(&self, archive: &Path) -> Result<usize, StateError> {
+        let input = File::open(archive).map_err(io_error(archive))?;
+        let mut tarball = tar::Archive::new(GzDecoder::new(input));
+        let mut restored = Vec::new();
+        for entry in tarball.entries().map_err(io_error(archive))? {
+            let mut entry = entry.map_err(io_error(archive))?;
+            let name = entry
+                .path()
+                .map_err(io_error(archive))?
+                .to_string_lossy()
+                .to_string();
+            let file = STATE_FILES
+                .iter()
+                .find(|file| file.name == name)
+                .ok_or(StateError::UnknownFile(name))?;
+            let mut contents = String::new();
+            entry
+                .read_to_string(&mut contents)
+                .map_err(io_error(archive))?;
+            restored.push((file, parse(file, &contents)?));
+        }
+        for (file, data) in &restored {
+            write_state(&self.path(file), file, data)?;
+        }
+        Ok(restored.len())
+    }
+}
@@ -0,0 +1,111 @@
+//! Live WebSocket subscriptions
+//!
+//! Connections to `/graphql/ws` register here while they are connected, so
+//! operators can see what is subscribed and by whom. Each connection registers
+//! a callback that ends it; closing a subscription removes it and runs the
+//! callback, which is how a misbehaving subscription is terminated.
+
+use crate::models::data::SubscriptionKind;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A connected subscription
+#[derive(Debug, Clone)]
+pub struct ActiveSubscription {
+    pub id: String,
+    pub kind: SubscriptionKind,
+    /// Document of the operation the client last started, empty until its first
+    pub query: String,
+    /// `sub` claim of the caller, `None` for anonymous connections
+    pub user: Option<String>,
+    pub started_at: DateTime<Utc>,
+}
+
+struct Registration {
+    subscription: ActiveSubscription,
+    close: Box<dyn Fn() + Send + Sync>,
+}
+
+/// Subscriptions by id
+#[derive(Default)]
+pub struct SubscriptionRegistry {
+    subscriptions: Mutex<HashMap<String, Registration>>,
+}
+
+impl SubscriptionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a connected subscription, closed by calling `close`. Returns the
+    /// id of the subscription.
+    pub fn register(
+        &self,
+        kind: SubscriptionKind,
+        user: Option<String>,
+        close: impl Fn() + Send + Sync + 'static,
+    ) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let subscription = ActiveSubscription {
+            id: id.clone(),
+            kind,
+            query: String::new(),
+            user,
+            started_at: Utc::now(),
+        };
+        self.subscriptions.lock().unwrap().insert(
+            id.clone(),
+            Registration {
+                subscription,
+                close: Box::new(close),
+            },
+        );
+        id
+    }
+
+    /// Record the operation a subscription started
+    pub fn set_query(&self, id: &str, query: &str) {
+        if let Some(registration) = self.subscriptions.lock().unwrap().get_mut(id) {
+            registration.subscription.query = query.to_string();
+        }
+    }
+
+    /// Record who a subscription runs as, once its client authenticates
+    pub fn set_user(&self, id: &str, user: Option<String>) {
+        if let Some(registration) = self.subscriptions.lock().unwrap().get_mut(id) {
+            registration.subscription.user = user;
+        }
+    }
+
+    /// Forget a subscription whose connection ended
+    pub fn deregister(&self, id: &str) {
+        self.subscriptions.lock().unwrap().remove(id);
+    }
+
+    /// Connected subscriptions, oldest first
+    pub fn list(&self) -> Vec<ActiveSubscription> {
+        let mut subscriptions: Vec<ActiveSubscription> = self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .values()
+            .map(|registration| registration.subscription.clone())
+            .collect();
+        subscriptions.sort_by_key(|subscription| subscription.started_at);
+        subscriptions
+    }
+
+    /// Terminate a subscription. Returns whether one with that id was connected.
+    pub fn close(&self, id: &str) -> bool {
+        // The callback may deregister, so it runs without the lock
+        let registration = self.subscriptions.lock().unwrap().remove(id);
+        match registration {
+            Some(registration) => {
+                (registration.close)();
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! TLS and client certificate verification (mTLS)
+//!
+//! With `tls_cert_path` and `tls_key_path` set the server speaks HTTPS through
+//! rustls. Setting `tls_client_ca_path` also makes every client present a
+//! certificate signed by one of those CAs; connections without a valid one fail
+//! the handshake. The common name of a verified certificate identifies the caller,
+//! with the role `client_cert_roles` gives it, on requests without a bearer token.
+
+use crate::auth::{Claims, ClientIdentity};
+use crate::config::Config;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::RootCertStore;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+use thiserror::Error;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to read {path}: {source}")]
+    Io { path: String, source: io::Error },
+    #[error("No {what} found in {path}")]
+    Missing { what: &'static str, path: String },
+    #[error("Invalid TLS configuration: {0}")]
+    Config(String),
+}
+
+fn read_pem(path: &str) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|source| TlsError::Io {
+            path: path.to_string(),
+            source,
+        })
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = rustls_pemfile::certs(&mut read_pem(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| TlsError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+    if certs.is_empty() {
+        return Err(TlsError::Missing {
+            what: "certificate",
+            path: path.to_string(),
+        });
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, TlsError> {
+    rustls_pemfile::private_key(&mut read_pem(path)?)
+        .map_err(|source| TlsError::Io {
+            path: path.to_string(),
+            source,
+        })?
+        .ok_or_else(|| TlsError::Missing {
+            what: "private key",
+            path: path.to_string(),
+        })
+}
+
+/// rustls configuration of the server, requiring client certificates when a
+/// client CA is configured
+pub fn server_config(config: &Config) -> Result<rustls::ServerConfig, TlsError> {
+    let certs = load_certs(&config.tls_cert_path)?;
+    let key = load_key(&config.tls_key_path)?;
+
+    // Explicit, as several crypto providers may be compiled in
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| TlsError::Config(e.to_string()))?;
+    let builder = if config.tls_client_ca_path.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(&config.tls_client_ca_path)? {
+            roots
+                .add(cert)
+                .map_err(|e| TlsError::Config(format!("Invalid client CA: {}", e)))?;
+        }
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .map_err(|e| TlsError::Config(e.to_string()))?;
+        builder.with_client_cert_verifier(verifier)
+    };
+    builder
+        .with_single_cert(certs, key)
+        .map_err(|e| TlsError::Config(e.to_string()))
+}
+
+/// Common name of the subject of a DER certificate
+pub fn common_name(cert: &[u8]) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    let name = cert
+        .subject()
+        .iter_common_name()
+        .next()?
+        .as_str()
+        .ok()?
+        .to_string();
+    Some(name)
+}
+
+/// Maps verified client certificates to callers
+#[derive(Debug, Clone)]
+pub struct ClientCertRoles {
+    roles: BTreeMap<String, String>,
+    default_role: String,
+}
+
+impl ClientCertRoles {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            roles: config.client_cert_roles.clone(),
+            default_role: config.client_cert_default_role.clone(),
+        }
+    }
+
+    /// Caller of a verified certificate, named after its common name
+    pub fn identity(&self, cert: &[u8]) -> Option<ClientIdentity> {
+        let name = common_name(cert)?;
+        let role = self.roles.get(&name).unwrap_or(&self.default_role).clone();
+        Some(ClientIdentity(Claims::new(name, role)))
+    }
+
+    /// `HttpServer::on_connect` callback keeping the identity of the client
+    /// certificate in the connection data
+    pub fn on_connect(&self, connection: &dyn Any, data: &mut Extensions) {
+        let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+            return;
+        };
+        let (_, session) = stream.get_ref();
+        // Only present once the verifier accepted the certificate
+        let identity = session
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| self.identity(cert));
+        if let Some(identity) = identity {
+            data.insert(identity);
+        }
+    }
+}
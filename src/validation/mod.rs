@@ -1,3 +1,4 @@
+use crate::models::data::categorical_codes;
 use async_graphql::{Context, Error, InputObject, Result};
 // use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -76,6 +77,18 @@ pub fn validate_filter_input(_ctx: &Context<'_>, input: FilterInput) -> Result<F
         )));
     }
 
+    // Categorical columns only hold their known codes
+    if let Some(codes) = categorical_codes(&input.field) {
+        let exact = matches!(input.operator.as_str(), "=" | "!=");
+        if exact && !codes.contains(&input.value.as_str()) {
+            return Err(Error::new(format!(
+                "Invalid value for {}. Must be one of: {}",
+                input.field,
+                codes.join(", ")
+            )));
+        }
+    }
+
     Ok(input)
 }
 
@@ -0,0 +1,59 @@
+//! Fixtures shared by the unit and integration tests
+
+// Each test crate uses only some of the fixtures
+#![allow(dead_code)]
+
+use datafusion::arrow::record_batch::RecordBatch;
+use graphql_datafusion::agents::orchestrator::AgentOrchestrator;
+use graphql_datafusion::config::Config;
+use graphql_datafusion::datafusion::context::DataFusionContext;
+use graphql_datafusion::graphql::schema::{AppSchema, build_schema};
+use serde_json::json;
+use std::sync::Arc;
+use wiremock::ResponseTemplate;
+
+/// Reply of the Ollama generate API with `text` as the model's response
+pub fn ollama_reply(text: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "model": "llama2",
+        "created_at": "2024-01-01T00:00:00Z",
+        "response": text,
+        "done": true
+    }))
+}
+
+/// Two customers with a key and a name
+pub fn customer_batch() -> RecordBatch {
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("c_custkey", DataType::Int64, false),
+        Field::new("c_name", DataType::Utf8, false),
+    ]));
+    RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2])),
+            Arc::new(StringArray::from(vec!["Customer#1", "Customer#2"])),
+        ],
+    )
+    .unwrap()
+}
+
+/// Context holding only the `customer_batch` customers
+pub fn customer_fixture() -> Arc<DataFusionContext> {
+    let ctx = DataFusionContext::in_memory();
+    ctx.register_batches("customer", vec![customer_batch()])
+        .unwrap();
+    Arc::new(ctx)
+}
+
+/// Schema over a context with the default agent and configuration
+pub fn default_schema(df_ctx: Arc<DataFusionContext>) -> AppSchema {
+    build_schema(
+        df_ctx,
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(Config::default()),
+    )
+}
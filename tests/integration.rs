@@ -1,6 +1,19 @@
+mod common;
+
+use actix_web::{App, test, web};
+use common::{customer_fixture, default_schema, ollama_reply};
+use graphql_datafusion::agents::client::AgentClient;
+use graphql_datafusion::agents::orchestrator::AgentOrchestrator;
+use graphql_datafusion::auth::{AuthGuard, Claims};
+use graphql_datafusion::config::Config;
 use graphql_datafusion::datafusion::context::DataFusionContext;
+use graphql_datafusion::graphql::schema::build_schema;
+use graphql_datafusion::http::{configure, configure_ws, custom_headers, response_time};
 use reqwest::Client;
 use serde_json::json;
+use std::sync::Arc;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 #[tokio::test]
 async fn test_server_health() {
@@ -319,3 +332,1516 @@ async fn test_datafusion_integration() {
 
     println!("DataFusion integration test passed");
 }
+
+#[actix_web::test]
+async fn test_graphql_validate_endpoint() {
+    let schema = default_schema(Arc::new(DataFusionContext::in_memory()));
+    let auth = AuthGuard::new("test-secret");
+    let token = |role: &str| {
+        auth.issue_token(&Claims::new("alice".to_string(), role.to_string()))
+            .unwrap()
+    };
+    let (admin, analyst) = (token("admin"), token("analyst"));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .app_data(web::Data::new(auth))
+            .configure(configure),
+    )
+    .await;
+
+    // Only admins may validate documents
+    let req = test::TestRequest::post()
+        .uri("/graphql/validate")
+        .set_json(json!({ "query": "{ tables }" }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 401);
+    let req = test::TestRequest::post()
+        .uri("/graphql/validate")
+        .insert_header(("Authorization", format!("Bearer {}", analyst)))
+        .set_json(json!({ "query": "{ tables }" }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 403);
+
+    let req = test::TestRequest::post()
+        .uri("/graphql/validate")
+        .insert_header(("Authorization", format!("Bearer {}", admin)))
+        .set_json(json!({ "query": "{ tables unknownField }" }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(body["valid"], json!(false));
+    assert!(
+        body["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("unknownField")
+    );
+}
+
+#[actix_web::test]
+async fn test_custom_headers_on_responses() {
+    let mut headers = std::collections::BTreeMap::new();
+    headers.insert("X-Deployment".to_string(), "blue".to_string());
+    let app = test::init_service(
+        App::new()
+            .wrap(custom_headers(&headers))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/playground").to_request();
+    let res = test::call_service(&app, req).await;
+
+    assert_eq!(res.headers().get("X-Deployment").unwrap(), "blue");
+}
+
+#[actix_web::test]
+async fn test_options_preflight_on_graphql() {
+    let app = test::init_service(
+        App::new()
+            .wrap(custom_headers(&Config::default().custom_headers))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/graphql")
+        .insert_header(("Origin", "https://dashboard.example"))
+        .insert_header(("Access-Control-Request-Method", "POST"))
+        .insert_header((
+            "Access-Control-Request-Headers",
+            "content-type, authorization, x-anything",
+        ))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::NO_CONTENT);
+    let headers = res.headers();
+    assert_eq!(headers.get("Access-Control-Allow-Origin").unwrap(), "*");
+    assert!(
+        headers
+            .get("Access-Control-Allow-Methods")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .contains("POST")
+    );
+    // The headers allowed are those the server reads, not whatever was asked for
+    let allowed = headers
+        .get("Access-Control-Allow-Headers")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(allowed.contains("Content-Type") && allowed.contains("Authorization"));
+    assert!(!allowed.to_lowercase().contains("x-anything"));
+
+    // Actual responses allow the same origin
+    let req = test::TestRequest::get()
+        .uri("/no-such-endpoint")
+        .insert_header(("Origin", "https://dashboard.example"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(
+        res.headers().get("Access-Control-Allow-Origin").unwrap(),
+        "*"
+    );
+
+    // A configured origin is the one allowed
+    let mut config = Config::default();
+    config.custom_headers.insert(
+        "Access-Control-Allow-Origin".to_string(),
+        "https://dashboard.example".to_string(),
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config.clone()))
+            .wrap(custom_headers(&config.custom_headers))
+            .configure(configure),
+    )
+    .await;
+    let req = test::TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri("/graphql/validate")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status(), actix_web::http::StatusCode::NO_CONTENT);
+    assert_eq!(
+        res.headers().get("Access-Control-Allow-Origin").unwrap(),
+        "https://dashboard.example"
+    );
+    let req = test::TestRequest::get()
+        .uri("/no-such-endpoint")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(
+        res.headers().get("Access-Control-Allow-Origin").unwrap(),
+        "https://dashboard.example"
+    );
+}
+
+#[actix_web::test]
+async fn test_natural_language_query_is_audited() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains("customer names"))
+        .respond_with(ollama_reply("SELECT c_name FROM customer"))
+        .mount(&ollama)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains("supplier names"))
+        .respond_with(ollama_reply("SELECT s_name FROM supplier"))
+        .mount(&ollama)
+        .await;
+
+    let orchestrator = Arc::new(
+        AgentOrchestrator::new()
+            .with_agent(
+                "default".to_string(),
+                AgentClient::new(ollama.uri(), "sqlcoder".to_string()),
+            )
+            .with_context(customer_fixture())
+            .with_max_correction_attempts(0),
+    );
+    let schema = build_schema(
+        customer_fixture(),
+        orchestrator.clone(),
+        Arc::new(Config::default()),
+    );
+    let auth = AuthGuard::new("test-secret");
+    let token = auth
+        .issue_token(&Claims::new("alice".to_string(), "analyst".to_string()))
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .app_data(web::Data::new(auth))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({
+            "query": r#"{ naturalLanguageQuery(input: "list customer names") }"#
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+    assert_eq!(
+        body["data"]["naturalLanguageQuery"],
+        json!("SELECT c_name FROM customer")
+    );
+    let extension = &body["extensions"]["naturalLanguageQuery"];
+    assert_eq!(extension["model"], json!("sqlcoder"));
+    assert_eq!(extension["tables"], json!(["customer"]));
+    assert_eq!(extension["rowCount"], json!(2));
+    assert_eq!(extension["validation"]["valid"], json!(true));
+    // The sample and the prompt hash are for admins only
+    assert_eq!(extension["sample"], json!([]));
+    assert_eq!(extension["promptHash"], json!(null));
+
+    let record = orchestrator.audit_log().records().pop().unwrap();
+    assert_eq!(record.user.as_deref(), Some("alice"));
+    assert_eq!(record.question, "list customer names");
+    assert_eq!(record.sql.as_deref(), Some("SELECT c_name FROM customer"));
+    assert_eq!(record.agent, "default");
+    assert_eq!(record.model, "sqlcoder");
+    assert_eq!(record.tables, vec!["customer".to_string()]);
+    assert_eq!(
+        serde_json::to_value(&record.lineage).unwrap(),
+        json!([{ "output": "c_name", "sources": [{ "table": "customer", "column": "c_name" }] }])
+    );
+    assert_eq!(record.row_count, Some(2));
+    assert!(record.success);
+
+    // Failed executions are audited too
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({
+            "query": r#"{ naturalLanguageQuery(input: "list supplier names") }"#
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body["errors"].is_array());
+
+    let record = orchestrator.audit_log().records().pop().unwrap();
+    assert_eq!(record.user.as_deref(), Some("alice"));
+    assert_eq!(record.sql.as_deref(), Some("SELECT s_name FROM supplier"));
+    assert_eq!(record.tables, vec!["supplier".to_string()]);
+    assert_eq!(record.row_count, None);
+    assert!(!record.success);
+    assert!(record.error.is_some());
+}
+
+#[actix_web::test]
+async fn test_paged_customers_and_orders() {
+    use datafusion::arrow::array::{Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Seven orders, five of them open
+    let orders = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("o_orderkey", DataType::Int64, false),
+            Field::new("o_custkey", DataType::Int64, false),
+            Field::new("o_orderstatus", DataType::Utf8, false),
+            Field::new("o_totalprice", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![10, 11, 12, 13, 14, 15, 16])),
+            Arc::new(Int64Array::from(vec![1, 1, 2, 2, 2, 1, 2])),
+            Arc::new(StringArray::from(vec!["O", "F", "O", "O", "F", "O", "O"])),
+            Arc::new(Float64Array::from(vec![
+                10.0, 20.0, 30.0, 40.0, 50.0, 60.0, 70.0,
+            ])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = customer_fixture();
+    df_ctx.register_batches("orders", vec![orders]).unwrap();
+    let schema = default_schema(df_ctx);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .configure(configure),
+    )
+    .await;
+    let graphql = |query: String| {
+        test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({ "query": query }))
+            .to_request()
+    };
+
+    let req = graphql(
+        "{ customersPaged(params: { limit: 1 }) { data { c_custkey c_name } totalCount \
+         hasMore pageInfo { currentPage pageSize totalPages } } }"
+            .to_string(),
+    );
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body["data"]["customersPaged"],
+        json!({
+            "data": [{ "c_custkey": 1, "c_name": "Customer#1" }],
+            "totalCount": 2,
+            "hasMore": true,
+            "pageInfo": { "currentPage": 1, "pageSize": 1, "totalPages": 2 }
+        })
+    );
+
+    // Paging through the open orders visits each of them once
+    let mut keys = Vec::new();
+    let mut offset = 0;
+    loop {
+        let req = graphql(format!(
+            "{{ ordersPaged(params: {{ limit: 2, offset: {} }}, status: OPEN) {{ \
+             data {{ o_orderkey }} totalCount hasMore \
+             pageInfo {{ currentPage pageSize totalPages }} }} }}",
+            offset
+        ));
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let page = &body["data"]["ordersPaged"];
+        assert_eq!(page["totalCount"], json!(5), "{}", body);
+        assert_eq!(
+            page["pageInfo"],
+            json!({ "currentPage": offset / 2 + 1, "pageSize": 2, "totalPages": 3 })
+        );
+        keys.extend(
+            page["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|order| order["o_orderkey"].as_i64().unwrap()),
+        );
+        offset += 2;
+        if !page["hasMore"].as_bool().unwrap() {
+            break;
+        }
+    }
+    assert_eq!(keys, vec![10, 12, 13, 15, 16]);
+
+    // A page past the end is empty rather than an error
+    let req = graphql(
+        "{ ordersPaged(params: { limit: 2, offset: 10 }) { data { o_orderkey } totalCount \
+         hasMore pageInfo { currentPage totalPages } } }"
+            .to_string(),
+    );
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body["data"]["ordersPaged"],
+        json!({
+            "data": [],
+            "totalCount": 7,
+            "hasMore": false,
+            "pageInfo": { "currentPage": 6, "totalPages": 4 }
+        })
+    );
+
+    let req = graphql("{ ordersPaged(params: { limit: 0 }) { totalCount } }".to_string());
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body["errors"].is_array());
+
+    // The limit and offset arguments still page, the params taking precedence
+    let req = graphql(
+        "{ plain: customersPaged(limit: 1, offset: 1) { data { c_custkey } } \
+         both: customersPaged(limit: 1, offset: 1, params: { offset: 0 }) \
+         { data { c_custkey } } }"
+            .to_string(),
+    );
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["plain"]["data"], json!([{ "c_custkey": 2 }]));
+    assert_eq!(body["data"]["both"]["data"], json!([{ "c_custkey": 1 }]));
+
+    // The plain list fields check their pages the same way
+    for query in [
+        "{ customers(limit: -5) { c_custkey } }",
+        "{ orders(params: { offset: -1 }) { o_orderkey } }",
+    ] {
+        let req = graphql(query.to_string());
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(body["errors"].is_array(), "{}", body);
+    }
+}
+
+#[actix_web::test]
+async fn test_graphql_content_types() {
+    let schema = default_schema(customer_fixture());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Content-Type", "text/plain"))
+        .set_payload(r#"{"query":"{ customers(limit: 1) { c_name } }"}"#)
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 415);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("UNSUPPORTED_MEDIA_TYPE"));
+    assert_eq!(
+        body["error"]["accepted"],
+        json!(["application/json", "application/graphql"])
+    );
+
+    // The raw query document
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Content-Type", "application/graphql"))
+        .set_payload("{ customers(limit: 1) { c_name } }")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body["data"]["customers"],
+        json!([{ "c_name": "Customer#1" }])
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .insert_header(("Content-Type", "application/json; charset=utf-8"))
+        .set_payload(r#"{"query":"{ customers(limit: 1) { c_name } }"}"#)
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        body["data"]["customers"],
+        json!([{ "c_name": "Customer#1" }])
+    );
+}
+
+async fn ready_status(config: Config, orchestrator: AgentOrchestrator) -> serde_json::Value {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(customer_fixture()))
+            .app_data(web::Data::new(orchestrator))
+            .app_data(web::Data::new(config))
+            .configure(configure),
+    )
+    .await;
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let res = test::call_service(&app, req).await;
+    let status = res.status().as_u16();
+    let body: serde_json::Value = test::read_body_json(res).await;
+    json!({ "status": status, "body": body })
+}
+
+#[actix_web::test]
+async fn test_ready_skips_ollama_when_ai_disabled() {
+    // Nothing listens on this address; a check would fail
+    let config = Config {
+        enable_ai: false,
+        ollama_url: "http://127.0.0.1:9".to_string(),
+        ..Config::default()
+    };
+
+    let ready = ready_status(config, AgentOrchestrator::empty()).await;
+    assert_eq!(ready["status"], json!(200));
+    assert_eq!(ready["body"]["checks"]["ollama"], json!("disabled"));
+}
+
+#[actix_web::test]
+async fn test_ready_checks_ollama_when_ai_enabled() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ollama_reply("ok"))
+        .mount(&ollama)
+        .await;
+    let orchestrator = AgentOrchestrator::empty().with_agent(
+        "default".to_string(),
+        AgentClient::new(ollama.uri(), "llama2".to_string()),
+    );
+    let config = Config {
+        ollama_url: ollama.uri(),
+        ..Config::default()
+    };
+
+    let ready = ready_status(config, orchestrator).await;
+    assert_eq!(ready["status"], json!(200));
+    assert_eq!(ready["body"]["checks"]["ollama"], json!("ok"));
+
+    let unreachable = AgentOrchestrator::empty().with_agent(
+        "default".to_string(),
+        AgentClient::new("http://127.0.0.1:9".to_string(), "llama2".to_string()),
+    );
+    let ready = ready_status(Config::default(), unreachable).await;
+    assert_eq!(ready["status"], json!(503));
+    assert_eq!(ready["body"]["checks"]["ollama"], json!("failed"));
+}
+
+#[actix_web::test]
+async fn test_ready_reports_missing_source_files() {
+    use datafusion::dataframe::DataFrameWriteOptions;
+    use datafusion::prelude::SessionContext;
+
+    let df_ctx = DataFusionContext::in_memory();
+    let mut paths = Vec::new();
+    for table in ["customer", "orders"] {
+        let path = std::env::temp_dir().join(format!("{}_{}.parquet", table, uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap().to_string();
+        SessionContext::new()
+            .sql("SELECT 1 AS id")
+            .await
+            .unwrap()
+            .write_parquet(
+                &path,
+                DataFrameWriteOptions::new().with_single_file_output(true),
+                None,
+            )
+            .await
+            .unwrap();
+        df_ctx.register_parquet(table, &path).await.unwrap();
+        paths.push(path);
+    }
+    let config = Config {
+        enable_ai: false,
+        check_data_source: true,
+        ..Config::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(df_ctx))
+            .app_data(web::Data::new(config))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 200);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["checks"]["data_source"], json!("ok"));
+
+    // A file removed after registration fails readiness with the table named
+    std::fs::remove_file(&paths[1]).unwrap();
+    let req = test::TestRequest::get().uri("/ready").to_request();
+    let res = test::call_service(&app, req).await;
+    std::fs::remove_file(&paths[0]).ok();
+    assert_eq!(res.status().as_u16(), 503);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["status"], json!("degraded"));
+    assert_eq!(body["checks"]["data_source"], json!("degraded"));
+    let problems = body["data_source_problems"].as_array().unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0]["table"], json!("orders"));
+    assert_eq!(problems[0]["path"], json!(paths[1]));
+    assert!(
+        problems[0]["problem"]
+            .as_str()
+            .unwrap()
+            .starts_with("missing")
+    );
+}
+
+#[actix_web::test]
+async fn test_unknown_route_lists_endpoints() {
+    let config = Config {
+        http_port: 8000,
+        ..Config::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(config))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/graphql")
+        .insert_header(("Accept", "application/json"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 404);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("NOT_FOUND"));
+    assert_eq!(body["error"]["port"], json!(8000));
+    let routes: Vec<&str> = body["error"]["endpoints"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|endpoint| endpoint["route"].as_str().unwrap())
+        .collect();
+    assert!(routes.contains(&"POST /graphql"));
+    assert!(routes.contains(&"GET /playground"));
+
+    // Browsers get an HTML page
+    let req = test::TestRequest::get()
+        .uri("/graphiql")
+        .insert_header(("Accept", "text/html,application/xhtml+xml"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 404);
+    assert!(
+        res.headers()
+            .get("content-type")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/html")
+    );
+}
+
+#[actix_web::test]
+async fn test_graphql_get_without_query_hints_playground() {
+    let schema = default_schema(customer_fixture());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/graphql").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 400);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("MISSING_QUERY"));
+    assert_eq!(body["error"]["playground"], json!("/playground"));
+
+    // GET with a query still executes it
+    let req = test::TestRequest::get()
+        .uri("/graphql?query=%7B%20tables%20%7D")
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["tables"], json!(["customer"]));
+
+    // Mutations are refused over GET, also when picked by operation name
+    for uri in [
+        "/graphql?query=mutation%20%7B%20__typename%20%7D",
+        "/graphql?query=query%20A%20%7B%20tables%20%7D%20mutation%20B%20%7B%20__typename%20%7D\
+         &operationName=B",
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status().as_u16(), 405);
+        assert_eq!(res.headers().get("Allow").unwrap(), "POST");
+        let body: serde_json::Value = test::read_body_json(res).await;
+        assert_eq!(body["error"]["code"], json!("METHOD_NOT_ALLOWED"));
+    }
+    let req = test::TestRequest::get()
+        .uri(
+            "/graphql?query=query%20A%20%7B%20tables%20%7D%20mutation%20B%20%7B%20__typename%20%7D\
+             &operationName=A",
+        )
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["tables"], json!(["customer"]));
+}
+
+#[actix_web::test]
+async fn test_export_errors_use_json_envelope() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(customer_fixture()))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELEC%20c_name%20FROM%20customer")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 400);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("INVALID_SQL"));
+    assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+
+    // Missing parameter and write statements get the same envelope
+    let req = test::TestRequest::get().uri("/export/csv").to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 400);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("BAD_REQUEST"));
+
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=DROP%20TABLE%20customer")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 400);
+
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%20c_name%20FROM%20customer%20ORDER%20BY%20c_custkey")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 200);
+    let body = test::read_body(res).await;
+    assert_eq!(body, "c_name\nCustomer#1\nCustomer#2\n");
+}
+
+#[actix_web::test]
+async fn test_export_csv_options() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(customer_fixture()))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%20c_custkey%2C%20c_name%20FROM%20customer%20ORDER%20BY%20c_custkey&delimiter=%09&header=false")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 200);
+    let body = test::read_body(res).await;
+    assert_eq!(body, "1\tCustomer#1\n2\tCustomer#2\n");
+
+    // Fields containing the delimiter are wrapped in the quote character
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%20%27a%3Bb%27%20AS%20v&delimiter=%3B&quote=%27")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    let body = test::read_body(res).await;
+    assert_eq!(body, "v\n'a;b'\n");
+
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%201&delimiter=%3B%3B")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 400);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("BAD_REQUEST"));
+}
+
+#[actix_web::test]
+async fn test_export_runs_as_a_logged_query() {
+    let df_ctx = customer_fixture();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(df_ctx.clone()))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%20c_name%20FROM%20customer")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 200);
+    test::read_body(res).await;
+
+    // The query is logged once its stream ends, and no longer listed as running
+    let logged = df_ctx.recent_queries();
+    let entry = logged
+        .iter()
+        .find(|entry| entry.sql == "SELECT c_name FROM customer")
+        .expect("export not logged");
+    assert!(entry.success);
+    assert_eq!(entry.rows, 2);
+    assert!(df_ctx.running_queries().is_empty());
+
+    // Revisions are read like those of executeSql
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%20c_name%20FROM%20customer&revision=42")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(!res.status().is_success());
+}
+
+/// Port that was free a moment ago
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Connection to `/graphql/ws` on `port`, upgraded to `graphql-transport-ws`
+async fn ws_connect(port: u16) -> tokio::net::TcpStream {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .unwrap();
+    let handshake = format!(
+        "GET /graphql/ws HTTP/1.1\r\n\
+         Host: 127.0.0.1:{}\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+         Sec-WebSocket-Version: 13\r\n\
+         Sec-WebSocket-Protocol: graphql-transport-ws\r\n\r\n",
+        port
+    );
+    stream.write_all(handshake.as_bytes()).await.unwrap();
+    let mut buf = vec![0u8; 1024];
+    let n = stream.read(&mut buf).await.unwrap();
+    let response = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+    assert!(response.starts_with("http/1.1 101"), "{}", response);
+    assert!(response.contains("sec-websocket-protocol: graphql-transport-ws"));
+    stream
+}
+
+/// Send a text frame, masked with a zero key as clients must mask
+async fn ws_send(stream: &mut tokio::net::TcpStream, message: serde_json::Value) {
+    use tokio::io::AsyncWriteExt;
+
+    let text = message.to_string();
+    let mut frame = vec![0x81];
+    if text.len() < 126 {
+        frame.push(0x80 | text.len() as u8);
+    } else {
+        frame.push(0x80 | 126);
+        frame.extend((text.len() as u16).to_be_bytes());
+    }
+    frame.extend([0u8; 4]);
+    frame.extend(text.as_bytes());
+    stream.write_all(&frame).await.unwrap();
+}
+
+/// Next text message from the server, `None` once the connection closes
+async fn ws_receive(stream: &mut tokio::net::TcpStream) -> Option<serde_json::Value> {
+    use tokio::io::AsyncReadExt;
+
+    loop {
+        let mut head = [0u8; 2];
+        stream.read_exact(&mut head).await.ok()?;
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).await.ok()?;
+                u16::from_be_bytes(len) as usize
+            }
+            127 => {
+                let mut len = [0u8; 8];
+                stream.read_exact(&mut len).await.ok()?;
+                u64::from_be_bytes(len) as usize
+            }
+            len => len as usize,
+        };
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await.ok()?;
+        match head[0] & 0x0f {
+            0x1 => return serde_json::from_slice(&body).ok(),
+            0x8 => return None,
+            _ => {}
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_websocket_operations_run_as_the_caller() {
+    use actix_web::HttpServer;
+
+    let port = free_port();
+    let schema = web::Data::new(default_schema(customer_fixture()));
+    let auth = web::Data::new(AuthGuard::new("test-secret"));
+    let admin = auth
+        .issue_token(&Claims::new("alice".to_string(), "admin".to_string()))
+        .unwrap();
+    let server_auth = auth.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(schema.clone())
+            .app_data(server_auth.clone())
+            .configure(configure_ws)
+    })
+    .bind(("127.0.0.1", port))
+    .unwrap()
+    .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let init = |token: &str| {
+        json!({
+            "type": "connection_init",
+            "payload": { "Authorization": format!("Bearer {}", token) }
+        })
+    };
+    let listing = json!({
+        "id": "1",
+        "type": "subscribe",
+        "payload": { "query": "{ activeSubscriptions { user query } }" }
+    });
+
+    // The token sent with connection_init authenticates the operations
+    let mut stream = ws_connect(port).await;
+    ws_send(&mut stream, init(&admin)).await;
+    assert_eq!(
+        ws_receive(&mut stream).await.unwrap()["type"],
+        json!("connection_ack")
+    );
+    ws_send(&mut stream, listing.clone()).await;
+    let next = ws_receive(&mut stream).await.unwrap();
+    assert_eq!(next["type"], json!("next"), "{}", next);
+    // The connection shows the operation it is running
+    assert_eq!(
+        next["payload"]["data"]["activeSubscriptions"],
+        json!([{ "user": "alice", "query": "{ activeSubscriptions { user query } }" }])
+    );
+
+    // Anonymous connections may not run admin operations
+    let mut anonymous = ws_connect(port).await;
+    ws_send(&mut anonymous, json!({ "type": "connection_init" })).await;
+    assert_eq!(
+        ws_receive(&mut anonymous).await.unwrap()["type"],
+        json!("connection_ack")
+    );
+    ws_send(&mut anonymous, listing).await;
+    let next = ws_receive(&mut anonymous).await.unwrap();
+    assert!(
+        next["payload"]["data"]["activeSubscriptions"].is_null(),
+        "{}",
+        next
+    );
+    assert!(!next["payload"]["errors"].as_array().unwrap().is_empty());
+
+    // An invalid token refuses the connection
+    let mut forged = ws_connect(port).await;
+    ws_send(&mut forged, init("forged")).await;
+    assert!(ws_receive(&mut forged).await.is_none());
+    handle.stop(true).await;
+
+    // So does an invalid token on the upgrade request
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(default_schema(customer_fixture())))
+            .app_data(auth)
+            .configure(configure_ws),
+    )
+    .await;
+    let req = test::TestRequest::get()
+        .uri("/graphql/ws")
+        .insert_header(("Upgrade", "websocket"))
+        .insert_header(("Connection", "Upgrade"))
+        .insert_header(("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="))
+        .insert_header(("Sec-WebSocket-Version", "13"))
+        .insert_header(("Sec-WebSocket-Protocol", "graphql-transport-ws"))
+        .insert_header(("Authorization", "Bearer forged"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn test_websocket_served_on_ws_port() {
+    use actix_web::HttpServer;
+    use graphql_datafusion::models::data::SubscriptionKind;
+    use graphql_datafusion::subscriptions::SubscriptionRegistry;
+    use tokio::io::AsyncReadExt;
+
+    let config = Config {
+        http_port: free_port(),
+        ws_port: free_port(),
+        ..Config::default()
+    };
+    let schema = web::Data::new(build_schema(
+        customer_fixture(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(config.clone()),
+    ));
+
+    let ws_schema = schema.clone();
+    let ws_server = HttpServer::new(move || {
+        App::new()
+            .app_data(ws_schema.clone())
+            .configure(configure_ws)
+    })
+    .bind(("127.0.0.1", config.ws_port))
+    .unwrap()
+    .run();
+    let ws_handle = ws_server.handle();
+    actix_web::rt::spawn(ws_server);
+
+    let mut stream = ws_connect(config.ws_port).await;
+    let mut buf = vec![0u8; 1024];
+
+    // The connection is listed until an admin closes it, which ends it
+    let subscriptions = schema.data::<Arc<SubscriptionRegistry>>().unwrap();
+    let listed = subscriptions.list();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].kind, SubscriptionKind::Graphql);
+    assert!(subscriptions.close(&listed[0].id));
+    let ended = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            match stream.read(&mut buf).await {
+                // End of stream, or a close frame
+                Ok(0) | Err(_) => break,
+                Ok(_) if buf[0] == 0x88 => break,
+                Ok(_) => {}
+            }
+        }
+    })
+    .await;
+    assert!(ended.is_ok(), "closed connection stayed open");
+    assert!(subscriptions.list().is_empty());
+    ws_handle.stop(true).await;
+
+    // The HTTP routes do not serve WebSockets when the ports differ
+    let app = test::init_service(App::new().app_data(schema).configure(configure)).await;
+    let req = test::TestRequest::get()
+        .uri("/graphql/ws")
+        .insert_header(("Accept", "application/json"))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_export_quota_exceeded() {
+    use graphql_datafusion::quota::{QuotaManager, RoleQuota};
+    use std::collections::BTreeMap;
+
+    let quotas = QuotaManager::new(BTreeMap::from([(
+        "viewer".to_string(),
+        RoleQuota {
+            max_rows_per_query: None,
+            max_export_rows_per_day: Some(3),
+        },
+    )]));
+    let auth = AuthGuard::new("secret");
+    let token = auth
+        .issue_token(&Claims::new("alice".to_string(), "viewer".to_string()))
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(customer_fixture()))
+            .app_data(web::Data::new(auth))
+            .app_data(web::Data::new(quotas))
+            .configure(configure),
+    )
+    .await;
+    let export = || {
+        test::TestRequest::get()
+            .uri("/export/csv?sql=SELECT%20c_name%20FROM%20customer")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    let res = test::call_service(&app, export()).await;
+    assert_eq!(res.status().as_u16(), 200);
+
+    // Two more rows would exceed the daily quota of three
+    let res = test::call_service(&app, export()).await;
+    assert_eq!(res.status().as_u16(), 429);
+    assert!(res.headers().contains_key("retry-after"));
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("EXPORT_QUOTA_EXCEEDED"));
+    assert!(body["error"]["resetAt"].is_string());
+}
+
+#[actix_web::test]
+async fn test_response_time_header() {
+    use actix_web::HttpResponse;
+    use actix_web::middleware::from_fn;
+    use std::time::Duration;
+
+    let config = Config {
+        response_time_budget_ms: 5,
+        ..Config::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(response_time))
+            .app_data(web::Data::new(config))
+            .route(
+                "/slow",
+                web::get().to(|| async {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    HttpResponse::Ok().finish()
+                }),
+            )
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let res = test::call_service(&app, req).await;
+    let elapsed = res
+        .headers()
+        .get("x-response-time-ms")
+        .expect("timing header")
+        .to_str()
+        .unwrap();
+    assert!(elapsed.parse::<u64>().is_ok(), "{}", elapsed);
+    assert!(res.headers().get("x-over-budget").is_none());
+
+    let req = test::TestRequest::get().uri("/slow").to_request();
+    let res = test::call_service(&app, req).await;
+    let elapsed: u64 = res.headers()["x-response-time-ms"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(elapsed >= 20);
+    assert_eq!(res.headers()["x-over-budget"], "true");
+}
+
+#[tokio::test]
+async fn test_webhook_signed_and_retried() {
+    use graphql_datafusion::datafusion::context::RetryPolicy;
+    use graphql_datafusion::events::{Event, EventKind};
+    use graphql_datafusion::notifier::{Notifier, SIGNATURE_HEADER, WebhookEndpoint, sign};
+    use std::time::Duration;
+
+    let webhook = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .with_priority(1)
+        .mount(&webhook)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&webhook)
+        .await;
+    let notifier = Notifier::new(vec![WebhookEndpoint {
+        url: webhook.uri(),
+        secret: "s3cret".to_string(),
+        events: vec![],
+    }])
+    .with_retry_policy(RetryPolicy {
+        max_retries: 3,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+    });
+
+    let event = Event::new(EventKind::ReloadFailed, "orders", "file not found");
+    assert_eq!(notifier.notify(&event).await, 1);
+
+    let requests = webhook.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+    for request in &requests {
+        let signature = request.headers.get(SIGNATURE_HEADER).unwrap();
+        assert_eq!(signature.to_str().unwrap(), sign("s3cret", &request.body));
+    }
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["event"], "reload_failed");
+    assert_eq!(body["table"], "orders");
+    assert_eq!(
+        body["text"],
+        "Reloading table orders failed: file not found"
+    );
+}
+
+#[tokio::test]
+async fn test_failed_reload_routed_to_webhook_and_dead_lettered() {
+    use graphql_datafusion::datafusion::context::RetryPolicy;
+    use graphql_datafusion::events::{EventBus, EventKind};
+    use graphql_datafusion::notifier::{Notifier, WebhookEndpoint};
+    use std::time::Duration;
+
+    let failing = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&failing)
+        .await;
+    let reloads = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&reloads)
+        .await;
+    let dead_letters =
+        std::env::temp_dir().join(format!("dead-letters-{}.jsonl", uuid::Uuid::new_v4()));
+    let events = EventBus::default();
+    Notifier::new(vec![
+        WebhookEndpoint {
+            url: failing.uri(),
+            secret: String::new(),
+            events: vec![EventKind::ReloadFailed],
+        },
+        WebhookEndpoint {
+            url: reloads.uri(),
+            secret: String::new(),
+            events: vec![EventKind::TableReloaded],
+        },
+    ])
+    .with_retry_policy(RetryPolicy {
+        max_retries: 1,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+    })
+    .with_dead_letter_log(&dead_letters)
+    .spawn(&events);
+    let df_ctx = DataFusionContext::in_memory().with_events(events);
+
+    assert!(df_ctx.reload_table("orders").await.is_err());
+
+    let mut waited = 0;
+    let written = |path: &std::path::Path| {
+        std::fs::read_to_string(path).is_ok_and(|contents| contents.ends_with('\n'))
+    };
+    while !written(&dead_letters) && waited < 50 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        waited += 1;
+    }
+    let contents = std::fs::read_to_string(&dead_letters).unwrap();
+    let record: serde_json::Value = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+    assert_eq!(record["attempts"], 2);
+    assert_eq!(record["payload"]["event"], "reload_failed");
+    assert_eq!(failing.received_requests().await.unwrap().len(), 2);
+    // Only reload failures are routed to the first endpoint
+    assert!(reloads.received_requests().await.unwrap().is_empty());
+    std::fs::remove_file(dead_letters).unwrap();
+}
+
+#[actix_web::test]
+async fn test_read_only_mode_over_http() {
+    let df_ctx = customer_fixture();
+    let schema = build_schema(
+        df_ctx.clone(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(Config {
+            read_only: true,
+            ..Config::default()
+        }),
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .app_data(web::Data::from(df_ctx))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/health").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body, json!({ "status": "ok", "readOnly": true }));
+
+    // Exports count against quotas and are refused
+    let req = test::TestRequest::get()
+        .uri("/export/csv?sql=SELECT%201")
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.status().as_u16(), 503);
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert_eq!(body["error"]["code"], json!("SERVICE_READ_ONLY"));
+
+    // Data queries keep working
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .set_json(json!({ "query": "{ tableCount(tableName: \"customer\") }" }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["data"]["tableCount"], json!(2));
+}
+
+#[actix_web::test]
+async fn test_oversized_response_is_truncated() {
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)])),
+        vec![Arc::new(Int64Array::from(vec![1]))],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    for i in 0..200 {
+        df_ctx
+            .register_batches(&format!("partitioned_events_{:03}", i), vec![batch.clone()])
+            .unwrap();
+    }
+    let schema = default_schema(Arc::new(df_ctx));
+    let config = Config {
+        max_response_bytes: 1000,
+        ..Config::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .app_data(web::Data::new(config))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .set_json(json!({ "query": "{ tables }" }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.headers().get("X-Response-Truncated").unwrap(), "true");
+    let body = test::read_body(res).await;
+    assert!(body.len() <= 1000, "{} bytes", body.len());
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["extensions"]["truncated"], json!(true));
+    let tables = body["data"]["tables"].as_array().unwrap();
+    assert!(!tables.is_empty() && tables.len() < 200, "{}", tables.len());
+
+    // Responses within the limit are untouched
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .set_json(json!({ "query": "{ tableCount(tableName: \"partitioned_events_000\") }" }))
+        .to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(res.headers().get("X-Response-Truncated").is_none());
+    let body: serde_json::Value = test::read_body_json(res).await;
+    assert!(body["extensions"].get("truncated").is_none());
+}
+
+#[cfg(feature = "demo")]
+#[actix_web::test]
+async fn test_demo_mode_end_to_end() {
+    use graphql_datafusion::datafusion::demo::{
+        DEMO_CUSTOMERS, DEMO_ORDERS_PER_CUSTOMER, register_demo_tables,
+    };
+
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains("orders per region"))
+        .respond_with(ollama_reply(
+            "SELECT r_name, COUNT(*) AS order_count FROM orders \
+             JOIN customer ON o_custkey = c_custkey \
+             JOIN nation ON c_nationkey = n_nationkey \
+             JOIN region ON n_regionkey = r_regionkey GROUP BY r_name",
+        ))
+        .mount(&ollama)
+        .await;
+
+    let df_ctx = DataFusionContext::in_memory();
+    register_demo_tables(&df_ctx).unwrap();
+    let df_ctx = Arc::new(df_ctx);
+    df_ctx.refresh_rollups().await.unwrap();
+    assert!(df_ctx.check_model_schemas().await.is_empty());
+    let orchestrator = Arc::new(
+        AgentOrchestrator::new()
+            .with_agent(
+                "default".to_string(),
+                AgentClient::new(ollama.uri(), "sqlcoder".to_string()),
+            )
+            .with_context(df_ctx.clone()),
+    );
+    let schema = build_schema(
+        df_ctx.clone(),
+        orchestrator,
+        Arc::new(Config {
+            demo_mode: true,
+            ..Config::default()
+        }),
+    );
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(schema))
+            .app_data(web::Data::from(df_ctx))
+            .configure(configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/playground").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    assert!(String::from_utf8_lossy(&body).contains("Demo mode"));
+
+    let query = r#"{
+        serverInfo { demo }
+        customers(limit: 3) { cCustkey cName cMktsegment }
+        lineItems(orderKey: 1) { lLinenumber lShipdate }
+        salesAnalytics(topN: 3) {
+            totalOrders
+            totalSales
+            salesByRegion { region customerCount }
+            topCustomers { totalSpent }
+            monthlyTrends { month }
+        }
+    }"#;
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .set_json(json!({ "query": query }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body.get("errors").is_none(), "{}", body);
+    let data = &body["data"];
+    assert_eq!(data["serverInfo"]["demo"], json!(true));
+    assert_eq!(data["customers"].as_array().unwrap().len(), 3);
+    assert_ne!(data["customers"][0]["cMktsegment"], json!("UNKNOWN"));
+    assert!(!data["lineItems"].as_array().unwrap().is_empty());
+    let analytics = &data["salesAnalytics"];
+    assert_eq!(
+        analytics["totalOrders"],
+        json!(DEMO_CUSTOMERS * DEMO_ORDERS_PER_CUSTOMER)
+    );
+    assert!(analytics["totalSales"].as_f64().unwrap() > 0.0);
+    assert_eq!(analytics["salesByRegion"].as_array().unwrap().len(), 5);
+    assert_eq!(analytics["topCustomers"].as_array().unwrap().len(), 3);
+    assert!(analytics["monthlyTrends"].as_array().unwrap().len() > 12);
+
+    // Generated SQL runs against the demo tables
+    let req = test::TestRequest::post()
+        .uri("/graphql")
+        .set_json(json!({
+            "query": r#"{ naturalLanguageQuery(input: "orders per region") }"#
+        }))
+        .to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert!(body.get("errors").is_none(), "{}", body);
+    assert_eq!(
+        body["extensions"]["naturalLanguageQuery"]["rowCount"],
+        json!(5)
+    );
+}
+
+#[cfg(feature = "tls")]
+#[actix_web::test]
+async fn test_mtls_client_certificates() {
+    use actix_web::HttpServer;
+    use graphql_datafusion::tls::{ClientCertRoles, server_config};
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use std::collections::BTreeMap;
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "Test CA");
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["127.0.0.1".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca_cert, &ca_key)
+        .unwrap();
+    // PEM of a client certificate and its key, signed by `issuer` or self-signed
+    let client_identity = |name: &str, issuer: Option<(&rcgen::Certificate, &KeyPair)>| {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let cert = match issuer {
+            Some((cert, issuer_key)) => params.signed_by(&key, cert, issuer_key).unwrap(),
+            None => params.self_signed(&key).unwrap(),
+        };
+        reqwest::Identity::from_pem(format!("{}{}", cert.pem(), key.serialize_pem()).as_bytes())
+            .unwrap()
+    };
+
+    let dir = std::env::temp_dir().join(format!("mtls-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, pem: String| {
+        let path = dir.join(name);
+        std::fs::write(&path, pem).unwrap();
+        path.to_string_lossy().into_owned()
+    };
+    let config = Config {
+        http_port: free_port(),
+        tls_cert_path: write("server.pem", server_cert.pem()),
+        tls_key_path: write("server.key", server_key.serialize_pem()),
+        tls_client_ca_path: write("ca.pem", ca_cert.pem()),
+        client_cert_roles: BTreeMap::from([("ops-dashboard".to_string(), "admin".to_string())]),
+        ..Config::default()
+    };
+    config.validate().unwrap();
+
+    let schema = web::Data::new(build_schema(
+        customer_fixture(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(config.clone()),
+    ));
+    let roles = ClientCertRoles::new(&config);
+    let server = HttpServer::new(move || App::new().app_data(schema.clone()).configure(configure))
+        .on_connect(move |connection, data| roles.on_connect(connection, data))
+        .bind_rustls_0_23(
+            ("127.0.0.1", config.http_port),
+            server_config(&config).unwrap(),
+        )
+        .unwrap()
+        .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    let url = format!("https://127.0.0.1:{}/graphql", config.http_port);
+    let ca = reqwest::Certificate::from_pem(ca_cert.pem().as_bytes()).unwrap();
+    let client = |identity: Option<reqwest::Identity>| {
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(ca.clone());
+        if let Some(identity) = identity {
+            builder = builder.identity(identity);
+        }
+        builder.build().unwrap()
+    };
+    // Admin only
+    let query = json!({ "query": "{ slowQueries { sql } }" });
+
+    // The certificate's common name is the caller, with its mapped role
+    let res = client(Some(client_identity(
+        "ops-dashboard",
+        Some((&ca_cert, &ca_key)),
+    )))
+    .post(&url)
+    .json(&query)
+    .send()
+    .await
+    .unwrap();
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert!(body.get("errors").is_none(), "{}", body);
+
+    // Unmapped names get the default role
+    let res = client(Some(client_identity(
+        "reporting",
+        Some((&ca_cert, &ca_key)),
+    )))
+    .post(&url)
+    .json(&query)
+    .send()
+    .await
+    .unwrap();
+    let body: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(body["errors"][0]["extensions"]["code"], json!("FORBIDDEN"));
+
+    // Without a certificate, or with one from another CA, the handshake fails
+    assert!(client(None).post(&url).json(&query).send().await.is_err());
+    assert!(
+        client(Some(client_identity("ops-dashboard", None)))
+            .post(&url)
+            .json(&query)
+            .send()
+            .await
+            .is_err()
+    );
+
+    handle.stop(true).await;
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -1,8 +1,47 @@
+mod common;
+
+use common::{customer_batch, customer_fixture, default_schema, ollama_reply};
 use graphql_datafusion::agents::client::AgentClient;
 use graphql_datafusion::agents::orchestrator::AgentOrchestrator;
 use graphql_datafusion::agents::types::AgentConfig;
-use graphql_datafusion::datafusion::context::DataFusionContext;
-use graphql_datafusion::models::data::{Customer, SalesAnalytics};
+use graphql_datafusion::config::Config;
+use graphql_datafusion::datafusion::context::{DataFusionContext, ErrorClass, classify_error};
+use graphql_datafusion::graphql::dry_run::validate_document;
+use graphql_datafusion::graphql::schema::{AppSchema, build_schema};
+use graphql_datafusion::models::data::{Customer, MarketSegment, SalesAnalytics};
+use graphql_datafusion::query_queue::{QueryClass, QueryQueue, QueryQueueConfig, QueueError};
+use serde_json::json;
+use std::sync::Arc;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer};
+
+fn test_schema(config: Config) -> AppSchema {
+    build_schema(
+        customer_fixture(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(config),
+    )
+}
+
+/// Write a batch to a uniquely named parquet file in the temp directory
+async fn write_parquet_fixture(batch: datafusion::arrow::record_batch::RecordBatch) -> String {
+    use datafusion::dataframe::DataFrameWriteOptions;
+    use datafusion::prelude::SessionContext;
+
+    let path = std::env::temp_dir().join(format!("fixture_{}.parquet", uuid::Uuid::new_v4()));
+    let path = path.to_str().unwrap().to_string();
+    SessionContext::new()
+        .read_batch(batch)
+        .unwrap()
+        .write_parquet(
+            &path,
+            DataFrameWriteOptions::new().with_single_file_output(true),
+            None,
+        )
+        .await
+        .unwrap();
+    path
+}
 
 #[tokio::test]
 async fn test_datafusion_context_creation() {
@@ -11,6 +50,127 @@ async fn test_datafusion_context_creation() {
     assert!(ctx.is_ok());
 }
 
+#[tokio::test]
+async fn test_datafusion_context_with_own_tables() {
+    use graphql_datafusion::datafusion::context::{TableDef, TableFormat};
+
+    let dir = std::env::temp_dir().join(format!("tables_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("events.csv"),
+        "id,kind\n1,click\n2,view\n3,click\n",
+    )
+    .unwrap();
+    let customers = write_parquet_fixture(customer_batch()).await;
+
+    // Relative paths are read from the data path, absolute ones as they are
+    let ctx = DataFusionContext::with_tables(
+        dir.to_str().unwrap(),
+        &[
+            TableDef::new("events", "events.csv", TableFormat::Csv),
+            TableDef::new("clients", &customers, TableFormat::Parquet),
+        ],
+    )
+    .await
+    .unwrap();
+    let mut tables = ctx.get_table_names();
+    tables.sort();
+    assert_eq!(tables, vec!["clients", "events"]);
+
+    let batches = ctx
+        .execute_query(
+            "SELECT COUNT(*) AS clicks FROM events e JOIN clients c ON e.id = c.c_custkey \
+             WHERE e.kind = 'click'",
+        )
+        .await
+        .unwrap();
+    let clicks = batches[0]
+        .column(0)
+        .as_any()
+        .downcast_ref::<datafusion::arrow::array::Int64Array>()
+        .unwrap()
+        .value(0);
+    assert_eq!(clicks, 1);
+
+    // A missing file fails the context instead of leaving the table out
+    let missing = DataFusionContext::with_tables(
+        dir.to_str().unwrap(),
+        &[TableDef::new("gone", "gone.parquet", TableFormat::Parquet)],
+    )
+    .await;
+    assert!(missing.is_err());
+
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::remove_file(&customers).ok();
+}
+
+#[tokio::test]
+async fn test_table_format_detected_from_extension() {
+    use graphql_datafusion::datafusion::context::{TableDef, TableFormat};
+
+    assert_eq!(
+        TableFormat::from_path("a/events.CSV"),
+        Some(TableFormat::Csv)
+    );
+    assert_eq!(
+        TableFormat::from_path("orders.parquet"),
+        Some(TableFormat::Parquet)
+    );
+    assert_eq!(TableFormat::from_path("orders/"), None);
+
+    // Config entries without a format are detected too
+    let tables: Vec<TableDef> = serde_json::from_str(
+        r#"[{"name":"events","path":"events.csv"},{"name":"raw","path":"raw.txt","format":"csv","has_header":false}]"#,
+    )
+    .unwrap();
+    assert_eq!(tables[0], TableDef::from_path("events", "events.csv"));
+    assert_eq!(
+        tables[1],
+        TableDef::new("raw", "raw.txt", TableFormat::Csv).without_header()
+    );
+
+    let dir = std::env::temp_dir().join(format!("formats_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("events.csv"), "id,kind\n1,click\n2,view\n").unwrap();
+    std::fs::write(dir.join("raw.txt"), "1,click\n3,view\n4,view\n").unwrap();
+    let customers = write_parquet_fixture(customer_batch()).await;
+
+    let mut tables = tables;
+    tables.push(TableDef::from_path("clients", &customers));
+    let ctx = DataFusionContext::with_tables(dir.to_str().unwrap(), &tables)
+        .await
+        .unwrap();
+
+    let count = |sql: &'static str| {
+        let ctx = &ctx;
+        async move {
+            let batches = ctx.execute_query(sql).await.unwrap();
+            batches[0]
+                .column(0)
+                .as_any()
+                .downcast_ref::<datafusion::arrow::array::Int64Array>()
+                .unwrap()
+                .value(0)
+        }
+    };
+    assert_eq!(
+        count("SELECT COUNT(*) FROM events WHERE kind = 'view'").await,
+        1
+    );
+    // Headerless columns are numbered
+    assert_eq!(
+        count("SELECT COUNT(*) FROM raw WHERE column_2 = 'view'").await,
+        2
+    );
+    assert_eq!(
+        count("SELECT COUNT(*) FROM clients c JOIN raw r ON r.column_1 = c.c_custkey").await,
+        1
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::remove_file(&customers).ok();
+}
+
 #[tokio::test]
 async fn test_datafusion_query_execution() {
     let ctx = DataFusionContext::new("/opt/data/tpch").await.unwrap();
@@ -79,22 +239,24 @@ async fn test_agent_client_insights_generation() {
         Customer {
             c_custkey: 1,
             c_name: "Customer 1".to_string(),
-            c_address: "Address 1".to_string(),
+            c_address: Some("Address 1".to_string()),
             c_nationkey: 1,
-            c_phone: "123-456-7890".to_string(),
+            c_phone: Some("123-456-7890".to_string()),
             c_acctbal: 1000.0,
-            c_mktsegment: "BUILDING".to_string(),
-            c_comment: "Test customer".to_string(),
+            c_mktsegment: MarketSegment::Building,
+            c_mktsegment_raw: None,
+            c_comment: Some("Test customer".to_string()),
         },
         Customer {
             c_custkey: 2,
             c_name: "Customer 2".to_string(),
-            c_address: "Address 2".to_string(),
+            c_address: Some("Address 2".to_string()),
             c_nationkey: 2,
-            c_phone: "098-765-4321".to_string(),
+            c_phone: Some("098-765-4321".to_string()),
             c_acctbal: 2000.0,
-            c_mktsegment: "AUTOMOBILE".to_string(),
-            c_comment: "Test customer 2".to_string(),
+            c_mktsegment: MarketSegment::Automobile,
+            c_mktsegment_raw: None,
+            c_comment: Some("Test customer 2".to_string()),
         },
     ];
 
@@ -115,7 +277,7 @@ async fn test_agent_client_insights_generation() {
 #[tokio::test]
 async fn test_agent_orchestrator_creation() {
     // Test that AgentOrchestrator can be created
-    let mut orchestrator = AgentOrchestrator::new();
+    let orchestrator = AgentOrchestrator::new();
 
     // Test that it can process queries (will fail if Ollama is not running)
     let result = orchestrator
@@ -123,9 +285,9 @@ async fn test_agent_orchestrator_creation() {
         .await;
 
     match result {
-        Ok((customers, insights)) => {
-            assert!(!customers.is_empty());
-            assert!(!insights.is_empty());
+        Ok(report) => {
+            assert!(report.sql.is_some());
+            assert!(report.insights.is_some() || !report.warnings.is_empty());
         }
         Err(_) => {
             // Expected if Ollama is not running
@@ -144,6 +306,8 @@ async fn test_sales_analytics_creation() {
         top_customers: vec![],
         sales_by_region: vec![],
         monthly_trends: vec![],
+        order_value_distribution: Default::default(),
+        errors: vec![],
     };
 
     assert_eq!(analytics.total_sales, 1000000.0);
@@ -157,18 +321,19 @@ async fn test_customer_creation() {
     let customer = Customer {
         c_custkey: 1,
         c_name: "Test Customer".to_string(),
-        c_address: "Test Address".to_string(),
+        c_address: Some("Test Address".to_string()),
         c_nationkey: 1,
-        c_phone: "123-456-7890".to_string(),
+        c_phone: Some("123-456-7890".to_string()),
         c_acctbal: 1000.0,
-        c_mktsegment: "BUILDING".to_string(),
-        c_comment: "Test customer".to_string(),
+        c_mktsegment: MarketSegment::Building,
+        c_mktsegment_raw: None,
+        c_comment: Some("Test customer".to_string()),
     };
 
     assert_eq!(customer.c_custkey, 1);
     assert_eq!(customer.c_name, "Test Customer");
     assert_eq!(customer.c_acctbal, 1000.0);
-    assert_eq!(customer.c_mktsegment, "BUILDING");
+    assert_eq!(customer.c_mktsegment, MarketSegment::Building);
 }
 
 #[tokio::test]
@@ -197,3 +362,5741 @@ async fn test_agent_config_default() {
     assert_eq!(config.temperature, None);
     assert_eq!(config.max_tokens, None);
 }
+
+#[tokio::test]
+async fn test_orchestrator_corrects_invalid_sql() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains(
+            "Translate this natural language query",
+        ))
+        .respond_with(ollama_reply("SELECT c_nme FROM customer"))
+        .expect(1)
+        .mount(&ollama)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains("failed with error"))
+        .respond_with(ollama_reply("```sql\nSELECT c_name FROM customer;\n```"))
+        .expect(1)
+        .mount(&ollama)
+        .await;
+
+    let orchestrator = AgentOrchestrator::new()
+        .with_agent(
+            "default".to_string(),
+            AgentClient::new(ollama.uri(), "llama2".to_string()),
+        )
+        .with_context(customer_fixture())
+        .with_max_correction_attempts(1);
+
+    let (sql, batches) = orchestrator
+        .execute_natural_language("list customer names", None)
+        .await
+        .unwrap();
+
+    assert_eq!(sql, "SELECT c_name FROM customer");
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+}
+
+fn two_agent_orchestrator() -> AgentOrchestrator {
+    AgentOrchestrator::empty()
+        .with_agent(
+            "fast".to_string(),
+            AgentClient::new("http://localhost:1".to_string(), "small".to_string()),
+        )
+        .with_agent(
+            "accurate".to_string(),
+            AgentClient::new("http://localhost:2".to_string(), "large".to_string()),
+        )
+}
+
+#[test]
+fn test_agent_selection_skips_open_circuit_breaker() {
+    use std::time::Duration;
+
+    let orchestrator =
+        two_agent_orchestrator().with_priority(vec!["accurate".to_string(), "fast".to_string()]);
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("accurate"));
+
+    // Two failures stay under the default threshold of three
+    for _ in 0..2 {
+        orchestrator.record_agent_result("accurate", Duration::from_millis(10), false);
+    }
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("accurate"));
+
+    orchestrator.record_agent_result("accurate", Duration::from_millis(10), false);
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("fast"));
+}
+
+#[test]
+fn test_agent_selection_by_latency() {
+    use graphql_datafusion::agents::health::HealthPolicy;
+    use std::time::Duration;
+
+    let orchestrator = two_agent_orchestrator().with_health_policy(HealthPolicy {
+        max_p95_latency: Some(Duration::from_millis(500)),
+        ..HealthPolicy::default()
+    });
+    orchestrator.record_agent_result("fast", Duration::from_millis(50), true);
+    orchestrator.record_agent_result("accurate", Duration::from_millis(300), true);
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("fast"));
+
+    // Over the p95 threshold the faster agent is out of rotation
+    for _ in 0..5 {
+        orchestrator.record_agent_result("fast", Duration::from_secs(2), true);
+    }
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("accurate"));
+
+    // With every agent unhealthy and no default agent configured, the first
+    // agent by name is used
+    for _ in 0..3 {
+        orchestrator.record_agent_result("accurate", Duration::from_millis(300), false);
+    }
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("accurate"));
+
+    let orchestrator = orchestrator.with_priority(vec!["fast".to_string()]);
+    for _ in 0..3 {
+        orchestrator.record_agent_result("fast", Duration::from_millis(300), false);
+    }
+    assert_eq!(orchestrator.select_agent().as_deref(), Some("fast"));
+
+    assert_eq!(AgentOrchestrator::empty().select_agent(), None);
+}
+
+#[test]
+fn test_config_rejects_unknown_prioritized_agent() {
+    let mut config = Config {
+        agent_priority: vec!["accurate".to_string(), "default".to_string()],
+        ..Config::default()
+    };
+    let error = config.validate().unwrap_err();
+    assert!(error.contains("'accurate'"), "{}", error);
+
+    config
+        .agents
+        .insert("accurate".to_string(), "sqlcoder:15b".to_string());
+    assert!(config.validate().is_ok());
+
+    config.agent_failure_threshold = 0;
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_natural_language_routes_around_failing_agent() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ollama_reply("SELECT c_name FROM customer"))
+        .mount(&ollama)
+        .await;
+
+    let orchestrator = AgentOrchestrator::empty()
+        .with_agent(
+            "accurate".to_string(),
+            AgentClient::new("http://127.0.0.1:9".to_string(), "large".to_string()),
+        )
+        .with_agent(
+            "fast".to_string(),
+            AgentClient::new(ollama.uri(), "small".to_string()),
+        )
+        .with_priority(vec!["accurate".to_string(), "fast".to_string()])
+        .with_context(customer_fixture());
+
+    // The unreachable agent fails until its circuit breaker opens
+    for _ in 0..3 {
+        let result = orchestrator
+            .execute_natural_language_for(None, "list customer names", None)
+            .await;
+        assert!(result.is_err());
+    }
+
+    let result = orchestrator
+        .execute_natural_language_for(None, "list customer names", None)
+        .await
+        .unwrap();
+    assert_eq!(result.audit.agent, "fast");
+    assert_eq!(result.audit.model, "small");
+    assert_eq!(result.report.agent_type, "fast");
+}
+
+#[tokio::test]
+async fn test_validate_document_accepts_valid_query() {
+    let schema = test_schema(Config::default());
+    let report = validate_document(&schema, async_graphql::Request::new("{ tables }")).await;
+
+    assert!(report.valid);
+    assert!(report.errors.is_empty());
+    assert!(report.depth.is_some());
+}
+
+#[tokio::test]
+async fn test_validate_document_reports_syntax_error() {
+    let schema = test_schema(Config::default());
+    let report = validate_document(&schema, async_graphql::Request::new("{ tables ")).await;
+
+    assert!(!report.valid);
+    assert_eq!(report.errors.len(), 1);
+    assert!(report.errors[0].line.is_some());
+}
+
+#[tokio::test]
+async fn test_validate_document_rejects_over_limit_query() {
+    let config = Config {
+        max_query_depth: 2,
+        ..Config::default()
+    };
+    let schema = test_schema(config);
+    let report = validate_document(
+        &schema,
+        async_graphql::Request::new("{ salesAnalytics { topCustomers { customer { c_name } } } }"),
+    )
+    .await;
+
+    assert!(!report.valid);
+    assert!(report.errors[0].message.contains("too deep"));
+}
+
+#[tokio::test]
+async fn test_identical_concurrent_queries_execute_once() {
+    use graphql_datafusion::metrics::COALESCED_REQUESTS_TOTAL;
+    use graphql_datafusion::singleflight::{GraphQLFlight, request_key};
+    use std::time::Duration;
+
+    let df_ctx = customer_fixture();
+    let schema = default_schema(df_ctx.clone());
+    let flight = GraphQLFlight::new(Duration::from_secs(5));
+    let query = "{ customers { c_name } }";
+    let key = request_key(&async_graphql::Request::new(query)).unwrap();
+
+    let coalesced = COALESCED_REQUESTS_TOTAL.get();
+    let schema = &schema;
+    let responses = futures::future::join_all((0..10).map(|_| {
+        flight.run(key.clone(), || async move {
+            // Let the other requests arrive while this one executes
+            tokio::task::yield_now().await;
+            Ok(Arc::new(schema.execute(query).await))
+        })
+    }))
+    .await;
+
+    assert_eq!(df_ctx.recent_queries().len(), 1);
+    assert_eq!(COALESCED_REQUESTS_TOTAL.get() - coalesced, 9);
+    for response in &responses {
+        let response = response.as_ref().unwrap();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.clone().into_json().unwrap()["customers"][1]["c_name"],
+            json!("Customer#2")
+        );
+    }
+    assert_eq!(flight.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn test_coalesced_request_stops_waiting_after_max_wait() {
+    use graphql_datafusion::singleflight::SingleFlight;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let flight = SingleFlight::new(Duration::from_millis(20));
+    let executions = &AtomicUsize::new(0);
+    let execute = || async move {
+        executions.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        42
+    };
+    let (leader, follower) = tokio::join!(
+        flight.run("key".to_string(), execute),
+        flight.run("key".to_string(), execute)
+    );
+    assert_eq!((leader, follower), (42, 42));
+    assert_eq!(executions.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_request_key() {
+    use graphql_datafusion::singleflight::request_key;
+
+    let request = |query: &str| async_graphql::Request::new(query);
+    let key = request_key(&request("{ customers { c_name } }")).unwrap();
+    assert_eq!(
+        request_key(&request("{ customers { c_name } }")).unwrap(),
+        key
+    );
+    assert_ne!(
+        request_key(&request("{ customers { c_custkey } }")).unwrap(),
+        key
+    );
+    let with_n = |n: i32| {
+        request("query($n: Int) { customers(limit: $n) { c_name } }")
+            .variables(async_graphql::Variables::from_json(json!({ "n": n })))
+    };
+    assert_ne!(request_key(&with_n(1)), request_key(&with_n(2)));
+    assert_eq!(request_key(&with_n(1)), request_key(&with_n(1)));
+
+    // Only queries are coalesced
+    assert!(request_key(&request("mutation { refreshConnection }")).is_none());
+    let document = "query Read { tables } mutation Write { refreshConnection }";
+    assert!(request_key(&request(document).operation_name("Read")).is_some());
+    assert!(request_key(&request(document).operation_name("Write")).is_none());
+    assert!(request_key(&request("{ customers {")).is_none());
+}
+
+#[tokio::test]
+async fn test_query_queue_serves_short_burst() {
+    let queue = Arc::new(QueryQueue::new(QueryQueueConfig {
+        max_concurrent: 2,
+        capacity: 8,
+        max_wait: std::time::Duration::from_secs(2),
+        ..QueryQueueConfig::default()
+    }));
+
+    let handles: Vec<_> = (0..6)
+        .map(|_| {
+            let queue = queue.clone();
+            tokio::spawn(async move {
+                let _permit = queue.acquire(QueryClass::Interactive).await?;
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                Ok::<_, QueueError>(())
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert!(handle.await.unwrap().is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_query_queue_sheds_flood() {
+    let queue = Arc::new(QueryQueue::new(QueryQueueConfig {
+        max_concurrent: 1,
+        capacity: 1,
+        max_wait: std::time::Duration::from_millis(50),
+        ..QueryQueueConfig::default()
+    }));
+
+    let held = queue.acquire(QueryClass::Interactive).await.unwrap();
+    let waiter = {
+        let queue = queue.clone();
+        tokio::spawn(async move { queue.acquire(QueryClass::Interactive).await.map(|_| ()) })
+    };
+    while queue.waiting() == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    // The queue is full, so the next request is rejected immediately
+    assert_eq!(
+        queue.acquire(QueryClass::Interactive).await.unwrap_err(),
+        QueueError::Full
+    );
+    // The queued request gives up once the wait cap is exceeded
+    assert_eq!(waiter.await.unwrap().unwrap_err(), QueueError::Timeout);
+    drop(held);
+}
+
+#[test]
+fn test_user_concurrency_cap() {
+    let queue = QueryQueue::new(QueryQueueConfig {
+        max_concurrent_per_user: 2,
+        ..QueryQueueConfig::default()
+    });
+
+    let first = queue.acquire_user("alice").unwrap();
+    let _second = queue.acquire_user("alice").unwrap();
+    assert_eq!(
+        queue.acquire_user("alice").unwrap_err(),
+        QueueError::UserLimit { limit: 2 }
+    );
+    // Other users have their own slots
+    let _bob = queue.acquire_user("bob").unwrap();
+    assert_eq!(queue.user_in_flight("bob"), 1);
+
+    // A finished request frees its slot
+    drop(first);
+    assert_eq!(queue.user_in_flight("alice"), 1);
+    assert!(queue.acquire_user("alice").is_ok());
+}
+
+#[test]
+fn test_classify_transient_io_errors() {
+    use datafusion::error::DataFusionError;
+    use std::io::{Error, ErrorKind};
+
+    let timeout = DataFusionError::IoError(Error::new(ErrorKind::TimedOut, "read timed out"));
+    assert_eq!(classify_error(&timeout), ErrorClass::Transient);
+
+    let reset = DataFusionError::Context(
+        "reading customer.parquet".to_string(),
+        Box::new(DataFusionError::IoError(Error::new(
+            ErrorKind::ConnectionReset,
+            "connection reset by peer",
+        ))),
+    );
+    assert_eq!(classify_error(&reset), ErrorClass::Transient);
+
+    let missing = DataFusionError::IoError(Error::new(ErrorKind::NotFound, "no such file"));
+    assert_eq!(classify_error(&missing), ErrorClass::Logical);
+}
+
+#[test]
+fn test_classify_object_store_errors() {
+    use datafusion::error::DataFusionError;
+
+    let unavailable = DataFusionError::from(object_store::Error::Generic {
+        store: "S3",
+        source: "503 Service Unavailable".into(),
+    });
+    assert_eq!(classify_error(&unavailable), ErrorClass::Transient);
+
+    // Neither message says "not found", but retrying cannot help
+    let denied = DataFusionError::from(object_store::Error::PermissionDenied {
+        path: "tpch/customer.parquet".to_string(),
+        source: "403 Forbidden".into(),
+    });
+    assert_eq!(classify_error(&denied), ErrorClass::Logical);
+    let unauthenticated = DataFusionError::from(object_store::Error::Unauthenticated {
+        path: "tpch/customer.parquet".to_string(),
+        source: "401 Unauthorized".into(),
+    });
+    assert_eq!(classify_error(&unauthenticated), ErrorClass::Logical);
+
+    let missing = DataFusionError::from(object_store::Error::NotFound {
+        path: "tpch/customer.parquet".to_string(),
+        source: "404".into(),
+    });
+    assert_eq!(classify_error(&missing), ErrorClass::Logical);
+}
+
+#[test]
+fn test_classify_planner_and_logical_errors() {
+    use datafusion::error::DataFusionError;
+
+    let plan = DataFusionError::Plan("table 'foo' not found".to_string());
+    assert_eq!(classify_error(&plan), ErrorClass::Planner);
+
+    let execution = DataFusionError::Execution("division by zero".to_string());
+    assert_eq!(classify_error(&execution), ErrorClass::Logical);
+}
+
+#[tokio::test]
+async fn test_estimated_table_count_matches_exact() {
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_parquet("customer", &path).await.unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(
+            r#"{
+                exact: tableCount(tableName: "customer")
+                estimate: tableCount(tableName: "customer", exact: false)
+            }"#,
+        )
+        .await;
+    std::fs::remove_file(&path).ok();
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["exact"], json!(2));
+    assert_eq!(data["estimate"], data["exact"]);
+    assert!(response.extensions.contains_key("countEstimates"));
+}
+
+#[tokio::test]
+async fn test_saturated_heavy_pool_leaves_lookups_unaffected() {
+    let queue = QueryQueue::new(QueryQueueConfig {
+        max_concurrent: 2,
+        max_concurrent_heavy: 1,
+        capacity: 4,
+        max_wait: std::time::Duration::from_millis(20),
+        ..QueryQueueConfig::default()
+    });
+
+    assert_eq!(
+        queue.classify("{ salesAnalytics { totalSales } }"),
+        QueryClass::Heavy
+    );
+    assert_eq!(queue.classify("{ tables }"), QueryClass::Interactive);
+    // Heavy fields selected through fragments count too
+    assert_eq!(
+        queue.classify("{ ... on Query { salesAnalytics { totalSales } } }"),
+        QueryClass::Heavy
+    );
+    assert_eq!(
+        queue.classify(
+            "query { ...Outer } fragment Outer on Query { ...Inner } \
+             fragment Inner on Query { tables salesAnalytics { totalSales } }"
+        ),
+        QueryClass::Heavy
+    );
+    assert_eq!(
+        queue.classify("query { ...Lookup } fragment Lookup on Query { tables }"),
+        QueryClass::Interactive
+    );
+
+    // The heavy pool is full and borrows one idle interactive permit
+    let _heavy = queue.acquire(QueryClass::Heavy).await.unwrap();
+    let borrowed = queue.acquire(QueryClass::Heavy).await.unwrap();
+    assert_eq!(borrowed.pool(), QueryClass::Interactive);
+
+    // The last interactive permit is reserved for lookups
+    assert_eq!(
+        queue.acquire(QueryClass::Heavy).await.unwrap_err(),
+        QueueError::Timeout
+    );
+    assert!(queue.acquire(QueryClass::Interactive).await.is_ok());
+}
+
+#[test]
+fn test_config_rejects_invalid_custom_header() {
+    let mut config = Config::default();
+    config
+        .custom_headers
+        .insert("Cache-Control".to_string(), "no-store".to_string());
+    assert!(config.validate().is_ok());
+
+    config
+        .custom_headers
+        .insert("Bad Header".to_string(), "value".to_string());
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_dimension_cache_matches_sql_join() {
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let nation = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("n_nationkey", DataType::Int64, false),
+            Field::new("n_name", DataType::Utf8, false),
+            Field::new("n_regionkey", DataType::Int64, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![0, 1, 2])),
+            Arc::new(StringArray::from(vec!["ALGERIA", "ARGENTINA", "CHINA"])),
+            Arc::new(Int64Array::from(vec![0, 1, 2])),
+        ],
+    )
+    .unwrap();
+    let region = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("r_regionkey", DataType::Int64, false),
+            Field::new("r_name", DataType::Utf8, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![0, 1, 2])),
+            Arc::new(StringArray::from(vec!["AFRICA", "AMERICA", "ASIA"])),
+        ],
+    )
+    .unwrap();
+    let nation_path = write_parquet_fixture(nation).await;
+    let region_path = write_parquet_fixture(region).await;
+
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx
+        .register_parquet("nation", &nation_path)
+        .await
+        .unwrap();
+    df_ctx
+        .register_parquet("region", &region_path)
+        .await
+        .unwrap();
+    assert!(df_ctx.dimensions().is_empty());
+
+    df_ctx.cache_dimensions().await.unwrap();
+    // A reload re-reads the source files and swaps the cache
+    df_ctx.cache_dimensions().await.unwrap();
+    std::fs::remove_file(&nation_path).ok();
+    std::fs::remove_file(&region_path).ok();
+
+    let batches = df_ctx
+        .execute_query(
+            "SELECT n_nationkey, n_name, r_name FROM nation \
+             JOIN region ON n_regionkey = r_regionkey ORDER BY n_nationkey",
+        )
+        .await
+        .unwrap();
+    let dimensions = df_ctx.dimensions();
+    let mut rows = 0;
+    for batch in &batches {
+        let keys = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        let nations = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let regions = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        for i in 0..batch.num_rows() {
+            assert_eq!(
+                dimensions.nation_name(keys.value(i)),
+                Some(nations.value(i))
+            );
+            assert_eq!(
+                dimensions.region_of_nation(keys.value(i)),
+                Some(regions.value(i))
+            );
+            rows += 1;
+        }
+    }
+    assert_eq!(rows, 3);
+    assert_eq!(dimensions.nation_name(99), None);
+}
+
+#[tokio::test]
+async fn test_dry_run_register_table_does_not_register() {
+    use graphql_datafusion::auth::Claims;
+
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    let config = Config {
+        data_path: std::env::temp_dir().to_str().unwrap().to_string(),
+        ..Config::default()
+    };
+    let schema = build_schema(
+        df_ctx.clone(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(config),
+    );
+    let as_admin = |query: String| {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+    let register = |path: &str| {
+        format!(
+            r#"mutation {{
+                registerTable(tableName: "customer", path: "{}", dryRun: true) {{
+                    columns dryRun applied
+                }}
+            }}"#,
+            path
+        )
+    };
+
+    let response = schema.execute(as_admin(register(&path))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["registerTable"]["columns"],
+        json!(["c_custkey", "c_name"])
+    );
+    assert_eq!(data["registerTable"]["dryRun"], json!(true));
+    assert_eq!(data["registerTable"]["applied"], json!(false));
+    assert!(df_ctx.get_table_names().is_empty());
+
+    // Paths are read relative to the data directory, and still validated on a
+    // dry run
+    let file_name = std::path::Path::new(&path).file_name().unwrap();
+    let response = schema
+        .execute(as_admin(register(file_name.to_str().unwrap())))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let response = schema.execute(as_admin(register("missing.parquet"))).await;
+    assert!(!response.errors.is_empty());
+
+    // Only admins register tables, and only from the data directory
+    let response = schema.execute(register(&path)).await;
+    assert!(!response.errors.is_empty());
+    for outside in [
+        "/etc/hosts",
+        "../../etc/hosts",
+        "s3://bucket/customer.parquet",
+    ] {
+        let response = schema.execute(as_admin(register(outside))).await;
+        assert!(
+            response.errors[0]
+                .message
+                .contains("outside the data directory"),
+            "{}: {:?}",
+            outside,
+            response.errors
+        );
+    }
+    std::fs::remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn test_dry_run_refresh_connection_reports_the_refresh() {
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    let config = Config {
+        cache_dimension_tables: false,
+        ..Config::default()
+    };
+    let schema = build_schema(
+        df_ctx.clone(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(config),
+    );
+    let refresh = |dry_run: bool| {
+        format!(
+            "mutation {{ refreshConnection(dryRun: {}) {{ dimensionTables rebuildsRollup dryRun applied }} }}",
+            dry_run
+        )
+    };
+
+    // Without orders there is no rollup to build
+    let response = schema.execute(refresh(true)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["refreshConnection"],
+        json!({ "dimensionTables": [], "rebuildsRollup": false, "dryRun": true, "applied": false })
+    );
+
+    df_ctx
+        .register_batches("orders", vec![orders_batch(1.0)])
+        .unwrap();
+    let response = schema.execute(refresh(true)).await;
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["refreshConnection"]["rebuildsRollup"], json!(true));
+    assert!(!df_ctx.has_daily_revenue());
+
+    let response = schema.execute(refresh(false)).await;
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["refreshConnection"]["rebuildsRollup"], json!(true));
+    assert_eq!(data["refreshConnection"]["applied"], json!(true));
+    assert!(df_ctx.has_daily_revenue());
+}
+
+#[test]
+fn test_translator_quotes_reserved_and_mixed_case_identifiers() {
+    use graphql_datafusion::graphql::query_translator::{
+        QueryFilter, QueryParams, QuerySort, QueryTranslator, SqlDialect,
+    };
+
+    let params = QueryParams {
+        table: "orders".to_string(),
+        fields: Some(vec!["o_orderkey".to_string(), "order".to_string()]),
+        filters: Some(vec![QueryFilter {
+            field: "OrderStatus".to_string(),
+            operator: "=".to_string(),
+            value: "F".to_string(),
+        }]),
+        sort: Some(vec![QuerySort {
+            field: "order".to_string(),
+            order: "desc".to_string(),
+        }]),
+        limit: None,
+        offset: None,
+    };
+
+    let sql = QueryTranslator::new().translate(&params).unwrap();
+    assert_eq!(
+        sql,
+        r#"SELECT o_orderkey, "order" FROM orders WHERE "OrderStatus" = 'F' ORDER BY "order" DESC, o_orderkey"#
+    );
+
+    let sql = QueryTranslator::new()
+        .with_dialect(SqlDialect::MySql)
+        .translate(&params)
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT o_orderkey, `order` FROM orders WHERE `OrderStatus` = 'F' ORDER BY `order` DESC, o_orderkey"
+    );
+}
+
+#[tokio::test]
+async fn test_translator_projects_reserved_word_column() {
+    use datafusion::arrow::array::{Float64Array, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::graphql::query_translator::{
+        QueryFilter, QueryParams, QuerySort, QueryTranslator,
+    };
+
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("order", DataType::Int64, false),
+            Field::new("Total", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2, 3])),
+            Arc::new(Int64Array::from(vec![30, 10, 20])),
+            Arc::new(Float64Array::from(vec![1.5, 2.5, 3.5])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("events", vec![batch]).unwrap();
+    let mut translator = QueryTranslator::new();
+    translator.register_schema("events", df_ctx.table_schema("events").await.unwrap());
+
+    let params = |field: &str| QueryParams {
+        table: "events".to_string(),
+        fields: Some(vec!["order".to_string(), field.to_string()]),
+        filters: Some(vec![QueryFilter {
+            field: "order".to_string(),
+            operator: "!=".to_string(),
+            value: "20".to_string(),
+        }]),
+        sort: Some(vec![QuerySort {
+            field: "order".to_string(),
+            order: "desc".to_string(),
+        }]),
+        limit: None,
+        offset: None,
+    };
+    let sql = translator.translate(&params("Total")).unwrap();
+    let batches = df_ctx.execute_query(&sql).await.unwrap();
+    let table = datafusion::arrow::util::pretty::pretty_format_batches(&batches)
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        table.lines().collect::<Vec<_>>(),
+        vec![
+            "+-------+-------+",
+            "| order | Total |",
+            "+-------+-------+",
+            "| 30    | 1.5   |",
+            "| 10    | 2.5   |",
+            "+-------+-------+",
+        ]
+    );
+
+    // Quoted names are matched exactly against the schema
+    let err = translator.translate(&params("total")).unwrap_err();
+    assert!(
+        err.message.contains("did you mean Total"),
+        "{}",
+        err.message
+    );
+    assert!(translator.translate(&params("missing")).is_err());
+}
+
+#[tokio::test]
+async fn test_decimal_money_columns_read_as_float() {
+    use datafusion::arrow::array::{Decimal128Array, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let balances = Decimal128Array::from(vec![123456, -99999])
+        .with_precision_and_scale(15, 2)
+        .unwrap();
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("c_custkey", DataType::Int64, false),
+            Field::new("c_acctbal", DataType::Decimal128(15, 2), false),
+        ])),
+        vec![Arc::new(Int64Array::from(vec![1, 2])), Arc::new(balances)],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("customer", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute("{ customers { c_custkey c_acctbal } }")
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"],
+        json!([
+            { "c_custkey": 1, "c_acctbal": 1234.56 },
+            { "c_custkey": 2, "c_acctbal": -999.99 },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_customers_projects_selected_columns() {
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    df_ctx.register_parquet("customer", &path).await.unwrap();
+    let schema = default_schema(df_ctx.clone());
+
+    let response = schema.execute("{ customers { c_name } }").await;
+    std::fs::remove_file(&path).ok();
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["customers"],
+        json!([{ "c_name": "Customer#1" }, { "c_name": "Customer#2" }])
+    );
+
+    let executed = df_ctx.recent_queries().pop().unwrap().sql;
+    assert!(executed.contains("c_custkey, c_name"), "{}", executed);
+    for column in ["c_address", "c_phone", "c_acctbal", "c_comment"] {
+        assert!(!executed.contains(column), "{}", executed);
+    }
+}
+
+/// Orders on 2024-01-05 (two) and 2024-02-10, scaled by `factor`
+fn orders_batch(factor: f64) -> datafusion::arrow::record_batch::RecordBatch {
+    use datafusion::arrow::array::{Date32Array, Float64Array, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("o_orderkey", DataType::Int64, false),
+            Field::new("o_orderdate", DataType::Date32, false),
+            Field::new("o_totalprice", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2, 3])),
+            Arc::new(Date32Array::from(vec![19727, 19727, 19763])),
+            Arc::new(Float64Array::from(vec![
+                10.0 * factor,
+                5.0 * factor,
+                7.5 * factor,
+            ])),
+        ],
+    )
+    .unwrap()
+}
+
+/// Schema over the given orders and an empty customer table with all columns
+fn analytics_schema(orders: datafusion::arrow::record_batch::RecordBatch) -> AppSchema {
+    default_schema(analytics_context(orders))
+}
+
+fn analytics_context(
+    orders: datafusion::arrow::record_batch::RecordBatch,
+) -> Arc<DataFusionContext> {
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let customer = Schema::new(vec![
+        Field::new("c_custkey", DataType::Int64, false),
+        Field::new("c_name", DataType::Utf8View, false),
+        Field::new("c_address", DataType::Utf8View, false),
+        Field::new("c_nationkey", DataType::Int64, false),
+        Field::new("c_phone", DataType::Utf8View, false),
+        Field::new("c_acctbal", DataType::Float64, false),
+        Field::new("c_mktsegment", DataType::Utf8View, false),
+        Field::new("c_comment", DataType::Utf8View, false),
+    ]);
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx
+        .register_batches("customer", vec![RecordBatch::new_empty(Arc::new(customer))])
+        .unwrap();
+    df_ctx.register_batches("orders", vec![orders]).unwrap();
+    Arc::new(df_ctx)
+}
+
+#[tokio::test]
+async fn test_order_value_distribution() {
+    use datafusion::arrow::array::{Date32Array, Decimal128Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Decimal prices as in TPCH: 100.00, 200.00, 300.00, 400.00
+    let orders_schema = Arc::new(Schema::new(vec![
+        Field::new("o_orderdate", DataType::Date32, false),
+        Field::new("o_totalprice", DataType::Decimal128(15, 2), false),
+    ]));
+    let prices = Decimal128Array::from(vec![10000, 20000, 30000, 40000])
+        .with_precision_and_scale(15, 2)
+        .unwrap();
+    let orders = RecordBatch::try_new(
+        orders_schema.clone(),
+        vec![
+            Arc::new(Date32Array::from(vec![19727; 4])),
+            Arc::new(prices),
+        ],
+    )
+    .unwrap();
+
+    let query = "{ salesAnalytics(histogramBuckets: 3) {
+        orderValueDistribution { p50 p90 p99 histogram { bucketStart bucketEnd count } }
+    } }";
+    let response = analytics_schema(orders).execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let distribution = &data["salesAnalytics"]["orderValueDistribution"];
+    let p50 = distribution["p50"].as_f64().unwrap();
+    let p90 = distribution["p90"].as_f64().unwrap();
+    let p99 = distribution["p99"].as_f64().unwrap();
+    assert!((100.0..=400.0).contains(&p50), "{}", p50);
+    assert!(p50 <= p90 && p90 <= p99 && p99 <= 400.0);
+    assert_eq!(
+        distribution["histogram"],
+        json!([
+            { "bucketStart": 100.0, "bucketEnd": 200.0, "count": 1 },
+            { "bucketStart": 200.0, "bucketEnd": 300.0, "count": 1 },
+            { "bucketStart": 300.0, "bucketEnd": 400.0, "count": 2 },
+        ])
+    );
+
+    // No orders: no percentiles and no buckets
+    let response = analytics_schema(RecordBatch::new_empty(orders_schema))
+        .execute(query)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["salesAnalytics"]["orderValueDistribution"],
+        json!({ "p50": null, "p90": null, "p99": null, "histogram": [] })
+    );
+}
+
+#[tokio::test]
+async fn test_top_customers_rank_by() {
+    use datafusion::arrow::array::{Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let text = |values: [&str; 4]| Arc::new(StringArray::from(values.to_vec()));
+    let customer = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("c_custkey", DataType::Int64, false),
+            Field::new("c_name", DataType::Utf8, false),
+            Field::new("c_address", DataType::Utf8, false),
+            Field::new("c_nationkey", DataType::Int64, false),
+            Field::new("c_phone", DataType::Utf8, false),
+            Field::new("c_acctbal", DataType::Float64, false),
+            Field::new("c_mktsegment", DataType::Utf8, false),
+            Field::new("c_comment", DataType::Utf8, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2, 3, 4])),
+            text(["Customer#1", "Customer#2", "Customer#3", "Customer#4"]),
+            text(["a", "b", "c", "d"]),
+            Arc::new(Int64Array::from(vec![0, 1, 2, 3])),
+            text(["1", "2", "3", "4"]),
+            // Account balances rank the customers the other way round
+            Arc::new(Float64Array::from(vec![9000.0, 10.0, 20.0, 30.0])),
+            text(["BUILDING", "MACHINERY", "BUILDING", "HOUSEHOLD"]),
+            text(["", "", "", ""]),
+        ],
+    )
+    .unwrap();
+    // Customer 1: 3 orders of 100; 2: one of 500; 3: two of 200; 4: two of 150
+    let orders = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("o_orderkey", DataType::Int64, false),
+            Field::new("o_custkey", DataType::Int64, false),
+            Field::new("o_totalprice", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5, 6, 7, 8])),
+            Arc::new(Int64Array::from(vec![1, 1, 1, 2, 3, 3, 4, 4])),
+            Arc::new(Float64Array::from(vec![
+                100.0, 100.0, 100.0, 500.0, 200.0, 200.0, 150.0, 150.0,
+            ])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("customer", vec![customer]).unwrap();
+    df_ctx.register_batches("orders", vec![orders]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let ranking = |arguments: &str| {
+        let schema = schema.clone();
+        let query = format!(
+            "{{ salesAnalytics({}) {{ topCustomers {{ customer {{ c_custkey }} rankedBy rankValue }} }} }}",
+            arguments
+        );
+        async move {
+            let response = schema.execute(query).await;
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+            let data = response.data.into_json().unwrap();
+            data["salesAnalytics"]["topCustomers"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| {
+                    (
+                        entry["customer"]["c_custkey"].as_i64().unwrap(),
+                        entry["rankValue"].as_f64().unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // Customers 1 and 4 tie on 300 spent and are ordered by key
+    assert_eq!(
+        ranking("topN: 4").await,
+        vec![(2, 500.0), (3, 400.0), (1, 300.0), (4, 300.0)]
+    );
+    assert_eq!(
+        ranking("topN: 3, rankBy: ORDER_COUNT").await,
+        vec![(1, 3.0), (3, 2.0), (4, 2.0)]
+    );
+    assert_eq!(
+        ranking("topN: 2, rankBy: AVG_ORDER_VALUE").await,
+        vec![(2, 500.0), (3, 200.0)]
+    );
+
+    let response = schema
+        .execute(
+            "{ salesAnalytics { topCustomers { rankedBy customer { c_name c_mktsegment } } } }",
+        )
+        .await;
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["salesAnalytics"]["topCustomers"][0],
+        json!({ "rankedBy": "TOTAL_SPENT", "customer": { "c_name": "Customer#2", "c_mktsegment": "MACHINERY" } })
+    );
+
+    for invalid in ["topN: 0", "topN: 101"] {
+        let response = schema
+            .execute(format!(
+                "{{ salesAnalytics({}) {{ topCustomers {{ rankValue }} }} }}",
+                invalid
+            ))
+            .await;
+        assert!(!response.errors.is_empty(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn test_failed_analytics_section_as_data() {
+    // Orders without o_custkey: the top customers tile cannot be computed
+    let schema = analytics_schema(orders_batch(1.0));
+
+    let query = "{ salesAnalytics { monthlyTrends { month } topCustomers { totalSpent } } }";
+    let response = schema.execute(query).await;
+    assert!(!response.errors.is_empty());
+    assert_eq!(response.data.into_json().unwrap(), json!(null));
+
+    let query = "{ salesAnalytics(errorsAsData: true) {
+        monthlyTrends { month }
+        topCustomers { totalSpent }
+        errors { section message }
+    } }";
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let analytics = &data["salesAnalytics"];
+    assert_eq!(
+        analytics["monthlyTrends"],
+        json!([{ "month": "2024-01" }, { "month": "2024-02" }])
+    );
+    assert_eq!(analytics["topCustomers"], json!([]));
+    let errors = analytics["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["section"], json!("topCustomers"));
+    assert!(
+        errors[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Customers query failed")
+    );
+}
+
+#[tokio::test]
+async fn test_skipped_analytics_sections_are_not_queried() {
+    use datafusion::arrow::array::{Date32Array, Float64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let orders = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("o_orderdate", DataType::Date32, false),
+            Field::new("o_totalprice", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Date32Array::from(vec![19727, 19758])),
+            Arc::new(Float64Array::from(vec![100.0, 200.0])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = analytics_context(orders);
+    let schema = default_schema(df_ctx.clone());
+    let executed = |df_ctx: &DataFusionContext| -> Vec<String> {
+        df_ctx
+            .recent_queries()
+            .into_iter()
+            .map(|entry| entry.sql)
+            .collect()
+    };
+
+    let query = "query($wantTrends: Boolean!) { salesAnalytics {
+        totalSales
+        topCustomers @skip(if: true) { totalSpent }
+        orderValueDistribution @skip(if: true) { p50 }
+        monthlyTrends @include(if: $wantTrends) { month orderCount }
+    } }";
+    let request = async_graphql::Request::new(query).variables(
+        async_graphql::Variables::from_json(json!({ "wantTrends": true })),
+    );
+    let response = schema.execute(request).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["salesAnalytics"]["monthlyTrends"]
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(
+        data["salesAnalytics"]
+            .get("orderValueDistribution")
+            .is_none()
+    );
+    // The totals and the trends
+    let sql = executed(&df_ctx);
+    assert_eq!(sql.len(), 2, "{:?}", sql);
+    assert!(
+        sql.iter().any(|sql| sql.contains("date_trunc('month'")),
+        "{:?}",
+        sql
+    );
+
+    // Excluding every queried section leaves only the totals
+    let request = async_graphql::Request::new(query).variables(
+        async_graphql::Variables::from_json(json!({ "wantTrends": false })),
+    );
+    let response = schema.execute(request).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let sql = executed(&df_ctx);
+    assert_eq!(sql.len(), 3, "{:?}", sql);
+    assert!(
+        sql.iter()
+            .all(|sql| !sql.contains("approx_percentile_cont")),
+        "{:?}",
+        sql
+    );
+}
+
+#[tokio::test]
+async fn test_daily_revenue_rollup_serves_order_trends() {
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    df_ctx
+        .register_batches("orders", vec![orders_batch(1.0)])
+        .unwrap();
+    df_ctx.refresh_rollups().await.unwrap();
+    assert!(df_ctx.has_daily_revenue());
+    assert!(graphql_datafusion::metrics::render().contains("rollup_build_seconds"));
+
+    let batches = df_ctx
+        .execute_query("SELECT order_count FROM daily_revenue ORDER BY order_date")
+        .await
+        .unwrap();
+    let counts = datafusion::arrow::util::pretty::pretty_format_batches(&batches)
+        .unwrap()
+        .to_string();
+    assert!(
+        counts.contains("| 2 ") && counts.contains("| 1 "),
+        "{}",
+        counts
+    );
+
+    // Newer orders are not seen until the rollup is rebuilt
+    df_ctx
+        .register_batches("orders", vec![orders_batch(2.0)])
+        .unwrap();
+    let schema = default_schema(df_ctx.clone());
+    let query = r#"{ timeSeries(table: "orders", timeColumn: "o_orderdate",
+                     valueColumn: "o_totalprice") { period value } }"#;
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["timeSeries"],
+        json!([
+            { "period": "2024-01-01", "value": 15.0 },
+            { "period": "2024-02-01", "value": 7.5 },
+        ])
+    );
+
+    // Without the rollup the orders are aggregated directly
+    df_ctx.drop_table("daily_revenue").unwrap();
+    let response = schema.execute(query).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["timeSeries"][0]["value"],
+        json!(30.0)
+    );
+
+    // A failed build leaves no rollup behind
+    let empty = DataFusionContext::in_memory();
+    assert!(empty.refresh_rollups().await.is_err());
+    assert!(!empty.has_daily_revenue());
+}
+
+#[tokio::test]
+async fn test_customers_pagination_extension() {
+    let schema = test_schema(Config::default());
+
+    let response = schema
+        .execute("{ customers(limit: 1) { c_custkey } }")
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let extensions = serde_json::to_value(&response.extensions).unwrap();
+    assert_eq!(
+        extensions["pagination"],
+        json!({ "limit": 1, "offset": 0, "returned": 1, "hasMore": true })
+    );
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["customers"], json!([{ "c_custkey": 1 }]));
+
+    // The last page has no extra row to fetch
+    let response = schema
+        .execute("{ customers(limit: 1, offset: 1) { c_custkey } }")
+        .await;
+    let extensions = serde_json::to_value(&response.extensions).unwrap();
+    assert_eq!(
+        extensions["pagination"],
+        json!({ "limit": 1, "offset": 1, "returned": 1, "hasMore": false })
+    );
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["customers"], json!([{ "c_custkey": 2 }]));
+
+    let response = schema
+        .execute("{ customers(limit: 2) { c_custkey } }")
+        .await;
+    let extensions = serde_json::to_value(&response.extensions).unwrap();
+    assert_eq!(extensions["pagination"]["returned"], json!(2));
+    assert_eq!(extensions["pagination"]["hasMore"], json!(false));
+}
+
+#[tokio::test]
+async fn test_customers_from_plain_utf8_parquet() {
+    use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::compute::cast;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::datafusion::context::TableDef;
+
+    // Standard Utf8 columns, as older DataFusion versions and most tools write them
+    let text = |value: &str| -> ArrayRef { Arc::new(StringArray::from(vec![value])) };
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        ("c_custkey", Arc::new(Int64Array::from(vec![7]))),
+        ("c_name", text("Customer#7")),
+        ("c_address", text("Main Street")),
+        ("c_nationkey", Arc::new(Int64Array::from(vec![3]))),
+        ("c_phone", text("13-555-0100")),
+        ("c_acctbal", Arc::new(Float64Array::from(vec![12.5]))),
+        ("c_mktsegment", text("MACHINERY")),
+        ("c_comment", text("regular")),
+    ];
+    let batch = |string_type: &DataType| {
+        let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = columns
+            .iter()
+            .map(|(name, array)| {
+                let array = match array.data_type() {
+                    DataType::Utf8 => cast(array, string_type).unwrap(),
+                    _ => array.clone(),
+                };
+                (Field::new(*name, array.data_type().clone(), false), array)
+            })
+            .unzip();
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).unwrap()
+    };
+    let expected = json!([{
+        "c_custkey": 7,
+        "c_name": "Customer#7",
+        "c_phone": "13-555-0100",
+        "c_mktsegment": "MACHINERY",
+        "c_comment": "regular"
+    }]);
+    let query = "{ customers(limit: 1) { c_custkey c_name c_phone c_mktsegment c_comment } }";
+
+    let path = write_parquet_fixture(batch(&DataType::Utf8)).await;
+    let parquet_ctx = DataFusionContext::with_tables("", &[TableDef::from_path("customer", &path)])
+        .await
+        .unwrap();
+
+    let large_ctx = DataFusionContext::in_memory();
+    large_ctx
+        .register_batches("customer", vec![batch(&DataType::LargeUtf8)])
+        .unwrap();
+    let view_ctx = DataFusionContext::in_memory();
+    view_ctx
+        .register_batches("customer", vec![batch(&DataType::Utf8View)])
+        .unwrap();
+
+    for df_ctx in [parquet_ctx, large_ctx, view_ctx] {
+        let schema = default_schema(Arc::new(df_ctx));
+        let response = schema.execute(query).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(response.data.into_json().unwrap()["customers"], expected);
+    }
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[tokio::test]
+async fn test_customers_with_null_columns() {
+    use datafusion::arrow::array::{Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("c_custkey", DataType::Int64, false),
+            Field::new("c_name", DataType::Utf8, true),
+            Field::new("c_address", DataType::Utf8, true),
+            Field::new("c_acctbal", DataType::Float64, true),
+            Field::new("c_mktsegment", DataType::Utf8, true),
+            Field::new("c_comment", DataType::Utf8, true),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2])),
+            Arc::new(StringArray::from(vec![Some("Customer#1"), None])),
+            Arc::new(StringArray::from(vec![Some("Main Street"), None])),
+            Arc::new(Float64Array::from(vec![Some(10.5), None])),
+            Arc::new(StringArray::from(vec![Some("BUILDING"), None])),
+            Arc::new(StringArray::from(vec![None, Some("regular")])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("customer", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(
+            "{ customers { c_custkey c_name c_address c_acctbal c_mktsegment c_mktsegment_raw c_comment } }",
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"],
+        json!([
+            {
+                "c_custkey": 1,
+                "c_name": "Customer#1",
+                "c_address": "Main Street",
+                "c_acctbal": 10.5,
+                "c_mktsegment": "BUILDING",
+                "c_mktsegment_raw": null,
+                "c_comment": null
+            },
+            {
+                "c_custkey": 2,
+                "c_name": "",
+                "c_address": null,
+                "c_acctbal": 0.0,
+                "c_mktsegment": "UNKNOWN",
+                "c_mktsegment_raw": null,
+                "c_comment": "regular"
+            }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_analysis_filters_scope_a_session() {
+    use graphql_datafusion::auth::Claims;
+
+    let schema = test_schema(Config::default());
+    let as_user = |user: &str, query: &str| {
+        async_graphql::Request::new(query).data(Claims::new(user.to_string(), "user".to_string()))
+    };
+    let customers = "{ customers { c_custkey } }";
+
+    let response = schema
+        .execute(as_user(
+            "alice",
+            r#"mutation { setAnalysisFilters(filters: [{ column: "c_custkey", operator: NE, value: "1" }]) { column operator value } }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["setAnalysisFilters"],
+        json!([{ "column": "c_custkey", "operator": "NE", "value": "1" }])
+    );
+
+    // Only the session that set the filters sees fewer rows
+    let response = schema.execute(as_user("alice", customers)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert!(!response.cache_control.public);
+    let extensions = serde_json::to_value(&response.extensions).unwrap();
+    assert_eq!(
+        extensions["analysisFilters"][0]["column"],
+        json!("c_custkey")
+    );
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"],
+        json!([{ "c_custkey": 2 }])
+    );
+    let response = schema.execute(as_user("bob", customers)).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"],
+        json!([{ "c_custkey": 1 }, { "c_custkey": 2 }])
+    );
+
+    // Unknown columns, values of the wrong type and anonymous callers are refused
+    for filter in [
+        r#"{ column: "c_missing", operator: EQ, value: "1" }"#,
+        r#"{ column: "c_custkey", operator: GT, value: "one" }"#,
+        r#"{ column: "c_name", operator: GT, value: "x" }"#,
+    ] {
+        let query = format!(
+            "mutation {{ setAnalysisFilters(filters: [{}]) {{ column }} }}",
+            filter
+        );
+        let response = schema.execute(as_user("alice", &query)).await;
+        assert!(!response.errors.is_empty(), "{} was accepted", filter);
+    }
+    let response = schema
+        .execute("mutation { setAnalysisFilters(filters: []) { column } }")
+        .await;
+    assert!(!response.errors.is_empty());
+
+    // Clearing the filters restores the full data
+    let response = schema
+        .execute(as_user(
+            "alice",
+            "mutation { setAnalysisFilters(filters: []) { column } }",
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let response = schema.execute(as_user("alice", customers)).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"],
+        json!([{ "c_custkey": 1 }, { "c_custkey": 2 }])
+    );
+}
+
+/// Output columns of a statement with their sources as `table.column`
+async fn lineage_of(df_ctx: &DataFusionContext, sql: &str) -> Vec<(String, Vec<String>)> {
+    df_ctx
+        .column_lineage(sql)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|column| {
+            let sources = column
+                .sources
+                .iter()
+                .map(|source| format!("{}.{}", source.table, source.column))
+                .collect();
+            (column.output, sources)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_column_lineage_of_tpch_queries() {
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let df_ctx = DataFusionContext::in_memory();
+    let tables: [(&str, &[(&str, DataType)]); 4] = [
+        (
+            "customer",
+            &[
+                ("c_custkey", DataType::Int64),
+                ("c_name", DataType::Utf8),
+                ("c_nationkey", DataType::Int64),
+            ],
+        ),
+        (
+            "orders",
+            &[
+                ("o_orderkey", DataType::Int64),
+                ("o_custkey", DataType::Int64),
+                ("o_totalprice", DataType::Float64),
+            ],
+        ),
+        (
+            "lineitem",
+            &[
+                ("l_orderkey", DataType::Int64),
+                ("l_extendedprice", DataType::Float64),
+                ("l_discount", DataType::Float64),
+            ],
+        ),
+        (
+            "nation",
+            &[("n_nationkey", DataType::Int64), ("n_name", DataType::Utf8)],
+        ),
+    ];
+    for (name, columns) in tables {
+        let fields: Vec<Field> = columns
+            .iter()
+            .map(|(column, data_type)| Field::new(*column, data_type.clone(), true))
+            .collect();
+        let batch = RecordBatch::new_empty(Arc::new(Schema::new(fields)));
+        df_ctx.register_batches(name, vec![batch]).unwrap();
+    }
+    let lineage = |output: &str, sources: &[&str]| {
+        (
+            output.to_string(),
+            sources
+                .iter()
+                .map(|source| source.to_string())
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    // Aliases keep their source, aggregates over a join take their argument's
+    let sql = "SELECT c_name AS customer, SUM(o_totalprice) AS total \
+               FROM customer JOIN orders ON c_custkey = o_custkey \
+               WHERE o_totalprice > 10 GROUP BY c_name ORDER BY total DESC";
+    assert_eq!(
+        lineage_of(&df_ctx, sql).await,
+        vec![
+            lineage("customer", &["customer.c_name"]),
+            lineage("total", &["orders.o_totalprice"]),
+        ]
+    );
+
+    // An expression combines the sources of all its columns
+    let sql = "SELECT l_orderkey, SUM(l_extendedprice * (1 - l_discount)) AS revenue \
+               FROM lineitem GROUP BY l_orderkey";
+    assert_eq!(
+        lineage_of(&df_ctx, sql).await,
+        vec![
+            lineage("l_orderkey", &["lineitem.l_orderkey"]),
+            lineage(
+                "revenue",
+                &["lineitem.l_discount", "lineitem.l_extendedprice"]
+            ),
+        ]
+    );
+
+    // COUNT(*) reads no column; joined keys are not sources
+    let sql = "SELECT n.n_name, COUNT(*) AS customers \
+               FROM customer c JOIN nation n ON c.c_nationkey = n.n_nationkey GROUP BY n.n_name";
+    assert_eq!(
+        lineage_of(&df_ctx, sql).await,
+        vec![
+            lineage("n_name", &["nation.n_name"]),
+            lineage("customers", &[]),
+        ]
+    );
+
+    // Subquery aliases and unions map their columns by position
+    let sql = "SELECT t.k FROM (SELECT c_custkey AS k FROM customer \
+               UNION ALL SELECT o_custkey FROM orders) t";
+    assert_eq!(
+        lineage_of(&df_ctx, sql).await,
+        vec![lineage("k", &["customer.c_custkey", "orders.o_custkey"])]
+    );
+
+    // Scalar subqueries add the sources of their result
+    let sql = "SELECT c_name, (SELECT MAX(o_totalprice) FROM orders) - 1 AS gap FROM customer";
+    assert_eq!(
+        lineage_of(&df_ctx, sql).await,
+        vec![
+            lineage("c_name", &["customer.c_name"]),
+            lineage("gap", &["orders.o_totalprice"]),
+        ]
+    );
+
+    // Wildcards list every column of the table
+    let sql = "SELECT * FROM nation";
+    assert_eq!(
+        lineage_of(&df_ctx, sql).await,
+        vec![
+            lineage("n_nationkey", &["nation.n_nationkey"]),
+            lineage("n_name", &["nation.n_name"]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_query_lineage_of_executed_sql() {
+    use graphql_datafusion::auth::Claims;
+
+    let df_ctx = customer_fixture();
+    let schema = default_schema(df_ctx.clone());
+    let response = schema
+        .execute(
+            "{ executeSql(query: \"SELECT upper(c_name) AS name, c_custkey * 2 + length(c_name) AS score FROM customer\") { rowCount } }",
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let extensions = serde_json::to_value(&response.extensions).unwrap();
+    let query_id = extensions["executeSql"]["queryId"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // The lineage is kept with the query log entry
+    let entry = df_ctx.logged_query(&query_id).unwrap();
+    assert_eq!(entry.lineage.len(), 2);
+
+    let query = format!(
+        "{{ queryLineage(queryId: \"{}\") {{ queryId sql columns {{ output sources {{ table column }} }} }} }}",
+        query_id
+    );
+    let response = schema
+        .execute(
+            async_graphql::Request::new(query.clone())
+                .data(Claims::new("ops".to_string(), "admin".to_string())),
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["queryLineage"]["queryId"], json!(query_id));
+    assert_eq!(
+        data["queryLineage"]["columns"],
+        json!([
+            { "output": "name", "sources": [{ "table": "customer", "column": "c_name" }] },
+            {
+                "output": "score",
+                "sources": [
+                    { "table": "customer", "column": "c_custkey" },
+                    { "table": "customer", "column": "c_name" },
+                ],
+            },
+        ])
+    );
+
+    // Unknown ids fail, and only admins may read lineage
+    let response = schema
+        .execute(
+            async_graphql::Request::new("{ queryLineage(queryId: \"missing\") { sql } }")
+                .data(Claims::new("ops".to_string(), "admin".to_string())),
+        )
+        .await;
+    assert!(response.errors[0].message.contains("Unknown query"));
+    assert!(!schema.execute(query).await.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_list_and_close_subscriptions() {
+    use graphql_datafusion::auth::Claims;
+    use graphql_datafusion::models::data::SubscriptionKind;
+    use graphql_datafusion::subscriptions::SubscriptionRegistry;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let schema = default_schema(customer_fixture());
+    let admin = |query: String| {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+
+    // Opened the way a `/graphql/ws` connection opens it
+    let subscriptions = schema.data::<Arc<SubscriptionRegistry>>().unwrap();
+    let closed = Arc::new(AtomicBool::new(false));
+    let id = {
+        let closed = closed.clone();
+        subscriptions.register(
+            SubscriptionKind::Graphql,
+            Some("alice".to_string()),
+            move || closed.store(true, Ordering::SeqCst),
+        )
+    };
+    subscriptions.set_query(&id, "{ customers { name } }");
+
+    let list = "{ activeSubscriptions { id kind query user startedAt } }";
+    let response = schema.execute(admin(list.to_string())).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let listed = &data["activeSubscriptions"][0];
+    assert_eq!(listed["id"], json!(id));
+    assert_eq!(listed["kind"], json!("GRAPHQL"));
+    assert_eq!(listed["query"], json!("{ customers { name } }"));
+    assert_eq!(listed["user"], json!("alice"));
+
+    // Only admins may list or close subscriptions
+    let close = format!("mutation {{ closeSubscription(id: \"{}\") }}", id);
+    assert!(!schema.execute(list).await.errors.is_empty());
+    assert!(!schema.execute(close.clone()).await.errors.is_empty());
+    assert!(!closed.load(Ordering::SeqCst));
+
+    let response = schema.execute(admin(close.clone())).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["closeSubscription"],
+        json!(true)
+    );
+    assert!(closed.load(Ordering::SeqCst));
+    let response = schema.execute(admin(list.to_string())).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["activeSubscriptions"],
+        json!([])
+    );
+    let response = schema.execute(admin(close)).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["closeSubscription"],
+        json!(false)
+    );
+}
+
+#[tokio::test]
+async fn test_slow_query_appears_in_slow_queries() {
+    use graphql_datafusion::auth::Claims;
+    use std::time::Duration;
+
+    let df_ctx =
+        Arc::new(DataFusionContext::in_memory().with_slow_query_log(Duration::from_millis(5), 10));
+    let slow_sql = "SELECT SUM(value) AS total FROM generate_series(1, 5000000)";
+    df_ctx.execute_query(slow_sql).await.unwrap();
+
+    let schema = default_schema(df_ctx);
+    let query = "{ slowQueries { sql durationMs rowCount success } }";
+
+    let response = schema
+        .execute(
+            async_graphql::Request::new(query)
+                .data(Claims::new("ops".to_string(), "admin".to_string())),
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let slow = data["slowQueries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["sql"] == json!(slow_sql))
+        .expect("slow query listed");
+    assert!(slow["durationMs"].as_f64().unwrap() >= 5.0);
+    assert_eq!(slow["rowCount"], json!(1));
+
+    // Only admins may read the list
+    let response = schema
+        .execute(
+            async_graphql::Request::new(query)
+                .data(Claims::new("viewer".to_string(), "viewer".to_string())),
+        )
+        .await;
+    assert!(!response.errors.is_empty());
+    assert!(!schema.execute(query).await.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_reload_replans_dependent_views() {
+    use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_parquet("customer", &path).await.unwrap();
+    df_ctx
+        .register_view("customer_rows", "SELECT * FROM customer")
+        .await
+        .unwrap();
+    df_ctx
+        .register_view("customer_copy", "SELECT * FROM customer_rows")
+        .await
+        .unwrap();
+
+    // The rewritten file gains a column, which only a replanned view exposes
+    let wider = RecordBatch::try_from_iter(vec![
+        ("c_custkey", Arc::new(Int64Array::from(vec![1])) as ArrayRef),
+        ("c_name", Arc::new(StringArray::from(vec!["Customer#1"]))),
+        ("c_phone", Arc::new(StringArray::from(vec!["25-989-741"]))),
+    ])
+    .unwrap();
+    let rewritten = write_parquet_fixture(wider).await;
+    std::fs::rename(rewritten, &path).unwrap();
+    df_ctx.reload_table("customer").await.unwrap();
+
+    for view in ["customer_rows", "customer_copy"] {
+        let batches = df_ctx
+            .execute_query(&format!("SELECT c_phone FROM {}", view))
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+    }
+}
+
+#[tokio::test]
+async fn test_reload_and_drop_single_table() {
+    use graphql_datafusion::auth::Claims;
+
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    df_ctx.register_parquet("customer", &path).await.unwrap();
+    df_ctx
+        .register_view("named_customers", "SELECT c_name FROM customer")
+        .await
+        .unwrap();
+    let schema = default_schema(df_ctx.clone());
+    let admin = |query: &str| {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+
+    // Reloading picks up the rewritten file
+    let smaller = write_parquet_fixture(customer_batch().slice(0, 1)).await;
+    std::fs::copy(&smaller, &path).unwrap();
+    std::fs::remove_file(&smaller).ok();
+    let response = schema
+        .execute(admin(r#"mutation { reloadTable(tableName: "customer") }"#))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(df_ctx.get_table_count("customer").await.unwrap(), 1);
+
+    // Views keep the table from being dropped
+    let response = schema
+        .execute(admin(r#"mutation { dropTable(tableName: "customer") }"#))
+        .await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("DEPENDENCY_ERROR"));
+    assert_eq!(error["extensions"]["views"], json!(["named_customers"]));
+
+    for table in ["named_customers", "customer"] {
+        let response = schema
+            .execute(admin(&format!(
+                r#"mutation {{ dropTable(tableName: "{}") }}"#,
+                table
+            )))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+    }
+    std::fs::remove_file(&path).ok();
+    assert!(df_ctx.get_table_names().is_empty());
+    assert!(df_ctx.table_files("customer").is_none());
+
+    // Only admins may change registrations
+    let response = schema
+        .execute(r#"mutation { reloadTable(tableName: "customer") }"#)
+        .await;
+    assert!(!response.errors.is_empty());
+}
+
+#[test]
+fn test_jwt_leeway_tolerates_clock_skew() {
+    use graphql_datafusion::auth::{AuthGuard, Claims};
+
+    let now = chrono::Utc::now().timestamp() as usize;
+    let issuer = AuthGuard::new("test-secret");
+    let token_expiring_at = |exp: usize| {
+        let mut claims = Claims::new("alice".to_string(), "viewer".to_string());
+        claims.exp = exp;
+        issuer.issue_token(&claims).unwrap()
+    };
+    let recently_expired = token_expiring_at(now - 10);
+    let long_expired = token_expiring_at(now - 3600);
+
+    let guard = AuthGuard::new("test-secret").with_leeway(30);
+    assert_eq!(guard.verify_token(&recently_expired).unwrap().sub, "alice");
+    assert!(guard.verify_token(&long_expired).is_err());
+
+    let strict = AuthGuard::new("test-secret").with_leeway(0);
+    assert!(strict.verify_token(&recently_expired).is_err());
+}
+
+#[tokio::test]
+async fn test_ai_fields_disabled_without_ai() {
+    let config = Config {
+        enable_ai: false,
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+    let schema = test_schema(config);
+
+    for query in [
+        r#"{ naturalLanguageQuery(input: "list customers") }"#,
+        r#"{ insights(input: "summarize") }"#,
+        "{ agentStatus }",
+    ] {
+        let response = schema.execute(query).await;
+        assert_eq!(response.errors.len(), 1, "{}", query);
+        let code = response.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code"))
+            .cloned();
+        assert_eq!(code, Some(async_graphql::Value::from("FEATURE_DISABLED")));
+    }
+
+    // Non-AI fields keep working
+    let response = schema.execute("{ tables }").await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+}
+
+#[tokio::test]
+async fn test_ai_fields_enabled_by_default() {
+    let schema = test_schema(Config::default());
+
+    let response = schema.execute("{ agentStatus }").await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert!(data["agentStatus"].as_str().unwrap().contains("default"));
+}
+
+#[tokio::test]
+async fn test_query_over_memory_limit_is_rejected() {
+    use graphql_datafusion::datafusion::memory::memory_limit_exceeded;
+
+    let df_ctx = DataFusionContext::in_memory().with_query_memory_limit(1024 * 1024);
+
+    // Sorting a million rows needs several megabytes
+    let err = df_ctx
+        .execute_query("SELECT value FROM generate_series(1, 1000000) ORDER BY value DESC")
+        .await
+        .unwrap_err();
+    let exceeded = memory_limit_exceeded(&err).expect("memory limit error");
+    assert_eq!(exceeded.limit_bytes, 1024 * 1024);
+    assert!(exceeded.peak_bytes > exceeded.limit_bytes);
+    assert!(err.to_string().contains("exceeded memory limit"), "{}", err);
+
+    // Small queries still fit
+    let batches = df_ctx
+        .execute_query("SELECT COUNT(*) FROM generate_series(1, 1000)")
+        .await
+        .unwrap();
+    assert_eq!(batches[0].num_rows(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cancel_running_query() {
+    use graphql_datafusion::datafusion::context::is_cancelled;
+    use std::time::{Duration, Instant};
+
+    let df_ctx =
+        Arc::new(DataFusionContext::in_memory().with_query_timeout(Duration::from_secs(600)));
+    let schema = default_schema(df_ctx.clone());
+
+    let running_ctx = df_ctx.clone();
+    let slow = tokio::spawn(async move {
+        running_ctx
+            .execute_query("SELECT SUM(value) FROM generate_series(1, 10000000000)")
+            .await
+    });
+
+    let id = loop {
+        if let Some(query) = df_ctx.running_queries().pop() {
+            break query.id;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    let cancelled_at = Instant::now();
+    let response = schema
+        .execute(format!(r#"mutation {{ cancelQuery(id: "{}") }}"#, id))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["cancelQuery"],
+        json!(true)
+    );
+
+    let err = tokio::time::timeout(Duration::from_secs(5), slow)
+        .await
+        .expect("cancelled query returns promptly")
+        .unwrap()
+        .unwrap_err();
+    assert!(is_cancelled(&err), "{}", err);
+    assert!(cancelled_at.elapsed() < Duration::from_secs(5));
+    assert!(df_ctx.running_queries().is_empty());
+
+    // Unknown or finished ids report false
+    let response = schema
+        .execute(format!(r#"mutation {{ cancelQuery(id: "{}") }}"#, id))
+        .await;
+    assert_eq!(
+        response.data.into_json().unwrap()["cancelQuery"],
+        json!(false)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_cancel_query_needs_owner_or_admin() {
+    use graphql_datafusion::auth::Claims;
+    use graphql_datafusion::datafusion::context::{is_cancelled, with_query_owner};
+    use std::time::Duration;
+
+    let df_ctx =
+        Arc::new(DataFusionContext::in_memory().with_query_timeout(Duration::from_secs(600)));
+    let schema = default_schema(df_ctx.clone());
+    let cancel = |id: &str, user: &str, role: &str| {
+        async_graphql::Request::new(format!(r#"mutation {{ cancelQuery(id: "{}") }}"#, id))
+            .data(Claims::new(user.to_string(), role.to_string()))
+    };
+
+    for canceller in [("alice", "user"), ("ops", "admin")] {
+        let running_ctx = df_ctx.clone();
+        let slow = tokio::spawn(with_query_owner(Some("alice".to_string()), async move {
+            running_ctx
+                .execute_query("SELECT SUM(value) FROM generate_series(1, 10000000000)")
+                .await
+        }));
+        let id = loop {
+            if let Some(query) = df_ctx.running_queries().pop() {
+                assert_eq!(query.owner.as_deref(), Some("alice"));
+                break query.id;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        // Other users and anonymous callers cannot cancel it
+        let response = schema.execute(cancel(&id, "bob", "user")).await;
+        assert_eq!(response.errors.len(), 1);
+        let error = serde_json::to_value(&response.errors[0]).unwrap();
+        assert_eq!(error["extensions"]["code"], json!("FORBIDDEN"));
+        let response = schema
+            .execute(format!(r#"mutation {{ cancelQuery(id: "{}") }}"#, id))
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert!(df_ctx.running_query(&id).is_some());
+
+        let (user, role) = canceller;
+        let response = schema.execute(cancel(&id, user, role)).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap()["cancelQuery"],
+            json!(true)
+        );
+        let err = tokio::time::timeout(Duration::from_secs(5), slow)
+            .await
+            .expect("cancelled query returns promptly")
+            .unwrap()
+            .unwrap_err();
+        assert!(is_cancelled(&err), "{}", err);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_admin_kills_running_query() {
+    use std::time::Duration;
+
+    let df_ctx =
+        Arc::new(DataFusionContext::in_memory().with_query_timeout(Duration::from_secs(600)));
+    let schema = default_schema(df_ctx.clone());
+    let admin = |query: &str| {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+
+    // The victim runs on its own connection
+    let victim_schema = schema.clone();
+    let victim = tokio::spawn(async move {
+        victim_schema
+            .execute(
+                async_graphql::Request::new(
+                    r#"{ executeSql(query: "SELECT SUM(value) FROM generate_series(1, 10000000000)") { rowCount } }"#,
+                )
+                .data(Claims::new("alice".to_string(), "user".to_string())),
+            )
+            .await
+    });
+
+    let id = loop {
+        let response = schema
+            .execute(admin("{ runningQueries { queryId sql elapsedMs } }"))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let running = response.data.into_json().unwrap()["runningQueries"].clone();
+        if let Some(query) = running.as_array().and_then(|queries| queries.first()) {
+            assert!(query["sql"].as_str().unwrap().contains("generate_series"));
+            break query["queryId"].as_str().unwrap().to_string();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+
+    // Only admins may kill queries
+    let response = schema
+        .execute(
+            async_graphql::Request::new(format!(r#"mutation {{ killQuery(id: "{}") }}"#, id))
+                .data(Claims::new("bob".to_string(), "user".to_string())),
+        )
+        .await;
+    assert_eq!(response.errors.len(), 1);
+
+    let response = schema
+        .execute(admin(&format!(r#"mutation {{ killQuery(id: "{}") }}"#, id)))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["killQuery"], json!(true));
+
+    let response = tokio::time::timeout(Duration::from_secs(5), victim)
+        .await
+        .expect("killed query returns promptly")
+        .unwrap();
+    assert_eq!(response.errors.len(), 1);
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("QUERY_CANCELLED"));
+    assert!(
+        error["message"].as_str().unwrap().contains("by admin ops"),
+        "{}",
+        error["message"]
+    );
+
+    // The killed statement shows up in the history under the same id
+    let response = schema
+        .execute(admin("{ recentQueries { queryId success } }"))
+        .await;
+    let recent = response.data.into_json().unwrap()["recentQueries"].clone();
+    assert!(
+        recent
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "queryId": id, "success": false })),
+        "{}",
+        recent
+    );
+
+    let response = schema
+        .execute(admin(&format!(r#"mutation {{ killQuery(id: "{}") }}"#, id)))
+        .await;
+    assert_eq!(
+        response.data.into_json().unwrap()["killQuery"],
+        json!(false)
+    );
+}
+
+#[tokio::test]
+async fn test_time_series_moving_average() {
+    use datafusion::arrow::array::{Date32Array, Float64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Five consecutive days starting 2024-01-01, two rows on the first day
+    let days = [19723, 19723, 19724, 19725, 19726, 19727];
+    let amounts = [4.0, 6.0, 20.0, 30.0, 40.0, 50.0];
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("day", DataType::Date32, false),
+            Field::new("amount", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Date32Array::from(days.to_vec())),
+            Arc::new(Float64Array::from(amounts.to_vec())),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("sales", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let query = r#"{ timeSeries(table: "sales", timeColumn: "day", valueColumn: "amount",
+                     granularity: DAY, movingAverage: 3) { period value movingAverage } }"#;
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let points = data["timeSeries"].as_array().unwrap();
+    assert_eq!(points.len(), 5);
+    assert_eq!(points[0]["period"], json!("2024-01-01"));
+    assert_eq!(points[0]["value"], json!(10.0));
+
+    // 2024-01-04 averages itself and the two preceding days
+    assert_eq!(points[3]["period"], json!("2024-01-04"));
+    let expected = (20.0 + 30.0 + 40.0) / 3.0;
+    assert!((points[3]["movingAverage"].as_f64().unwrap() - expected).abs() < 1e-9);
+    // Before a full window, the average covers the available periods
+    assert_eq!(points[0]["movingAverage"], json!(10.0));
+
+    for invalid in [
+        r#"{ timeSeries(table: "sales", timeColumn: "day", valueColumn: "amount", movingAverage: 0) { value } }"#,
+        r#"{ timeSeries(table: "sales", timeColumn: "day", valueColumn: "price") { value } }"#,
+        r#"{ timeSeries(table: "sales", timeColumn: "amount", valueColumn: "amount") { value } }"#,
+        r#"{ timeSeries(table: "missing", timeColumn: "day", valueColumn: "amount") { value } }"#,
+    ] {
+        let response = schema.execute(invalid).await;
+        assert!(!response.errors.is_empty(), "{}", invalid);
+    }
+}
+
+#[tokio::test]
+async fn test_time_series_period_comparison() {
+    use datafusion::arrow::array::{Date32Array, Float64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // 2023-03-10, 2023-03-20, 2023-04-05, 2024-01-15, 2024-02-10,
+    // 2024-03-10, 2024-03-25, 2024-04-05, 2024-05-02
+    let days = [
+        19426, 19436, 19452, 19737, 19763, 19792, 19807, 19818, 19845,
+    ];
+    let amounts = [100.0, 50.0, 80.0, 30.0, 20.0, 150.0, 60.0, 40.0, 10.0];
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("day", DataType::Date32, false),
+            Field::new("amount", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(Date32Array::from(days.to_vec())),
+            Arc::new(Float64Array::from(amounts.to_vec())),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("sales", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let series = |arguments: &str| {
+        format!(
+            r#"{{ timeSeries(table: "sales", timeColumn: "day", valueColumn: "amount", {})
+                {{ period value partial comparisonPeriod comparisonValue changePercent }} }}"#,
+            arguments
+        )
+    };
+
+    // The range starts mid-March, so March only counts 2024-03-25 and is
+    // compared with the same days of 2023
+    let response = schema
+        .execute(series(
+            r#"from: "2024-03-15", to: "2024-04-30", compareTo: PREVIOUS_YEAR"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["timeSeries"],
+        json!([
+            { "period": "2024-03-01", "value": 60.0, "partial": true,
+              "comparisonPeriod": "2023-03-01", "comparisonValue": 50.0, "changePercent": 20.0 },
+            { "period": "2024-04-01", "value": 40.0, "partial": false,
+              "comparisonPeriod": "2023-04-01", "comparisonValue": 80.0, "changePercent": -50.0 },
+        ])
+    );
+
+    // March and April are compared with January and February
+    let response = schema
+        .execute(series(
+            r#"from: "2024-03-01", to: "2024-04-30", compareTo: PREVIOUS_PERIOD"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["timeSeries"],
+        json!([
+            { "period": "2024-03-01", "value": 210.0, "partial": false,
+              "comparisonPeriod": "2024-01-01", "comparisonValue": 30.0, "changePercent": 600.0 },
+            { "period": "2024-04-01", "value": 40.0, "partial": false,
+              "comparisonPeriod": "2024-02-01", "comparisonValue": 20.0, "changePercent": 100.0 },
+        ])
+    );
+
+    // Nothing was sold in May 2023
+    let response = schema
+        .execute(series(
+            r#"from: "2024-05-01", to: "2024-05-31", compareTo: PREVIOUS_YEAR"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["timeSeries"],
+        json!([
+            { "period": "2024-05-01", "value": 10.0, "partial": false,
+              "comparisonPeriod": null, "comparisonValue": null, "changePercent": null },
+        ])
+    );
+
+    for invalid in [
+        r#"from: "2024-04-30", to: "2024-03-01""#,
+        r#"from: "2024-13-01""#,
+        r#"to: "yesterday""#,
+        r#"from: "2024-03-01", compareTo: PREVIOUS_PERIOD"#,
+    ] {
+        let response = schema.execute(series(invalid)).await;
+        assert!(!response.errors.is_empty(), "{}", invalid);
+    }
+}
+
+fn small_quotas() -> graphql_datafusion::quota::QuotaManager {
+    use graphql_datafusion::quota::{QuotaManager, RoleQuota};
+    use std::collections::BTreeMap;
+
+    QuotaManager::new(BTreeMap::from([
+        (
+            "viewer".to_string(),
+            RoleQuota {
+                max_rows_per_query: Some(1),
+                max_export_rows_per_day: Some(3),
+            },
+        ),
+        ("admin".to_string(), RoleQuota::UNLIMITED),
+    ]))
+}
+
+#[test]
+fn test_export_quota_persists_daily_usage() {
+    let path = std::env::temp_dir().join(format!("quota_{}.json", uuid::Uuid::new_v4()));
+    let quotas = small_quotas().with_state_file(&path);
+
+    quotas.record_export("alice", Some("viewer"), 1).unwrap();
+    quotas.record_export("alice", Some("viewer"), 1).unwrap();
+    // Per-query limit applies to exports as well
+    let err = quotas
+        .record_export("alice", Some("viewer"), 2)
+        .unwrap_err();
+    assert_eq!(err.code(), "ROW_LIMIT_EXCEEDED");
+    assert!(
+        quotas
+            .record_export("admin", Some("admin"), 1_000_000)
+            .is_ok()
+    );
+
+    // Counters survive a restart
+    let restarted = small_quotas().with_state_file(&path);
+    assert_eq!(
+        restarted.usage("alice", Some("viewer")).exported_rows_today,
+        2
+    );
+    restarted.record_export("alice", Some("viewer"), 1).unwrap();
+    let err = restarted
+        .record_export("alice", Some("viewer"), 1)
+        .unwrap_err();
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(err.code(), "EXPORT_QUOTA_EXCEEDED");
+    assert!(err.reset_at().unwrap() > chrono::Utc::now());
+    // Other users have their own counter
+    assert_eq!(restarted.usage("bob", None).exported_rows_today, 0);
+}
+
+#[tokio::test]
+async fn test_row_limit_and_my_quota() {
+    use graphql_datafusion::auth::Claims;
+
+    let schema = test_schema(Config::default());
+    let quotas = Arc::new(small_quotas());
+    let request = |query: &str, role: &str| {
+        async_graphql::Request::new(query)
+            .data(Claims::new("alice".to_string(), role.to_string()))
+            .data(quotas.clone())
+    };
+
+    let response = schema
+        .execute(request("{ customers { c_custkey } }", "viewer"))
+        .await;
+    let code = response.errors[0]
+        .extensions
+        .as_ref()
+        .and_then(|extensions| extensions.get("code"))
+        .cloned();
+    assert_eq!(code, Some(async_graphql::Value::from("ROW_LIMIT_EXCEEDED")));
+
+    let response = schema
+        .execute(request("{ customers { c_custkey } }", "admin"))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    quotas.record_export("alice", Some("viewer"), 1).unwrap();
+    let response = schema
+        .execute(request(
+            "{ myQuota { role maxRowsPerQuery maxExportRowsPerDay exportedRowsToday remainingExportRows } }",
+            "viewer",
+        ))
+        .await;
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["myQuota"],
+        json!({
+            "role": "viewer",
+            "maxRowsPerQuery": 1,
+            "maxExportRowsPerDay": 3,
+            "exportedRowsToday": 1,
+            "remainingExportRows": 2,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_paging_without_sort_uses_default_sort() {
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::graphql::query_translator::{QueryParams, QueryTranslator};
+
+    // Ten events in shuffled batches, so scan order differs from key order
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("kind", DataType::Utf8, false),
+    ]));
+    let batch = |ids: Vec<i64>| {
+        let kinds: Vec<&str> = ids.iter().map(|_| "click").collect();
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(ids)),
+                Arc::new(StringArray::from(kinds)),
+            ],
+        )
+        .unwrap()
+    };
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx
+        .register_batches(
+            "events",
+            vec![
+                batch(vec![7, 2, 9]),
+                batch(vec![4, 10, 1]),
+                batch(vec![5, 3, 8, 6]),
+            ],
+        )
+        .unwrap();
+    let page = |offset: i32| QueryParams {
+        table: "events".to_string(),
+        fields: Some(vec!["id".to_string()]),
+        filters: None,
+        sort: None,
+        limit: Some(5),
+        offset: Some(offset),
+    };
+    let ids = |batches: Vec<RecordBatch>| -> Vec<i64> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let ids = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap();
+                ids.values().to_vec()
+            })
+            .collect()
+    };
+
+    // Neither a configured sort nor a cached schema: paging is refused
+    assert!(QueryTranslator::new().translate(&page(0)).is_err());
+
+    let config = Config {
+        default_sort: [("events".to_string(), vec!["id".to_string()])].into(),
+        ..Config::default()
+    };
+    let mut schema_only = QueryTranslator::new();
+    schema_only.register_schema("events", df_ctx.table_schema("events").await.unwrap());
+    for translator in [QueryTranslator::from_config(&config), schema_only] {
+        let first = translator.translate(&page(0)).unwrap();
+        assert!(first.contains("ORDER BY id"), "{}", first);
+        let second = translator.translate(&page(5)).unwrap();
+        let first = ids(df_ctx.execute_query(&first).await.unwrap());
+        let second = ids(df_ctx.execute_query(&second).await.unwrap());
+        assert_eq!(first, vec![1, 2, 3, 4, 5]);
+        assert_eq!(second, vec![6, 7, 8, 9, 10]);
+    }
+}
+
+#[tokio::test]
+async fn test_paging_over_duplicate_sort_values_is_stable() {
+    use datafusion::arrow::array::{Float64Array, Int64Array};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::graphql::query_translator::{QueryParams, QuerySort, QueryTranslator};
+    use std::collections::HashSet;
+
+    // 60 orders over three prices, spread across several batches
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("o_orderkey", DataType::Int64, false),
+        Field::new("o_totalprice", DataType::Float64, false),
+    ]));
+    let batches: Vec<RecordBatch> = (0..6)
+        .map(|chunk| {
+            let keys: Vec<i64> = (0..10).map(|i| 60 - (chunk * 10 + i)).collect();
+            let prices: Vec<f64> = keys.iter().map(|key| (key % 3) as f64 * 100.0).collect();
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(keys)),
+                    Arc::new(Float64Array::from(prices)),
+                ],
+            )
+            .unwrap()
+        })
+        .collect();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("orders", batches).unwrap();
+
+    let translator = QueryTranslator::new();
+    let mut seen = HashSet::new();
+    let page_size = 7;
+    for page in 0..9 {
+        let sql = translator
+            .translate(&QueryParams {
+                table: "orders".to_string(),
+                fields: Some(vec!["o_orderkey".to_string()]),
+                filters: None,
+                sort: Some(vec![QuerySort {
+                    field: "o_totalprice".to_string(),
+                    order: "desc".to_string(),
+                }]),
+                limit: Some(page_size),
+                offset: Some(page * page_size),
+            })
+            .unwrap();
+        assert!(
+            sql.contains("ORDER BY o_totalprice DESC, o_orderkey"),
+            "{}",
+            sql
+        );
+
+        for batch in df_ctx.execute_query(&sql).await.unwrap() {
+            let keys = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            for key in keys.values() {
+                assert!(seen.insert(*key), "order {} seen twice", key);
+            }
+        }
+    }
+    assert_eq!(seen.len(), 60);
+
+    // Paging without a sort still orders by the primary key
+    let sql = translator
+        .translate(&QueryParams {
+            table: "orders".to_string(),
+            fields: None,
+            filters: None,
+            sort: None,
+            limit: Some(10),
+            offset: None,
+        })
+        .unwrap();
+    assert_eq!(sql, "SELECT * FROM orders ORDER BY o_orderkey LIMIT 10");
+}
+
+#[tokio::test]
+async fn test_order_status_enum_and_filter() {
+    use datafusion::arrow::array::{Int64Array, StringViewArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("o_orderkey", DataType::Int64, false),
+            Field::new("o_orderstatus", DataType::Utf8View, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2, 3])),
+            Arc::new(StringViewArray::from(vec!["O", "F", "X"])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("orders", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(
+            r#"{
+                all: orders { o_orderkey o_orderstatus o_orderstatus_raw }
+                open: orders(status: OPEN) { o_orderkey }
+                unknown: orders(status: UNKNOWN) { o_orderkey }
+            }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let statuses: Vec<_> = data["all"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|order| {
+            (
+                order["o_orderstatus"].clone(),
+                order["o_orderstatus_raw"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            (json!("OPEN"), json!(null)),
+            (json!("FULFILLED"), json!(null)),
+            (json!("UNKNOWN"), json!("X")),
+        ]
+    );
+    assert_eq!(data["open"], json!([{ "o_orderkey": 1 }]));
+    assert_eq!(data["unknown"], json!([{ "o_orderkey": 3 }]));
+
+    // Values outside the enum are rejected during validation
+    let response = schema
+        .execute(r#"{ orders(status: SHIPPED) { o_orderkey } }"#)
+        .await;
+    assert!(!response.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_order_dates_from_date32_and_date64() {
+    use datafusion::arrow::array::{ArrayRef, Date32Array, Date64Array, Int64Array};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // 9497 days is 1996-01-02; 912517200000 ms is 1998-12-01 13:00 UTC
+    let cases: Vec<(ArrayRef, Vec<serde_json::Value>)> = vec![
+        (
+            Arc::new(Date32Array::from(vec![Some(9497), None])),
+            vec![json!("1996-01-02"), json!(null)],
+        ),
+        (
+            Arc::new(Date64Array::from(vec![None, Some(912517200000)])),
+            vec![json!(null), json!("1998-12-01")],
+        ),
+    ];
+    for (dates, expected) in cases {
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "o_orderkey",
+                Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef,
+            ),
+            ("o_orderdate", dates),
+        ])
+        .unwrap();
+        let df_ctx = DataFusionContext::in_memory();
+        df_ctx.register_batches("orders", vec![batch]).unwrap();
+        let schema = default_schema(Arc::new(df_ctx));
+
+        let response = schema.execute("{ orders { o_orderkey oOrderdate } }").await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        let dates: Vec<_> = data["orders"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|order| order["oOrderdate"].clone())
+            .collect();
+        assert_eq!(dates, expected);
+    }
+}
+
+#[test]
+fn test_record_batch_ext_reads_any_physical_type() {
+    use datafusion::arrow::array::{
+        ArrayRef, Date32Array, Decimal128Array, Int32Array, LargeStringArray, StringArray,
+        StringViewArray, UInt8Array,
+    };
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::datafusion::column_accessor::RecordBatchExt;
+
+    let balances = Decimal128Array::from(vec![Some(123456), None])
+        .with_precision_and_scale(15, 2)
+        .unwrap();
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "utf8",
+            Arc::new(StringArray::from(vec![Some("Customer#1"), None])),
+        ),
+        (
+            "large",
+            Arc::new(LargeStringArray::from(vec![Some("Customer#1"), None])),
+        ),
+        (
+            "view",
+            Arc::new(StringViewArray::from(vec![Some("Customer#1"), None])),
+        ),
+        ("key", Arc::new(Int32Array::from(vec![Some(7), None]))),
+        ("size", Arc::new(UInt8Array::from(vec![Some(200), None]))),
+        ("balance", Arc::new(balances)),
+        ("date", Arc::new(Date32Array::from(vec![Some(19727), None]))),
+        (
+            "date_text",
+            Arc::new(StringViewArray::from(vec![Some("2024-01-05"), None])),
+        ),
+    ];
+    let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+    for column in ["utf8", "large", "view"] {
+        assert_eq!(
+            batch.get_string(column, 0).unwrap().as_deref(),
+            Some("Customer#1"),
+            "{}",
+            column
+        );
+    }
+    assert_eq!(batch.get_i64("key", 0).unwrap(), Some(7));
+    assert_eq!(batch.get_i64("size", 0).unwrap(), Some(200));
+    assert_eq!(batch.get_f64("key", 0).unwrap(), Some(7.0));
+    assert_eq!(batch.get_f64("balance", 0).unwrap(), Some(1234.56));
+    assert_eq!(
+        batch.get_date_string("date", 0).unwrap().as_deref(),
+        Some("2024-01-05")
+    );
+    assert_eq!(
+        batch.get_date_string("date_text", 0).unwrap().as_deref(),
+        Some("2024-01-05")
+    );
+
+    // Nulls and columns the batch does not have read as `None`
+    for column in ["utf8", "large", "view", "date_text", "missing"] {
+        assert_eq!(batch.get_string(column, 1).unwrap(), None, "{}", column);
+    }
+    assert_eq!(batch.get_i64("key", 1).unwrap(), None);
+    assert_eq!(batch.get_f64("balance", 1).unwrap(), None);
+    assert_eq!(batch.get_date_string("date", 1).unwrap(), None);
+
+    // Types that cannot hold the value fail instead of guessing
+    assert!(batch.get_i64("view", 0).is_err());
+    assert!(batch.get_f64("utf8", 0).is_err());
+    assert!(batch.get_string("key", 0).is_err());
+}
+
+#[test]
+fn test_date_value_of_date_and_timestamp_columns() {
+    use datafusion::arrow::array::{
+        Array, Date32Array, Date64Array, Int64Array, TimestampMillisecondArray,
+        TimestampSecondArray,
+    };
+    use graphql_datafusion::datafusion::arrow_convert::date_value;
+
+    let dates = |array: &dyn Array| -> Vec<Option<String>> {
+        (0..array.len())
+            .map(|row| date_value(array, row).unwrap())
+            .collect()
+    };
+    let date = |text: &str| Some(text.to_string());
+
+    // 9497 days is 1996-01-02; 912517200000 ms is 1998-12-01 13:00 UTC
+    assert_eq!(
+        dates(&Date32Array::from(vec![Some(9497), None])),
+        vec![date("1996-01-02"), None]
+    );
+    assert_eq!(
+        dates(&Date64Array::from(vec![None, Some(912517200000)])),
+        vec![None, date("1998-12-01")]
+    );
+    assert_eq!(
+        dates(&TimestampSecondArray::from(vec![Some(912517200), None])),
+        vec![date("1998-12-01"), None]
+    );
+    // Timestamps with a timezone are dated in that timezone
+    let timestamps =
+        TimestampMillisecondArray::from(vec![Some(912517200000)]).with_timezone("+12:00");
+    assert_eq!(dates(&timestamps), vec![date("1998-12-02")]);
+
+    let error = date_value(&Int64Array::from(vec![1]), 0).unwrap_err();
+    assert!(
+        error.to_string().contains("Cannot read a date"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+async fn test_json_extract_nested_struct_field() {
+    use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray, StructArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Fields, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // profile: { address: { city }, tier }
+    let address_fields = Fields::from(vec![Field::new("city", DataType::Utf8, true)]);
+    let address = StructArray::new(
+        address_fields.clone(),
+        vec![Arc::new(StringArray::from(vec![Some("Oslo"), None])) as ArrayRef],
+        None,
+    );
+    let profile_fields = Fields::from(vec![
+        Field::new("address", DataType::Struct(address_fields), true),
+        Field::new("tier", DataType::Int64, true),
+    ]);
+    let profile = StructArray::new(
+        profile_fields.clone(),
+        vec![
+            Arc::new(address) as ArrayRef,
+            Arc::new(Int64Array::from(vec![1, 2])),
+        ],
+        None,
+    );
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("profile", DataType::Struct(profile_fields), true),
+            Field::new("payload", DataType::Utf8, true),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2])),
+            Arc::new(profile),
+            Arc::new(StringArray::from(vec![
+                r#"{"items": [{"sku": "A-1"}]}"#,
+                r#"{"items": []}"#,
+            ])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("accounts", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(r#"{ jsonExtract(table: "accounts", column: "profile", path: "address.city") }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["jsonExtract"], json!(["Oslo", null]));
+
+    // Whole nested structs come back as JSON objects
+    let response = schema
+        .execute(
+            r#"{ jsonExtract(table: "accounts", column: "profile", path: "address", limit: 1) }"#,
+        )
+        .await;
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["jsonExtract"], json!([{ "city": "Oslo" }]));
+
+    // JSON text columns are parsed and walked by path
+    let response = schema
+        .execute(r#"{ jsonExtract(table: "accounts", column: "payload", path: "items.0.sku") }"#)
+        .await;
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["jsonExtract"], json!(["A-1", null]));
+
+    for invalid in [
+        r#"{ jsonExtract(table: "accounts", column: "id", path: "x") }"#,
+        r#"{ jsonExtract(table: "accounts", column: "profile", path: "address.zip") }"#,
+        r#"{ jsonExtract(table: "accounts", column: "profile", path: "tier.x") }"#,
+        r#"{ jsonExtract(table: "accounts", column: "profile", path: "") }"#,
+    ] {
+        let response = schema.execute(invalid).await;
+        assert!(!response.errors.is_empty(), "{}", invalid);
+    }
+
+    // The caller's analysis filters narrow the rows read
+    let as_alice = |query: &str| {
+        async_graphql::Request::new(query).data(graphql_datafusion::auth::Claims::new(
+            "alice".to_string(),
+            "user".to_string(),
+        ))
+    };
+    let response = schema
+        .execute(as_alice(
+            r#"mutation { setAnalysisFilters(filters: [{ column: "id", operator: NE, value: "1" }]) { column } }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let response = schema
+        .execute(as_alice(
+            r#"{ jsonExtract(table: "accounts", column: "payload", path: "items.0.sku") }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["jsonExtract"], json!([null]));
+}
+
+#[tokio::test]
+async fn test_table_files_reports_parquet_metadata() {
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx
+        .register_parquet("customer_files", &path)
+        .await
+        .unwrap();
+    df_ctx
+        .register_batches("customer", vec![customer_batch()])
+        .unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(
+            r#"{
+                tableFiles(tableName: "customer_files") {
+                    fileCount totalBytes rowGroups rows compression
+                    files { sizeBytes rows lastModified }
+                    partitions { column }
+                }
+            }"#,
+        )
+        .await;
+    std::fs::remove_file(&path).ok();
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let files = response.data.into_json().unwrap()["tableFiles"].clone();
+    assert_eq!(files["fileCount"], json!(1));
+    assert!(files["totalBytes"].as_i64().unwrap() > 0);
+    assert_eq!(files["totalBytes"], files["files"][0]["sizeBytes"]);
+    assert!(files["rowGroups"].as_i64().unwrap() >= 1);
+    assert_eq!(files["rows"], json!(2));
+    assert!(!files["compression"].as_array().unwrap().is_empty());
+    assert_eq!(files["partitions"], json!([]));
+    assert!(
+        graphql_datafusion::metrics::render().contains(r#"table_bytes{table="customer_files"}"#)
+    );
+
+    // In-memory tables have no files behind them
+    let response = schema
+        .execute(r#"{ tableFiles(tableName: "customer") { fileCount } }"#)
+        .await;
+    assert!(
+        response.errors[0]
+            .message
+            .contains("not backed by parquet files")
+    );
+}
+
+#[tokio::test]
+async fn test_table_files_partition_ranges_and_paths() {
+    use graphql_datafusion::auth::Claims;
+
+    // Partition values compare as numbers, so 9 is the minimum, not 10
+    let fixture = write_parquet_fixture(customer_batch()).await;
+    let dir = std::env::temp_dir().join(format!("partitioned_{}", uuid::Uuid::new_v4()));
+    for part in ["9", "10"] {
+        let part_dir = dir.join(format!("part={}", part));
+        std::fs::create_dir_all(&part_dir).unwrap();
+        std::fs::copy(&fixture, part_dir.join("data.parquet")).unwrap();
+    }
+    std::fs::remove_file(&fixture).ok();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx
+        .register_parquet("customer", dir.to_str().unwrap())
+        .await
+        .unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(r#"{ tableFiles(tableName: "customer") { partitions { column min max } } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["tableFiles"]["partitions"],
+        json!([{ "column": "part", "min": "9", "max": "10" }])
+    );
+
+    // File paths are only shown to admins
+    let query = r#"{ tableFiles(tableName: "customer") { files { path } } }"#;
+    let response = schema.execute(query).await;
+    assert!(!response.errors.is_empty());
+    let response = schema
+        .execute(
+            async_graphql::Request::new(query)
+                .data(Claims::new("ops".to_string(), "admin".to_string())),
+        )
+        .await;
+    std::fs::remove_dir_all(&dir).ok();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let files = response.data.into_json().unwrap()["tableFiles"]["files"].clone();
+    assert_eq!(files.as_array().unwrap().len(), 2);
+    assert!(files[0]["path"].as_str().unwrap().ends_with("data.parquet"));
+}
+
+#[tokio::test]
+async fn test_typed_resolvers_read_narrow_integer_keys() {
+    use datafusion::arrow::array::{ArrayRef, Int32Array, StringViewArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Keys written as Int32 and names as Utf8View, as some parquet writers do
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "n_nationkey",
+            Arc::new(Int32Array::from(vec![7])) as ArrayRef,
+        ),
+        ("n_name", Arc::new(StringViewArray::from(vec!["GERMANY"]))),
+        ("n_regionkey", Arc::new(Int32Array::from(vec![3]))),
+        (
+            "n_comment",
+            Arc::new(StringViewArray::from(vec![None::<&str>])),
+        ),
+    ])
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("nation", vec![batch]).unwrap();
+    assert!(df_ctx.check_model_schemas().await.is_empty());
+
+    let schema = default_schema(Arc::new(df_ctx));
+    let response = schema
+        .execute("{ nations { n_nationkey n_name n_regionkey n_comment } }")
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["nations"],
+        json!([{ "n_nationkey": 7, "n_name": "GERMANY", "n_regionkey": 3, "n_comment": "" }])
+    );
+}
+
+#[tokio::test]
+async fn test_schema_mismatch_disables_typed_resolvers() {
+    use datafusion::arrow::array::{Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // c_acctbal was renamed upstream
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("c_custkey", DataType::Int64, false),
+        Field::new("c_name", DataType::Utf8, false),
+        Field::new("c_address", DataType::Utf8, false),
+        Field::new("c_nationkey", DataType::Int64, false),
+        Field::new("c_phone", DataType::Utf8, false),
+        Field::new("c_acct_balance", DataType::Float64, false),
+        Field::new("c_mktsegment", DataType::Utf8, false),
+        Field::new("c_comment", DataType::Utf8, false),
+    ]));
+    let text = || Arc::new(StringArray::from(vec!["x"]));
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(Int64Array::from(vec![1])),
+            text(),
+            text(),
+            Arc::new(Int64Array::from(vec![1])),
+            text(),
+            Arc::new(Float64Array::from(vec![10.0])),
+            Arc::new(StringArray::from(vec!["BUILDING"])),
+            text(),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("customer", vec![batch]).unwrap();
+
+    // orders is not registered and is not reported
+    let mismatches = df_ctx.check_model_schemas().await;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].model, "Customer");
+    assert_eq!(mismatches[0].column, "c_acctbal");
+    assert_eq!(mismatches[0].found, None);
+
+    let schema = default_schema(Arc::new(df_ctx));
+    let response = schema.execute("{ customers { c_custkey } }").await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("SCHEMA_MISMATCH"));
+    assert_eq!(error["extensions"]["column"], json!("c_acctbal"));
+    assert!(response.errors[0].message.contains("customer.c_acctbal"));
+
+    // Generic resolvers keep working
+    let response = schema
+        .execute(r#"{ tableCount(tableName: "customer") }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["tableCount"], json!(1));
+}
+
+#[tokio::test]
+async fn test_schema_without_services_reports_missing_context() {
+    use graphql_datafusion::graphql::schema::{MutationRoot, QueryRoot};
+
+    let schema =
+        async_graphql::Schema::build(QueryRoot, MutationRoot, async_graphql::EmptySubscription)
+            .finish();
+
+    let response = schema.execute("{ tables }").await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("CONTEXT_MISSING"));
+}
+
+#[test]
+fn test_normalized_sql_shares_cache_key() {
+    let df_ctx = customer_fixture();
+
+    let plain = df_ctx
+        .normalize_sql("SELECT c_name FROM customer WHERE c_custkey = 1")
+        .unwrap();
+    let cosmetic = df_ctx
+        .normalize_sql(
+            "select   c_name -- the name\n\tfrom customer /* all rows */\nwhere c_custkey=1",
+        )
+        .unwrap();
+    assert_eq!(plain, cosmetic);
+    assert_eq!(plain, "SELECT c_name FROM customer WHERE c_custkey = 1");
+
+    // A comment cannot hide a second statement
+    assert!(
+        df_ctx
+            .normalize_sql("SELECT 1; -- \nDROP TABLE customer")
+            .is_err()
+    );
+}
+
+#[test]
+fn test_reaper_removes_idle_entries() {
+    use graphql_datafusion::reaper::{Expirable, Reaper, TtlMap};
+    use std::time::{Duration, Instant};
+
+    let conversations = Arc::new(TtlMap::new("conversations", Duration::from_secs(600)));
+    let jobs = Arc::new(TtlMap::new("insight_jobs", Duration::from_secs(60)));
+    conversations.insert("idle", "hello".to_string());
+    conversations.insert("active", "hi".to_string());
+    jobs.insert("finished", 1);
+    let reaper = Reaper::new(Duration::from_secs(30))
+        .register(conversations.clone())
+        .register(jobs.clone());
+
+    // Before any TTL has passed nothing is reclaimed
+    assert_eq!(reaper.sweep(Instant::now()), 0);
+
+    // The job expires after its TTL, the conversations live longer
+    assert_eq!(reaper.sweep(Instant::now() + Duration::from_secs(61)), 1);
+    assert!(jobs.is_empty());
+    assert_eq!(conversations.live_entries(), 2);
+
+    // Reading a conversation keeps it alive past the other's TTL
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(conversations.get("active").as_deref(), Some("hi"));
+    let idle_expired = Instant::now() + Duration::from_secs(600) - Duration::from_millis(100);
+    assert_eq!(reaper.sweep(idle_expired), 1);
+    assert_eq!(conversations.get("idle"), None);
+    assert_eq!(conversations.get("active").as_deref(), Some("hi"));
+    assert!(
+        graphql_datafusion::metrics::render()
+            .contains(r#"state_live_entries{registry="conversations"} 1"#)
+    );
+}
+
+#[tokio::test]
+async fn test_query_history_is_per_user() {
+    use graphql_datafusion::auth::Claims;
+
+    let schema = test_schema(Config::default());
+    let as_user = |query: &str, user: &str| {
+        async_graphql::Request::new(query).data(Claims::new(user.to_string(), "viewer".to_string()))
+    };
+    let history = "{ myQueryHistory { operationName query success durationMs } }";
+
+    let response = schema
+        .execute(as_user("query CustomerTables { tables }", "alice"))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let response = schema.execute(as_user(history, "alice")).await;
+    let entries = response.data.into_json().unwrap()["myQueryHistory"].clone();
+    assert_eq!(entries.as_array().unwrap().len(), 1);
+    assert_eq!(entries[0]["operationName"], json!("CustomerTables"));
+    assert_eq!(
+        entries[0]["query"],
+        json!("query CustomerTables { tables }")
+    );
+    assert_eq!(entries[0]["success"], json!(true));
+
+    // Other users only see their own operations
+    let response = schema.execute(as_user(history, "bob")).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["myQueryHistory"],
+        json!([])
+    );
+
+    // Anonymous callers have no history
+    let response = schema.execute(history).await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("UNAUTHENTICATED"));
+}
+
+#[tokio::test]
+async fn test_operation_allow_list_by_role() {
+    use graphql_datafusion::auth::Claims;
+    use graphql_datafusion::graphql::allow_list::{AllowListRules, document_hash};
+
+    let list = "query CustomerList { tables }";
+    let rules: AllowListRules = serde_json::from_value(json!({
+        "roles": {
+            "viewer": ["CustomerList", "SalesDashboard"],
+            "analyst": ["tables"]
+        },
+        "allow_anonymous": false,
+        "documents": { "CustomerList": document_hash(list) }
+    }))
+    .unwrap();
+    let schema = test_schema(Config {
+        operation_allow_list: rules,
+        ..Config::default()
+    });
+
+    // A listed name only counts with the document it was listed for
+    let renamed = r#"query CustomerList { executeSql(query: "SELECT 1") { rowCount } }"#;
+    let other = "query Other { tables }";
+    let fields = r#"query Counts { tableCount(tableName: "customer") }"#;
+    let anonymous = "{ tables }";
+    let cases = [
+        (Some("viewer"), list, true),
+        (Some("viewer"), renamed, false),
+        (Some("viewer"), other, false),
+        (Some("viewer"), fields, false),
+        (Some("viewer"), anonymous, false),
+        // Operations are also allowed when all their top-level fields are listed
+        (Some("analyst"), list, true),
+        (Some("analyst"), renamed, false),
+        (Some("analyst"), other, true),
+        (Some("analyst"), fields, false),
+        (Some("analyst"), anonymous, false),
+        // Admins bypass the lists, unlisted roles are only bound by the anonymous rule
+        (Some("admin"), other, true),
+        (Some("admin"), fields, true),
+        (Some("admin"), anonymous, true),
+        (Some("ops"), fields, true),
+        (Some("ops"), anonymous, false),
+        // Callers without a token get the viewer list
+        (None, list, true),
+        (None, renamed, false),
+        (None, other, false),
+    ];
+    for (role, query, allowed) in cases {
+        let mut request = async_graphql::Request::new(query);
+        if let Some(role) = role {
+            request = request.data(Claims::new("user".to_string(), role.to_string()));
+        }
+        let response = schema.execute(request).await;
+        if allowed {
+            assert!(
+                response.errors.is_empty(),
+                "{:?} {}: {:?}",
+                role,
+                query,
+                response.errors
+            );
+        } else {
+            let error = serde_json::to_value(&response.errors[0]).unwrap();
+            assert_eq!(
+                error["extensions"]["code"],
+                json!("FORBIDDEN"),
+                "{:?} {}",
+                role,
+                query
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_operation_allow_list_reloads_file() {
+    use graphql_datafusion::graphql::allow_list::document_hash;
+    use std::time::{Duration, SystemTime};
+
+    let path = std::env::temp_dir().join(format!("allow-list-{}.json", uuid::Uuid::new_v4()));
+    let write_rules = |rules: serde_json::Value, modified: SystemTime| {
+        std::fs::write(&path, rules.to_string()).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    };
+    write_rules(json!({ "roles": { "viewer": [] } }), SystemTime::now());
+    let schema = test_schema(Config {
+        operation_allow_list_path: path.to_string_lossy().to_string(),
+        ..Config::default()
+    });
+
+    let query = "query CustomerList { tables }";
+    let response = schema.execute(query).await;
+    assert_eq!(
+        serde_json::to_value(&response.errors[0]).unwrap()["extensions"]["code"],
+        json!("FORBIDDEN")
+    );
+
+    // Edits to the file apply without rebuilding the schema
+    write_rules(
+        json!({
+            "roles": { "viewer": ["CustomerList"] },
+            "documents": { "CustomerList": document_hash(query) }
+        }),
+        SystemTime::now() + Duration::from_secs(1),
+    );
+    let response = schema.execute(query).await;
+    std::fs::remove_file(&path).ok();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+}
+
+#[tokio::test]
+async fn test_query_retried_after_failing_during_reload() {
+    use graphql_datafusion::datafusion::context::RetryPolicy;
+    use graphql_datafusion::request_retry::{RequestRetry, is_transient};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let df_ctx = customer_fixture();
+    let schema = default_schema(df_ctx.clone());
+    let retry = RequestRetry::new(RetryPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(10),
+        ..RetryPolicy::default()
+    });
+    let query = "{ customers { c_custkey c_name } }";
+
+    // Mid-reload the old registration is gone and the new one not yet added
+    let reload = Mutex::new(Some(df_ctx.begin_reload("customer")));
+    df_ctx.drop_table("customer").unwrap();
+
+    // Operations that are not read-only run once even when the failure is transient
+    let attempts = AtomicUsize::new(0);
+    let response = retry
+        .run(false, || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            schema.execute(query)
+        })
+        .await;
+    assert!(is_transient(&response));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    // The reload completes after the first attempt, which the retry hides
+    let attempts = AtomicUsize::new(0);
+    let response = retry
+        .run(true, || {
+            if attempts.fetch_add(1, Ordering::SeqCst) == 1 {
+                df_ctx
+                    .register_batches("customer", vec![customer_batch()])
+                    .unwrap();
+                reload.lock().unwrap().take();
+            }
+            schema.execute(query)
+        })
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"][1]["c_name"],
+        "Customer#2"
+    );
+
+    // Outside a reload a missing table is not transient, nor during the reload
+    // of another table
+    df_ctx.drop_table("customer").unwrap();
+    assert!(!is_transient(&schema.execute(query).await));
+    let _reload = df_ctx.begin_reload("orders");
+    assert!(!is_transient(&schema.execute(query).await));
+}
+
+#[test]
+fn test_io_errors_retried_at_one_layer() {
+    use datafusion::error::DataFusionError;
+    use graphql_datafusion::datafusion::context::RetryPolicy;
+    use std::io::{Error, ErrorKind};
+
+    let reset = DataFusionError::IoError(Error::new(ErrorKind::ConnectionReset, "reset"));
+
+    // execute_query retries it already, running the request again would
+    // multiply the attempts
+    let df_ctx = DataFusionContext::in_memory();
+    assert!(!df_ctx.is_transient(&reset));
+
+    let df_ctx = DataFusionContext::in_memory().with_retry_policy(RetryPolicy {
+        max_retries: 0,
+        ..RetryPolicy::default()
+    });
+    assert!(df_ctx.is_transient(&reset));
+}
+
+#[tokio::test]
+async fn test_alias_amplification_rejected() {
+    let schema = test_schema(Config::default());
+
+    // The same customers page under fifty aliases stays far below the
+    // complexity limit but would run one query fifty times
+    let aliases: Vec<String> = (0..50)
+        .map(|i| format!("c{}: customers(limit: 100) {{ c_custkey c_name }}", i))
+        .collect();
+    let response = schema.execute(format!("{{ {} }}", aliases.join(" "))).await;
+    assert_eq!(response.errors.len(), 1);
+    let error = &response.errors[0];
+    assert!(
+        error.message.contains("QueryRoot.customers"),
+        "{}",
+        error.message
+    );
+    assert_eq!(
+        error.extensions.as_ref().and_then(|ext| ext.get("code")),
+        Some(&async_graphql::Value::from("AMPLIFICATION_LIMIT"))
+    );
+    assert!(!error.locations.is_empty());
+
+    // Variables and fragments do not hide the repetition
+    let fragment_bomb = r#"
+        query Bomb($limit: Int) {
+            a: customers(limit: $limit) { ...Names }
+            b: customers(limit: 100) { ...Names }
+            ...More
+        }
+        fragment Names on Customer { c_name }
+        fragment More on QueryRoot {
+            c: customers(limit: 100) { c_name }
+            d: customers(limit: 100) { c_name }
+            e: customers(limit: 100) { c_name }
+            f: customers(limit: 100) { c_name }
+        }
+    "#;
+    let request = async_graphql::Request::new(fragment_bomb)
+        .variables(async_graphql::Variables::from_json(json!({ "limit": 100 })));
+    let response = schema.execute(request).await;
+    assert_eq!(response.errors.len(), 1, "{:?}", response.errors);
+    assert!(response.errors[0].message.contains("more than 5 times"));
+
+    // A dashboard fetching distinct pages and counts under aliases still runs
+    let dashboard = r#"{
+        firstPage: customers(limit: 1) { c_custkey c_name }
+        secondPage: customers(limit: 1, offset: 1) { c_custkey c_name }
+        customerCount: tableCount(tableName: "customer")
+        tableList: tables
+    }"#;
+    let response = schema.execute(dashboard).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(data["secondPage"][0]["c_name"], "Customer#2");
+    assert_eq!(data["customerCount"], 2);
+}
+
+#[tokio::test]
+async fn test_alias_and_list_field_caps() {
+    let schema = test_schema(Config {
+        max_query_aliases: 2,
+        max_list_fields: 3,
+        ..Config::default()
+    });
+
+    let response = schema
+        .execute("{ a: tables b: tables c: tableCount(tableName: \"customer\") }")
+        .await;
+    assert_eq!(response.errors.len(), 1);
+    assert!(
+        response.errors[0]
+            .message
+            .contains("Alias of QueryRoot.tableCount exceeds the limit of 2 aliases")
+    );
+
+    let response = schema
+        .execute("{ tables customers { c_name } orders { o_orderkey } slowQueries { sql } }")
+        .await;
+    assert_eq!(response.errors.len(), 1);
+    assert!(
+        response.errors[0]
+            .message
+            .contains("List field QueryRoot.slowQueries exceeds the limit of 3")
+    );
+}
+
+#[tokio::test]
+async fn test_filter_capabilities_by_column_type() {
+    let schema = test_schema(Config::default());
+    let response = schema
+        .execute(
+            r#"{ filterCapabilities(table: "customer") { column dataType nullable operators } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let columns = data["filterCapabilities"].as_array().unwrap();
+    assert_eq!(columns.len(), 2);
+
+    assert_eq!(columns[0]["column"], "c_custkey");
+    assert_eq!(columns[0]["dataType"], "Int64");
+    assert_eq!(
+        columns[0]["operators"],
+        json!(["EQ", "NE", "GT", "GTE", "LT", "LTE", "IN"])
+    );
+
+    assert_eq!(columns[1]["column"], "c_name");
+    assert_eq!(
+        columns[1]["operators"],
+        json!(["EQ", "NE", "LIKE", "ILIKE", "IN"])
+    );
+
+    let response = schema
+        .execute(r#"{ filterCapabilities(table: "nope") { column } }"#)
+        .await;
+    assert!(response.errors[0].message.contains("Unknown table"));
+}
+
+#[tokio::test]
+async fn test_request_deadline() {
+    use graphql_datafusion::graphql::deadline::{RequestDeadline, with_deadline};
+    use std::time::{Duration, Instant};
+
+    let schema = test_schema(Config::default());
+    let code = |response: &async_graphql::Response| {
+        response.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|ext| ext.get("code"))
+            .cloned()
+    };
+
+    // Fields not resolved by the deadline fail with a timeout
+    let request = async_graphql::Request::new("{ tables }").data(RequestDeadline(Instant::now()));
+    let response = schema.execute(request).await;
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(code(&response), Some(async_graphql::Value::from("TIMEOUT")));
+    assert!(response.errors[0].message.contains("tables"));
+
+    // Within the deadline the request runs normally
+    let request = async_graphql::Request::new("{ tables }")
+        .data(RequestDeadline::after(Duration::from_secs(30)));
+    assert!(schema.execute(request).await.errors.is_empty());
+
+    // An execution outliving the timeout as a whole is answered with a timeout
+    let response = with_deadline(Duration::from_millis(20), async {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        async_graphql::Response::new(async_graphql::Value::Null)
+    })
+    .await;
+    assert_eq!(code(&response), Some(async_graphql::Value::from("TIMEOUT")));
+}
+
+#[tokio::test]
+async fn test_pivot_rejects_high_cardinality_column() {
+    use datafusion::arrow::array::{Float64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("region", DataType::Utf8, false),
+            Field::new("product", DataType::Utf8, false),
+            Field::new("amount", DataType::Float64, false),
+        ])),
+        vec![
+            Arc::new(StringArray::from(vec!["EU", "EU", "US", "US", "US"])),
+            Arc::new(StringArray::from(vec!["a", "b", "a", "a", "c"])),
+            Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0, 4.0, 5.0])),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("sales", vec![batch]).unwrap();
+    let schema = build_schema(
+        Arc::new(df_ctx),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(Config {
+            max_pivot_columns: 3,
+            ..Config::default()
+        }),
+    );
+    let pivot = |column: &str| {
+        format!(
+            r#"{{ pivot(table: "sales", rowColumn: "region", pivotColumn: "{}", valueColumn: "amount") {{ columns rows {{ key values }} }} }}"#,
+            column
+        )
+    };
+
+    let response = schema.execute(pivot("product")).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["pivot"],
+        json!({
+            "columns": ["a", "b", "c"],
+            "rows": [
+                { "key": "EU", "values": [1.0, 2.0, null] },
+                { "key": "US", "values": [7.0, null, 5.0] },
+            ]
+        })
+    );
+
+    // Every amount is distinct, more than the three columns allowed
+    let response = schema.execute(pivot("amount")).await;
+    assert_eq!(response.errors.len(), 1);
+    let error = &response.errors[0];
+    assert!(
+        error
+            .message
+            .contains("amount has more than 3 distinct values")
+    );
+    assert_eq!(
+        error.extensions.as_ref().and_then(|ext| ext.get("code")),
+        Some(&async_graphql::Value::from("PIVOT_TOO_WIDE"))
+    );
+}
+
+#[tokio::test]
+async fn test_columnar_customers_match_rows() {
+    let schema = test_schema(Config::default());
+
+    let rows = schema
+        .execute("{ customers(limit: 10) { c_custkey c_name } }")
+        .await;
+    assert!(rows.errors.is_empty(), "{:?}", rows.errors);
+    let rows = rows.data.into_json().unwrap()["customers"].clone();
+
+    let columnar = schema
+        .execute(
+            r#"{ customersColumnar(limit: 10, columns: ["c_custkey", "c_name", "c_custkey"]) {
+                rowCount columns { name type values }
+            } }"#,
+        )
+        .await;
+    assert!(columnar.errors.is_empty(), "{:?}", columnar.errors);
+    let columnar = columnar.data.into_json().unwrap()["customersColumnar"].clone();
+    assert_eq!(columnar["rowCount"], 2);
+    assert_eq!(columnar["columns"][0]["type"], "Int64");
+
+    // Reassembling rows from the columns gives the row-oriented result
+    let columns = columnar["columns"].as_array().unwrap();
+    assert_eq!(columns.len(), 2);
+    let reassembled: Vec<serde_json::Value> = (0..2)
+        .map(|i| {
+            let mut row = serde_json::Map::new();
+            for column in columns {
+                row.insert(
+                    column["name"].as_str().unwrap().to_string(),
+                    column["values"][i].clone(),
+                );
+            }
+            serde_json::Value::Object(row)
+        })
+        .collect();
+    assert_eq!(serde_json::Value::Array(reassembled), rows);
+
+    let response = schema
+        .execute(r#"{ customersColumnar(columns: ["c_secret"]) { rowCount } }"#)
+        .await;
+    assert!(
+        response.errors[0]
+            .message
+            .contains("Unknown customer column")
+    );
+}
+
+#[tokio::test]
+async fn test_schema_diff_reports_column_changes() {
+    use datafusion::arrow::array::{Int32Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let df_ctx = customer_fixture();
+    let changed = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("c_custkey", DataType::Int32, false),
+            Field::new("c_name", DataType::Utf8, true),
+            Field::new("c_phone", DataType::Utf8, false),
+        ])),
+        vec![
+            Arc::new(Int32Array::from(vec![1])),
+            Arc::new(StringArray::from(vec![Some("Customer#1")])),
+            Arc::new(StringArray::from(vec!["25-989-741-2988"])),
+        ],
+    )
+    .unwrap();
+    df_ctx
+        .register_batches("customer_v2", vec![changed])
+        .unwrap();
+    let schema = default_schema(df_ctx);
+    let diff = |a: &str, b: &str| {
+        format!(
+            r#"{{ schemaDiff(tableA: "{}", tableB: "{}") {{ column change typeA typeB }} }}"#,
+            a, b
+        )
+    };
+
+    let response = schema.execute(diff("customer", "customer_v2")).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["schemaDiff"],
+        json!([
+            { "column": "c_custkey", "change": "TYPE_CHANGED", "typeA": "Int64", "typeB": "Int32" },
+            { "column": "c_name", "change": "NULLABILITY_CHANGED", "typeA": "Utf8", "typeB": "Utf8" },
+            { "column": "c_phone", "change": "ADDED", "typeA": null, "typeB": "Utf8" },
+        ])
+    );
+
+    let response = schema.execute(diff("customer_v2", "customer")).await;
+    let entries = response.data.into_json().unwrap()["schemaDiff"].clone();
+    assert_eq!(
+        entries[2],
+        json!({ "column": "c_phone", "change": "REMOVED", "typeA": "Utf8", "typeB": null })
+    );
+
+    let response = schema.execute(diff("customer", "customer")).await;
+    assert_eq!(response.data.into_json().unwrap()["schemaDiff"], json!([]));
+}
+
+#[tokio::test]
+async fn test_data_dictionary_merged_into_table_schema() {
+    let path = std::env::temp_dir().join(format!("dictionary-{}.yaml", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &path,
+        "customer:\n  c_name:\n    description: Customer name\n    tags: [pii]\n  c_custkey:\n    description: Primary key\n    unit: id\n",
+    )
+    .unwrap();
+    let schema = test_schema(Config {
+        data_dictionary_path: path.to_str().unwrap().to_string(),
+        ..Config::default()
+    });
+
+    let response = schema
+        .execute(r#"{ tableSchema(table: "customer") { column dataType description tags unit } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["tableSchema"],
+        json!([
+            { "column": "c_custkey", "dataType": "Int64", "description": "Primary key", "tags": [], "unit": "id" },
+            { "column": "c_name", "dataType": "Utf8", "description": "Customer name", "tags": ["pii"], "unit": null },
+        ])
+    );
+
+    let response = schema
+        .execute(r#"{ searchColumns(text: "NAME") { table column } }"#)
+        .await;
+    assert_eq!(
+        response.data.into_json().unwrap()["searchColumns"],
+        json!([{ "table": "customer", "column": "c_name" }])
+    );
+    let response = schema
+        .execute(r#"{ searchColumns(text: "primary") { column } }"#)
+        .await;
+    assert_eq!(
+        response.data.into_json().unwrap()["searchColumns"],
+        json!([{ "column": "c_custkey" }])
+    );
+
+    // Without a dictionary the descriptions are empty
+    let response = test_schema(Config::default())
+        .execute(r#"{ tableSchema(table: "customer") { description tags } }"#)
+        .await;
+    assert_eq!(
+        response.data.into_json().unwrap()["tableSchema"][1],
+        json!({ "description": "", "tags": [] })
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_malformed_data_dictionary_fails_validation() {
+    let path = std::env::temp_dir().join(format!("dictionary-{}.yaml", uuid::Uuid::new_v4()));
+    std::fs::write(&path, "customer:\n  c_name:\n    description: [unclosed\n").unwrap();
+    let config = Config {
+        data_dictionary_path: path.to_str().unwrap().to_string(),
+        ..Config::default()
+    };
+
+    let error = config.validate().unwrap_err();
+    assert!(error.contains("line "), "{}", error);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_insights_over_large_record_set_stay_within_prompt_limit() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .respond_with(ollama_reply(
+            "Mostly building customers with balances around 500.",
+        ))
+        .mount(&ollama)
+        .await;
+    let client = AgentClient::new(ollama.uri(), "llama2".to_string()).with_max_prompt_chars(1000);
+    let customers: Vec<Customer> = (0..2000)
+        .map(|i| Customer {
+            c_custkey: i,
+            c_name: format!("Customer#{}", i),
+            c_address: Some("Address".to_string()),
+            c_nationkey: i % 25,
+            c_phone: Some("25-989-741-2988".to_string()),
+            c_acctbal: (i % 1000) as f64,
+            c_mktsegment: MarketSegment::Building,
+            c_mktsegment_raw: None,
+            c_comment: Some("Test customer".to_string()),
+        })
+        .collect();
+
+    let insights = client.generate_insights(customers).await.unwrap();
+    assert!(!insights.is_empty());
+
+    let prompts: Vec<String> = ollama
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["prompt"].as_str().unwrap().to_string()
+        })
+        .collect();
+    // Chunk summaries, then the insights over them
+    assert!(prompts.len() > 2, "{} prompts", prompts.len());
+    assert!(prompts.iter().all(|prompt| prompt.len() <= 1000));
+    let last = prompts.last().unwrap();
+    assert!(last.contains("business insights") && last.contains("Summaries of its parts"));
+}
+
+#[tokio::test]
+async fn test_compare_queries_identical_reordered_and_different() {
+    use graphql_datafusion::auth::Claims;
+
+    let schema = analytics_schema(orders_batch(1.0));
+    let compare = |sql_a: &str, sql_b: &str, arguments: &str| {
+        async_graphql::Request::new(format!(
+            r#"{{ compareQueries(sqlA: "{}", sqlB: "{}" {}) {{
+                identical rowsA rowsB onlyInACount onlyInBCount differenceCount
+                onlyInA {{ key values }} onlyInB {{ key values }}
+                differences {{ key column valueA valueB }}
+            }} }}"#,
+            sql_a, sql_b, arguments
+        ))
+        .data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+    let orders = "SELECT o_orderkey, o_totalprice FROM orders ORDER BY o_orderkey";
+    let by_key = r#"keyColumns: ["o_orderkey"]"#;
+
+    // The same rows in another order, aligned on the key or on all values
+    let reordered = "SELECT o_totalprice, o_orderkey FROM orders ORDER BY o_orderkey DESC";
+    for arguments in [by_key, ""] {
+        let response = schema.execute(compare(orders, reordered, arguments)).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let comparison = response.data.into_json().unwrap()["compareQueries"].clone();
+        assert_eq!(comparison["identical"], json!(true));
+        assert_eq!(comparison["rowsA"], json!(3));
+        assert_eq!(comparison["rowsB"], json!(3));
+    }
+
+    // Order 1 dropped, order 4 added and the prices of orders 2 and 3 up by 0.1%
+    let changed = "SELECT o_orderkey, o_totalprice * 1.001 AS o_totalprice FROM orders \
+                   WHERE o_orderkey > 1 UNION ALL SELECT 4, 1.0";
+    let response = schema.execute(compare(orders, changed, by_key)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let comparison = response.data.into_json().unwrap()["compareQueries"].clone();
+    assert_eq!(comparison["identical"], json!(false));
+    assert_eq!(
+        comparison["onlyInA"],
+        json!([{ "key": [1], "values": [1, 10.0] }])
+    );
+    assert_eq!(
+        comparison["onlyInB"],
+        json!([{ "key": [4], "values": [4, 1.0] }])
+    );
+    let mut differences: Vec<_> = comparison["differences"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| {
+            (
+                d["key"].to_string(),
+                d["column"].clone(),
+                d["valueA"].clone(),
+            )
+        })
+        .collect();
+    differences.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        differences,
+        vec![
+            ("[2]".to_string(), json!("o_totalprice"), json!(5.0)),
+            ("[3]".to_string(), json!("o_totalprice"), json!(7.5)),
+        ]
+    );
+
+    // Within the tolerances only the missing and added rows remain
+    for tolerance in ["tolerance: 0.01", "relativeTolerance: 0.002"] {
+        let arguments = format!("{} {}", by_key, tolerance);
+        let response = schema.execute(compare(orders, changed, &arguments)).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let comparison = response.data.into_json().unwrap()["compareQueries"].clone();
+        assert_eq!(comparison["differenceCount"], json!(0));
+        assert_eq!(comparison["onlyInACount"], json!(1));
+        assert_eq!(comparison["onlyInBCount"], json!(1));
+    }
+
+    // Without keys a changed row is missing from one side and added to the other
+    let response = schema.execute(compare(orders, changed, "")).await;
+    let comparison = response.data.into_json().unwrap()["compareQueries"].clone();
+    assert_eq!(comparison["onlyInACount"], json!(3));
+    assert_eq!(comparison["onlyInBCount"], json!(3));
+    assert_eq!(comparison["differenceCount"], json!(0));
+
+    // Large results are matched through hashed partitions
+    let series = "SELECT value, value * 2 AS doubled FROM generate_series(1, 20000)";
+    let shuffled = format!("{} ORDER BY value % 7, value DESC", series);
+    let response = schema
+        .execute(compare(series, &shuffled, r#"keyColumns: ["value"]"#))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let comparison = response.data.into_json().unwrap()["compareQueries"].clone();
+    assert_eq!(comparison["identical"], json!(true));
+    assert_eq!(comparison["rowsB"], json!(20000));
+
+    let response = schema
+        .execute(compare(orders, reordered, r#"keyColumns: ["o_custkey"]"#))
+        .await;
+    assert!(
+        response.errors[0]
+            .message
+            .contains("Unknown key column o_custkey")
+    );
+
+    // Admins only
+    let response = schema
+        .execute(format!(
+            r#"{{ compareQueries(sqlA: "{}", sqlB: "{}") {{ identical }} }}"#,
+            orders, orders
+        ))
+        .await;
+    assert!(!response.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_compare_queries_bounded_in_rows_and_bytes() {
+    use graphql_datafusion::auth::Claims;
+
+    let query = r#"{ compareQueries(
+        sqlA: "SELECT c_custkey, c_name FROM customer",
+        sqlB: "SELECT c_custkey, c_name FROM customer"
+    ) { identical } }"#;
+    let admin = || {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+
+    let schema = test_schema(Config::default());
+    let response = schema.execute(admin()).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    // The two customers are one more than the ceiling
+    let schema = test_schema(Config {
+        max_compared_rows: 1,
+        ..Config::default()
+    });
+    let response = schema.execute(admin()).await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("RESULT_TOO_LARGE"));
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("more than 1 rows")
+    );
+
+    // Each row's values take more JSON than the budget
+    let schema = test_schema(Config {
+        max_result_json_bytes: 10,
+        ..Config::default()
+    });
+    let response = schema.execute(admin()).await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("RESULT_TOO_LARGE"));
+}
+
+#[tokio::test]
+async fn test_table_file_paths_admin_only() {
+    use graphql_datafusion::auth::Claims;
+
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_parquet("customer", &path).await.unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let as_admin = |query: &str| {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+
+    let response = schema
+        .execute(as_admin(r#"{ tableFilePaths(name: "customer") }"#))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let paths = response.data.into_json().unwrap()["tableFilePaths"].clone();
+    let file_name = std::path::Path::new(&path)
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert_eq!(paths.as_array().unwrap().len(), 1);
+    assert!(paths[0].as_str().unwrap().ends_with(file_name));
+
+    let response = schema
+        .execute(as_admin(r#"{ tableFilePaths(name: "missing") }"#))
+        .await;
+    assert!(response.errors[0].message.contains("Unknown table"));
+
+    let response = schema
+        .execute(r#"{ tableFilePaths(name: "customer") }"#)
+        .await;
+    std::fs::remove_file(&path).ok();
+    assert!(!response.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_table_files_shows_paths_to_admins_only() {
+    use graphql_datafusion::auth::Claims;
+
+    let path = write_parquet_fixture(customer_batch()).await;
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_parquet("customer", &path).await.unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let query = r#"{ tableFiles(tableName: "customer") { files { path } } }"#;
+
+    let response = schema
+        .execute(
+            async_graphql::Request::new(query)
+                .data(Claims::new("ops".to_string(), "admin".to_string())),
+        )
+        .await;
+    std::fs::remove_file(&path).ok();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let paths = response.data.into_json().unwrap()["tableFiles"]["files"].clone();
+    let file_name = std::path::Path::new(&path)
+        .file_name()
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert_eq!(paths.as_array().unwrap().len(), 1);
+    let backing = paths[0]["path"].as_str().unwrap();
+    assert!(backing.ends_with(".parquet") && backing.ends_with(file_name));
+
+    let response = schema.execute(query).await;
+    assert!(!response.errors.is_empty());
+}
+
+#[test]
+fn test_state_export_import_round_trip() {
+    use graphql_datafusion::state::{QUOTA_STATE, STATE_FILES, StateDir, read_state};
+
+    let root = std::env::temp_dir().join(format!("state-{}", uuid::Uuid::new_v4()));
+    let source = StateDir::new(root.join("source"));
+    small_quotas()
+        .with_state_file(source.path(&QUOTA_STATE))
+        .record_export("alice", Some("viewer"), 1)
+        .unwrap();
+    // Every state file must be part of the round trip
+    for file in STATE_FILES {
+        assert!(source.path(file).exists(), "{} not written", file.name);
+    }
+
+    let archive = root.join("state.tar.gz");
+    assert_eq!(source.export(&archive).unwrap(), STATE_FILES.len());
+    let restored = StateDir::new(root.join("restored"));
+    assert_eq!(restored.import(&archive).unwrap(), STATE_FILES.len());
+
+    for file in STATE_FILES {
+        let original: serde_json::Value = read_state(&source.path(file), file).unwrap().unwrap();
+        let copy: serde_json::Value = read_state(&restored.path(file), file).unwrap().unwrap();
+        assert_eq!(original, copy, "{}", file.name);
+    }
+    let quotas = small_quotas().with_state_file(restored.path(&QUOTA_STATE));
+    assert_eq!(quotas.usage("alice", Some("viewer")).exported_rows_today, 1);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn test_state_versions_migrated_or_rejected() {
+    use graphql_datafusion::state::{QUOTA_STATE, StateDir};
+
+    let root = std::env::temp_dir().join(format!("state-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&root).unwrap();
+    let state_dir = StateDir::new(&root);
+    let path = state_dir.path(&QUOTA_STATE);
+
+    // Unversioned files from before the envelope are migrated in place
+    std::fs::write(&path, r#"{"day":"2024-01-01","exported":{"alice":1}}"#).unwrap();
+    state_dir.migrate().unwrap();
+    let migrated: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(migrated["version"], json!(1));
+    assert_eq!(migrated["data"]["exported"]["alice"], json!(1));
+
+    // Files from a newer server are rejected
+    std::fs::write(&path, r#"{"version":9,"data":{}}"#).unwrap();
+    let error = state_dir.migrate().unwrap_err().to_string();
+    assert!(
+        error.contains("quota.json") && error.contains("version 9") && error.contains("up to 1"),
+        "{}",
+        error
+    );
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_column_stats_approx_distinct_within_tolerance() {
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // 1000 rows over 200 distinct values, every tenth row null
+    let values: Vec<Option<i64>> = (0..1000)
+        .map(|i| (i % 10 != 0).then_some(i % 200 + 1))
+        .collect();
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new("v", DataType::Int64, true)])),
+        vec![Arc::new(Int64Array::from(values))],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("samples", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let stats = |approx: bool| {
+        format!(
+            r#"{{ columnStats(table: "samples", column: "v", approx: {}) {{ rowCount nullCount distinctCount approximate }} }}"#,
+            approx
+        )
+    };
+
+    let response = schema.execute(stats(false)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let exact = response.data.into_json().unwrap()["columnStats"].clone();
+    assert_eq!(
+        exact,
+        json!({ "rowCount": 1000, "nullCount": 100, "distinctCount": 180, "approximate": false })
+    );
+
+    let response = schema.execute(stats(true)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let approx = response.data.into_json().unwrap()["columnStats"].clone();
+    assert_eq!(approx["approximate"], json!(true));
+    assert_eq!(approx["nullCount"], json!(100));
+    let estimate = approx["distinctCount"].as_i64().unwrap();
+    assert!((estimate - 180).abs() <= 9, "estimate {}", estimate);
+}
+
+#[tokio::test]
+async fn test_read_only_mode_blocks_writes_and_ai() {
+    use graphql_datafusion::auth::Claims;
+
+    let schema = test_schema(Config {
+        enable_ai: true,
+        ..Config::default()
+    });
+    let admin = |query: &str| {
+        async_graphql::Request::new(query).data(Claims::new("ops".to_string(), "admin".to_string()))
+    };
+    let code = |response: &async_graphql::Response| {
+        response.errors[0]
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code"))
+            .cloned()
+    };
+    let read_only_code = Some(async_graphql::Value::from("SERVICE_READ_ONLY"));
+
+    let response = schema
+        .execute(admin("mutation { setReadOnly(enabled: true) }"))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert!(graphql_datafusion::metrics::render().contains("read_only_mode"));
+    let response = schema.execute("{ serverInfo { readOnly } }").await;
+    assert_eq!(
+        response.data.into_json().unwrap()["serverInfo"],
+        json!({ "readOnly": true })
+    );
+
+    // Mutations, also when selected through a fragment
+    for mutation in [
+        r#"mutation { dropTable(tableName: "customer") }"#,
+        r#"mutation { reloadTable(tableName: "customer") }"#,
+        r#"mutation { ...Drop } fragment Drop on MutationRoot { dropTable(tableName: "customer") }"#,
+    ] {
+        let response = schema.execute(admin(mutation)).await;
+        assert_eq!(code(&response), read_only_code, "{}", mutation);
+    }
+    // AI queries
+    let response = schema
+        .execute(r#"{ naturalLanguageQuery(input: "top customers") }"#)
+        .await;
+    assert_eq!(code(&response), read_only_code);
+    // Plain data queries still run
+    let response = schema
+        .execute(r#"{ tableCount(tableName: "customer") }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let response = schema
+        .execute(admin("mutation { setReadOnly(enabled: false) }"))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let response = schema
+        .execute(admin(r#"mutation { dropTable(tableName: "customer") }"#))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+}
+
+#[tokio::test]
+async fn test_field_naming_policy() {
+    use graphql_datafusion::graphql::naming::{NamingPolicy, camel_case};
+
+    assert_eq!(camel_case("c_custkey"), "cCustkey");
+    assert_eq!(camel_case("c_mktsegment_raw"), "cMktsegmentRaw");
+    assert_eq!("Camel".parse::<NamingPolicy>(), Ok(NamingPolicy::Camel));
+    assert!("kebab".parse::<NamingPolicy>().is_err());
+
+    let introspect = r#"{ __type(name: "Customer") {
+        fields(includeDeprecated: true) { name isDeprecated }
+    } }"#;
+    let field_names = |data: serde_json::Value| -> Vec<(String, bool)> {
+        data["__type"]["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| {
+                (
+                    field["name"].as_str().unwrap().to_string(),
+                    field["isDeprecated"].as_bool().unwrap(),
+                )
+            })
+            .collect()
+    };
+
+    // Both spellings resolve to the same values
+    let schema = test_schema(Config {
+        field_naming: NamingPolicy::Both,
+        ..Config::default()
+    });
+    let response = schema
+        .execute("{ customers { c_custkey cCustkey c_name cName } }")
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    let customers = data["customers"].as_array().unwrap();
+    assert_eq!(customers.len(), 2);
+    for customer in customers {
+        assert_eq!(customer["c_custkey"], customer["cCustkey"]);
+        assert_eq!(customer["c_name"], customer["cName"]);
+    }
+    assert_eq!(customers[1]["cName"], json!("Customer#2"));
+    let fields = field_names(schema.execute(introspect).await.data.into_json().unwrap());
+    assert!(fields.contains(&("cCustkey".to_string(), false)));
+    assert!(fields.contains(&("c_custkey".to_string(), true)));
+
+    // Each single-spelling policy hides and refuses the other spelling
+    for (policy, exposed, hidden) in [
+        (NamingPolicy::Raw, "c_custkey", "cCustkey"),
+        (NamingPolicy::Camel, "cCustkey", "c_custkey"),
+    ] {
+        let schema = test_schema(Config {
+            field_naming: policy,
+            ..Config::default()
+        });
+        let fields = field_names(schema.execute(introspect).await.data.into_json().unwrap());
+        assert!(fields.iter().any(|(name, _)| name == exposed), "{}", policy);
+        assert!(!fields.iter().any(|(name, _)| name == hidden), "{}", policy);
+
+        let response = schema
+            .execute(format!("{{ customers {{ {} }} }}", exposed))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let response = schema
+            .execute(format!("{{ customers {{ {} }} }}", hidden))
+            .await;
+        assert!(!response.errors.is_empty(), "{} in {}", hidden, policy);
+    }
+}
+
+#[tokio::test]
+async fn test_quantile_median_of_known_column() {
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // 1 to 100 in reverse, with nulls that do not count
+    let values: Vec<Option<i64>> = (1..=100).rev().map(Some).chain([None, None]).collect();
+    let labels: Vec<String> = (0..values.len()).map(|i| format!("row {}", i)).collect();
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("v", DataType::Int64, true),
+            Field::new("label", DataType::Utf8, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(values)),
+            Arc::new(StringArray::from(labels)),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("samples", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let quantile = |column: &str, q: f64, approx: bool| {
+        let query = format!(
+            r#"{{ quantile(table: "samples", column: "{}", q: {}, approx: {}) }}"#,
+            column, q, approx
+        );
+        let schema = schema.clone();
+        async move { schema.execute(query).await }
+    };
+
+    for (q, expected) in [(0.5, 50.5), (0.25, 25.75), (0.0, 1.0), (1.0, 100.0)] {
+        let response = quantile("v", q, false).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let value = response.data.into_json().unwrap()["quantile"]
+            .as_f64()
+            .unwrap();
+        assert!((value - expected).abs() < 1e-9, "q {}: {}", q, value);
+    }
+
+    let response = quantile("v", 0.5, true).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let estimate = response.data.into_json().unwrap()["quantile"]
+        .as_f64()
+        .unwrap();
+    assert!((estimate - 50.5).abs() <= 1.0, "estimate {}", estimate);
+
+    let response = quantile("label", 0.5, false).await;
+    assert!(response.errors[0].message.contains("not numeric"));
+    let response = quantile("v", 1.5, false).await;
+    assert!(response.errors[0].message.contains("between 0 and 1"));
+}
+
+#[tokio::test]
+async fn test_outliers_flag_injected_value() {
+    use datafusion::arrow::array::{Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Amounts around 100 with one obvious outlier at id 99
+    let mut amounts: Vec<f64> = (0..40).map(|i| 95.0 + (i % 10) as f64).collect();
+    amounts.push(10_000.0);
+    let ids: Vec<i64> = (0..40).chain([99]).collect();
+    let batch = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("amount", DataType::Float64, false),
+            Field::new("label", DataType::Utf8, false),
+        ])),
+        vec![
+            Arc::new(Int64Array::from(ids.clone())),
+            Arc::new(Float64Array::from(amounts)),
+            Arc::new(StringArray::from(
+                ids.iter()
+                    .map(|id| format!("row {}", id))
+                    .collect::<Vec<_>>(),
+            )),
+        ],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("payments", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    for method in ["iqr", "zscore"] {
+        let response = schema
+            .execute(format!(
+                r#"{{ outliers(table: "payments", column: "amount", method: "{}") }}"#,
+                method
+            ))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let extensions = serde_json::to_value(&response.extensions).unwrap();
+        assert!(extensions["outliers"]["upper"].as_f64().unwrap() < 10_000.0);
+        assert_eq!(
+            response.data.into_json().unwrap()["outliers"],
+            json!([{ "id": 99, "amount": 10000.0, "label": "row 99" }]),
+            "{}",
+            method
+        );
+    }
+
+    // A threshold wide enough keeps every row
+    let response = schema
+        .execute(
+            r#"{ outliers(table: "payments", column: "amount", method: "zscore", threshold: 100) }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["outliers"], json!([]));
+
+    for query in [
+        r#"{ outliers(table: "payments", column: "label", method: "iqr") }"#,
+        r#"{ outliers(table: "payments", column: "amount", method: "mad") }"#,
+        r#"{ outliers(table: "payments", column: "amount", method: "iqr", threshold: -1) }"#,
+        r#"{ outliers(table: "missing", column: "amount", method: "iqr") }"#,
+    ] {
+        let response = schema.execute(query).await;
+        assert_eq!(response.errors.len(), 1, "{}", query);
+    }
+
+    // Values whose spread overflows f64 leave no finite bounds to query with
+    let extremes = RecordBatch::try_new(
+        Arc::new(Schema::new(vec![Field::new(
+            "amount",
+            DataType::Float64,
+            false,
+        )])),
+        vec![Arc::new(Float64Array::from(vec![
+            1e308, -1e308, 1e308, -1e308,
+        ]))],
+    )
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("extremes", vec![extremes]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    for method in ["iqr", "zscore"] {
+        let response = schema
+            .execute(format!(
+                r#"{{ outliers(table: "extremes", column: "amount", method: "{}") }}"#,
+                method
+            ))
+            .await;
+        assert_eq!(response.errors.len(), 1, "{}", method);
+        assert!(
+            response.errors[0].message.contains("not finite"),
+            "{}: {:?}",
+            method,
+            response.errors
+        );
+    }
+}
+
+#[test]
+fn test_pipeline_result_json_shape() {
+    use graphql_datafusion::agents::pipeline::{
+        ExecutionStats, PipelineResult, StageDurations, ValidationOutcome,
+    };
+    use graphql_datafusion::models::data::{ColumnLineage, SourceColumn};
+
+    let report = PipelineResult {
+        question: "list customer names".to_string(),
+        agent: "default".to_string(),
+        agent_type: "default".to_string(),
+        model: "sqlcoder".to_string(),
+        prompt_hash: Some("ab12".to_string()),
+        sql: Some("SELECT c_name FROM customer".to_string()),
+        validation: ValidationOutcome {
+            valid: true,
+            corrections: 1,
+            error: None,
+        },
+        execution: ExecutionStats {
+            tables: vec!["customer".to_string()],
+            row_count: Some(2),
+            lineage: vec![ColumnLineage {
+                output: "c_name".to_string(),
+                sources: vec![SourceColumn {
+                    table: "customer".to_string(),
+                    column: "c_name".to_string(),
+                }],
+            }],
+        },
+        sample: vec![
+            json!({ "c_name": "Customer#1" }),
+            json!({ "c_name": "Customer#2" }),
+        ],
+        insights: Some("Two customers".to_string()),
+        durations: StageDurations {
+            llm_ms: 120,
+            execution_ms: 4,
+            insights_ms: 80,
+            total_ms: 210,
+        },
+        warnings: vec!["Generated SQL failed and was corrected by the model: x".to_string()],
+    };
+
+    let expected = json!({
+        "question": "list customer names",
+        "agent": "default",
+        "agentType": "default",
+        "model": "sqlcoder",
+        "promptHash": "ab12",
+        "sql": "SELECT c_name FROM customer",
+        "validation": { "valid": true, "corrections": 1, "error": null },
+        "tables": ["customer"],
+        "rowCount": 2,
+        "lineage": [{ "output": "c_name", "sources": [{ "table": "customer", "column": "c_name" }] }],
+        "sample": [{ "c_name": "Customer#1" }, { "c_name": "Customer#2" }],
+        "insights": "Two customers",
+        "llmMs": 120,
+        "executionMs": 4,
+        "insightsMs": 80,
+        "totalMs": 210,
+        "warnings": ["Generated SQL failed and was corrected by the model: x"],
+    });
+    assert_eq!(serde_json::to_value(&report).unwrap(), expected);
+    let parsed: PipelineResult = serde_json::from_value(expected).unwrap();
+    assert_eq!(parsed, report);
+
+    // Only admins see the sample and the prompt hash
+    assert_eq!(report.for_role(Some("admin")), report);
+    for role in [None, Some("analyst")] {
+        let filtered = serde_json::to_value(report.for_role(role)).unwrap();
+        assert_eq!(filtered["promptHash"], json!(null));
+        assert_eq!(filtered["sample"], json!([]));
+        assert_eq!(filtered["sql"], json!("SELECT c_name FROM customer"));
+    }
+}
+
+#[tokio::test]
+async fn test_process_query_reports_every_stage() {
+    let ollama = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains("Translate"))
+        .respond_with(ollama_reply(
+            "SELECT c_name FROM customer ORDER BY c_custkey",
+        ))
+        .mount(&ollama)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/generate"))
+        .and(body_string_contains("Customer#2"))
+        .respond_with(ollama_reply("Two customers"))
+        .mount(&ollama)
+        .await;
+    let client = AgentClient::new(ollama.uri(), "sqlcoder".to_string());
+    let prompt = client.translation_prompt("list customer names");
+    let orchestrator = AgentOrchestrator::new()
+        .with_agent("default".to_string(), client)
+        .with_context(customer_fixture());
+
+    let report = orchestrator
+        .process_query("list customer names", None)
+        .await
+        .unwrap();
+    assert_eq!(report.question, "list customer names");
+    assert_eq!(report.agent, "default");
+    assert_eq!(report.model, "sqlcoder");
+    assert_eq!(
+        report.prompt_hash,
+        Some(graphql_datafusion::agents::pipeline::prompt_hash(&prompt))
+    );
+    assert_eq!(
+        report.sql.as_deref(),
+        Some("SELECT c_name FROM customer ORDER BY c_custkey")
+    );
+    assert!(report.validation.valid);
+    assert_eq!(report.execution.tables, vec!["customer".to_string()]);
+    assert_eq!(report.execution.row_count, Some(2));
+    assert_eq!(
+        report.sample,
+        vec![
+            json!({ "c_name": "Customer#1" }),
+            json!({ "c_name": "Customer#2" })
+        ]
+    );
+    assert_eq!(report.insights.as_deref(), Some("Two customers"));
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    assert!(report.durations.total_ms >= report.durations.insights_ms);
+
+    let record = orchestrator.audit_log().records().pop().unwrap();
+    assert_eq!(record.prompt_hash, report.prompt_hash);
+    assert!(record.success);
+}
+
+#[tokio::test]
+async fn test_upload_csv_and_query_it() {
+    use graphql_datafusion::auth::Claims;
+    use graphql_datafusion::datafusion::upload::UploadedTables;
+    use graphql_datafusion::reaper::Expirable;
+    use std::time::{Duration, Instant};
+
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    let schema = build_schema(
+        df_ctx.clone(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(Config {
+            max_upload_bytes: 64,
+            upload_ttl_secs: 60,
+            max_uploaded_tables: 2,
+            ..Config::default()
+        }),
+    );
+    let as_user = |user: &str, query: &str| {
+        async_graphql::Request::new(query).data(Claims::new(user.to_string(), "user".to_string()))
+    };
+    let pasted = r#"mutation {
+        uploadTable(name: "pasted", format: CSV, data: "id,amount\n1,10.5\n2,20\n3,30\n") {
+            tableName columns rowCount expiresAt
+        }
+    }"#;
+
+    // Uploads need a caller to own them
+    let response = schema.execute(pasted).await;
+    assert!(!response.errors.is_empty());
+
+    let response = schema.execute(as_user("alice", pasted)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let result = &response.data.into_json().unwrap()["uploadTable"];
+    assert_eq!(result["tableName"], "pasted");
+    assert_eq!(result["columns"], json!(["id", "amount"]));
+    assert_eq!(result["rowCount"], 3);
+
+    let response = schema
+        .execute(r#"{ quantile(table: "pasted", column: "amount", q: 0.5) }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["quantile"], 20.0);
+
+    // Another user may not replace it, its owner may
+    let response = schema.execute(as_user("bob", pasted)).await;
+    assert!(
+        response.errors[0]
+            .message
+            .contains("uploaded by another user"),
+        "{:?}",
+        response.errors
+    );
+    let response = schema.execute(as_user("alice", pasted)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+
+    let response = schema
+        .execute(as_user(
+            "bob",
+            r#"mutation {
+                uploadTable(name: "rows", format: JSON, data: "[{\"id\": 1}, {\"id\": 2}]") { rowCount }
+            }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["uploadTable"]["rowCount"],
+        2
+    );
+
+    let oversized = "id\n".to_string() + &"1\n".repeat(40);
+    let response = schema
+        .execute(
+            as_user(
+                "bob",
+                "mutation($data: String!) { uploadTable(name: \"big\", format: CSV, data: $data) { rowCount } }",
+            )
+            .variables(async_graphql::Variables::from_json(json!({ "data": oversized }))),
+        )
+        .await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], "PAYLOAD_TOO_LARGE");
+    assert!(!df_ctx.get_table_names().contains(&"big".to_string()));
+
+    // Two uploads are live, the limit
+    let response = schema
+        .execute(as_user(
+            "carol",
+            r#"mutation { uploadTable(name: "third", format: CSV, data: "id\n1\n") { rowCount } }"#,
+        ))
+        .await;
+    assert!(
+        response.errors[0].message.contains("the limit"),
+        "{:?}",
+        response.errors
+    );
+
+    // Uploads are dropped once their time to live has passed
+    let uploads = schema.data::<Arc<UploadedTables>>().unwrap();
+    assert_eq!(uploads.live_entries(), 2);
+    let mut expired = uploads.reap_expired(Instant::now() + Duration::from_secs(60));
+    expired.sort();
+    assert_eq!(expired, vec!["pasted".to_string(), "rows".to_string()]);
+    assert!(!df_ctx.get_table_names().contains(&"pasted".to_string()));
+}
+
+#[tokio::test]
+async fn test_line_items_of_one_order() {
+    use datafusion::arrow::array::{
+        ArrayRef, Date32Array, Float64Array, Int32Array, Int64Array, StringArray,
+    };
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Two lines of order 1 and one of order 2; 9568 days is 1996-03-13
+    let strings = |values: [&str; 3]| Arc::new(StringArray::from(values.to_vec())) as ArrayRef;
+    let floats = |values: [f64; 3]| Arc::new(Float64Array::from(values.to_vec())) as ArrayRef;
+    let dates =
+        |first: i32| Arc::new(Date32Array::from(vec![first, first + 1, first + 2])) as ArrayRef;
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "l_orderkey",
+            Arc::new(Int64Array::from(vec![1, 1, 2])) as ArrayRef,
+        ),
+        (
+            "l_partkey",
+            Arc::new(Int64Array::from(vec![155, 68, 107])) as ArrayRef,
+        ),
+        (
+            "l_suppkey",
+            Arc::new(Int64Array::from(vec![7, 3, 2])) as ArrayRef,
+        ),
+        (
+            "l_linenumber",
+            Arc::new(Int32Array::from(vec![2, 1, 1])) as ArrayRef,
+        ),
+        ("l_quantity", floats([36.0, 17.0, 38.0])),
+        ("l_extendedprice", floats([45983.16, 21168.23, 44694.46])),
+        ("l_discount", floats([0.09, 0.04, 0.0])),
+        ("l_tax", floats([0.06, 0.02, 0.05])),
+        ("l_returnflag", strings(["N", "N", "X"])),
+        ("l_linestatus", strings(["O", "O", "O"])),
+        ("l_shipdate", dates(9568)),
+        ("l_commitdate", dates(9540)),
+        ("l_receiptdate", dates(9590)),
+        (
+            "l_shipinstruct",
+            strings(["DELIVER IN PERSON", "TAKE BACK RETURN", "NONE"]),
+        ),
+        ("l_shipmode", strings(["TRUCK", "MAIL", "RAIL"])),
+        ("l_comment", strings(["first", "second", "third"])),
+    ])
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("lineitem", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(
+            "{ lineItems(limit: 10, orderKey: 1) {
+                l_linenumber l_quantity l_extendedprice l_shipdate lReturnflag lComment
+            } }",
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["lineItems"],
+        json!([
+            {
+                "l_linenumber": 1,
+                "l_quantity": 17.0,
+                "l_extendedprice": 21168.23,
+                "l_shipdate": "1996-03-14",
+                "lReturnflag": "NOT_RETURNED",
+                "lComment": "second"
+            },
+            {
+                "l_linenumber": 2,
+                "l_quantity": 36.0,
+                "l_extendedprice": 45983.16,
+                "l_shipdate": "1996-03-13",
+                "lReturnflag": "NOT_RETURNED",
+                "lComment": "first"
+            }
+        ])
+    );
+
+    // Unexpected codes keep their raw value
+    let response = schema
+        .execute("{ lineItems(orderKey: 2) { lReturnflag lReturnflagRaw lReceiptdate } }")
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["lineItems"],
+        json!([{
+            "lReturnflag": "UNKNOWN",
+            "lReturnflagRaw": "X",
+            "lReceiptdate": "1996-04-06"
+        }])
+    );
+}
+
+#[tokio::test]
+async fn test_wide_results_stop_at_the_json_byte_limit() {
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // 2000 notes of 1000 characters, about 2 MB of JSON
+    let notes: Vec<String> = (0..2000).map(|i| format!("{:0>1000}", i)).collect();
+    let batch = RecordBatch::try_from_iter(vec![
+        ("id", Arc::new(Int64Array::from_iter_values(0..2000)) as _),
+        ("note", Arc::new(StringArray::from(notes)) as _),
+    ])
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("notes", vec![batch]).unwrap();
+    let config = Config {
+        max_result_json_bytes: 100_000,
+        ..Config::default()
+    };
+    let schema = build_schema(
+        Arc::new(df_ctx),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(config),
+    );
+
+    let response = schema
+        .execute(r#"{ executeSql(query: "SELECT id, note FROM notes ORDER BY id") { rowCount } }"#)
+        .await;
+    assert_eq!(response.errors.len(), 1, "{:?}", response.errors);
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("RESULT_TOO_LARGE"));
+    assert_eq!(error["extensions"]["limit"], json!(100_000));
+    // Each row takes a little over 1000 bytes
+    let rows = error["extensions"]["rows"].as_u64().unwrap();
+    assert!((90..100).contains(&rows), "{} rows", rows);
+
+    // Narrow columns of the same rows fit
+    let response = schema
+        .execute(r#"{ executeSql(query: "SELECT id FROM notes") { rowCount } }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["executeSql"]["rowCount"],
+        json!(2000)
+    );
+}
+
+#[tokio::test]
+async fn test_execute_sql_returns_json_rows() {
+    let schema = default_schema(customer_fixture());
+    let execute = |sql: &str| {
+        format!(
+            r#"{{ executeSql(query: "{}") {{ columns rows rowCount }} }}"#,
+            sql
+        )
+    };
+
+    // Int64, Utf8View, Float64, Boolean and Date32 columns
+    let response = schema
+        .execute(execute(
+            "WITH big AS (SELECT c_custkey FROM customer WHERE c_custkey > 1) \
+             SELECT c_custkey, arrow_cast(c_name, 'Utf8View') AS name, \
+             c_custkey * 1.5 AS score, c_custkey IN (SELECT c_custkey FROM big) AS big, \
+             CASE WHEN c_custkey = 1 THEN DATE '1996-01-02' END AS since \
+             FROM customer ORDER BY c_custkey",
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["executeSql"],
+        json!({
+            "columns": ["c_custkey", "name", "score", "big", "since"],
+            "rows": [
+                { "c_custkey": 1, "name": "Customer#1", "score": 1.5, "big": false, "since": "1996-01-02" },
+                { "c_custkey": 2, "name": "Customer#2", "score": 3.0, "big": true, "since": null },
+            ],
+            "rowCount": 2,
+        })
+    );
+
+    for statement in [
+        "DROP TABLE customer",
+        "INSERT INTO customer VALUES (3, 'Customer#3')",
+        "EXPLAIN SELECT * FROM customer",
+        "SELECT 1; SELECT 2",
+    ] {
+        let response = schema.execute(execute(statement)).await;
+        assert!(
+            response.errors[0].message.starts_with("Invalid query"),
+            "{}: {:?}",
+            statement,
+            response.errors
+        );
+    }
+    let response = schema.execute("{ tables }").await;
+    assert_eq!(
+        response.data.into_json().unwrap()["tables"],
+        json!(["customer"])
+    );
+}
+
+#[tokio::test]
+async fn test_cache_hints_of_dimension_and_volatile_fields() {
+    use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let region = RecordBatch::try_from_iter(vec![
+        (
+            "r_regionkey",
+            Arc::new(Int64Array::from(vec![0])) as ArrayRef,
+        ),
+        (
+            "r_name",
+            Arc::new(StringArray::from(vec!["AFRICA"])) as ArrayRef,
+        ),
+        (
+            "r_comment",
+            Arc::new(StringArray::from(vec![""])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+    let df_ctx = customer_fixture();
+    df_ctx.register_batches("region", vec![region]).unwrap();
+    let schema = default_schema(df_ctx);
+    let cache_control = |query: &'static str| {
+        let schema = schema.clone();
+        async move {
+            let response = schema.execute(query).await;
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+            response.cache_control
+        }
+    };
+
+    let dimension = cache_control("{ regions { rName } }").await;
+    let rows = cache_control("{ customers { c_name } }").await;
+    let count = cache_control(r#"{ tableCount(tableName: "customer") }"#).await;
+    assert_eq!(dimension.value(), Some("max-age=3600".to_string()));
+    assert_eq!(rows.value(), Some("max-age=60".to_string()));
+    assert_eq!(count.value(), Some("no-cache".to_string()));
+    assert!(dimension.max_age > rows.max_age && rows.max_age > count.max_age);
+
+    // The shortest hint of a response wins
+    let mixed = cache_control("{ regions { rName } customers { c_name } }").await;
+    assert_eq!(mixed.max_age, 60);
+    let mixed = cache_control(r#"{ regions { rName } tableCount(tableName: "region") }"#).await;
+    assert_eq!(mixed.value(), Some("no-cache".to_string()));
+}
+
+#[tokio::test]
+async fn test_supplier_and_part_supply_resolvers_over_parquet() {
+    use datafusion::arrow::array::{ArrayRef, Float64Array, Int32Array, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let strings = |values: [&str; 2]| Arc::new(StringArray::from(values.to_vec())) as ArrayRef;
+    let ints = |values: [i64; 2]| Arc::new(Int64Array::from(values.to_vec())) as ArrayRef;
+    let floats = |values: [f64; 2]| Arc::new(Float64Array::from(values.to_vec())) as ArrayRef;
+    let supplier = RecordBatch::try_from_iter(vec![
+        ("s_suppkey", ints([2, 1])),
+        ("s_name", strings(["Supplier#2", "Supplier#1"])),
+        ("s_address", strings(["2 Supply Road", "1 Supply Road"])),
+        // 64-bit, as in the TPCH parquet files
+        ("s_nationkey", ints([7, 17])),
+        ("s_phone", strings(["27-918-335-1736", "17-369-885-8216"])),
+        ("s_acctbal", floats([4032.68, 5755.94])),
+        ("s_comment", strings(["second", "first"])),
+    ])
+    .unwrap();
+    let partsupp = RecordBatch::try_from_iter(vec![
+        ("ps_partkey", ints([1, 1])),
+        ("ps_suppkey", ints([2, 1])),
+        (
+            "ps_availqty",
+            Arc::new(Int32Array::from(vec![3325, 8076])) as ArrayRef,
+        ),
+        ("ps_supplycost", floats([771.64, 993.49])),
+        ("ps_comment", strings(["second", "first"])),
+    ])
+    .unwrap();
+    let supplier_path = write_parquet_fixture(supplier).await;
+    let partsupp_path = write_parquet_fixture(partsupp).await;
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx
+        .register_parquet("supplier", &supplier_path)
+        .await
+        .unwrap();
+    df_ctx
+        .register_parquet("partsupp", &partsupp_path)
+        .await
+        .unwrap();
+    let df_ctx = Arc::new(df_ctx);
+    let mismatches = df_ctx.check_model_schemas().await;
+    assert!(mismatches.is_empty(), "{:?}", mismatches);
+    let schema = default_schema(df_ctx);
+
+    let response = schema
+        .execute(
+            "{
+                suppliers(limit: 1) { sSuppkey sName sNationkey sAcctbal }
+                partSupplies { psPartkey psSuppkey psAvailqty psSupplycost }
+            }",
+        )
+        .await;
+    std::fs::remove_file(&supplier_path).ok();
+    std::fs::remove_file(&partsupp_path).ok();
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    let data = response.data.into_json().unwrap();
+    assert_eq!(
+        data["suppliers"],
+        json!([{ "sSuppkey": 1, "sName": "Supplier#1", "sNationkey": 17, "sAcctbal": 5755.94 }])
+    );
+    assert_eq!(
+        data["partSupplies"],
+        json!([
+            { "psPartkey": 1, "psSuppkey": 1, "psAvailqty": 8076, "psSupplycost": 993.49 },
+            { "psPartkey": 1, "psSuppkey": 2, "psAvailqty": 3325, "psSupplycost": 771.64 },
+        ])
+    );
+
+    // Pages are checked like those of the other list fields
+    let response = schema
+        .execute("{ suppliers(limit: -5) { sSuppkey } }")
+        .await;
+    assert_eq!(response.errors[0].message, "limit must be positive");
+
+    // The other tables are not registered here
+    let response = schema.execute("{ nations { nName } }").await;
+    assert!(!response.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_sales_analytics_totals_and_regions() {
+    use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let ints = |values: Vec<i64>| Arc::new(Int64Array::from(values)) as ArrayRef;
+    let strings = |values: Vec<&str>| Arc::new(StringArray::from(values)) as ArrayRef;
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    let tables = [
+        (
+            "orders",
+            RecordBatch::try_from_iter(vec![
+                ("o_orderkey", ints(vec![1, 2, 3, 4])),
+                ("o_custkey", ints(vec![1, 1, 2, 3])),
+                (
+                    "o_totalprice",
+                    Arc::new(Float64Array::from(vec![100.0, 50.0, 30.0, 20.0])) as ArrayRef,
+                ),
+            ]),
+        ),
+        (
+            "customer",
+            RecordBatch::try_from_iter(vec![
+                ("c_custkey", ints(vec![1, 2, 3])),
+                ("c_nationkey", ints(vec![0, 1, 2])),
+            ]),
+        ),
+        (
+            "nation",
+            RecordBatch::try_from_iter(vec![
+                ("n_nationkey", ints(vec![0, 1, 2])),
+                ("n_name", strings(vec!["CANADA", "CHINA", "JAPAN"])),
+                ("n_regionkey", ints(vec![1, 2, 2])),
+            ]),
+        ),
+        (
+            "region",
+            RecordBatch::try_from_iter(vec![
+                ("r_regionkey", ints(vec![1, 2])),
+                ("r_name", strings(vec!["AMERICA", "ASIA"])),
+            ]),
+        ),
+    ];
+    for (name, batch) in tables {
+        df_ctx.register_batches(name, vec![batch.unwrap()]).unwrap();
+    }
+    let schema = default_schema(df_ctx.clone());
+    let query = "{ salesAnalytics {
+        totalSales totalOrders avgOrderValue
+        salesByRegion { region totalSales customerCount }
+    } }";
+    let expected = json!({
+        "totalSales": 200.0,
+        "totalOrders": 4,
+        "avgOrderValue": 50.0,
+        "salesByRegion": [
+            { "region": "AMERICA", "totalSales": 150.0, "customerCount": 1 },
+            { "region": "ASIA", "totalSales": 50.0, "customerCount": 2 }
+        ]
+    });
+
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["salesAnalytics"],
+        expected
+    );
+
+    // With the dimensions cached, regions are named from the cache instead of
+    // joining nation and region, with the same result
+    df_ctx.cache_dimensions().await.unwrap();
+    assert_eq!(df_ctx.dimensions().region_of_nation(1), Some("ASIA"));
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["salesAnalytics"],
+        expected
+    );
+}
+
+#[tokio::test]
+async fn test_query_as_of_earlier_revision() {
+    use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::datafusion::context::TableDef;
+
+    let customers = |count: i64| {
+        RecordBatch::try_from_iter(vec![
+            (
+                "c_custkey",
+                Arc::new(Int64Array::from_iter_values(1..=count)) as ArrayRef,
+            ),
+            (
+                "c_name",
+                Arc::new(StringArray::from_iter_values(
+                    (1..=count).map(|key| format!("Customer#{}", key)),
+                )) as ArrayRef,
+            ),
+        ])
+        .unwrap()
+    };
+    // Stands in for an ETL run adding a file to the table's directory
+    let dir = std::env::temp_dir().join(format!("revisions_{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let add_file = |count: i64| {
+        let dir = dir.clone();
+        async move {
+            let written = write_parquet_fixture(customers(count)).await;
+            let name = format!("part-{}.parquet", uuid::Uuid::new_v4());
+            std::fs::rename(written, dir.join(name)).unwrap();
+        }
+    };
+    let count_at = |revision: Option<i64>| match revision {
+        Some(revision) => format!(
+            r#"{{ executeSql(query: "SELECT COUNT(*) AS n FROM customer", asOfRevision: {}) {{ rows }} }}"#,
+            revision
+        ),
+        None => {
+            r#"{ executeSql(query: "SELECT COUNT(*) AS n FROM customer") { rows } }"#.to_string()
+        }
+    };
+
+    add_file(2).await;
+    let path = dir.to_str().unwrap();
+    let df_ctx = DataFusionContext::with_tables("", &[TableDef::from_path("customer", path)])
+        .await
+        .unwrap()
+        .with_kept_revisions(1);
+    let df_ctx = Arc::new(df_ctx);
+    let schema = default_schema(df_ctx.clone());
+
+    add_file(1).await;
+    df_ctx.reload_table("customer").await.unwrap();
+    assert_eq!(df_ctx.current_revision(), 2);
+
+    let response = schema.execute(count_at(None)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["executeSql"]["rows"],
+        json!([{ "n": 3 }])
+    );
+    let extensions = serde_json::to_value(&response.extensions).unwrap();
+    assert_eq!(extensions["executeSql"]["revision"], json!(2));
+    // The earlier revision kept the files the table had, not the added one
+    let response = schema.execute(count_at(Some(1))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["executeSql"]["rows"],
+        json!([{ "n": 2 }])
+    );
+
+    let response = schema
+        .execute("{ tableRevisions { revision current supersededBy } }")
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["tableRevisions"],
+        json!([
+            { "revision": 2, "current": true, "supersededBy": null },
+            { "revision": 1, "current": false, "supersededBy": "customer" }
+        ])
+    );
+
+    // Only one earlier revision is kept, so a second reload evicts the first
+    add_file(1).await;
+    df_ctx.reload_table("customer").await.unwrap();
+    let response = schema.execute(count_at(Some(1))).await;
+    assert!(
+        response.errors[0].message.contains("no longer kept"),
+        "{:?}",
+        response.errors
+    );
+    let response = schema.execute(count_at(Some(2))).await;
+    assert_eq!(
+        response.data.into_json().unwrap()["executeSql"]["rows"],
+        json!([{ "n": 3 }])
+    );
+    let response = schema.execute(count_at(Some(9))).await;
+    assert!(
+        response.errors[0].message.contains("does not exist"),
+        "{:?}",
+        response.errors
+    );
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_config_rejects_unordered_latency_buckets() {
+    let config = Config {
+        query_latency_buckets: vec![0.1, 0.5, 0.25],
+        ..Config::default()
+    };
+    let error = config.validate().unwrap_err();
+    assert!(error.contains("strictly increasing"), "{}", error);
+
+    let config = Config {
+        http_latency_buckets: vec![0.1, f64::NAN],
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+    let config = Config {
+        llm_latency_buckets: Vec::new(),
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+    let config = Config {
+        metric_prefix: "acme-".to_string(),
+        ..Config::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn test_metrics_render_configured_buckets_and_exemplars() {
+    use graphql_datafusion::metrics::{Metrics, READ_ONLY_MODE, with_request_id};
+
+    let config = Config {
+        metric_prefix: "acme_".to_string(),
+        query_latency_buckets: vec![0.1, 1.0],
+        llm_latency_buckets: vec![5.0],
+        metric_exemplars: true,
+        ..Config::default()
+    };
+    config.validate().unwrap();
+    let metrics = Metrics::new(&config);
+    READ_ONLY_MODE.get();
+
+    metrics
+        .query_latency
+        .observe_for(0.05, Some("req-1".to_string()));
+    metrics.query_latency.observe_for(2.0, None);
+    with_request_id(Some("req-2".to_string()), async {
+        metrics.llm_latency.observe(7.5);
+    })
+    .await;
+
+    let text = metrics.render(false);
+    for line in [
+        "# TYPE acme_query_duration_seconds histogram",
+        "acme_query_duration_seconds_bucket{le=\"0.1\"} 1\n",
+        "acme_query_duration_seconds_bucket{le=\"1.0\"} 1\n",
+        "acme_query_duration_seconds_bucket{le=\"+Inf\"} 2\n",
+        "acme_query_duration_seconds_sum 2.05\n",
+        "acme_query_duration_seconds_count 2\n",
+        "acme_llm_request_duration_seconds_bucket{le=\"5.0\"} 0\n",
+        "acme_llm_request_duration_seconds_bucket{le=\"+Inf\"} 1\n",
+        // Unconfigured histograms keep the default buckets
+        "acme_http_request_duration_seconds_bucket{le=\"0.005\"} 0\n",
+        "acme_read_only_mode ",
+    ] {
+        assert!(text.contains(line), "{} missing from\n{}", line, text);
+    }
+    // The text format has no exemplars
+    assert!(!text.contains("request_id"));
+
+    let openmetrics = metrics.render(true);
+    assert!(
+        openmetrics.contains(
+            "acme_query_duration_seconds_bucket{le=\"0.1\"} 1 # {request_id=\"req-1\"} 0.05 "
+        ),
+        "{}",
+        openmetrics
+    );
+    assert!(openmetrics.contains(
+        "acme_llm_request_duration_seconds_bucket{le=\"+Inf\"} 1 # {request_id=\"req-2\"} 7.5 "
+    ));
+    assert!(openmetrics.contains("# TYPE acme_read_only_mode gauge\n"));
+    assert!(openmetrics.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn test_customers_filtered_and_sorted_by_params() {
+    use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "c_custkey",
+            Arc::new(Int64Array::from(vec![1, 2, 3, 4])) as ArrayRef,
+        ),
+        (
+            "c_name",
+            Arc::new(StringArray::from(vec![
+                "Customer#1",
+                "Customer#2",
+                "Customer#3",
+                "Customer#4",
+            ])) as ArrayRef,
+        ),
+        (
+            "c_acctbal",
+            Arc::new(Float64Array::from(vec![10.0, 40.0, 30.0, 20.0])) as ArrayRef,
+        ),
+        (
+            "c_mktsegment",
+            Arc::new(StringArray::from(vec![
+                "BUILDING",
+                "MACHINERY",
+                "BUILDING",
+                "BUILDING",
+            ])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("customer", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(
+            r#"{ customers(params: {
+                filters: [{ column: "c_mktsegment", operator: EQ, value: "BUILDING" }],
+                sortBy: "c_acctbal", sortOrder: DESC, limit: 2
+            }) { c_custkey c_acctbal } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["customers"],
+        json!([
+            { "c_custkey": 3, "c_acctbal": 30.0 },
+            { "c_custkey": 4, "c_acctbal": 20.0 }
+        ])
+    );
+
+    let response = schema
+        .execute(
+            r#"{ customersPaged(params: {
+                filters: [{ column: "c_acctbal", operator: GTE, value: "20" }],
+                sortBy: "c_acctbal", limit: 1, offset: 1
+            }) { data { c_custkey } totalCount hasMore } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["customersPaged"],
+        json!({ "data": [{ "c_custkey": 3 }], "totalCount": 3, "hasMore": true })
+    );
+
+    // A quoted value is compared as it is, not spliced into the SQL
+    let response = schema
+        .execute(
+            r#"{ customers(params: {
+                filters: [{ column: "c_mktsegment", operator: EQ, value: "BUILDING' OR '1'='1" }]
+            }) { c_custkey } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(response.data.into_json().unwrap()["customers"], json!([]));
+
+    for params in [
+        r#"sortBy: "1;DROP TABLE customer""#,
+        r#"filters: [{ column: "1;DROP TABLE customer", operator: EQ, value: "1" }]"#,
+    ] {
+        let response = schema
+            .execute(format!(
+                "{{ customers(params: {{ {} }}) {{ c_custkey }} }}",
+                params
+            ))
+            .await;
+        assert_eq!(response.errors.len(), 1, "{:?}", response.errors);
+        assert!(
+            response.errors[0].message.contains("1;DROP TABLE customer"),
+            "{}",
+            response.errors[0].message
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_query_table_escapes_filter_values() {
+    use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use graphql_datafusion::graphql::query_translator::{
+        QueryFilter, QueryParams, QueryTranslator,
+    };
+
+    let sql = QueryTranslator::new()
+        .translate(&QueryParams {
+            table: "customer".to_string(),
+            fields: None,
+            filters: Some(vec![QueryFilter {
+                field: "c_name".to_string(),
+                operator: "in".to_string(),
+                value: "O'Brien, 'quoted'".to_string(),
+            }]),
+            sort: None,
+            limit: None,
+            offset: None,
+        })
+        .unwrap();
+    assert_eq!(
+        sql,
+        "SELECT * FROM customer WHERE c_name IN ('O''Brien', '''quoted''')"
+    );
+
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "c_custkey",
+            Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef,
+        ),
+        (
+            "c_name",
+            Arc::new(StringArray::from(vec![
+                "Customer#1",
+                "O'Brien",
+                "Customer#3",
+            ])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("customer", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+    let query_table = |filter: &str| {
+        format!(
+            r#"{{ queryTable(params: {{ table: "customer", fields: ["c_custkey", "c_name"],
+                filters: [{}], sort: [{{ field: "c_custkey", order: "asc" }}] }}) {{ rows rowCount }} }}"#,
+            filter
+        )
+    };
+
+    let response = schema
+        .execute(query_table(
+            r#"{ field: "c_name", operator: "=", value: "O'Brien" }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["queryTable"]["rows"],
+        json!([{ "c_custkey": 2, "c_name": "O'Brien" }])
+    );
+
+    let response = schema
+        .execute(query_table(
+            r#"{ field: "c_name", operator: "in", value: "Customer#3, O'Brien" }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["queryTable"]["rowCount"],
+        json!(2)
+    );
+
+    // A value closing the literal itself stays a value
+    let response = schema
+        .execute(query_table(
+            r#"{ field: "c_name", operator: "=", value: "x' OR '1'='1" }"#,
+        ))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["queryTable"]["rowCount"],
+        json!(0)
+    );
+
+    let response = schema
+        .execute(r#"{ queryTable(params: { table: "information_schema.tables" }) { rowCount } }"#)
+        .await;
+    assert!(
+        response.errors[0].message.contains("Unknown table"),
+        "{:?}",
+        response.errors
+    );
+}
+
+#[tokio::test]
+async fn test_join_cardinality_of_customers_and_orders() {
+    use datafusion::arrow::array::{ArrayRef, BooleanArray, Int64Array};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    let df_ctx = customer_fixture();
+    let orders = RecordBatch::try_from_iter(vec![
+        (
+            "o_orderkey",
+            Arc::new(Int64Array::from(vec![10, 11, 12, 13, 14, 15])) as ArrayRef,
+        ),
+        // Order 15 belongs to a customer that does not exist
+        (
+            "o_custkey",
+            Arc::new(Int64Array::from(vec![1, 1, 2, 2, 2, 9])) as ArrayRef,
+        ),
+        (
+            "o_shipped",
+            Arc::new(BooleanArray::from(vec![
+                true, false, true, true, false, true,
+            ])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+    df_ctx.register_batches("orders", vec![orders]).unwrap();
+    let schema = default_schema(df_ctx);
+
+    let response = schema
+        .execute(
+            r#"{ joinCardinality(left: "customer", leftKey: "c_custkey", right: "orders",
+                rightKey: "o_custkey") { leftRows rightRows matched avgFanout } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["joinCardinality"],
+        json!({ "leftRows": 2, "rightRows": 6, "matched": 5, "avgFanout": 2.5 })
+    );
+
+    // Counted from the rows per key: 2 * 2 + 3 * 3 + 1 * 1 for a self-join
+    let response = schema
+        .execute(
+            r#"{ joinCardinality(left: "orders", leftKey: "o_custkey", right: "orders",
+                rightKey: "o_custkey") { matched } }"#,
+        )
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["joinCardinality"]["matched"],
+        json!(14)
+    );
+
+    for (arguments, error) in [
+        (
+            r#"left: "customer", leftKey: "c_custkey", right: "orders", rightKey: "missing""#,
+            "Unknown column missing in orders",
+        ),
+        (
+            r#"left: "customers", leftKey: "c_custkey", right: "orders", rightKey: "o_custkey""#,
+            "Unknown table: customers",
+        ),
+        (
+            r#"left: "customer", leftKey: "c_custkey", right: "orders", rightKey: "o_shipped""#,
+            "Cannot join customer.c_custkey",
+        ),
+    ] {
+        let response = schema
+            .execute(format!(
+                "{{ joinCardinality({}) {{ matched }} }}",
+                arguments
+            ))
+            .await;
+        assert!(
+            response.errors[0].message.contains(error),
+            "{:?}",
+            response.errors
+        );
+    }
+}
+
+#[test]
+fn test_translator_rejects_malicious_identifiers() {
+    use graphql_datafusion::graphql::query_translator::{
+        QueryFilter, QueryParams, QuerySort, QueryTranslator,
+    };
+
+    let params = || QueryParams {
+        table: "orders".to_string(),
+        fields: Some(vec!["o_orderkey".to_string()]),
+        filters: Some(vec![QueryFilter {
+            field: "o_orderstatus".to_string(),
+            operator: "=".to_string(),
+            value: "F".to_string(),
+        }]),
+        sort: Some(vec![QuerySort {
+            field: "o_totalprice".to_string(),
+            order: "desc".to_string(),
+        }]),
+        limit: Some(10),
+        offset: None,
+    };
+    let translator = QueryTranslator::new();
+    assert!(translator.translate(&params()).is_ok());
+
+    for name in [
+        "1=1; DROP TABLE orders",
+        "o_orderkey FROM orders; --",
+        "o_orderkey\"",
+        "1o_orderkey",
+        "o-orderkey",
+        "",
+    ] {
+        let mut table = params();
+        table.table = name.to_string();
+        let mut fields = params();
+        fields.fields = Some(vec!["o_orderkey".to_string(), name.to_string()]);
+        let mut filters = params();
+        filters.filters.as_mut().unwrap()[0].field = name.to_string();
+        let mut sort = params();
+        sort.sort.as_mut().unwrap()[0].field = name.to_string();
+
+        for (kind, params) in [
+            ("table", table),
+            ("column", fields),
+            ("column", filters),
+            ("column", sort),
+        ] {
+            let err = translator.translate(&params).unwrap_err();
+            assert!(
+                err.message.starts_with(&format!("Invalid {} name", kind)),
+                "{}: {}",
+                name,
+                err.message
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dynamic_query_returns_rows_of_any_table() {
+    use datafusion::arrow::array::{ArrayRef, Int64Array, StringArray};
+    use datafusion::arrow::record_batch::RecordBatch;
+
+    // Stored out of key order; paged rows come back ordered by n_nationkey
+    let batch = RecordBatch::try_from_iter(vec![
+        (
+            "n_nationkey",
+            Arc::new(Int64Array::from(vec![5, 0, 3, 1, 4, 2])) as ArrayRef,
+        ),
+        (
+            "n_name",
+            Arc::new(StringArray::from(vec![
+                "ETHIOPIA",
+                "ALGERIA",
+                "CANADA",
+                "ARGENTINA",
+                "EGYPT",
+                "BRAZIL",
+            ])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+    let df_ctx = DataFusionContext::in_memory();
+    df_ctx.register_batches("nation", vec![batch]).unwrap();
+    let schema = default_schema(Arc::new(df_ctx));
+
+    let response = schema
+        .execute(r#"{ dynamicQuery(params: { table: "nation", fields: ["n_name"], limit: 5 }) }"#)
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data.into_json().unwrap()["dynamicQuery"],
+        json!([
+            { "n_name": "ALGERIA" },
+            { "n_name": "ARGENTINA" },
+            { "n_name": "BRAZIL" },
+            { "n_name": "CANADA" },
+            { "n_name": "EGYPT" },
+        ])
+    );
+
+    for (params, error) in [
+        (
+            r#"table: "region", fields: ["r_name"]"#,
+            "Unknown table: region",
+        ),
+        (
+            r#"table: "nation", fields: ["n_comment"]"#,
+            "Unknown column n_comment in nation",
+        ),
+        (
+            r#"table: "nation", fields: ["n_name FROM nation; DROP TABLE nation; --"]"#,
+            "Invalid column name",
+        ),
+    ] {
+        let response = schema
+            .execute(format!("{{ dynamicQuery(params: {{ {} }}) }}", params))
+            .await;
+        assert!(
+            response.errors[0].message.contains(error),
+            "{:?}",
+            response.errors
+        );
+    }
+}
+
+#[test]
+fn test_uploads_capped_by_total_bytes() {
+    use graphql_datafusion::datafusion::upload::UploadedTables;
+    use graphql_datafusion::models::data::UploadFormat;
+    use std::time::Duration;
+
+    let df_ctx = Arc::new(DataFusionContext::in_memory());
+    let uploads =
+        UploadedTables::new(df_ctx.clone(), 1024, Duration::from_secs(60)).with_totals(0, 40);
+    let data = "id,amount\n1,10.5\n2,20\n"; // 22 bytes
+
+    uploads
+        .upload("alice", "first", UploadFormat::Csv, data)
+        .unwrap();
+    // Replacing an upload frees its bytes, a second table would not fit
+    uploads
+        .upload("alice", "first", UploadFormat::Csv, data)
+        .unwrap();
+    let err = uploads
+        .upload("bob", "second", UploadFormat::Csv, data)
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("over the limit of 40 bytes"),
+        "{}",
+        err
+    );
+    assert!(!df_ctx.get_table_names().contains(&"second".to_string()));
+}
+
+#[tokio::test]
+async fn test_schema_quotas_bind_requests_without_handler_data() {
+    use graphql_datafusion::quota::RoleQuota;
+    use std::collections::BTreeMap;
+
+    // As over WebSocket: no quotas come with the request
+    let df_ctx = customer_fixture();
+    let schema = build_schema(
+        df_ctx.clone(),
+        Arc::new(AgentOrchestrator::new()),
+        Arc::new(Config {
+            role_quotas: BTreeMap::from([(
+                "viewer".to_string(),
+                RoleQuota {
+                    max_rows_per_query: Some(1),
+                    max_export_rows_per_day: None,
+                },
+            )]),
+            ..Config::default()
+        }),
+    );
+
+    let response = schema.execute("{ customers { c_custkey } }").await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("ROW_LIMIT_EXCEEDED"));
+    // One row past the limit is enough to tell, no more are read
+    let sql = df_ctx.recent_queries().pop().unwrap().sql;
+    assert!(sql.contains("LIMIT 2 "), "{}", sql);
+
+    let response = schema
+        .execute(r#"{ dynamicQuery(params: { table: "customer", fields: ["c_name"] }) }"#)
+        .await;
+    let error = serde_json::to_value(&response.errors[0]).unwrap();
+    assert_eq!(error["extensions"]["code"], json!("ROW_LIMIT_EXCEEDED"));
+    let sql = df_ctx.recent_queries().pop().unwrap().sql;
+    assert!(sql.ends_with("LIMIT 2"), "{}", sql);
+}
+
+#[test]
+fn test_export_quota_shared_through_state_file() {
+    let path = std::env::temp_dir().join(format!("quota_{}.json", uuid::Uuid::new_v4()));
+    let replica_a = small_quotas().with_state_file(&path);
+    let replica_b = small_quotas().with_state_file(&path);
+
+    replica_a.record_export("alice", Some("viewer"), 1).unwrap();
+    replica_a.record_export("alice", Some("viewer"), 1).unwrap();
+    // The other replica sees the exports counted after it started
+    assert_eq!(
+        replica_b.usage("alice", Some("viewer")).exported_rows_today,
+        2
+    );
+    replica_b.record_export("alice", Some("viewer"), 1).unwrap();
+    let err = replica_a
+        .record_export("alice", Some("viewer"), 1)
+        .unwrap_err();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(err.code(), "EXPORT_QUOTA_EXCEEDED");
+}